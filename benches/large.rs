@@ -0,0 +1,60 @@
+//! 大きな盤面のベンチマーク (`cargo bench --bench large`)
+//!
+//! `large::LargeMazeState` の100×100と200×200の盤面で、状態の操作・1手分のビームサーチ・
+//! 盤面の書き出しを測る。面積が4倍になっても子の評価と範囲を切り出した描画の時間は変わらず、
+//! ビームサーチは残した状態を複製する分しか増えないことを確かめる。
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use num_collect_maze::{
+    game::GameState,
+    large::{LargeEvaluator, LargeMazeState},
+    search::arena_beam_search_action,
+};
+
+const SIZES: [usize; 2] = [100, 200];
+
+/// 測る盤面 (評価関数は周りの64マスを見るポテンシャル)
+fn bench_state(size: usize) -> LargeMazeState {
+    let mut state = LargeMazeState::new(0, size, size);
+    state.evaluator = LargeEvaluator::sampled_potential(64, 12, 50);
+    state
+}
+
+fn state_operations(c: &mut Criterion) {
+    for size in SIZES {
+        let state = bench_state(size);
+        let action = state.legal_actions()[0];
+        let mut group = c.benchmark_group(format!("large/{size}x{size}"));
+        group.bench_function("advance", |b| {
+            b.iter_batched_ref(
+                || state.clone(),
+                |state| state.advance(black_box(action)),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("clone", |b| b.iter(|| black_box(&state).clone()));
+        group.bench_function("preview_child", |b| {
+            b.iter(|| black_box(&state).preview_child(black_box(action)))
+        });
+        group.bench_function("beam(width=10,depth=10)", |b| {
+            b.iter(|| arena_beam_search_action(black_box(&state), 10, 10))
+        });
+        group.bench_function("render", |b| b.iter(|| black_box(&state).to_string()));
+        group.bench_function("render_window(30x30)", |b| {
+            b.iter(|| black_box(&state).render_window(size / 2, size / 2, 30, 30))
+        });
+        group.bench_function("write_chunked(rows=16)", |b| {
+            b.iter(|| {
+                let mut out = Vec::with_capacity((size + 1) * size + 32);
+                black_box(&state).write_chunked(&mut out, 16).unwrap();
+                out
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, state_operations);
+criterion_main!(benches);
@@ -0,0 +1,103 @@
+//! 探索のホットパスのベンチマーク (`cargo bench`)
+//!
+//! 盤面・パラメータ・乱数のシードを固定するので、実行ごとの差は実装の速さの差だけになる。
+//! 状態の遷移・合法手の列挙・状態の複製と、各探索の1手分の思考を測る。
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use num_collect_maze::{
+    game::{Direction, GameState, State},
+    search::{
+        arena_beam_search_action, beam_search_action, chokudai_search_action, greedy_action,
+        mcts_action, primitive_montecarlo_action, two_ply_greedy_action,
+    },
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+/// 測る盤面 (序盤の分岐が多い局面にするため、盤面0を数手進めておく)
+fn bench_state() -> State {
+    let mut state = State::new(0);
+    for _ in 0..5 {
+        let action = greedy_action(&state).unwrap();
+        state.advance(action);
+    }
+    state
+}
+
+fn state_operations(c: &mut Criterion) {
+    let state = bench_state();
+    let action = state.legal_actions()[0];
+    c.bench_function("advance", |b| {
+        b.iter_batched_ref(
+            || state.clone(),
+            |state| state.advance(black_box(action)),
+            BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("legal_actions", |b| {
+        b.iter(|| black_box(&state).legal_actions())
+    });
+    c.bench_function("clone", |b| b.iter(|| black_box(&state).clone()));
+    c.bench_function("evaluate", |b| {
+        b.iter_batched_ref(
+            || state.clone(),
+            |state| state.evaluate(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn decisions(c: &mut Criterion) {
+    let state = bench_state();
+    let mut group = c.benchmark_group("decision");
+    group.bench_function("greedy", |b| b.iter(|| greedy_action(black_box(&state))));
+    group.bench_function("two_ply_greedy", |b| {
+        b.iter(|| two_ply_greedy_action(black_box(&state)))
+    });
+    group.bench_function("beam(width=5,depth=10)", |b| {
+        b.iter(|| beam_search_action(black_box(&state), 5, 10))
+    });
+    group.bench_function("beam(width=200,depth=10)", |b| {
+        b.iter(|| beam_search_action(black_box(&state), 200, 10))
+    });
+    group.bench_function("arena_beam(width=5,depth=10)", |b| {
+        b.iter(|| arena_beam_search_action(black_box(&state), 5, 10))
+    });
+    group.bench_function("chokudai(width=1,depth=20,num=2)", |b| {
+        b.iter(|| chokudai_search_action(black_box(&state), 1, 20, 2))
+    });
+    group.bench_function("montecarlo(playouts=100)", |b| {
+        b.iter_batched_ref(
+            || ChaCha12Rng::seed_from_u64(0),
+            |rng| primitive_montecarlo_action(black_box(&state), 100, rng),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("mcts(playouts=100)", |b| {
+        b.iter_batched_ref(
+            || ChaCha12Rng::seed_from_u64(0),
+            |rng| mcts_action(black_box(&state), 100, 1.0, 10, rng),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// 1手の思考だけでなく、ビームサーチで盤面0を最後まで遊ぶ時間も測る
+fn whole_game(c: &mut Criterion) {
+    c.bench_function("game/beam(width=5,depth=5)", |b| {
+        b.iter(|| {
+            let mut state = State::new(black_box(0));
+            while !state.is_done() {
+                let action: Direction = beam_search_action(&state, 5, 5).unwrap();
+                state.advance(action);
+            }
+            state.game_score
+        })
+    });
+}
+
+criterion_group!(benches, state_operations, decisions, whole_game);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+/* DylibEvaluatorから読み込める評価関数の例
+ *
+ *   cc -shared -fPIC -O2 -o libscore.so evaluators/score.c
+ *   cargo run --release --features dylib -- evaluator ./libscore.so
+ */
+#include <stddef.h>
+#include <stdint.h>
+
+/* state_buffer: [H, W, turn, y, x, game_score, points...] */
+int64_t score(const int32_t *state_buffer, size_t len) {
+    (void)len;
+    return state_buffer[5];
+}
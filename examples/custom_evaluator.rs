@@ -0,0 +1,43 @@
+//! `game::Evaluator` を実装した評価関数でビームサーチを動かす
+//!
+//! ```text
+//! cargo run --release --example custom_evaluator
+//! ```
+
+use std::sync::Arc;
+
+use num_collect_maze::{prelude::*, search::beam_search_action_with_evaluator};
+
+/// スコアを優先し、同じスコアなら盤面の中央に近いほど高くする評価関数
+///
+/// 端に寄ると進める方向が減るので、中央寄りの盤面を少しだけ好む。
+struct CenterEvaluator {
+    /// 中央からの距離1マスあたりに引く点数 (スコア1点は100)
+    weight: i64,
+}
+
+impl Evaluator for CenterEvaluator {
+    fn evaluate(&self, state: &MazeState) -> Eval {
+        let distance =
+            (state.character.y - H as i32 / 2).abs() + (state.character.x - W as i32 / 2).abs();
+        Eval(100 * Eval::from(state.game_score).0 - self.weight * distance as i64)
+    }
+}
+
+fn main() {
+    let evaluator: Arc<dyn Evaluator> = Arc::new(CenterEvaluator { weight: 10 });
+    for seed in 0..5 {
+        let mut default = MazeState::new(seed);
+        let mut centered = MazeState::new(seed);
+        while !default.is_done() {
+            default.advance(beam_search_action(&default, 5, 10).unwrap());
+            centered.advance(
+                beam_search_action_with_evaluator(&centered, 5, 10, evaluator.clone()).unwrap(),
+            );
+        }
+        println!(
+            "seed {seed}: score {} (default evaluator), {} (center evaluator)",
+            default.game_score, centered.game_score
+        );
+    }
+}
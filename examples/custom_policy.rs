@@ -0,0 +1,62 @@
+//! `policy::Policy` を実装したAIを `play::run_game` で遊ばせ、組み込みのAIと比べる
+//!
+//! ```text
+//! cargo run --release --example custom_policy
+//! ```
+
+use num_collect_maze::prelude::*;
+
+/// 2手先までに取れる得点の合計が最大になる手を選ぶAI
+struct TwoStepPolicy;
+
+impl TwoStepPolicy {
+    /// `state` から `action` で進んだときに取る得点
+    fn gain(state: &State, action: Direction) -> u64 {
+        let mut next = state.clone();
+        next.advance(action);
+        next.game_score.0 - state.game_score.0
+    }
+}
+
+impl Policy for TwoStepPolicy {
+    fn name(&self) -> String {
+        "two-step".to_string()
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        state
+            .legal_actions()
+            .into_iter()
+            .max_by_key(|&action| {
+                let mut next = state.clone();
+                next.advance(action);
+                let second = if next.is_done() {
+                    0
+                } else {
+                    next.legal_actions()
+                        .into_iter()
+                        .map(|action| Self::gain(&next, action))
+                        .max()
+                        .unwrap_or(0)
+                };
+                Self::gain(state, action) + second
+            })
+            .expect("no legal action")
+    }
+}
+
+fn main() {
+    let config = PlayConfig::default();
+    let mut policies: Vec<Box<dyn Policy>> = vec![
+        Box::new(TwoStepPolicy),
+        parse_policy("greedy", 0).unwrap(),
+        parse_policy("beam:width=5,depth=10", 0).unwrap(),
+    ];
+    for policy in &mut policies {
+        let scores: Vec<usize> = (0..10)
+            .map(|seed| run_game(seed, policy.as_mut(), &config).game_score)
+            .collect();
+        let mean = scores.iter().sum::<usize>() as f64 / scores.len() as f64;
+        println!("{:<24} mean score {mean:.1}", policy.name());
+    }
+}
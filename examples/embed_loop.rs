@@ -0,0 +1,87 @@
+//! 盤面を別のプログラムのループに組み込む
+//!
+//! 強化学習の環境のように `reset` と `step` だけで迷路を進め、観測には
+//! `MazeState::to_buffer` の数値の並びを使う。手を決める側は盤面の型を知らなくてよい。
+//!
+//! ```text
+//! cargo run --release --example embed_loop
+//! ```
+
+use num_collect_maze::prelude::*;
+
+/// `reset` と `step` で迷路を進める環境
+struct MazeEnv {
+    config: GameConfig,
+    state: State,
+}
+
+/// `MazeEnv::step` の結果
+struct Step {
+    observation: Vec<i32>,
+    reward: u64,
+    done: bool,
+}
+
+impl MazeEnv {
+    fn new(config: GameConfig) -> Self {
+        let state = State::with_config(0, &config);
+        Self { config, state }
+    }
+
+    /// 盤面 `seed` で始め直し、最初の観測を返す
+    fn reset(&mut self, seed: u64) -> Vec<i32> {
+        self.state = State::with_config(seed, &self.config);
+        self.state.to_buffer()
+    }
+
+    /// 合法手の番号 (0: 右, 1: 左, 2: 下, 3: 上)
+    fn legal_actions(&self) -> Vec<usize> {
+        self.state
+            .legal_actions()
+            .into_iter()
+            .map(usize::from)
+            .collect()
+    }
+
+    /// 番号 `action` の行動で1ターン進める
+    fn step(&mut self, action: usize) -> Step {
+        let before = self.state.game_score;
+        self.state.advance(Direction::from(action));
+        Step {
+            observation: self.state.to_buffer(),
+            reward: self.state.game_score.0 - before.0,
+            done: self.state.is_done(),
+        }
+    }
+}
+
+fn main() {
+    let mut env = MazeEnv::new(GameConfig {
+        end_turn: 50,
+        ..GameConfig::default()
+    });
+    // 外側のループの手の決め方の例として、組み込みのAIに盤面を渡す
+    let mut policy = parse_policy("epsilon-greedy:eps=20", 7).unwrap();
+    for episode in 0..3 {
+        let observation = env.reset(episode);
+        println!(
+            "episode {episode}: observation has {} values",
+            observation.len()
+        );
+        let mut total = 0;
+        loop {
+            let action = usize::from(policy.select_action(&env.state));
+            assert!(env.legal_actions().contains(&action));
+            let step = env.step(action);
+            total += step.reward;
+            if step.done {
+                // 観測の3番目はターン数
+                println!(
+                    "  finished at turn {} with reward {total}",
+                    step.observation[2]
+                );
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! ブラウザのデモから呼ぶためのバックエンド
+//!
+//! 境界を越える値を整数と文字列だけにして、`wasm-bindgen` などで `DemoBackend` のメソッドを
+//! そのまま公開できる形にしてある。wasm32 では時間制限付きの探索が時刻をホストの
+//! `env.performance_now` から読むので、ホスト側で `performance.now()` を渡す
+//! (`util::PerformanceNowClock` を参照)。ここではネイティブのループで同じ呼び出しを試す。
+//!
+//! ```text
+//! cargo run --release --example wasm_backend
+//! ```
+
+use num_collect_maze::prelude::*;
+
+/// デモの画面が持つ1ゲーム分の状態
+pub struct DemoBackend {
+    state: State,
+    policy: Box<dyn Policy>,
+}
+
+impl DemoBackend {
+    /// 盤面 `seed` で `spec` のAIに遊ばせる (`spec` が読めなければその説明を返す)
+    pub fn new(seed: u64, spec: &str) -> Result<DemoBackend, String> {
+        let policy = parse_policy(spec, seed).map_err(|error| error.to_string())?;
+        Ok(Self {
+            state: State::new(seed),
+            policy,
+        })
+    }
+
+    /// AIに1手決めさせて進め、行動の番号を返す (終局していれば-1)
+    pub fn step(&mut self) -> i32 {
+        if self.state.is_done() {
+            return -1;
+        }
+        let action = self.policy.select_action(&self.state);
+        self.state.advance(action);
+        action.index() as i32
+    }
+
+    /// 人が選んだ番号 `action` の行動で進める (合法でなければfalse)
+    pub fn play(&mut self, action: u32) -> bool {
+        let Some(&action) = Direction::ALL.get(action as usize) else {
+            return false;
+        };
+        if self.state.is_done() || !self.state.legal_actions().contains(&action) {
+            return false;
+        }
+        self.state.advance(action);
+        true
+    }
+
+    pub fn score(&self) -> u64 {
+        self.state.game_score.0
+    }
+
+    pub fn turn(&self) -> u32 {
+        self.state.turn as u32
+    }
+
+    /// 画面に描く盤面 (`Display` の文字の並び)
+    pub fn board(&self) -> String {
+        self.state.to_string()
+    }
+}
+
+fn main() {
+    let mut backend = DemoBackend::new(0, "beam:width=5,us=500").unwrap();
+    assert!(backend.play(backend.state.legal_actions()[0].index() as u32));
+    while backend.step() >= 0 {}
+    print!("{}", backend.board());
+    println!("turn {} score {}", backend.turn(), backend.score());
+    assert!(DemoBackend::new(0, "beam:widht=5").is_err());
+}
@@ -0,0 +1,646 @@
+//! 2人のプレイヤーが交互に行動する数字集め迷路の変種とそのAI
+
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    game::{Coord, Direction},
+    util::TimeKeeper,
+};
+
+pub const ALTERNATE_H: usize = 5;
+pub const ALTERNATE_W: usize = 5;
+/// 2人の行動を合わせたターン数
+pub const ALTERNATE_END_TURN: usize = 12;
+
+#[derive(Clone, Copy)]
+pub struct Player {
+    pub character: Coord,
+    pub game_score: usize,
+}
+
+/// 手番のプレイヤーから見たゲームの結果
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WinningStatus {
+    Win,
+    Lose,
+    Draw,
+}
+
+/// 2人で交互に行動する迷路
+///
+/// `players[0]` が常に手番のプレイヤーで、1手進めるたびに入れ替える。
+#[derive(Clone)]
+pub struct AlternateMazeState {
+    pub points: Vec<Vec<usize>>,
+    pub turn: usize,
+    pub players: [Player; 2],
+}
+
+fn offset(coord: Coord, action: Direction) -> Option<Coord> {
+    let (dy, dx) = action.delta();
+    let (y, x) = (coord.y + dy, coord.x + dx);
+    ((0..ALTERNATE_H as i32).contains(&y) && (0..ALTERNATE_W as i32).contains(&x))
+        .then_some(Coord { y, x })
+}
+
+impl AlternateMazeState {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let y = ALTERNATE_H as i32 / 2;
+        let players = [
+            Player {
+                character: Coord::new(y, ALTERNATE_W as i32 / 2 - 1),
+                game_score: 0,
+            },
+            Player {
+                character: Coord::new(y, ALTERNATE_W as i32 / 2 + 1),
+                game_score: 0,
+            },
+        ];
+        let mut points = vec![vec![0; ALTERNATE_W]; ALTERNATE_H];
+        for y in 0..ALTERNATE_H {
+            for x in 0..ALTERNATE_W {
+                let coord = Coord::new(y as i32, x as i32);
+                if players.iter().all(|player| player.character != coord) {
+                    points[y][x] = rng.gen_range(0..10);
+                }
+            }
+        }
+        Self {
+            points,
+            turn: 0,
+            players,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.turn == ALTERNATE_END_TURN
+    }
+
+    /// 手番のプレイヤーが可能な行動
+    pub fn legal_actions(&self) -> Vec<Direction> {
+        Direction::ALL
+            .into_iter()
+            .filter(|&action| offset(self.players[0].character, action).is_some())
+            .collect()
+    }
+
+    /// 手番のプレイヤーが行動し、手番を相手に渡す
+    pub fn advance(&mut self, action: Direction) {
+        let player = &mut self.players[0];
+        player.character = offset(player.character, action).expect("illegal action");
+        let point = &mut self.points[player.character.y as usize][player.character.x as usize];
+        player.game_score += *point;
+        *point = 0;
+        self.turn += 1;
+        self.players.swap(0, 1);
+    }
+
+    /// 手番のプレイヤーから見た得点差
+    pub fn evaluate_score(&self) -> i64 {
+        self.players[0].game_score as i64 - self.players[1].game_score as i64
+    }
+
+    /// 終了していれば手番のプレイヤーから見た結果を返す
+    pub fn winning_status(&self) -> Option<WinningStatus> {
+        if !self.is_done() {
+            return None;
+        }
+        Some(match self.evaluate_score() {
+            s if s > 0 => WinningStatus::Win,
+            s if s < 0 => WinningStatus::Lose,
+            _ => WinningStatus::Draw,
+        })
+    }
+
+    /// 先手のプレイヤーから見た結果 (終了していなければNone)
+    pub fn first_player_status(&self) -> Option<WinningStatus> {
+        let status = self.winning_status()?;
+        if self.turn.is_multiple_of(2) {
+            return Some(status);
+        }
+        Some(match status {
+            WinningStatus::Win => WinningStatus::Lose,
+            WinningStatus::Lose => WinningStatus::Win,
+            WinningStatus::Draw => WinningStatus::Draw,
+        })
+    }
+}
+
+impl fmt::Display for AlternateMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 先手をA、後手をBで表示する
+        let (a, b) = if self.turn.is_multiple_of(2) {
+            (self.players[0], self.players[1])
+        } else {
+            (self.players[1], self.players[0])
+        };
+        writeln!(f, "turn:\t{}", self.turn)?;
+        writeln!(
+            f,
+            "score(A):\t{}\tscore(B):\t{}",
+            a.game_score, b.game_score
+        )?;
+        for y in 0..ALTERNATE_H {
+            for x in 0..ALTERNATE_W {
+                let coord = Coord::new(y as i32, x as i32);
+                if a.character == coord {
+                    write!(f, "A")?;
+                } else if b.character == coord {
+                    write!(f, "B")?;
+                } else if self.points[y][x] > 0 {
+                    write!(f, "{}", self.points[y][x])?;
+                } else {
+                    write!(f, ".")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// 手番のプレイヤーから見た `depth` 手先までのミニマックス値
+pub fn minimax_score(state: &AlternateMazeState, depth: usize) -> i64 {
+    if state.is_done() || depth == 0 {
+        return state.evaluate_score();
+    }
+    let mut best_score = i64::MIN;
+    for action in state.legal_actions() {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        best_score = best_score.max(-minimax_score(&next_state, depth - 1));
+    }
+    best_score
+}
+
+pub fn minimax_action(state: &AlternateMazeState, depth: usize) -> Direction {
+    let _span = tracing::info_span!("minimax").entered();
+    let mut best_action = None;
+    let mut best_score = i64::MIN;
+    for action in state.legal_actions() {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        let score = -minimax_score(&next_state, depth.saturating_sub(1));
+        if best_action.is_none() || score > best_score {
+            best_score = score;
+            best_action = Some(action);
+        }
+    }
+    best_action.unwrap()
+}
+
+/// 手番のプレイヤーから見た `depth` 手先までのアルファベータ法の評価値
+///
+/// `(alpha, beta)` の範囲外の値は範囲の端に丸めて返す。
+pub fn alpha_beta_score(
+    state: &AlternateMazeState,
+    mut alpha: i64,
+    beta: i64,
+    depth: usize,
+) -> i64 {
+    if state.is_done() || depth == 0 {
+        return state.evaluate_score();
+    }
+    for action in state.legal_actions() {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        let score = -alpha_beta_score(&next_state, -beta, -alpha, depth - 1);
+        if score > alpha {
+            alpha = score;
+        }
+        if alpha >= beta {
+            return alpha;
+        }
+    }
+    alpha
+}
+
+/// アルファベータ法で行動を決める (同じ深さのミニマックス法と同じ行動を返す)
+pub fn alpha_beta_action(state: &AlternateMazeState, depth: usize) -> Direction {
+    let _span = tracing::info_span!("alpha_beta").entered();
+    let legal_actions = state.legal_actions();
+    let mut best_action = legal_actions[0];
+    // 符号反転で溢れないように i64::MIN ではなく -i64::MAX を使う
+    let mut alpha = -i64::MAX;
+    let beta = i64::MAX;
+    for action in legal_actions {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        let score = -alpha_beta_score(&next_state, -beta, -alpha, depth.saturating_sub(1));
+        if score > alpha {
+            alpha = score;
+            best_action = action;
+        }
+    }
+    best_action
+}
+
+/// アルファベータ法で手を調べる順番の決め方
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveOrdering {
+    /// 反復深化で1つ浅い探索の最善手順 (PV) の手を先に調べる
+    pub previous_best: bool,
+    /// 同じ深さで直前にβカットを起こした手 (キラー手) を先に調べる
+    pub killers: bool,
+    /// これまでにβカットを起こした回数の多い (位置, 行動) を先に調べる (ヒストリー)
+    pub history: bool,
+}
+
+impl MoveOrdering {
+    pub const NONE: Self = Self {
+        previous_best: false,
+        killers: false,
+        history: false,
+    };
+    pub const ALL: Self = Self {
+        previous_best: true,
+        killers: true,
+        history: true,
+    };
+}
+
+impl std::str::FromStr for MoveOrdering {
+    type Err = String;
+
+    /// `none`, `all` か、`pv`・`killers`・`history` をカンマで並べたもの
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => return Ok(Self::NONE),
+            "all" => return Ok(Self::ALL),
+            _ => {}
+        }
+        let mut ordering = Self::NONE;
+        for name in s.split(',') {
+            match name {
+                "pv" => ordering.previous_best = true,
+                "killers" => ordering.killers = true,
+                "history" => ordering.history = true,
+                _ => return Err(format!("unknown move ordering {name:?}")),
+            }
+        }
+        Ok(ordering)
+    }
+}
+
+/// 手の並べ替えがどれだけ効いたかの記録
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrderingStats {
+    /// 子を展開した局面の数
+    pub nodes: u64,
+    /// βカットした局面の数
+    pub cutoffs: u64,
+    /// 最初に調べた手でβカットした局面の数
+    pub first_move_cutoffs: u64,
+    /// アスピレーション窓の上端以上の値が出て読み直した回数
+    pub fail_highs: u64,
+    /// アスピレーション窓の下端以下の値が出て読み直した回数
+    pub fail_lows: u64,
+    /// 時間制限付きの探索の回数
+    pub timed_searches: u64,
+    /// 時間制限付きの探索で読み切った深さの合計
+    pub timed_depth_sum: u64,
+}
+
+impl OrderingStats {
+    /// 展開した局面のうちβカットした割合
+    pub fn cutoff_rate(&self) -> f64 {
+        self.cutoffs as f64 / self.nodes.max(1) as f64
+    }
+
+    /// βカットのうち最初の手で起きた割合 (手の並びが良いほど1に近い)
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        self.first_move_cutoffs as f64 / self.cutoffs.max(1) as f64
+    }
+
+    /// 時間制限付きの探索で読み切った深さの平均
+    pub fn average_depth(&self) -> f64 {
+        self.timed_depth_sum as f64 / self.timed_searches.max(1) as f64
+    }
+
+    pub fn add(&mut self, other: &OrderingStats) {
+        self.nodes += other.nodes;
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.fail_highs += other.fail_highs;
+        self.fail_lows += other.fail_lows;
+        self.timed_searches += other.timed_searches;
+        self.timed_depth_sum += other.timed_depth_sum;
+    }
+}
+
+/// 手の並べ替えを使うアルファベータ法
+///
+/// キラー手とヒストリーは1回の探索 (`search`) の中で使い、探索ごとにリセットする。
+/// 同じ評価値の手は `legal_actions` の順で先の手を選ぶので、並べ方によらず
+/// `alpha_beta_action` や `minimax_action` と同じ行動を返す。
+/// アスピレーション窓で読み直しても、窓の外に出たら広げて読み直すので選ぶ行動は変わらない。
+pub struct OrderedAlphaBeta {
+    pub ordering: MoveOrdering,
+    /// 反復深化で1つ浅い探索の評価値から上下にこの幅の窓で読む (Noneなら窓を狭めない)
+    pub aspiration: Option<i64>,
+    /// 深さ(根からの手数)ごとの直近のキラー手2つ
+    killers: Vec<[Option<Direction>; 2]>,
+    /// `[手番のプレイヤーの位置][行動]` ごとのβカットの重み
+    history: Vec<[u64; 4]>,
+    /// 1つ浅い探索の最善手順
+    pv: Vec<Direction>,
+    /// 深さ(根からの手数)ごとの、その局面から先の最善手順
+    lines: Vec<Vec<Direction>>,
+    /// 時間制限付きの探索の時計
+    time_keeper: Option<TimeKeeper>,
+    /// 時間切れで探索を打ち切った
+    aborted: bool,
+    pub stats: OrderingStats,
+}
+
+impl OrderedAlphaBeta {
+    pub fn new(ordering: MoveOrdering) -> Self {
+        Self {
+            ordering,
+            aspiration: None,
+            killers: vec![],
+            history: vec![[0; 4]; ALTERNATE_H * ALTERNATE_W],
+            pv: vec![],
+            lines: vec![],
+            time_keeper: None,
+            aborted: false,
+            stats: OrderingStats::default(),
+        }
+    }
+
+    fn history_slot(state: &AlternateMazeState) -> usize {
+        let coord = state.players[0].character;
+        coord.y as usize * ALTERNATE_W + coord.x as usize
+    }
+
+    /// 調べる順に並べた合法手 (`pv_action` があれば最初)
+    fn ordered_actions(
+        &self,
+        state: &AlternateMazeState,
+        ply: usize,
+        pv_action: Option<Direction>,
+    ) -> Vec<Direction> {
+        let mut actions = state.legal_actions();
+        let killers = self.killers.get(ply).copied().unwrap_or_default();
+        let history = &self.history[Self::history_slot(state)];
+        // 小さいほど先 (安定ソートなので同じなら合法手の順)
+        actions.sort_by_key(|&action| {
+            if Some(action) == pv_action {
+                (0, 0)
+            } else if self.ordering.killers && Some(action) == killers[0] {
+                (1, 0)
+            } else if self.ordering.killers && Some(action) == killers[1] {
+                (2, 0)
+            } else if self.ordering.history {
+                (3, u64::MAX - history[action.index()])
+            } else {
+                (3, 0)
+            }
+        });
+        actions
+    }
+
+    fn record_cutoff(
+        &mut self,
+        state: &AlternateMazeState,
+        action: Direction,
+        ply: usize,
+        depth: usize,
+    ) {
+        if self.ordering.killers {
+            if self.killers.len() <= ply {
+                self.killers.resize(ply + 1, [None; 2]);
+            }
+            let killers = &mut self.killers[ply];
+            if killers[0] != Some(action) {
+                killers[1] = killers[0];
+                killers[0] = Some(action);
+            }
+        }
+        if self.ordering.history {
+            self.history[Self::history_slot(state)][action.index()] += (depth * depth) as u64;
+        }
+    }
+
+    /// `alpha_beta_score` と同じ値を返し、最善手順を `self.lines[ply]` に書く
+    fn score(
+        &mut self,
+        state: &AlternateMazeState,
+        mut alpha: i64,
+        beta: i64,
+        depth: usize,
+        ply: usize,
+        on_pv: bool,
+    ) -> i64 {
+        if self.lines.len() <= ply + 1 {
+            self.lines.resize(ply + 2, vec![]);
+        }
+        self.lines[ply].clear();
+        if state.is_done() || depth == 0 {
+            return state.evaluate_score();
+        }
+        if self.aborted || self.time_keeper.as_ref().is_some_and(TimeKeeper::is_over) {
+            // 打ち切った探索の値は使わないので何を返してもよい
+            self.aborted = true;
+            return alpha;
+        }
+        self.stats.nodes += 1;
+        let pv_action = if on_pv && self.ordering.previous_best {
+            self.pv.get(ply).copied()
+        } else {
+            None
+        };
+        for (i, action) in self
+            .ordered_actions(state, ply, pv_action)
+            .into_iter()
+            .enumerate()
+        {
+            let mut next_state = state.clone();
+            next_state.advance(action);
+            let child_on_pv = pv_action == Some(action);
+            let score = -self.score(&next_state, -beta, -alpha, depth - 1, ply + 1, child_on_pv);
+            if self.aborted {
+                return alpha;
+            }
+            if score > alpha {
+                alpha = score;
+                self.set_line(ply, action);
+            }
+            if alpha >= beta {
+                self.stats.cutoffs += 1;
+                if i == 0 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+                self.record_cutoff(state, action, ply, depth);
+                return alpha;
+            }
+        }
+        alpha
+    }
+
+    /// `self.lines[ply]` を `action` と1つ深い局面の最善手順をつないだものにする
+    fn set_line(&mut self, ply: usize, action: Direction) {
+        let (head, tail) = self.lines.split_at_mut(ply + 1);
+        let line = &mut head[ply];
+        line.clear();
+        line.push(action);
+        line.extend_from_slice(&tail[0]);
+    }
+
+    /// 根から `depth` 手先まで窓 `(low, high)` で読み、最善の行動とその評価値を返す
+    ///
+    /// 評価値が `high` 以上か `low` 以下なら、その値は窓の端に丸めた境界でしかない。
+    fn search_root(
+        &mut self,
+        state: &AlternateMazeState,
+        depth: usize,
+        low: i64,
+        high: i64,
+    ) -> (Direction, i64) {
+        let legal_actions = state.legal_actions();
+        let legal_index = |action| legal_actions.iter().position(|&a| a == action);
+        let pv_action = self.pv.first().copied();
+        let mut best: Option<(Direction, i64)> = None;
+        for action in self.ordered_actions(state, 0, pv_action) {
+            let mut next_state = state.clone();
+            next_state.advance(action);
+            // 同じ評価値の手も正確な値が分かるように、2手目以降は下限を1つ下げる
+            let alpha = best.map_or(low, |(_, score)| (score - 1).max(low));
+            let score = -self.score(
+                &next_state,
+                -high,
+                -alpha,
+                depth - 1,
+                1,
+                pv_action == Some(action),
+            );
+            if self.aborted {
+                break;
+            }
+            let is_better = best.is_none_or(|(best_action, best_score)| {
+                score > best_score
+                    || (score == best_score && legal_index(action) < legal_index(best_action))
+            });
+            if is_better {
+                best = Some((action, score));
+                self.set_line(0, action);
+            }
+            if score >= high {
+                break;
+            }
+        }
+        best.unwrap_or((legal_actions[0], 0))
+    }
+
+    /// 深さ `depth` の1回の反復 (時間切れならNone)
+    ///
+    /// `previous` は1つ浅い反復の評価値で、`aspiration` があればそれを中心にした窓で読み、
+    /// 窓の外に出たらその側を広げて読み直す。
+    fn iterate(
+        &mut self,
+        state: &AlternateMazeState,
+        depth: usize,
+        previous: Option<i64>,
+    ) -> Option<(Direction, i64)> {
+        let (mut low, mut high) = match (self.aspiration, previous) {
+            (Some(window), Some(score)) => (score - window, score + window),
+            _ => (-i64::MAX, i64::MAX),
+        };
+        loop {
+            let (action, score) = self.search_root(state, depth, low, high);
+            if self.aborted {
+                return None;
+            }
+            if score >= high {
+                self.stats.fail_highs += 1;
+                high = i64::MAX;
+            } else if score <= low {
+                self.stats.fail_lows += 1;
+                low = -i64::MAX;
+            } else {
+                self.pv = self.lines[0].clone();
+                return Some((action, score));
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.killers.clear();
+        self.history.iter_mut().for_each(|h| *h = [0; 4]);
+        self.pv.clear();
+        if self.lines.len() < 2 {
+            self.lines.resize(2, vec![]);
+        }
+        self.time_keeper = None;
+        self.aborted = false;
+    }
+
+    /// 深さ `depth` まで読んで行動を決める
+    ///
+    /// `previous_best` か `aspiration` があれば深さ1から反復深化する。
+    pub fn search(&mut self, state: &AlternateMazeState, depth: usize) -> Direction {
+        let _span = tracing::info_span!("ordered_alpha_beta").entered();
+        self.reset();
+        let depth = depth.max(1);
+        let first_depth = if self.ordering.previous_best || self.aspiration.is_some() {
+            1
+        } else {
+            depth
+        };
+        let mut best = None;
+        for iteration_depth in first_depth..=depth {
+            best = self.iterate(state, iteration_depth, best.map(|(_, score)| score));
+        }
+        best.unwrap().0
+    }
+
+    /// 時間制限 `time_threshold` ms の中で、読み切れた最も深い反復の行動を返す
+    ///
+    /// 深さ1は時間によらず読み切り、それより深い反復は途中で時間切れになったら捨てる。
+    pub fn search_with_time_threshold(
+        &mut self,
+        state: &AlternateMazeState,
+        time_threshold: u128,
+    ) -> Direction {
+        let _span = tracing::info_span!("ordered_alpha_beta_with_time_threshold").entered();
+        self.reset();
+        let time_keeper = TimeKeeper::new(time_threshold);
+        let mut best = self.iterate(state, 1, None).unwrap();
+        let mut depth_reached = 1;
+        self.time_keeper = Some(time_keeper);
+        // 残りのターン数より深く読んでも同じ結果になる
+        for iteration_depth in 2..=ALTERNATE_END_TURN - state.turn {
+            let Some(result) = self.iterate(state, iteration_depth, Some(best.1)) else {
+                break;
+            };
+            best = result;
+            depth_reached = iteration_depth;
+        }
+        self.time_keeper = None;
+        self.stats.timed_searches += 1;
+        self.stats.timed_depth_sum += depth_reached as u64;
+        best.0
+    }
+}
+
+/// 盤面 `seed` で `first` を先手、`second` を後手として対戦し、先手から見た結果を返す
+pub fn self_play(
+    seed: u64,
+    first: &dyn Fn(&AlternateMazeState) -> Direction,
+    second: &dyn Fn(&AlternateMazeState) -> Direction,
+) -> WinningStatus {
+    let mut state = AlternateMazeState::new(seed);
+    while !state.is_done() {
+        let action = if state.turn.is_multiple_of(2) {
+            first(&state)
+        } else {
+            second(&state)
+        };
+        state.advance(action);
+    }
+    state.first_player_status().unwrap()
+}
@@ -0,0 +1,154 @@
+//! 探索の速さの較正
+//!
+//! 手元の機械で各探索が1msあたりに展開できる状態の数を測ってプロファイルに書き出す。
+//! 設定では時間の代わりに展開する状態の数 (`nodes=N`) で1手の予算を指定しておき、
+//! 機械ごとのプロファイルで時間制限に読み替えると、速さの違う機械でも同じだけ読ませられる。
+
+use std::{fs::File, io::BufWriter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::policy_param,
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    schema::{check_calibration_version, CALIBRATION_SCHEMA_VERSION},
+    search::SearchStats,
+};
+
+/// 較正する探索と、測るときの設定 (`{ms}` を1手の時間制限に置き換える)
+pub const CALIBRATED_ALGORITHMS: [(&str, &str); 4] = [
+    ("beam", "beam:width=5,ms={ms}"),
+    ("chokudai", "chokudai:width=1,depth=100,ms={ms}"),
+    (
+        "parallel-chokudai",
+        "parallel-chokudai:width=1,depth=100,ms={ms}",
+    ),
+    ("iw", "iw:k=2,samples=16,ms={ms}"),
+];
+
+/// 1つの探索の速さ
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlgorithmRate {
+    /// `parse_policy` のAIの名前 (`beam` など)
+    pub algorithm: String,
+    /// 1msあたりに展開した状態の数
+    pub nodes_per_ms: f64,
+    /// 測ったときの探索の記録の合計
+    pub stats: SearchStats,
+}
+
+/// `calibrate` で書き出す機械ごとのプロファイル
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalibrationProfile {
+    /// 形式の版 (`schema::CALIBRATION_SCHEMA_VERSION`)
+    pub schema_version: u32,
+    pub crate_version: String,
+    /// UNIX時間[s]
+    pub created_at: u64,
+    /// 測ったときの1手の時間制限[ms]
+    pub budget_ms: u128,
+    pub rates: Vec<AlgorithmRate>,
+}
+
+impl CalibrationProfile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let profile: Self = serde_json::from_str(&text).map_err(|e| format!("{path}: {e}"))?;
+        check_calibration_version(profile.schema_version, path).map_err(|e| e.to_string())?;
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// `algorithm` の1msあたりに展開する状態の数
+    pub fn nodes_per_ms(&self, algorithm: &str) -> Result<f64, String> {
+        self.rates
+            .iter()
+            .find(|rate| rate.algorithm == algorithm)
+            .map(|rate| rate.nodes_per_ms)
+            .filter(|&rate| rate > 0.)
+            .ok_or_else(|| format!("{algorithm:?} is not calibrated"))
+    }
+
+    /// `nodes` 個の状態を展開するのにかかる時間[ms] (切り上げて1ms以上)
+    pub fn ms_for_nodes(&self, algorithm: &str, nodes: u64) -> Result<u128, String> {
+        Ok(((nodes as f64 / self.nodes_per_ms(algorithm)?).ceil() as u128).max(1))
+    }
+
+    /// `ms` の間に展開できる状態の数
+    pub fn nodes_for_ms(&self, algorithm: &str, ms: u128) -> Result<u64, String> {
+        Ok((ms as f64 * self.nodes_per_ms(algorithm)?).round() as u64)
+    }
+
+    /// `beam:width=5,nodes=2000` のような展開する状態の数で予算を決めた指定を、時間制限 `ms` の指定にする
+    ///
+    /// `nodes` のない指定はそのまま返す。組み合わせの式 (`fallback(...)` など) の中の `nodes` は読み替えずにエラーにする。
+    pub fn resolve(&self, spec: &str) -> Result<String, String> {
+        if !spec.contains("nodes=") {
+            return Ok(spec.to_string());
+        }
+        let nodes = match policy_param(spec, "nodes") {
+            Some(nodes) if !spec.contains('(') => nodes,
+            _ => return Err(format!("node budgets are not supported in {spec:?}")),
+        };
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let ms = self.ms_for_nodes(name, nodes)?;
+        let params: Vec<String> = params
+            .split(',')
+            .filter(|param| {
+                !param.is_empty() && !param.starts_with("ms=") && !param.starts_with("us=")
+            })
+            .map(|param| {
+                if param.starts_with("nodes=") {
+                    format!("ms={ms}")
+                } else {
+                    param.to_string()
+                }
+            })
+            .collect();
+        Ok(format!("{name}:{}", params.join(",")))
+    }
+}
+
+/// 各探索を1手 `budget_ms` で盤面 `0..games` を遊ばせ、1msあたりに展開した状態の数を測る
+///
+/// 他のゲームと時間を取り合わないように、ゲームは1つずつ順に遊ばせる。
+pub fn calibrate(games: u64, budget_ms: u128) -> CalibrationProfile {
+    let mut rates = vec![];
+    for (algorithm, template) in CALIBRATED_ALGORITHMS {
+        let spec = template.replace("{ms}", &budget_ms.to_string());
+        let mut stats = SearchStats::default();
+        for seed in 0..games {
+            let mut policy = parse_policy(&spec, seed).unwrap();
+            let result = run_game(seed, policy.as_mut(), &PlayConfig::default());
+            if let Some(search_stats) = &result.search_stats {
+                stats.add(search_stats);
+            }
+        }
+        let elapsed_ms = stats.elapsed_us as f64 / 1000.;
+        rates.push(AlgorithmRate {
+            algorithm: algorithm.to_string(),
+            nodes_per_ms: if elapsed_ms > 0. {
+                stats.nodes_expanded as f64 / elapsed_ms
+            } else {
+                0.
+            },
+            stats,
+        });
+    }
+    CalibrationProfile {
+        schema_version: CALIBRATION_SCHEMA_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        budget_ms,
+        rates,
+    }
+}
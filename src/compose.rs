@@ -0,0 +1,357 @@
+//! 既存のAIを組み合わせて新しいAIを作る式と、それを並べた設定ファイル
+//!
+//! ```text
+//! fallback(greedy, timeout(beam(width=10), 5ms))
+//! switch_at_turn(80, beam(width=5,depth=10), exact)
+//! ```
+
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::{
+    game::{Direction, State},
+    policy::{parse_policy, ActionProbabilities, Policy},
+    schema::check_config_version,
+    spec::SpecError,
+    util::splitmix64,
+};
+
+/// `inner` を時間制限 `time_threshold`[ms] で動かし、超過したら失敗とみなす
+///
+/// 単独で使ったときは超過しても `inner` の選んだ手を返す。
+pub struct TimeoutPolicy {
+    pub inner: Box<dyn Policy>,
+    pub time_threshold: u128,
+    /// 時間制限を超過した手の数
+    pub timeouts: usize,
+}
+
+impl TimeoutPolicy {
+    fn run(&mut self, state: &State) -> (Direction, bool) {
+        let default_time_threshold = self.inner.time_threshold_us();
+        self.inner.set_time_threshold_us(self.time_threshold * 1000);
+        let start = Instant::now();
+        let action = self.inner.select_action(state);
+        let in_time = start.elapsed().as_millis() <= self.time_threshold;
+        if let Some(time_threshold) = default_time_threshold {
+            self.inner.set_time_threshold_us(time_threshold);
+        }
+        if !in_time {
+            self.timeouts += 1;
+        }
+        (action, in_time)
+    }
+}
+
+impl Policy for TimeoutPolicy {
+    fn name(&self) -> String {
+        format!("timeout({}, {}ms)", self.inner.name(), self.time_threshold)
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        self.run(state).0
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        let (action, in_time) = self.run(state);
+        in_time.then_some(action)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold * 1000)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold = time_threshold_us / 1000;
+    }
+
+    fn warm_up(&mut self, state: &State) {
+        self.inner.warm_up(state);
+    }
+
+    fn active_evaluator(&self) -> Option<String> {
+        self.inner.active_evaluator()
+    }
+}
+
+/// `main` で手を決め、失敗したら `safe` で決める
+pub struct FallbackPolicy {
+    pub safe: Box<dyn Policy>,
+    pub main: Box<dyn Policy>,
+}
+
+impl Policy for FallbackPolicy {
+    fn name(&self) -> String {
+        format!("fallback({}, {})", self.safe.name(), self.main.name())
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        self.main
+            .try_select_action(state)
+            .unwrap_or_else(|| self.safe.select_action(state))
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.main
+            .try_select_action(state)
+            .or_else(|| self.safe.try_select_action(state))
+    }
+
+    fn warm_up(&mut self, state: &State) {
+        self.safe.warm_up(state);
+        self.main.warm_up(state);
+    }
+}
+
+/// ターン `turn` より前は `before`、以降は `after` で手を決める
+pub struct SwitchAtTurnPolicy {
+    pub turn: usize,
+    pub before: Box<dyn Policy>,
+    pub after: Box<dyn Policy>,
+}
+
+impl SwitchAtTurnPolicy {
+    fn current(&mut self, state: &State) -> &mut Box<dyn Policy> {
+        if state.turn < self.turn {
+            &mut self.before
+        } else {
+            &mut self.after
+        }
+    }
+}
+
+impl Policy for SwitchAtTurnPolicy {
+    fn name(&self) -> String {
+        format!(
+            "switch_at_turn({}, {}, {})",
+            self.turn,
+            self.before.name(),
+            self.after.name()
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        self.current(state).select_action(state)
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.current(state).try_select_action(state)
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        self.current(state).sample_action(state)
+    }
+
+    fn warm_up(&mut self, state: &State) {
+        self.before.warm_up(state);
+    }
+}
+
+/// 式でAIを組み合わせる名前
+pub const COMBINATOR_NAMES: &[&str] = &["fallback", "timeout", "switch_at_turn"];
+
+/// 組み合わせの式の再帰下降パーサ
+struct ExprParser<'a> {
+    text: &'a str,
+    pos: usize,
+    rng_seed: u64,
+    /// これまでに作った末端のAIの数 (乱数のシードを変えるのに使う)
+    leaves: u64,
+}
+
+impl<'a> ExprParser<'a> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, message: &str) -> SpecError {
+        SpecError::Invalid(format!("{message} at {:?} in {:?}", self.rest(), self.text))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SpecError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {c:?}")))
+        }
+    }
+
+    /// 英数字・`-`・`_` の並び
+    fn word(&mut self) -> Result<&'a str, SpecError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name or a number"));
+        }
+        self.pos += len;
+        Ok(&self.text[self.pos - len..self.pos])
+    }
+
+    fn number(&mut self) -> Result<usize, SpecError> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| SpecError::Invalid(format!("invalid number {word:?} in {:?}", self.text)))
+    }
+
+    /// `5ms` のような時間[ms]
+    fn duration_ms(&mut self) -> Result<u128, SpecError> {
+        let word = self.word()?;
+        word.strip_suffix("ms")
+            .and_then(|ms| ms.parse().ok())
+            .ok_or_else(|| SpecError::InvalidParam {
+                param: word.to_string(),
+                spec: self.text.to_string(),
+                expected: "a duration such as 5ms",
+            })
+    }
+
+    fn expr(&mut self) -> Result<Box<dyn Policy>, SpecError> {
+        let name = self.word()?.to_string();
+        let policy: Box<dyn Policy> = match name.as_str() {
+            "fallback" => {
+                self.expect('(')?;
+                let safe = self.expr()?;
+                self.expect(',')?;
+                let main = self.expr()?;
+                self.expect(')')?;
+                Box::new(FallbackPolicy { safe, main })
+            }
+            "timeout" => {
+                self.expect('(')?;
+                let inner = self.expr()?;
+                self.expect(',')?;
+                let time_threshold = self.duration_ms()?;
+                self.expect(')')?;
+                Box::new(TimeoutPolicy {
+                    inner,
+                    time_threshold,
+                    timeouts: 0,
+                })
+            }
+            "switch_at_turn" => {
+                self.expect('(')?;
+                let turn = self.number()?;
+                self.expect(',')?;
+                let before = self.expr()?;
+                self.expect(',')?;
+                let after = self.expr()?;
+                self.expect(')')?;
+                Box::new(SwitchAtTurnPolicy {
+                    turn,
+                    before,
+                    after,
+                })
+            }
+            _ => {
+                // beam(width=10) は parse_policy の beam:width=10 と同じ
+                let mut spec = name;
+                if self.eat('(') {
+                    let end = self
+                        .rest()
+                        .find(')')
+                        .ok_or_else(|| self.error("unclosed parameter list"))?;
+                    let params: String = self.rest()[..end]
+                        .chars()
+                        .filter(|c| !c.is_whitespace())
+                        .collect();
+                    self.pos += end + 1;
+                    spec = format!("{spec}:{params}");
+                }
+                self.leaves += 1;
+                parse_policy(&spec, splitmix64(self.rng_seed ^ self.leaves)).map_err(|error| {
+                    // 式の中では組み合わせの名前も書ける
+                    match error {
+                        SpecError::UnknownName {
+                            kind: "policy",
+                            name,
+                            mut valid,
+                        } => {
+                            valid.extend(COMBINATOR_NAMES);
+                            SpecError::UnknownName {
+                                kind: "policy",
+                                name,
+                                valid,
+                            }
+                        }
+                        error => error,
+                    }
+                })?
+            }
+        };
+        Ok(policy)
+    }
+}
+
+/// `fallback(greedy, timeout(beam(width=10), 5ms))` のような式からAIを作る
+///
+/// - `fallback(安全策, 本命)`: 本命で手を決め、失敗したら安全策で決める
+/// - `timeout(AI, 5ms)`: 時間制限を5msにして動かし、超過したら失敗とみなす
+/// - `switch_at_turn(80, 序盤, 終盤)`: ターン80から終盤のAIに切り替える
+/// - それ以外の `名前(キー=値,...)` は `parse_policy` の `名前:キー=値,...` と同じ (`exact` は終盤の全探索)
+pub fn parse_policy_expr(expr: &str, rng_seed: u64) -> Result<Box<dyn Policy>, SpecError> {
+    let mut parser = ExprParser {
+        text: expr,
+        pos: 0,
+        rng_seed,
+        leaves: 0,
+    };
+    let policy = parser.expr()?;
+    parser.skip_whitespace();
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected trailing input"));
+    }
+    Ok(policy)
+}
+
+/// AIの設定ファイル (TOML)
+///
+/// ```toml
+/// policies = [
+///     "fallback(greedy, timeout(beam(width=10), 5ms))",
+///     "switch_at_turn(80, beam(width=5,depth=10), exact)",
+/// ]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// 設定ファイルの形式の版 (省略したら1。`schema::CONFIG_SCHEMA_VERSION`)
+    #[serde(default = "default_config_schema_version")]
+    pub schema_version: u32,
+    pub policies: Vec<String>,
+}
+
+fn default_config_schema_version() -> u32 {
+    1
+}
+
+impl PolicyConfig {
+    /// 設定ファイルを読み込み、版と全ての式が解釈できることを確かめる
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let config: PolicyConfig = toml::from_str(&text).map_err(|e| format!("{path}: {e}"))?;
+        check_config_version(config.schema_version, path).map_err(|e| e.to_string())?;
+        for expr in &config.policies {
+            parse_policy(expr, 0).map_err(|e| format!("{path}: {e}"))?;
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,174 @@
+//! 探索中のpanicの記録
+//!
+//! `install_panic_hook` を呼ぶと、`play::run_game`・`play::play_game` やソークテストが毎ターンの盤面・AIの指定・
+//! 探索の統計をスレッドごとに覚えておき、panicしたらそれをファイルに書き出す。
+//! 大量の対戦やソークテストでまれに起きた失敗を、あとから同じ盤面と手順で再現できる。
+
+use std::{
+    cell::RefCell,
+    fmt::Write as _,
+    panic::PanicHookInfo,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    game::{GameConfig, State},
+    search::SearchStats,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CONTEXT: RefCell<Option<CrashContext>> = const { RefCell::new(None) };
+}
+
+/// panicしたときに書き出す、遊んでいるゲームの状況
+#[derive(Clone)]
+pub struct CrashContext {
+    pub seed: u64,
+    /// AIの指定 (`Policy::name`)
+    pub policy: String,
+    pub game: GameConfig,
+    /// 手を決めようとしていた盤面
+    pub state: State,
+    /// それまでに指した行動の番号
+    pub actions: Vec<usize>,
+    /// 直前のターンまでの探索の統計 (探索しないAIはNone)
+    pub stats: Option<SearchStats>,
+}
+
+impl CrashContext {
+    /// panicのメッセージと場所を添えた書き出す内容
+    pub fn dump(&self, message: &str, location: &str) -> String {
+        let mut dump = String::new();
+        writeln!(dump, "message:\t{message}").unwrap();
+        writeln!(dump, "location:\t{location}").unwrap();
+        writeln!(dump, "seed:\t{}", self.seed).unwrap();
+        writeln!(dump, "policy:\t{}", self.policy).unwrap();
+        writeln!(
+            dump,
+            "rules:\tend_turn={} revisit_penalty={} eval_noise={} mask={}",
+            self.game.end_turn,
+            self.game.revisit_penalty,
+            self.game.eval_noise,
+            self.game.mask.is_some()
+        )
+        .unwrap();
+        let actions: Vec<String> = self.actions.iter().map(usize::to_string).collect();
+        writeln!(dump, "actions:\t{}", actions.join(" ")).unwrap();
+        if let Some(stats) = &self.stats {
+            writeln!(dump, "stats:\t{}", serde_json::to_string(stats).unwrap()).unwrap();
+        }
+        write!(dump, "{}", self.state).unwrap();
+        dump
+    }
+
+    /// 書き出すファイルの名前 (`crash-<seed>-<turn>-<pid>.txt`)
+    pub fn file_name(&self) -> String {
+        format!(
+            "crash-{}-{}-{}.txt",
+            self.seed,
+            self.state.turn,
+            std::process::id()
+        )
+    }
+}
+
+/// `enter_game` の戻り値で、破棄するとゲームに入る前の状況に戻す
+pub struct CrashGuard {
+    previous: Option<Option<CrashContext>>,
+}
+
+impl Drop for CrashGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let _ = CONTEXT.try_with(|context| *context.borrow_mut() = previous);
+        }
+    }
+}
+
+/// このスレッドでゲームを始めたことを記録する (`install_panic_hook` の前は何もしない)
+pub fn enter_game(seed: u64, policy: String, game: &GameConfig, state: &State) -> CrashGuard {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return CrashGuard { previous: None };
+    }
+    let context = CrashContext {
+        seed,
+        policy,
+        game: game.clone(),
+        state: state.clone(),
+        actions: vec![],
+        stats: None,
+    };
+    CrashGuard {
+        previous: Some(CONTEXT.with(|current| current.replace(Some(context)))),
+    }
+}
+
+/// 手を決める前の盤面とそれまでの行動・探索の統計を記録する
+pub fn note_turn(state: &State, actions: &[usize], stats: Option<SearchStats>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    CONTEXT.with(|context| {
+        if let Some(context) = context.borrow_mut().as_mut() {
+            context.state = state.clone();
+            context.actions.clear();
+            context.actions.extend_from_slice(actions);
+            context.stats = stats;
+        }
+    });
+}
+
+/// このスレッドで遊んでいるゲームの状況 (ゲームの外や `install_panic_hook` の前はNone)
+pub fn current_context() -> Option<CrashContext> {
+    CONTEXT
+        .try_with(|context| {
+            context
+                .try_borrow()
+                .ok()
+                .and_then(|context| context.clone())
+        })
+        .ok()
+        .flatten()
+}
+
+/// panicしたら、ゲームの途中であればその状況を `dir` に書き出すフックを入れる
+///
+/// 元のフックも呼ぶので、panicのメッセージはこれまでどおり標準エラーに出る。
+pub fn install_panic_hook(dir: impl Into<PathBuf>) {
+    let dir = dir.into();
+    ENABLED.store(true, Ordering::Relaxed);
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if let Some(context) = current_context() {
+            match write_dump(&dir, &context, info) {
+                Ok(path) => eprintln!("crash dump: {}", path.display()),
+                Err(err) => eprintln!("failed to write crash dump: {err}"),
+            }
+        }
+    }));
+}
+
+fn write_dump(
+    dir: &Path,
+    context: &CrashContext,
+    info: &PanicHookInfo<'_>,
+) -> std::io::Result<PathBuf> {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(context.file_name());
+    std::fs::write(&path, context.dump(&message, &location))?;
+    Ok(path)
+}
@@ -0,0 +1,155 @@
+//! 固定したベンチマークを定期的に実行し、結果をデータベースに溜めてスコアの変化を検出する
+//!
+//! 毎回同じAIの一覧・シード・乱数のシードで遊ばせるので、前回の実行とシードごとに対応を取って
+//! Wilcoxonの符号付き順位検定で比べられる。crateを更新しながら動かし続けると、
+//! 探索の品質が知らないうちに落ちたり上がったりしたのを検出できる。
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    db::{DriftRecord, ResultDb},
+    play::{run_game, PlayConfig},
+    policy::{derive_policy_seed, parse_policy},
+    results::{GameResult, RunMeta},
+    stats::wilcoxon_signed_rank,
+    util::{run_games_parallel, ThreadConfig},
+};
+
+/// データベースに登録するときの実行の種類
+pub const DAEMON_KIND: &str = "daemon";
+
+/// 固定したベンチマークの設定
+#[derive(Clone)]
+pub struct DaemonConfig {
+    /// 毎回遊ばせるAIの指定
+    pub specs: Vec<String>,
+    /// 盤面のシード `0..games` で遊ばせる
+    pub games: usize,
+    pub rng_seed: u64,
+    pub thread_config: ThreadConfig,
+    pub play_config: PlayConfig,
+    /// このp値未満の変化を有意とみなす
+    pub alpha: f64,
+    pub meta: RunMeta,
+}
+
+/// 1つのAIの前回からの変化
+pub struct Drift {
+    pub record: DriftRecord,
+    /// 検定のp値が `alpha` 未満だったか
+    pub significant: bool,
+}
+
+/// `24h`・`30m`・`10s`・`2d` のような時間の長さを読む (単位を省略したら秒)
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval {text:?}"))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown interval unit {unit:?} (s, m, h, d)")),
+    };
+    Ok(Duration::from_secs(number * seconds))
+}
+
+/// ベンチマークを1回実行してデータベースに登録し、前回の実行からの変化を返す
+///
+/// 有意な変化はデータベースの `drifts` にも記録する。
+pub fn run_cycle(db: &mut ResultDb, config: &DaemonConfig) -> rusqlite::Result<Vec<Drift>> {
+    let mut all_results: Vec<GameResult> = vec![];
+    for (policy_index, spec) in config.specs.iter().enumerate() {
+        all_results.extend(run_games_parallel(
+            config.games,
+            config.thread_config,
+            |seed| {
+                let rng_seed = derive_policy_seed(config.rng_seed, policy_index, seed);
+                let mut policy = parse_policy(spec, rng_seed).unwrap();
+                run_game(seed, policy.as_mut(), &config.play_config)
+            },
+        ));
+    }
+    let run_id = db.record(DAEMON_KIND, &config.meta, &all_results)?;
+    let mut policies: Vec<&str> = vec![];
+    for result in &all_results {
+        if !policies.contains(&result.policy.as_str()) {
+            policies.push(&result.policy);
+        }
+    }
+    let mut drifts = vec![];
+    for policy in policies {
+        let Some((previous_run_id, previous)) = db.previous_scores(DAEMON_KIND, policy, run_id)?
+        else {
+            continue;
+        };
+        let (mut differences, mut previous_sum, mut sum) = (vec![], 0., 0.);
+        for result in all_results.iter().filter(|r| r.policy == policy) {
+            if let Some(&previous_score) = previous.get(&result.seed) {
+                differences.push(result.game_score as f64 - previous_score as f64);
+                previous_sum += previous_score as f64;
+                sum += result.game_score as f64;
+            }
+        }
+        if differences.is_empty() {
+            continue;
+        }
+        let n = differences.len() as f64;
+        let test = wilcoxon_signed_rank(&differences);
+        let drift = Drift {
+            record: DriftRecord {
+                run_id,
+                previous_run_id,
+                policy: policy.to_string(),
+                previous_mean: previous_sum / n,
+                score_mean: sum / n,
+                p_value: test.p_value,
+            },
+            significant: test.n > 0 && test.p_value < config.alpha,
+        };
+        if drift.significant {
+            db.record_drift(&drift.record)?;
+        }
+        drifts.push(drift);
+    }
+    Ok(drifts)
+}
+
+/// `interval` ごとに `run_cycle` を繰り返す (`cycles` 回で終わる。Noneなら止めるまで続ける)
+///
+/// 次の実行の予定時刻は前の実行の開始から決めるので、ベンチマークにかかった時間だけずれていかない。
+pub fn run_daemon(
+    db: &mut ResultDb,
+    config: &DaemonConfig,
+    interval: Duration,
+    cycles: Option<usize>,
+) -> rusqlite::Result<()> {
+    let mut cycle = 0;
+    loop {
+        let start = Instant::now();
+        println!("cycle {cycle}");
+        for drift in run_cycle(db, config)? {
+            let record = &drift.record;
+            println!(
+                "{}{}\tprevious_run: {}\t{:.2} -> {:.2}\tp: {:.4}",
+                if drift.significant { "DRIFT\t" } else { "" },
+                record.policy,
+                record.previous_run_id,
+                record.previous_mean,
+                record.score_mean,
+                record.p_value
+            );
+        }
+        cycle += 1;
+        if cycles.is_some_and(|cycles| cycle >= cycles) {
+            return Ok(());
+        }
+        std::thread::sleep(interval.saturating_sub(start.elapsed()));
+    }
+}
@@ -0,0 +1,197 @@
+//! 学習用データセットの出力
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use serde::Serialize;
+
+use crate::{
+    game::{State, H, W},
+    search::beam_search_action,
+};
+
+/// 学習用データセットの1サンプル
+///
+/// JSONLでは1行に1サンプルを出力する。
+/// - `seed`: 盤面生成に使ったシード
+/// - `turn`: サンプル時点のターン数
+/// - `character`: キャラクターの座標 `[y, x]`
+/// - `points`: 盤面の得点を行優先で平坦化したもの (長さ `H * W`)
+/// - `action`: 教師AIが選んだ行動 (0: 右, 1: 左, 2: 下, 3:上)
+/// - `value`: このターン以降に獲得できた得点 (最終スコア - 現在スコア)
+#[derive(Clone, Serialize)]
+pub struct DatasetSample {
+    pub seed: u64,
+    pub turn: usize,
+    pub character: [i32; 2],
+    pub points: Vec<u8>,
+    pub action: usize,
+    pub value: usize,
+}
+
+/// ビームサーチを教師として (state, action, value) のデータセットを生成する
+pub fn generate_dataset(num: usize, beam_width: usize, beam_depth: usize) -> Vec<DatasetSample> {
+    let mut samples = vec![];
+    for seed in 0..num {
+        let mut state = State::new(seed as u64);
+        let first = samples.len();
+        while !state.is_done() {
+            let Ok(action) = beam_search_action(&state, beam_width, beam_depth) else {
+                break;
+            };
+            samples.push(DatasetSample {
+                seed: seed as u64,
+                turn: state.turn,
+                character: [state.character.y, state.character.x],
+                points: state.points.to_vec(),
+                action: action.index(),
+                value: state.game_score.as_usize(),
+            });
+            state.advance(action);
+        }
+        // valueには一旦その時点のスコアを入れておき、ゲーム終了後に差分へ直す
+        for sample in &mut samples[first..] {
+            sample.value = state.game_score.as_usize() - sample.value;
+        }
+    }
+    samples
+}
+
+pub fn write_dataset_jsonl(samples: &[DatasetSample], path: &str) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for sample in samples {
+        serde_json::to_writer(&mut writer, sample)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// データセットをnumpyの`.npz`形式(無圧縮)で書き出す
+///
+/// 配列は `points (N, H, W) u8`, `character (N, 2) i32`, `turn (N,) i32`,
+/// `action (N,) i64`, `value (N,) i64` の5つ。
+pub fn write_dataset_npz(samples: &[DatasetSample], path: &str) -> std::io::Result<()> {
+    let n = samples.len();
+    let mut arrays: Vec<(&str, Vec<u8>)> = vec![];
+
+    let mut points = vec![];
+    for sample in samples {
+        points.extend_from_slice(&sample.points);
+    }
+    arrays.push(("points.npy", npy_bytes("|u1", &[n, H, W], &points)));
+
+    let mut character = vec![];
+    for sample in samples {
+        for v in sample.character {
+            character.extend(v.to_le_bytes());
+        }
+    }
+    arrays.push(("character.npy", npy_bytes("<i4", &[n, 2], &character)));
+
+    let turn: Vec<u8> = samples
+        .iter()
+        .flat_map(|s| (s.turn as i32).to_le_bytes())
+        .collect();
+    arrays.push(("turn.npy", npy_bytes("<i4", &[n], &turn)));
+
+    let action: Vec<u8> = samples
+        .iter()
+        .flat_map(|s| (s.action as i64).to_le_bytes())
+        .collect();
+    arrays.push(("action.npy", npy_bytes("<i8", &[n], &action)));
+
+    let value: Vec<u8> = samples
+        .iter()
+        .flat_map(|s| (s.value as i64).to_le_bytes())
+        .collect();
+    arrays.push(("value.npy", npy_bytes("<i8", &[n], &value)));
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&stored_zip(&arrays))?;
+    writer.flush()
+}
+
+/// `.npy`形式(version 1.0)のバイト列を作る
+pub fn npy_bytes(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    // magic(6) + version(2) + header_len(2) + header + '\n' を64バイト境界に揃える
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+    buf.extend((header.len() as u16).to_le_bytes());
+    buf.extend(header.as_bytes());
+    buf.extend(data);
+    buf
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// 無圧縮(stored)のzipアーカイブを作る
+pub fn stored_zip(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut buf = vec![];
+    let mut central = vec![];
+    for (name, data) in files {
+        let offset = buf.len() as u32;
+        let crc = crc32(data);
+        let size = data.len() as u32;
+
+        buf.extend(0x0403_4b50u32.to_le_bytes());
+        buf.extend([20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        buf.extend(crc.to_le_bytes());
+        buf.extend(size.to_le_bytes());
+        buf.extend(size.to_le_bytes());
+        buf.extend((name.len() as u16).to_le_bytes());
+        buf.extend(0u16.to_le_bytes());
+        buf.extend(name.as_bytes());
+        buf.extend(data);
+
+        central.extend(0x0201_4b50u32.to_le_bytes());
+        central.extend([20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        central.extend(crc.to_le_bytes());
+        central.extend(size.to_le_bytes());
+        central.extend(size.to_le_bytes());
+        central.extend((name.len() as u16).to_le_bytes());
+        central.extend([0; 12]);
+        central.extend(offset.to_le_bytes());
+        central.extend(name.as_bytes());
+    }
+    let central_offset = buf.len() as u32;
+    buf.extend(&central);
+    buf.extend(0x0605_4b50u32.to_le_bytes());
+    buf.extend([0; 4]);
+    buf.extend((files.len() as u16).to_le_bytes());
+    buf.extend((files.len() as u16).to_le_bytes());
+    buf.extend((central.len() as u32).to_le_bytes());
+    buf.extend(central_offset.to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+    buf
+}
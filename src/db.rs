@@ -0,0 +1,362 @@
+//! 計測・スイープ・比較の結果を蓄積するSQLiteのデータベース
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection};
+
+use crate::results::{GameResult, RunMeta};
+
+/// 結果のデータベース
+///
+/// 実行ごとに `runs` に1行、ゲームごとに `games` に1行を追加する。
+pub struct ResultDb {
+    pub conn: Connection,
+}
+
+/// AIの名前からパラメータの値を取り出す (`beam(width=5, ms=10)` の `width` なら5)
+pub fn policy_param(policy: &str, key: &str) -> Option<u64> {
+    policy
+        .split(['(', ',', ')'])
+        .find_map(|part| part.trim().strip_prefix(key)?.strip_prefix('='))
+        .and_then(|value| value.parse().ok())
+}
+
+/// AIの名前から1手あたりの時間制限[ms]を取り出す (`beam(width=5, ms=10)` なら10)
+pub fn budget_ms_from_policy_name(policy: &str) -> Option<u64> {
+    policy_param(policy, "ms")
+}
+
+/// 指定したタグ(Noneなら全て)の実行のゲームだけに絞るSQLの条件 (タグは `?1` で渡す)
+const TAG_FILTER: &str =
+    "(?1 IS NULL OR games.run_id IN (SELECT run_id FROM run_tags WHERE tag = ?1))";
+
+fn insert_run(conn: &Connection, kind: &str, meta: &RunMeta) -> rusqlite::Result<i64> {
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    conn.execute(
+        "INSERT INTO runs (kind, crate_version, created_at) VALUES (?1, ?2, ?3)",
+        params![kind, env!("CARGO_PKG_VERSION"), created_at],
+    )?;
+    let run_id = conn.last_insert_rowid();
+    for tag in &meta.tags {
+        conn.execute(
+            "INSERT INTO run_tags (run_id, tag) VALUES (?1, ?2)",
+            params![run_id, tag],
+        )?;
+    }
+    if let Some(note) = &meta.note {
+        conn.execute(
+            "INSERT INTO run_notes (run_id, note) VALUES (?1, ?2)",
+            params![run_id, note],
+        )?;
+    }
+    Ok(run_id)
+}
+
+fn insert_game(conn: &Connection, run_id: i64, result: &GameResult) -> rusqlite::Result<()> {
+    let move_us_mean =
+        result.move_times_us.iter().sum::<u64>() as f64 / result.move_times_us.len().max(1) as f64;
+    conn.execute(
+        "INSERT INTO games (run_id, seed, policy, budget_ms, end_turn, game_score, wasted_turns, move_us_mean, token)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            run_id,
+            result.seed as i64,
+            result.policy,
+            budget_ms_from_policy_name(&result.policy).map(|ms| ms as i64),
+            result.actions.len() as i64,
+            result.game_score as i64,
+            result.wasted_turns as i64,
+            move_us_mean,
+            result.token,
+        ],
+    )?;
+    Ok(())
+}
+
+/// 時間制限ごとの最も平均スコアが高いAI
+pub struct BestConfig {
+    pub budget_ms: Option<u64>,
+    pub policy: String,
+    pub score_mean: f64,
+    pub games: usize,
+}
+
+/// あるAIの1つの版での平均スコア
+pub struct TrendPoint {
+    pub crate_version: String,
+    pub score_mean: f64,
+    pub games: usize,
+}
+
+/// 登録された実行の一覧の1行
+pub struct RunInfo {
+    pub id: i64,
+    pub kind: String,
+    pub crate_version: String,
+    pub created_at: i64,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub games: usize,
+}
+
+/// 定期実行で前回からスコアが有意に変わったAIの記録
+pub struct DriftRecord {
+    pub run_id: i64,
+    pub previous_run_id: i64,
+    pub policy: String,
+    pub previous_mean: f64,
+    pub score_mean: f64,
+    pub p_value: f64,
+}
+
+/// 2つのAIの同じシードでの対戦成績
+#[derive(Default)]
+pub struct HeadToHead {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+impl ResultDb {
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                crate_version TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS games (
+                run_id INTEGER NOT NULL REFERENCES runs(id),
+                seed INTEGER NOT NULL,
+                policy TEXT NOT NULL,
+                budget_ms INTEGER,
+                end_turn INTEGER NOT NULL,
+                game_score INTEGER NOT NULL,
+                wasted_turns INTEGER NOT NULL,
+                move_us_mean REAL NOT NULL,
+                token TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS games_policy ON games(policy);
+            CREATE TABLE IF NOT EXISTS run_tags (
+                run_id INTEGER NOT NULL REFERENCES runs(id),
+                tag TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS run_tags_tag ON run_tags(tag);
+            CREATE TABLE IF NOT EXISTS run_notes (
+                run_id INTEGER NOT NULL REFERENCES runs(id),
+                note TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS drifts (
+                run_id INTEGER NOT NULL REFERENCES runs(id),
+                previous_run_id INTEGER NOT NULL REFERENCES runs(id),
+                policy TEXT NOT NULL,
+                previous_mean REAL NOT NULL,
+                score_mean REAL NOT NULL,
+                p_value REAL NOT NULL
+            );",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// 実行を1つ登録してそのidを返す (`kind` は bench/sweep/compare など)
+    pub fn start_run(&self, kind: &str, meta: &RunMeta) -> rusqlite::Result<i64> {
+        insert_run(&self.conn, kind, meta)
+    }
+
+    pub fn insert_game(&self, run_id: i64, result: &GameResult) -> rusqlite::Result<()> {
+        insert_game(&self.conn, run_id, result)
+    }
+
+    /// 実行1回分の結果をまとめて登録してそのidを返す
+    pub fn record(
+        &mut self,
+        kind: &str,
+        meta: &RunMeta,
+        results: &[GameResult],
+    ) -> rusqlite::Result<i64> {
+        let tx = self.conn.transaction()?;
+        let run_id = insert_run(&tx, kind, meta)?;
+        for result in results {
+            insert_game(&tx, run_id, result)?;
+        }
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// `before` より前の `kind` の実行のうち、`policy` を遊んだ最も新しいものの
+    /// idとシードごとのスコアを返す (なければNone)
+    pub fn previous_scores(
+        &self,
+        kind: &str,
+        policy: &str,
+        before: i64,
+    ) -> rusqlite::Result<Option<(i64, BTreeMap<u64, usize>)>> {
+        let run_id: Option<i64> = self.conn.query_row(
+            "SELECT MAX(runs.id) FROM runs JOIN games ON games.run_id = runs.id
+             WHERE runs.kind = ?1 AND games.policy = ?2 AND runs.id < ?3",
+            params![kind, policy, before],
+            |row| row.get(0),
+        )?;
+        let Some(run_id) = run_id else {
+            return Ok(None);
+        };
+        let mut stmt = self
+            .conn
+            .prepare("SELECT seed, game_score FROM games WHERE run_id = ?1 AND policy = ?2")?;
+        let scores = stmt
+            .query_map(params![run_id, policy], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Some((run_id, scores)))
+    }
+
+    pub fn record_drift(&self, drift: &DriftRecord) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO drifts (run_id, previous_run_id, policy, previous_mean, score_mean, p_value)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                drift.run_id,
+                drift.previous_run_id,
+                drift.policy,
+                drift.previous_mean,
+                drift.score_mean,
+                drift.p_value
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 記録された有意な変化を新しい順に返す
+    pub fn drifts(&self) -> rusqlite::Result<Vec<DriftRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT run_id, previous_run_id, policy, previous_mean, score_mean, p_value
+             FROM drifts ORDER BY run_id DESC, policy",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DriftRecord {
+                run_id: row.get(0)?,
+                previous_run_id: row.get(1)?,
+                policy: row.get(2)?,
+                previous_mean: row.get(3)?,
+                score_mean: row.get(4)?,
+                p_value: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 登録された実行を新しい順に返す (`tag` を指定するとそのタグの付いた実行だけ)
+    pub fn runs(&self, tag: Option<&str>) -> rusqlite::Result<Vec<RunInfo>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT runs.id, runs.kind, runs.crate_version, runs.created_at,
+               (SELECT group_concat(tag, ',') FROM run_tags WHERE run_tags.run_id = runs.id),
+               (SELECT note FROM run_notes WHERE run_notes.run_id = runs.id),
+               (SELECT COUNT(*) FROM games WHERE games.run_id = runs.id)
+             FROM runs
+             WHERE ?1 IS NULL OR runs.id IN (SELECT run_id FROM run_tags WHERE tag = ?1)
+             ORDER BY runs.id DESC",
+        )?;
+        let rows = stmt.query_map([tag], |row| {
+            Ok(RunInfo {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                crate_version: row.get(2)?,
+                created_at: row.get(3)?,
+                tags: row
+                    .get::<_, Option<String>>(4)?
+                    .map(|tags| tags.split(',').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                note: row.get(5)?,
+                games: row.get::<_, i64>(6)? as usize,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 時間制限ごとに、最も平均スコアが高いAIの設定を返す
+    pub fn best_per_budget(&self, tag: Option<&str>) -> rusqlite::Result<Vec<BestConfig>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT budget_ms, policy, AVG(game_score), COUNT(*) FROM games
+             WHERE {TAG_FILTER}
+             GROUP BY budget_ms, policy"
+        ))?;
+        let rows = stmt.query_map([tag], |row| {
+            Ok(BestConfig {
+                budget_ms: row.get::<_, Option<i64>>(0)?.map(|ms| ms as u64),
+                policy: row.get(1)?,
+                score_mean: row.get(2)?,
+                games: row.get::<_, i64>(3)? as usize,
+            })
+        })?;
+        let mut best: BTreeMap<Option<u64>, BestConfig> = BTreeMap::new();
+        for row in rows {
+            let row = row?;
+            if best
+                .get(&row.budget_ms)
+                .is_none_or(|b| b.score_mean < row.score_mean)
+            {
+                best.insert(row.budget_ms, row);
+            }
+        }
+        Ok(best.into_values().collect())
+    }
+
+    /// あるAIの平均スコアを版ごとに、初めて記録された順に返す
+    pub fn score_trend(
+        &self,
+        policy: &str,
+        tag: Option<&str>,
+    ) -> rusqlite::Result<Vec<TrendPoint>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT runs.crate_version, AVG(games.game_score), COUNT(*) FROM games
+             JOIN runs ON runs.id = games.run_id
+             WHERE {TAG_FILTER} AND games.policy = ?2
+             GROUP BY runs.crate_version
+             ORDER BY MIN(runs.created_at), MIN(runs.id)"
+        ))?;
+        let rows = stmt.query_map(params![tag, policy], |row| {
+            Ok(TrendPoint {
+                crate_version: row.get(0)?,
+                score_mean: row.get(1)?,
+                games: row.get::<_, i64>(2)? as usize,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 2つのAIを、両方が遊んだシードごとの平均スコアで比べる
+    pub fn head_to_head(
+        &self,
+        a: &str,
+        b: &str,
+        tag: Option<&str>,
+    ) -> rusqlite::Result<HeadToHead> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT x.score, y.score FROM
+               (SELECT seed, AVG(game_score) AS score FROM games
+                WHERE {TAG_FILTER} AND policy = ?2 GROUP BY seed) AS x
+             JOIN
+               (SELECT seed, AVG(game_score) AS score FROM games
+                WHERE {TAG_FILTER} AND policy = ?3 GROUP BY seed) AS y
+             ON x.seed = y.seed"
+        ))?;
+        let rows = stmt.query_map(params![tag, a, b], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?))
+        })?;
+        let mut result = HeadToHead::default();
+        for row in rows {
+            let (score_a, score_b) = row?;
+            match score_a.total_cmp(&score_b) {
+                std::cmp::Ordering::Greater => result.wins += 1,
+                std::cmp::Ordering::Less => result.losses += 1,
+                std::cmp::Ordering::Equal => result.draws += 1,
+            }
+        }
+        Ok(result)
+    }
+}
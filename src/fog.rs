@@ -0,0 +1,253 @@
+//! 霧のかかった迷路 (部分観測) の変種
+
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    game::{Coord, Direction, MazeState, H, W},
+    search::beam_search_action_with_time_threshold,
+};
+
+/// 視界の外の得点が見えない(霧のかかった)迷路
+#[derive(Clone)]
+pub struct FogMazeState {
+    pub state: MazeState,
+    /// キャラクターからこのマンハッタン距離以内のマスが見える
+    pub view_radius: i32,
+    /// 一度でも見えたことがあるマス
+    pub observed: Vec<Vec<bool>>,
+}
+
+impl FogMazeState {
+    pub fn new(seed: u64, view_radius: i32) -> Self {
+        let mut fog = Self {
+            state: MazeState::new(seed),
+            view_radius,
+            observed: vec![vec![false; W]; H],
+        };
+        fog.observe();
+        fog
+    }
+
+    /// 現在の視界に入っているマスを観測済みにする
+    pub fn observe(&mut self) {
+        let Coord { y: cy, x: cx } = self.state.character;
+        for y in 0..H {
+            for x in 0..W {
+                if (y as i32 - cy).abs() + (x as i32 - cx).abs() <= self.view_radius {
+                    self.observed[y][x] = true;
+                }
+            }
+        }
+    }
+
+    pub fn advance(&mut self, action: Direction) {
+        self.state.advance(action);
+        self.observe();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state.is_done()
+    }
+
+    /// 観測済みのマスについて分かっている得点 (未観測ならNone)
+    pub fn known_point(&self, y: usize, x: usize) -> Option<usize> {
+        self.observed[y][x].then(|| self.state.points[y * W + x] as usize)
+    }
+}
+
+/// 目標のマスに向かう最短経路の最初の行動をBFSで求める
+pub fn first_action_towards(
+    state: &MazeState,
+    is_goal: impl Fn(usize, usize) -> bool,
+) -> Option<Direction> {
+    let mut first_actions = vec![vec![None; W]; H];
+    let mut visited = vec![vec![false; W]; H];
+    let mut queue = std::collections::VecDeque::new();
+    let start = state.character;
+    visited[start.y as usize][start.x as usize] = true;
+    queue.push_back(start);
+    while let Some(now) = queue.pop_front() {
+        for action in Direction::ALL {
+            let Some(next) = now.offset(action) else {
+                continue;
+            };
+            let (ny, nx) = (next.y as usize, next.x as usize);
+            if visited[ny][nx] {
+                continue;
+            }
+            visited[ny][nx] = true;
+            let first_action = if now == start {
+                Some(action)
+            } else {
+                first_actions[now.y as usize][now.x as usize]
+            };
+            first_actions[ny][nx] = first_action;
+            if is_goal(ny, nx) {
+                return first_action;
+            }
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+/// フロンティア探索: 見えている得点があれば最寄りのものへ、なければ最寄りの未観測マスへ向かう
+///
+/// 残りターン数以内に到達できないマスは目標にしない。
+pub fn frontier_exploration_action(fog: &FogMazeState) -> Direction {
+    let state = &fog.state;
+    first_action_towards(state, |y, x| {
+        state.is_reachable(y, x) && fog.known_point(y, x).is_some_and(|p| p > 0)
+    })
+    .or_else(|| {
+        first_action_towards(state, |y, x| {
+            state.is_reachable(y, x) && !fog.observed[y][x]
+        })
+    })
+    .unwrap_or_else(|| state.legal_actions()[0])
+}
+
+/// 重み付きサンプルで真の状態の分布(信念)を表すパーティクルフィルタ
+pub struct ParticleFilter<T> {
+    pub particles: Vec<T>,
+    /// 合計が1になるように正規化された重み
+    pub weights: Vec<f64>,
+}
+
+impl<T: Clone> ParticleFilter<T> {
+    pub fn new(particles: Vec<T>) -> Self {
+        assert!(!particles.is_empty());
+        let weights = vec![1. / particles.len() as f64; particles.len()];
+        Self { particles, weights }
+    }
+
+    /// 各パーティクルに観測の尤度を掛けて重みを更新する
+    ///
+    /// 全ての尤度が0になった場合は一様な重みに戻す。
+    pub fn reweight(&mut self, likelihood: impl Fn(&T) -> f64) {
+        for (particle, weight) in self.particles.iter().zip(&mut self.weights) {
+            *weight *= likelihood(particle);
+        }
+        let total: f64 = self.weights.iter().sum();
+        let n = self.weights.len() as f64;
+        for weight in &mut self.weights {
+            *weight = if total > 0. { *weight / total } else { 1. / n };
+        }
+    }
+
+    /// 各パーティクルを書き換える (状態遷移や観測済みの情報の反映に使う)
+    pub fn update(&mut self, f: impl Fn(&mut T)) {
+        self.particles.iter_mut().for_each(f);
+    }
+
+    /// 有効サンプル数 (重みが偏るほど小さくなる)
+    pub fn effective_sample_size(&self) -> f64 {
+        1. / self.weights.iter().map(|w| w * w).sum::<f64>()
+    }
+
+    /// 系統リサンプリングを行い、重みを一様に戻す
+    pub fn resample(&mut self, rng: &mut ChaCha12Rng) {
+        let n = self.particles.len();
+        let step = 1. / n as f64;
+        let mut u = rng.gen::<f64>() * step;
+        let mut cumulative = self.weights[0];
+        let mut i = 0;
+        let mut resampled = Vec::with_capacity(n);
+        for _ in 0..n {
+            while u > cumulative && i + 1 < n {
+                i += 1;
+                cumulative += self.weights[i];
+            }
+            resampled.push(self.particles[i].clone());
+            u += step;
+        }
+        self.particles = resampled;
+        self.weights = vec![step; n];
+    }
+
+    /// 有効サンプル数がしきい値を下回ったときだけリサンプリングする
+    pub fn resample_if_degenerate(&mut self, threshold: f64, rng: &mut ChaCha12Rng) {
+        if self.effective_sample_size() < threshold {
+            self.resample(rng);
+        }
+    }
+
+    /// 重みに従ってパーティクルを1つ選ぶ
+    pub fn sample(&self, rng: &mut ChaCha12Rng) -> &T {
+        let mut u = rng.gen::<f64>();
+        for (particle, &weight) in self.particles.iter().zip(&self.weights) {
+            if u < weight {
+                return particle;
+            }
+            u -= weight;
+        }
+        self.particles.last().unwrap()
+    }
+}
+
+/// 霧のかかった迷路の観測と矛盾しない盤面を1つ作る (未観測のマスは一様にランダム)
+pub fn sample_fog_determinization(fog: &FogMazeState, rng: &mut ChaCha12Rng) -> MazeState {
+    let mut state = fog.state.clone();
+    for y in 0..H {
+        for x in 0..W {
+            if !fog.observed[y][x] {
+                state.points[y * W + x] = rng.gen_range(0..10);
+            }
+        }
+    }
+    state
+}
+
+/// パーティクルに観測済みのマスと現在のキャラクターの状態を反映する
+pub fn condition_on_observation(particle: &mut MazeState, fog: &FogMazeState) {
+    for y in 0..H {
+        for x in 0..W {
+            if fog.observed[y][x] {
+                particle.points[y * W + x] = fog.state.points[y * W + x];
+            }
+        }
+    }
+    particle.character = fog.state.character;
+    particle.visited = fog.state.visited;
+    particle.revisits = fog.state.revisits;
+    particle.turn = fog.state.turn;
+    particle.game_score = fog.state.game_score;
+}
+
+/// 霧のかかった迷路の信念をパーティクルフィルタで初期化する
+pub fn fog_belief(
+    fog: &FogMazeState,
+    num: usize,
+    rng: &mut ChaCha12Rng,
+) -> ParticleFilter<MazeState> {
+    ParticleFilter::new(
+        (0..num)
+            .map(|_| sample_fog_determinization(fog, rng))
+            .collect(),
+    )
+}
+
+/// 信念からサンプリングしたK個の盤面それぞれでビームサーチを行い、多数決で行動を決める
+pub fn determinized_ensemble_action(
+    belief: &ParticleFilter<MazeState>,
+    num_determinization: usize,
+    beam_width: usize,
+    time_threshold: u128,
+    rng: &mut ChaCha12Rng,
+) -> Direction {
+    let mut votes = [0; 4];
+    for _ in 0..num_determinization {
+        let state = belief.sample(rng);
+        if let Ok(action) =
+            beam_search_action_with_time_threshold(state, beam_width, time_threshold)
+        {
+            votes[action.index()] += 1;
+        }
+    }
+    let legal_actions = belief.particles[0].legal_actions();
+    *legal_actions
+        .iter()
+        .max_by_key(|&&action| (votes[action.index()], std::cmp::Reverse(action)))
+        .unwrap()
+}
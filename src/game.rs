@@ -0,0 +1,1141 @@
+//! 盤面と行動の定義
+
+use core::fmt;
+use std::cmp::Ordering;
+
+use rand::{prelude::*, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    util::{fnv1a, splitmix64, FNV_OFFSET_BASIS},
+    warm::potential_table,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Coord {
+    pub y: i32,
+    pub x: i32,
+}
+
+impl Coord {
+    pub fn new(y: i32, x: i32) -> Self {
+        Self { y, x }
+    }
+
+    /// `direction` の方向に1マス進んだ座標 (盤面の外に出るならNone)
+    pub fn offset(self, direction: Direction) -> Option<Coord> {
+        let (dy, dx) = direction.delta();
+        let y = self.y.checked_add(dy)?;
+        let x = self.x.checked_add(dx)?;
+        ((0..H as i32).contains(&y) && (0..W as i32).contains(&x)).then_some(Coord { y, x })
+    }
+
+    /// 盤面の配列 `MazeState::points` での番号 `y * W + x`
+    pub fn index(self) -> usize {
+        self.y as usize * W + self.x as usize
+    }
+}
+
+/// キャラクターの行動
+///
+/// 結果のJSONLなどでは従来どおり 0: 右, 1: 左, 2: 下, 3: 上 の番号で表す。
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    Right,
+    Left,
+    Down,
+    Up,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Right,
+        Direction::Left,
+        Direction::Down,
+        Direction::Up,
+    ];
+
+    /// 1マス進んだときの座標の変化 `(dy, dx)`
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Right => (0, 1),
+            Direction::Left => (0, -1),
+            Direction::Down => (1, 0),
+            Direction::Up => (-1, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Right => Direction::Left,
+            Direction::Left => Direction::Right,
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+        }
+    }
+
+    /// 行動の番号 (0: 右, 1: 左, 2: 下, 3: 上)
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// 表示名 (R/L/D/U)
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES[self.index()]
+    }
+}
+
+impl From<usize> for Direction {
+    /// 行動の番号から変換する (4以上ならpanicする)
+    fn from(action: usize) -> Self {
+        Direction::ALL[action]
+    }
+}
+
+impl From<Direction> for usize {
+    fn from(direction: Direction) -> Self {
+        direction.index()
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+
+    /// `R/L/D/U` または `0-3` を読む
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "R" | "0" => Ok(Direction::Right),
+            "L" | "1" => Ok(Direction::Left),
+            "D" | "2" => Ok(Direction::Down),
+            "U" | "3" => Ok(Direction::Up),
+            _ => Err(format!("unknown action {text:?}")),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// ゲームのスコア (取った得点の合計)
+///
+/// 探索の評価値 `Eval` とは別の型にして、取り違えて代入できないようにする。
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameScore(pub u64);
+
+impl GameScore {
+    /// 得点 `point` を足したスコア
+    pub fn add_point(self, point: u8) -> Self {
+        Self(self.0.saturating_add(point as u64))
+    }
+
+    /// 結果のJSONLなどに書く値
+    pub fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for GameScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// 探索で状態を比べるための評価値 (大きいほど良い)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Eval(pub i64);
+
+impl Eval {
+    pub const MIN: Eval = Eval(i64::MIN);
+    pub const MAX: Eval = Eval(i64::MAX);
+
+    pub fn saturating_add(self, delta: i64) -> Self {
+        Self(self.0.saturating_add(delta))
+    }
+}
+
+/// スコアをそのまま評価値にする
+impl From<GameScore> for Eval {
+    fn from(score: GameScore) -> Self {
+        Eval(i64::try_from(score.0).unwrap_or(i64::MAX))
+    }
+}
+
+impl fmt::Display for Eval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub const H: usize = 30;
+pub const W: usize = 30;
+pub const END_TURN: usize = 100;
+
+/// マスごとに1ビットの訪問済みの印
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VisitedCells([u64; VisitedCells::WORDS]);
+
+impl Default for VisitedCells {
+    fn default() -> Self {
+        Self([0; VisitedCells::WORDS])
+    }
+}
+
+impl VisitedCells {
+    const WORDS: usize = (H * W).div_ceil(64);
+
+    fn bit(coord: Coord) -> (usize, u64) {
+        let index = coord.index();
+        (index / 64, 1 << (index % 64))
+    }
+
+    /// 印を付けて、初めて訪れたマスならtrueを返す
+    pub fn insert(&mut self, coord: Coord) -> bool {
+        let (word, mask) = Self::bit(coord);
+        let is_new = self.0[word] & mask == 0;
+        self.0[word] |= mask;
+        is_new
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        let (word, mask) = Self::bit(coord);
+        self.0[word] & mask != 0
+    }
+
+    /// 訪れたマスの数
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+/// 盤面生成の形式のバージョン
+///
+/// `MazeState::new` の生成結果を意図して変えたときはこれを上げ、
+/// `EXPECTED_INSTANCE_FINGERPRINT` と `EXPECTED_POLICY_RNG_FINGERPRINT` を更新する。
+pub const INSTANCE_FORMAT_VERSION: u32 = 1;
+
+/// 実行時に変更できるゲームのルール
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    /// ゲームが終わるターン数
+    pub end_turn: usize,
+    /// 外から課す行動の制約
+    pub mask: Option<std::sync::Arc<ActionMask>>,
+    /// 評価値で訪問済みのマスへの再訪1回ごとに引く点数
+    pub revisit_penalty: i64,
+    /// 評価値に足すノイズの大きさ (0ならノイズなし)
+    pub eval_noise: i64,
+}
+
+/// 評価値に足す `[-magnitude, magnitude]` の一様なノイズ
+///
+/// 同じ盤面のシード・ターン・位置・スコアの状態には常に同じノイズを足すので、探索の結果は再現できる。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvalNoise {
+    pub magnitude: i64,
+    pub seed: u64,
+}
+
+impl EvalNoise {
+    pub fn sample(&self, state: &MazeState) -> i64 {
+        self.sample_at(state.turn, state.character, state.game_score)
+    }
+
+    /// ターン `turn` に位置 `character` でスコア `game_score` の状態に足すノイズ
+    pub fn sample_at(&self, turn: usize, character: Coord, game_score: GameScore) -> i64 {
+        let key = (turn as u64) << 48
+            ^ (character.y as u64) << 32
+            ^ (character.x as u64) << 16
+            ^ game_score.0;
+        let r = splitmix64(self.seed ^ splitmix64(key));
+        (r % (2 * self.magnitude as u64 + 1)) as i64 - self.magnitude
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            end_turn: END_TURN,
+            mask: None,
+            revisit_penalty: 0,
+            eval_noise: 0,
+        }
+    }
+}
+
+/// `legal_actions` から取り除く行動の条件
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaskRule {
+    /// `turns` のターン(半開区間)には `action` を選ばない
+    ForbidAction {
+        action: Direction,
+        turns: std::ops::Range<usize>,
+    },
+    /// 左上 `(y0, x0)` から右下 `(y1, x1)` まで(両端を含む)の領域には入らない
+    ForbidRegion { y0: i32, x0: i32, y1: i32, x1: i32 },
+}
+
+/// 行動の制約の集まり
+///
+/// アブレーションや外部から課される制約の再現に使う。全ての行動が禁止される場合は、
+/// ゲームを進められなくなるので制約を無視して元の合法手を返す。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActionMask {
+    pub rules: Vec<MaskRule>,
+}
+
+impl ActionMask {
+    pub fn allows(&self, turn: usize, action: Direction, next: Coord) -> bool {
+        self.rules.iter().all(|rule| match rule {
+            MaskRule::ForbidAction {
+                action: forbidden,
+                turns,
+            } => !(*forbidden == action && turns.contains(&turn)),
+            MaskRule::ForbidRegion { y0, x0, y1, x1 } => {
+                !((*y0..=*y1).contains(&next.y) && (*x0..=*x1).contains(&next.x))
+            }
+        })
+    }
+
+    /// `y0,x0,y1,x1` 形式の領域の制約を読む
+    pub fn parse_region(text: &str) -> Result<MaskRule, String> {
+        let values: Vec<i32> = text
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid region {text:?}"))?;
+        match values[..] {
+            [y0, x0, y1, x1] => Ok(MaskRule::ForbidRegion { y0, x0, y1, x1 }),
+            _ => Err(format!("region must be y0,x0,y1,x1: {text:?}")),
+        }
+    }
+
+    /// `R@10-20` のような行動の制約を読む (行動は R/L/D/U または 0-3、ターンは半開区間)
+    pub fn parse_action(text: &str) -> Result<MaskRule, String> {
+        let (action, turns) = text
+            .split_once('@')
+            .ok_or(format!("action rule must be ACTION@FROM-TO: {text:?}"))?;
+        let action = action.parse()?;
+        let (from, to) = turns
+            .split_once('-')
+            .ok_or(format!("turns must be FROM-TO: {text:?}"))?;
+        let from = from.parse().map_err(|_| format!("invalid turn {from:?}"))?;
+        let to = to.parse().map_err(|_| format!("invalid turn {to:?}"))?;
+        Ok(MaskRule::ForbidAction {
+            action,
+            turns: from..to,
+        })
+    }
+}
+
+/// 探索アルゴリズムが扱える1人ゲームの状態
+///
+/// ビームサーチなどは評価値の大きい状態から展開するので、`Ord` は評価値で比べる。
+/// 探索の結果がビルドによらず決まるように、評価値が同じ状態も順序が決まるようにする。
+pub trait GameState: Clone + Ord + fmt::Display {
+    type Action: Copy + Eq;
+
+    /// プレイヤーが可能な行動を全て取得する
+    fn legal_actions(&self) -> Vec<Self::Action>;
+
+    fn advance(&mut self, action: Self::Action);
+
+    fn is_done(&self) -> bool;
+
+    /// 評価値を計算して保持する
+    fn evaluate(&mut self);
+
+    fn evaluated_score(&self) -> Eval;
+
+    /// 探索の根で選んだ最初の行動
+    fn first_action(&self) -> Self::Action;
+
+    fn set_first_action(&mut self, action: Self::Action);
+
+    /// `self` から `other` への変化の短い説明 (デバッグ出力用)
+    fn diff(&self, other: &Self) -> String {
+        other.to_string()
+    }
+
+    /// 同じ状態とみなせる状態で等しくなるハッシュ (探索で重複を省くのに使う。Noneなら省かない)
+    fn state_hash(&self) -> Option<u64> {
+        None
+    }
+
+    /// `search::EvalCache` で子の評価値を引くときの親のハッシュ (既定は `state_hash`)
+    ///
+    /// 子の評価値が `state_hash` で区別しない値にもよるときは、それも混ぜて同じハッシュにならないようにする。
+    fn eval_cache_hash(&self) -> Option<u64> {
+        self.state_hash()
+    }
+
+    /// ここからどう進めても子孫の評価値が超えない上界 (分からなければNone)
+    fn evaluation_bound(&self) -> Option<Eval> {
+        None
+    }
+
+    /// `action` で進んだ子を作らずに計算した評価値・位置・`state_hash` (計算できなければNone)
+    ///
+    /// `search::arena_beam_search_action` が使う。同点の子は位置で比べるので、
+    /// `Ord` も同じターンの状態どうしは評価値・位置の順に比べるようにしておく。
+    fn preview_child(&self, _action: Self::Action) -> Option<ChildPreview> {
+        None
+    }
+
+    /// `evaluate` で計算済みの評価値 `score` をそのまま持たせる (できなければfalseで、呼び出し側が `evaluate` する)
+    ///
+    /// 探索が同じ親から同じ行動で進んだ子の評価値を使い回すのに使う。
+    fn set_evaluated_score(&mut self, _score: Eval) -> bool {
+        false
+    }
+}
+
+pub type State = MazeState;
+
+/// 状態の評価関数
+///
+/// `Fn(&MazeState) -> Eval` のクロージャもそのまま使える。
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, state: &MazeState) -> Eval;
+}
+
+impl<F: Fn(&MazeState) -> Eval + Send + Sync> Evaluator for F {
+    fn evaluate(&self, state: &MazeState) -> Eval {
+        self(state)
+    }
+}
+
+/// スコアに最寄りの得点までの距離を混ぜた評価関数
+///
+/// 評価値は `100 * スコア - weight * 最寄りの得点までの距離` なので、`weight` は1マスの距離を
+/// 何%の得点とみなすか。近くに得点がないときにもビームサーチが得点へ向かうようになる。
+#[derive(Clone, Copy, Debug)]
+pub struct NearestPointEvaluator {
+    pub weight: i64,
+}
+
+impl Evaluator for NearestPointEvaluator {
+    fn evaluate(&self, state: &MazeState) -> Eval {
+        let distance = state.distance_to_nearest_point().unwrap_or(0) as i64;
+        Eval(100 * Eval::from(state.game_score).0 - self.weight * distance)
+    }
+}
+
+/// 得点が作るポテンシャル場を混ぜた評価関数
+///
+/// 評価値は `100 * スコア + weight * Σ 100 * 得点 / (距離 + 1)^2 / 100` で、残りターン数以内に
+/// 届く得点のマスだけを数える。序盤の得点が多く残っている盤面で、得点の密集した方へ向かう。
+#[derive(Clone, Copy, Debug)]
+pub struct PotentialFieldEvaluator {
+    pub weight: i64,
+}
+
+impl Evaluator for PotentialFieldEvaluator {
+    fn evaluate(&self, state: &MazeState) -> Eval {
+        let (cy, cx) = (state.character.y, state.character.x);
+        let potential: i64 = state
+            .reachable_points()
+            .iter()
+            .map(|&(c, point)| {
+                let distance = ((c.y - cy).abs() + (c.x - cx).abs()) as usize;
+                potential_table().potential(distance, point)
+            })
+            .sum();
+        Eval(100 * Eval::from(state.game_score).0 + self.weight * potential / 100)
+    }
+}
+
+/// これ以降に獲得できる得点の上界 (`MazeState::future_gain_bound`) をスコアに足した評価関数
+///
+/// 残りターンが少なく上界が実際に取れる得点に近い終盤で、取り残しの少ない盤面を選ぶ。
+#[derive(Clone, Copy, Debug)]
+pub struct BoundEvaluator;
+
+impl Evaluator for BoundEvaluator {
+    fn evaluate(&self, state: &MazeState) -> Eval {
+        let bound = state.future_gain_bound() as i64;
+        Eval(100 * (Eval::from(state.game_score).0 + bound))
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MazeState {
+    /// マスごとの得点 (`y * W + x` 番目がマス `(y, x)`)
+    ///
+    /// ビームサーチでは状態の複製が時間の大半を占めるので、1つの連続した配列にして確保を1回にする。
+    pub points: Box<[u8]>,
+    pub turn: usize,
+    pub character: Coord,
+    pub game_score: GameScore,
+    pub evaluated_score: Eval,
+    pub first_action: Direction,
+    pub end_turn: usize,
+    pub mask: Option<std::sync::Arc<ActionMask>>,
+    /// キャラクターが訪れたマス (開始位置を含む)
+    pub visited: VisitedCells,
+    /// 訪問済みのマスに再び入った回数
+    pub revisits: usize,
+    pub revisit_penalty: i64,
+    pub eval_noise: Option<EvalNoise>,
+    /// 設定されていれば再訪のペナルティの代わりにこれで評価する (ノイズは足す。serdeでは保存しない)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub evaluator: Option<std::sync::Arc<dyn Evaluator>>,
+    /// 得点を取ったマスの集合のハッシュ (取るたびにマスごとの乱数をXORする)
+    pub collected_hash: u64,
+}
+
+impl MazeState {
+    pub fn new(seed: u64) -> Self {
+        Self::with_config(seed, &GameConfig::default())
+    }
+
+    pub fn with_config(seed: u64, config: &GameConfig) -> Self {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let character = Coord {
+            y: rng.gen::<i32>().rem_euclid(H as i32),
+            x: rng.gen::<i32>().rem_euclid(W as i32),
+        };
+
+        let mut points = vec![0; H * W].into_boxed_slice();
+        for y in 0..H {
+            for x in 0..W {
+                if y as i32 == character.y && x as i32 == character.x {
+                    continue;
+                }
+                points[y * W + x] = (rng.next_u64() % 10) as u8;
+            }
+        }
+        Self {
+            points,
+            turn: 0,
+            character,
+            game_score: GameScore(0),
+            evaluated_score: Eval(0),
+            first_action: Direction::Right,
+            end_turn: config.end_turn,
+            mask: config.mask.clone(),
+            visited: VisitedCells::default(),
+            revisits: 0,
+            revisit_penalty: config.revisit_penalty,
+            eval_noise: (config.eval_noise > 0).then(|| EvalNoise {
+                magnitude: config.eval_noise,
+                seed: splitmix64(seed),
+            }),
+            evaluator: None,
+            collected_hash: 0,
+        }
+        .with_visited_start()
+    }
+
+    /// 訪問済みの印を開始位置だけにする
+    fn with_visited_start(mut self) -> Self {
+        self.visited = VisitedCells::default();
+        self.visited.insert(self.character);
+        self.revisits = 0;
+        self
+    }
+
+    /// ゲームの終了判定
+    pub fn is_done(&self) -> bool {
+        self.turn == self.end_turn
+    }
+
+    /// 指定したactionでゲームを１ターン進める
+    /// 0: 右, 1: 左, 2: 下, 3:上
+    pub fn advance(&mut self, action: Direction) {
+        debug_assert!(
+            self.legal_actions().contains(&action),
+            "illegal action {action} at turn {}",
+            self.turn
+        );
+        self.character = self
+            .character
+            .offset(action)
+            .expect("action moves the character off the board");
+        let cell = self.character.index();
+        let point = &mut self.points[cell];
+        if *point > 0 {
+            self.game_score = self.game_score.add_point(*point);
+            *point = 0;
+            self.collected_hash ^= splitmix64(cell as u64);
+        }
+        if !self.visited.insert(self.character) {
+            self.revisits += 1;
+        }
+        self.turn += 1;
+    }
+
+    /// プレイヤーが可能な行動を全て取得する
+    pub fn legal_actions(&self) -> Vec<Direction> {
+        let mut legal_actions = vec![];
+        for action in Direction::ALL {
+            if self.character.offset(action).is_some() {
+                legal_actions.push(action);
+            }
+        }
+        if let Some(mask) = &self.mask {
+            let allowed: Vec<Direction> = legal_actions
+                .iter()
+                .copied()
+                .filter(|&action| {
+                    let next = self.character.offset(action).unwrap();
+                    mask.allows(self.turn, action, next)
+                })
+                .collect();
+            if !allowed.is_empty() {
+                return allowed;
+            }
+        }
+        legal_actions
+    }
+
+    /// `other` との違い (位置・取った得点・スコア・ターンの差) を返す
+    ///
+    /// 盤面全体を表示するよりも、探索中の1手の変化やリプレイどうしの違いを追いやすい。
+    pub fn diff(&self, other: &MazeState) -> StateDiff {
+        let mut collected = vec![];
+        let mut other_changes = 0;
+        for y in 0..H {
+            for x in 0..W {
+                let (before, after) = (self.points[y * W + x], other.points[y * W + x]);
+                if before > 0 && after == 0 {
+                    collected.push((Coord::new(y as i32, x as i32), before as usize));
+                } else if before != after {
+                    other_changes += 1;
+                }
+            }
+        }
+        StateDiff {
+            turn: other.turn as i64 - self.turn as i64,
+            from: self.character,
+            to: other.character,
+            collected,
+            other_changes,
+            score: other.game_score.0 as i64 - self.game_score.0 as i64,
+        }
+    }
+
+    /// 評価関数を差し替える
+    ///
+    /// 以降この状態から進めた状態は全て `evaluator` で評価されるので、探索の入口に渡す状態に
+    /// 設定すればどの探索でも使える。
+    pub fn with_evaluator(mut self, evaluator: std::sync::Arc<dyn Evaluator>) -> Self {
+        self.evaluator = Some(evaluator);
+        self.evaluate_score();
+        self
+    }
+
+    pub fn evaluate_score(&mut self) {
+        self.evaluated_score = match &self.evaluator {
+            Some(evaluator) => evaluator.evaluate(self),
+            None => self.penalized_score(self.game_score, self.revisits),
+        };
+        if let Some(noise) = self.eval_noise {
+            self.evaluated_score = self.evaluated_score.saturating_add(noise.sample(self));
+        }
+    }
+
+    /// 評価関数を差し替えていないときの、スコアから再訪のペナルティを引いた評価値
+    fn penalized_score(&self, game_score: GameScore, revisits: usize) -> Eval {
+        Eval::from(game_score).saturating_add(-self.revisit_penalty.saturating_mul(revisits as i64))
+    }
+
+    /// `action` で進んだ子を作らずに、その評価値 (`evaluate_score` の値) と `state_hash` を計算する
+    ///
+    /// 評価関数を差し替えた状態では盤面全体が要るので計算できず、Noneを返す。
+    pub fn preview_child(&self, action: Direction) -> Option<ChildPreview> {
+        if self.evaluator.is_some() {
+            return None;
+        }
+        let character = self
+            .character
+            .offset(action)
+            .expect("action moves the character off the board");
+        let cell = character.index();
+        let point = self.points[cell];
+        let (game_score, collected_hash) = if point > 0 {
+            (
+                self.game_score.add_point(point),
+                self.collected_hash ^ splitmix64(cell as u64),
+            )
+        } else {
+            (self.game_score, self.collected_hash)
+        };
+        let revisits = self.revisits + usize::from(self.visited.contains(character));
+        let turn = self.turn + 1;
+        let mut evaluated_score = self.penalized_score(game_score, revisits);
+        if let Some(noise) = self.eval_noise {
+            evaluated_score =
+                evaluated_score.saturating_add(noise.sample_at(turn, character, game_score));
+        }
+        Some(ChildPreview {
+            character,
+            evaluated_score,
+            state_hash: hash_state(collected_hash, character, turn),
+        })
+    }
+
+    /// `Display` で出力した形式の盤面を読み込む (`turn:` などの `:` を含む行は無視する)
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.contains(':'))
+            .collect();
+        if rows.len() != H {
+            return Err(format!("expected {H} rows, found {}", rows.len()));
+        }
+        let mut state = Self::new(0);
+        let mut character = None;
+        for (y, row) in rows.iter().enumerate() {
+            let cells: Vec<char> = row.chars().collect();
+            if cells.len() != W {
+                return Err(format!(
+                    "row {y}: expected {W} cells, found {}",
+                    cells.len()
+                ));
+            }
+            for (x, &c) in cells.iter().enumerate() {
+                state.points[y * W + x] = match c {
+                    '@' => {
+                        character = Some(Coord::new(y as i32, x as i32));
+                        0
+                    }
+                    '.' => 0,
+                    _ => c
+                        .to_digit(10)
+                        .ok_or(format!("row {y}: unexpected character {c:?}"))?
+                        as u8,
+                };
+            }
+        }
+        state.character = character.ok_or("no character '@' on the board")?;
+        Ok(state.with_visited_start())
+    }
+
+    /// 盤面の内容(大きさ・キャラクターの位置・得点)から計算する安定したハッシュ
+    ///
+    /// 生成したものでも読み込んだものでも、同じ盤面なら同じ値になる。
+    pub fn instance_hash(&self) -> String {
+        let mut hash = FNV_OFFSET_BASIS;
+        hash = fnv1a(hash, &(H as u32).to_le_bytes());
+        hash = fnv1a(hash, &(W as u32).to_le_bytes());
+        hash = fnv1a(hash, &self.character.y.to_le_bytes());
+        hash = fnv1a(hash, &self.character.x.to_le_bytes());
+        for row in self.points.chunks(W) {
+            hash = fnv1a(hash, row);
+        }
+        format!("{hash:016x}")
+    }
+
+    /// 盤面に残っている得点の合計
+    pub fn remaining_sum(&self) -> usize {
+        self.points.iter().map(|&p| p as usize).sum()
+    }
+
+    pub fn remaining_turns(&self) -> usize {
+        self.end_turn.saturating_sub(self.turn)
+    }
+
+    /// 残りターン数以内に到達できる(マンハッタン距離が残りターン数以下の)マスか
+    pub fn is_reachable(&self, y: usize, x: usize) -> bool {
+        let distance = (y as i32 - self.character.y).unsigned_abs() as usize
+            + (x as i32 - self.character.x).unsigned_abs() as usize;
+        distance <= self.remaining_turns()
+    }
+
+    /// 残りターン数以内に到達できる得点のあるマスとその得点
+    ///
+    /// 終盤では到達できない得点を上界や最寄りの得点の探索から除くのに使う。
+    pub fn reachable_points(&self) -> Vec<(Coord, usize)> {
+        let mut points = vec![];
+        for y in 0..H {
+            for x in 0..W {
+                let point = self.points[y * W + x] as usize;
+                if point > 0 && self.is_reachable(y, x) {
+                    points.push((Coord::new(y as i32, x as i32), point));
+                }
+            }
+        }
+        points
+    }
+
+    /// キャラクターから得点のある最寄りのマスまでのマンハッタン距離 (得点がなければNone)
+    ///
+    /// 壁がないのでBFSでの距離と同じ。近い順に菱形に探すので、近くに得点があればすぐ終わる。
+    pub fn distance_to_nearest_point(&self) -> Option<usize> {
+        let (cy, cx) = (self.character.y, self.character.x);
+        for distance in 1..(H + W) as i32 {
+            for dy in -distance..=distance {
+                let y = cy + dy;
+                if !(0..H as i32).contains(&y) {
+                    continue;
+                }
+                let dx = distance - dy.abs();
+                for x in [cx - dx, cx + dx] {
+                    if (0..W as i32).contains(&x) && self.points[y as usize * W + x as usize] > 0 {
+                        return Some(distance as usize);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// これ以降に獲得できる得点の上界
+    ///
+    /// 残りターン数以内に到達できるマスの得点の合計と、
+    /// 1ターンに取れる最大の得点 × 残りターン数の小さい方。
+    pub fn future_gain_bound(&self) -> usize {
+        let reachable_sum: usize = self.reachable_points().iter().map(|&(_, p)| p).sum();
+        reachable_sum.min(9 * self.remaining_turns())
+    }
+
+    /// 外部の評価関数に渡すための盤面バッファ
+    ///
+    /// `[H, W, turn, character.y, character.x, game_score, points(行優先 H * W 個)...]`
+    pub fn to_buffer(&self) -> Vec<i32> {
+        let mut buf = vec![
+            H as i32,
+            W as i32,
+            self.turn as i32,
+            self.character.y,
+            self.character.x,
+            self.game_score.0 as i32,
+        ];
+        buf.extend(self.points.iter().map(|&p| p as i32));
+        buf
+    }
+
+    pub fn greedy_action(&self) -> Direction {
+        let legal_actions = self.legal_actions();
+        assert!(!legal_actions.is_empty());
+        let mut best_action = None;
+        let mut highest = None;
+        for action in legal_actions {
+            let next = self.character.offset(action).unwrap();
+            let next_score = self.points[next.index()];
+            if highest.is_none() || next_score > highest.unwrap() {
+                highest = Some(next_score);
+                best_action = Some(action);
+            }
+        }
+        assert!(best_action.is_some());
+        best_action.unwrap()
+    }
+}
+
+/// 評価値で比べ、同じなら少ないターン数で着いた方、それも同じならキャラクターの位置で比べる
+impl Ord for MazeState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.evaluated_score
+            .cmp(&other.evaluated_score)
+            .then_with(|| other.turn.cmp(&self.turn))
+            .then_with(|| {
+                (self.character.y, self.character.x).cmp(&(other.character.y, other.character.x))
+            })
+    }
+}
+
+impl PartialOrd for MazeState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MazeState {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for MazeState {}
+
+impl GameState for MazeState {
+    type Action = Direction;
+
+    fn legal_actions(&self) -> Vec<Direction> {
+        MazeState::legal_actions(self)
+    }
+
+    fn advance(&mut self, action: Direction) {
+        MazeState::advance(self, action)
+    }
+
+    fn is_done(&self) -> bool {
+        MazeState::is_done(self)
+    }
+
+    fn evaluate(&mut self) {
+        self.evaluate_score()
+    }
+
+    fn evaluated_score(&self) -> Eval {
+        self.evaluated_score
+    }
+
+    fn first_action(&self) -> Direction {
+        self.first_action
+    }
+
+    fn set_first_action(&mut self, action: Direction) {
+        self.first_action = action;
+    }
+
+    fn diff(&self, other: &Self) -> String {
+        MazeState::diff(self, other).to_string()
+    }
+
+    /// キャラクターの位置・ターン・残りの得点 (同じ盤面から進めた状態どうしなら取ったマスの集合) のハッシュ
+    fn state_hash(&self) -> Option<u64> {
+        Some(hash_state(self.collected_hash, self.character, self.turn))
+    }
+
+    /// 再訪のペナルティがあれば、`state_hash` に再訪の回数と訪問済みのマスも混ぜる
+    ///
+    /// 同じ位置・ターン・取ったマスでも、経路によって子の再訪の回数が違い評価値も違う。
+    fn eval_cache_hash(&self) -> Option<u64> {
+        let hash = self.state_hash()?;
+        if self.revisit_penalty == 0 || self.evaluator.is_some() {
+            return Some(hash);
+        }
+        Some(
+            self.visited
+                .0
+                .iter()
+                .fold(splitmix64(hash ^ self.revisits as u64), |hash, &word| {
+                    splitmix64(hash ^ word)
+                }),
+        )
+    }
+
+    fn preview_child(&self, action: Direction) -> Option<ChildPreview> {
+        MazeState::preview_child(self, action)
+    }
+
+    fn set_evaluated_score(&mut self, score: Eval) -> bool {
+        self.evaluated_score = score;
+        true
+    }
+
+    /// スコアに `future_gain_bound` を足したもの (再訪のペナルティは評価値を下げるだけなので無視できる)
+    ///
+    /// 評価関数を差し替えたりノイズを足したりした状態は上界が分からないのでNone。
+    fn evaluation_bound(&self) -> Option<Eval> {
+        if self.evaluator.is_some() || self.eval_noise.is_some() {
+            return None;
+        }
+        let bound = self.game_score.0 + self.future_gain_bound() as u64;
+        Some(Eval::from(GameScore(bound)))
+    }
+}
+
+fn hash_state(collected_hash: u64, character: Coord, turn: usize) -> u64 {
+    let position = character.y as u64 * W as u64 + character.x as u64;
+    splitmix64(collected_hash ^ splitmix64(position ^ ((turn as u64) << 32)))
+}
+
+/// `GameState::preview_child` で計算した子の要約
+#[derive(Clone, Copy)]
+pub struct ChildPreview {
+    pub character: Coord,
+    pub evaluated_score: Eval,
+    pub state_hash: u64,
+}
+
+/// 2つの状態の違い
+pub struct StateDiff {
+    pub turn: i64,
+    pub from: Coord,
+    pub to: Coord,
+    /// `self` では得点があり `other` では0になっているマスとその得点
+    pub collected: Vec<(Coord, usize)>,
+    /// それ以外に得点が違うマスの数 (別の盤面どうしを比べたとき)
+    pub other_changes: usize,
+    pub score: i64,
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "turn {:+}, @({},{})->({},{}) ({:+},{:+}), score {:+}",
+            self.turn,
+            self.from.y,
+            self.from.x,
+            self.to.y,
+            self.to.x,
+            self.to.y - self.from.y,
+            self.to.x - self.from.x,
+            self.score
+        )?;
+        if self.collected.len() > 4 {
+            let sum: usize = self.collected.iter().map(|&(_, point)| point).sum();
+            write!(
+                f,
+                ", collected {} cells ({sum} points)",
+                self.collected.len()
+            )?;
+        } else if !self.collected.is_empty() {
+            write!(f, ", collected")?;
+            for (c, point) in &self.collected {
+                write!(f, " {point}@({},{})", c.y, c.x)?;
+            }
+        }
+        if self.other_changes > 0 {
+            write!(f, ", {} other cells differ", self.other_changes)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = String::new();
+        buf.push_str(&format!("turn:\t{}\n", self.turn));
+        buf.push_str(&format!("score:\t{}\n", self.game_score));
+        for y in 0..H {
+            for x in 0..W {
+                if self.character.y == y as i32 && self.character.x == x as i32 {
+                    buf.push('@');
+                } else if self.points[y * W + x] > 0 {
+                    buf.push(char::from_digit(self.points[y * W + x] as u32, 10).unwrap());
+                } else {
+                    buf.push('.');
+                }
+            }
+            buf.push('\n');
+        }
+        write!(f, "{}", buf)
+    }
+}
+
+impl MazeState {
+    /// ANSIエスケープで色を付けた盤面 (`Display` と同じ並び)
+    ///
+    /// 得点は大きいほど目立つ色で、キャラクターは反転表示、`last_action` を渡すと
+    /// 直前にいたマスを行動の向きの矢印で示す。
+    pub fn render_color(&self, last_action: Option<Direction>) -> String {
+        const RESET: &str = "\x1b[0m";
+        let previous = last_action.map(|action| {
+            let (dy, dx) = action.delta();
+            (
+                Coord::new(self.character.y - dy, self.character.x - dx),
+                action,
+            )
+        });
+        let mut buf = String::new();
+        buf.push_str(&format!("turn:\t{}\n", self.turn));
+        buf.push_str(&format!("score:\t{}\n", self.game_score));
+        for y in 0..H {
+            for x in 0..W {
+                let coord = Coord::new(y as i32, x as i32);
+                let point = self.points[y * W + x];
+                if self.character == coord {
+                    buf.push_str(&format!("\x1b[1;30;43m@{RESET}"));
+                } else if let Some((_, action)) = previous.filter(|&(from, _)| from == coord) {
+                    let arrow = match action {
+                        Direction::Right => '>',
+                        Direction::Left => '<',
+                        Direction::Down => 'v',
+                        Direction::Up => '^',
+                    };
+                    buf.push_str(&format!("\x1b[1;33m{arrow}{RESET}"));
+                } else if point > 0 {
+                    let color = match point {
+                        1..=3 => "34",
+                        4..=6 => "32",
+                        7..=8 => "33",
+                        _ => "1;31",
+                    };
+                    buf.push_str(&format!("\x1b[{color}m{point}{RESET}"));
+                } else {
+                    buf.push_str(&format!("\x1b[90m.{RESET}"));
+                }
+            }
+            buf.push('\n');
+        }
+        buf
+    }
+}
+
+/// 1ゲームの記録 (盤面のシード・行動列・各ターン後のスコア)
+///
+/// 盤面そのものはシードから作り直せるので持たない。`serde` フィーチャーでJSONなどに保存できる。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRecord {
+    pub seed: u64,
+    pub actions: Vec<Direction>,
+    /// `scores[i]` は `i + 1` ターン目の行動の後のスコア
+    pub scores: Vec<GameScore>,
+}
+
+impl GameRecord {
+    /// シード `seed` の盤面で `actions` を順に行った記録 (不正な行動があればErr)
+    pub fn from_actions(seed: u64, actions: &[Direction]) -> Result<Self, String> {
+        let mut state = MazeState::new(seed);
+        let mut scores = Vec::with_capacity(actions.len());
+        for &action in actions {
+            if !state.legal_actions().contains(&action) {
+                return Err(format!(
+                    "seed {seed}: illegal action {} at turn {}",
+                    action.name(),
+                    state.turn
+                ));
+            }
+            state.advance(action);
+            scores.push(state.game_score);
+        }
+        Ok(Self {
+            seed,
+            actions: actions.to_vec(),
+            scores,
+        })
+    }
+
+    /// 記録を再生して最後の状態を返す (不正な行動があるか、スコアが記録と違えばErr)
+    pub fn replay(&self) -> Result<MazeState, String> {
+        if self.scores.len() != self.actions.len() {
+            return Err(format!(
+                "seed {}: {} scores for {} actions",
+                self.seed,
+                self.scores.len(),
+                self.actions.len()
+            ));
+        }
+        let mut state = MazeState::new(self.seed);
+        for (&action, &score) in self.actions.iter().zip(&self.scores) {
+            if !state.legal_actions().contains(&action) {
+                return Err(format!(
+                    "seed {}: illegal action {} at turn {}",
+                    self.seed,
+                    action.name(),
+                    state.turn
+                ));
+            }
+            state.advance(action);
+            if state.game_score != score {
+                return Err(format!(
+                    "seed {}: score {} after turn {} differs from recorded {score}",
+                    self.seed, state.game_score, state.turn
+                ));
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// 行動の表示名 (0: 右, 1: 左, 2: 下, 3: 上)
+pub const ACTION_NAMES: [&str; 4] = ["R", "L", "D", "U"];
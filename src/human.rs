@@ -0,0 +1,105 @@
+//! 人がキーボードで遊ぶモード
+//!
+//! AIと同じ盤面を1手ずつ入力して遊ぶ。端末を生の入力に切り替えないので、
+//! `w`・`a`・`s`・`d` か矢印キーを押してからEnterで1手進める。
+
+use std::io::{BufRead, Write};
+
+use crate::{
+    game::{Direction, State},
+    play::{run_game, PlayConfig},
+    policy::Policy,
+};
+
+/// 人が遊んだ1ゲームの記録
+pub struct HumanGame {
+    pub state: State,
+    pub actions: Vec<Direction>,
+    /// AIのおすすめを表示したターン数
+    pub hinted: usize,
+    /// そのうちおすすめと同じ手を選んだターン数
+    pub agreed: usize,
+    /// 途中で `q` を入力してやめた
+    pub quit: bool,
+}
+
+/// 1行の入力を行動に読む (`w`・`a`・`s`・`d` か矢印キーのエスケープシーケンス。大文字でもよい)
+pub fn parse_key(line: &str) -> Option<Direction> {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "w" | "\x1b[a" => Some(Direction::Up),
+        "a" | "\x1b[d" => Some(Direction::Left),
+        "s" | "\x1b[b" => Some(Direction::Down),
+        "d" | "\x1b[c" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+/// 盤面 `seed` を `input` から読んだ手で最後まで遊ぶ
+///
+/// `hint` を渡すと毎ターンそのAIのおすすめの手を表示し、終局後に同じ盤面をAIが遊んだ
+/// スコアと並べる。入力が尽きるか `q` でやめたらその時点の盤面を返す。
+pub fn play_human(
+    seed: u64,
+    mut hint: Option<&mut dyn Policy>,
+    input: impl BufRead,
+    mut output: impl Write,
+    color: bool,
+) -> std::io::Result<HumanGame> {
+    let mut game = HumanGame {
+        state: State::new(seed),
+        actions: vec![],
+        hinted: 0,
+        agreed: 0,
+        quit: false,
+    };
+    let mut lines = input.lines();
+    'turns: while !game.state.is_done() {
+        let board = if color {
+            game.state.render_color(game.actions.last().copied())
+        } else {
+            game.state.to_string()
+        };
+        write!(output, "{board}")?;
+        let recommended = hint
+            .as_mut()
+            .map(|policy| policy.select_action(&game.state));
+        if let Some(action) = recommended {
+            writeln!(output, "AI: {action}")?;
+        }
+        let action = loop {
+            write!(output, "move (w/a/s/d, q: quit)> ")?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                break 'turns;
+            };
+            let line = line?;
+            if line.trim() == "q" {
+                game.quit = true;
+                break 'turns;
+            }
+            match parse_key(&line) {
+                Some(action) if game.state.legal_actions().contains(&action) => break action,
+                Some(action) => writeln!(output, "cannot move {action}")?,
+                None => writeln!(output, "unknown key {:?}", line.trim())?,
+            }
+        };
+        if let Some(recommended) = recommended {
+            game.hinted += 1;
+            if recommended == action {
+                game.agreed += 1;
+            }
+        }
+        let prev_state = game.state.clone();
+        game.state.advance(action);
+        game.actions.push(action);
+        writeln!(output, "{action} {}", prev_state.diff(&game.state))?;
+    }
+    write!(output, "{}", game.state)?;
+    writeln!(output, "you\tscore: {}", game.state.game_score)?;
+    if let Some(policy) = hint {
+        writeln!(output, "agreed with AI: {}/{}", game.agreed, game.hinted)?;
+        let result = run_game(seed, policy, &PlayConfig::default());
+        writeln!(output, "{}\tscore: {}", result.policy, result.game_score)?;
+    }
+    Ok(game)
+}
@@ -0,0 +1,388 @@
+//! 100×100を超える大きな盤面のための数字集め迷路
+//!
+//! `MazeState` は盤面の大きさが定数 `H`×`W` に固定なので、大きさを実行時に決める別の状態を用意する。
+//! 盤面は `u8` の1次元配列で持ち、隣のマスは同じ大きさの盤面どうしで共有する表から引く。
+//! 子を作らずに評価できる (`GameState::preview_child`) ので、`search::arena_beam_search_action` で
+//! 複製する状態をビーム幅に抑えて探索できる。評価関数は近くのマスを間引いて見る `LargeEvaluator` を使う。
+//! 30×30の盤面は `MazeState` と同じ盤面になる。
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    game::{ChildPreview, Coord, Direction, Eval, GameScore, GameState, END_TURN, H, W},
+    util::splitmix64,
+};
+
+/// 盤面の大きさに合わせた既定の最終ターン (30×30で `END_TURN` になるように縦横の和に比例させる)
+pub fn default_end_turn(height: usize, width: usize) -> usize {
+    END_TURN * (height + width) / (H + W)
+}
+
+/// `height`×`width` の盤面の各マスから進める行動と進んだ先のマス
+pub struct LargeNeighborTable {
+    pub height: usize,
+    pub width: usize,
+    /// マス `cell` の隣は `neighbors[starts[cell]..starts[cell + 1]]`
+    starts: Box<[u32]>,
+    neighbors: Box<[(Direction, u32)]>,
+}
+
+impl LargeNeighborTable {
+    fn new(height: usize, width: usize) -> Self {
+        let mut starts = Vec::with_capacity(height * width + 1);
+        let mut neighbors = Vec::with_capacity(height * width * 4);
+        starts.push(0);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                for action in Direction::ALL {
+                    let (dy, dx) = action.delta();
+                    let (ny, nx) = (y + dy, x + dx);
+                    if (0..height as i32).contains(&ny) && (0..width as i32).contains(&nx) {
+                        neighbors.push((action, ny as u32 * width as u32 + nx as u32));
+                    }
+                }
+                starts.push(neighbors.len() as u32);
+            }
+        }
+        Self {
+            height,
+            width,
+            starts: starts.into_boxed_slice(),
+            neighbors: neighbors.into_boxed_slice(),
+        }
+    }
+
+    /// マス `cell` から進める行動と進んだ先のマス (`Direction::ALL` の順)
+    pub fn neighbors(&self, cell: usize) -> &[(Direction, u32)] {
+        &self.neighbors[self.starts[cell] as usize..self.starts[cell + 1] as usize]
+    }
+}
+
+/// 盤面の大きさごとの隣のマスの表
+type NeighborTables = Mutex<HashMap<(usize, usize), Arc<LargeNeighborTable>>>;
+
+/// `height`×`width` の隣のマスの表 (同じ大きさなら全ての盤面で同じ表を共有する)
+pub fn large_neighbor_table(height: usize, width: usize) -> Arc<LargeNeighborTable> {
+    static TABLES: OnceLock<NeighborTables> = OnceLock::new();
+    let mut tables = TABLES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    tables
+        .entry((height, width))
+        .or_insert_with(|| Arc::new(LargeNeighborTable::new(height, width)))
+        .clone()
+}
+
+/// `LargeMazeState` の評価関数
+#[derive(Clone, Default)]
+pub enum LargeEvaluator {
+    /// スコアをそのまま評価値にする (`MazeState` の既定と同じ)
+    #[default]
+    Score,
+    /// キャラクターの周りから間引いて選んだマスの得点が作るポテンシャルをスコアに混ぜる
+    ///
+    /// 評価値は `100 * スコア + weight * ポテンシャル / 100` で、ポテンシャルは選んだマスごとの
+    /// `100 * 得点 / (距離 + 1)^2` の和。盤面全体を見ないので、1回の評価は盤面の大きさによらない。
+    SampledPotential {
+        /// キャラクターからの `(dy, dx, (距離 + 1)^2)`
+        offsets: Arc<[(i32, i32, i64)]>,
+        weight: i64,
+    },
+}
+
+impl LargeEvaluator {
+    /// キャラクターからマンハッタン距離 `radius` 以内のマスを近い順に並べ、等間隔に `samples` 個選ぶ
+    pub fn sampled_potential(samples: usize, radius: usize, weight: i64) -> Self {
+        let radius = radius as i32;
+        let mut cells = vec![];
+        for distance in 1..=radius {
+            for dy in -distance..=distance {
+                let dx = distance - dy.abs();
+                cells.push((dy, dx, ((distance + 1) * (distance + 1)) as i64));
+                if dx != 0 {
+                    cells.push((dy, -dx, ((distance + 1) * (distance + 1)) as i64));
+                }
+            }
+        }
+        let samples = samples.clamp(1, cells.len().max(1));
+        let offsets: Vec<_> = (0..samples)
+            .filter_map(|i| cells.get(i * cells.len() / samples).copied())
+            .collect();
+        Self::SampledPotential {
+            offsets: offsets.into(),
+            weight,
+        }
+    }
+
+    fn evaluate(&self, state: &LargeMazeState, character: Coord, game_score: GameScore) -> Eval {
+        match self {
+            Self::Score => Eval::from(game_score),
+            Self::SampledPotential { offsets, weight } => {
+                let potential: i64 = offsets
+                    .iter()
+                    .filter_map(|&(dy, dx, divisor)| {
+                        let (y, x) = (character.y + dy, character.x + dx);
+                        let inside = (0..state.height as i32).contains(&y)
+                            && (0..state.width as i32).contains(&x);
+                        inside.then(|| {
+                            100 * state.points[y as usize * state.width + x as usize] as i64
+                                / divisor
+                        })
+                    })
+                    .sum();
+                Eval(100 * Eval::from(game_score).0 + weight * potential / 100)
+            }
+        }
+    }
+}
+
+/// 大きさを実行時に決める数字集め迷路
+#[derive(Clone)]
+pub struct LargeMazeState {
+    pub height: usize,
+    pub width: usize,
+    /// 各マスの得点 (`y * width + x` の順)
+    pub points: Box<[u8]>,
+    pub turn: usize,
+    pub end_turn: usize,
+    pub character: Coord,
+    pub game_score: GameScore,
+    pub evaluated_score: Eval,
+    pub first_action: Direction,
+    /// 取った得点のマスのハッシュのXOR
+    pub collected_hash: u64,
+    pub evaluator: LargeEvaluator,
+    neighbors: Arc<LargeNeighborTable>,
+}
+
+impl LargeMazeState {
+    /// 盤面 `seed` の `height`×`width` の迷路 (最終ターンは `default_end_turn`)
+    pub fn new(seed: u64, height: usize, width: usize) -> Self {
+        assert!(height > 0 && width > 0, "board must not be empty");
+        assert!(
+            height * width <= u32::MAX as usize,
+            "board is too large: {height}x{width}"
+        );
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let character = Coord {
+            y: rng.gen::<i32>().rem_euclid(height as i32),
+            x: rng.gen::<i32>().rem_euclid(width as i32),
+        };
+        let mut points = vec![0; height * width].into_boxed_slice();
+        for y in 0..height {
+            for x in 0..width {
+                if y as i32 == character.y && x as i32 == character.x {
+                    continue;
+                }
+                points[y * width + x] = (rng.next_u64() % 10) as u8;
+            }
+        }
+        Self {
+            height,
+            width,
+            points,
+            turn: 0,
+            end_turn: default_end_turn(height, width),
+            character,
+            game_score: GameScore(0),
+            evaluated_score: Eval(0),
+            first_action: Direction::Right,
+            collected_hash: 0,
+            evaluator: LargeEvaluator::Score,
+            neighbors: large_neighbor_table(height, width),
+        }
+    }
+
+    /// 隣のマスの表 (同じ大きさの盤面と共有している)
+    pub fn neighbor_table(&self) -> &Arc<LargeNeighborTable> {
+        &self.neighbors
+    }
+
+    fn cell(&self, coord: Coord) -> usize {
+        coord.y as usize * self.width + coord.x as usize
+    }
+
+    fn coord(&self, cell: usize) -> Coord {
+        Coord::new((cell / self.width) as i32, (cell % self.width) as i32)
+    }
+
+    fn next_cell(&self, action: Direction) -> usize {
+        self.neighbors
+            .neighbors(self.cell(self.character))
+            .iter()
+            .find(|&&(a, _)| a == action)
+            .expect("action moves the character off the board")
+            .1 as usize
+    }
+
+    fn hash(&self, collected_hash: u64, cell: usize, turn: usize) -> u64 {
+        splitmix64(collected_hash ^ splitmix64(cell as u64 ^ ((turn as u64) << 32)))
+    }
+
+    fn write_row(&self, y: usize, left: usize, right: usize, row: &mut String) {
+        for x in left..right {
+            let point = self.points[y * self.width + x];
+            if self.character.y == y as i32 && self.character.x == x as i32 {
+                row.push('@');
+            } else if point > 0 {
+                row.push(char::from(b'0' + point));
+            } else {
+                row.push('.');
+            }
+        }
+        row.push('\n');
+    }
+
+    /// 左上が `(top, left)` の `height`×`width` の範囲だけの盤面 (盤面からはみ出す部分は切り詰める)
+    pub fn render_window(&self, top: usize, left: usize, height: usize, width: usize) -> String {
+        let bottom = top.saturating_add(height).min(self.height);
+        let right = left.saturating_add(width).min(self.width);
+        let mut buf = String::new();
+        for y in top.min(bottom)..bottom {
+            self.write_row(y, left.min(right), right, &mut buf);
+        }
+        buf
+    }
+
+    /// `Display` と同じ内容を `rows_per_chunk` 行ずつまとめて `out` に書く
+    ///
+    /// 盤面全体の文字列を作らないので、大きな盤面でも使うメモリは `rows_per_chunk` 行分で済む。
+    pub fn write_chunked(&self, out: &mut impl Write, rows_per_chunk: usize) -> io::Result<()> {
+        writeln!(out, "turn:\t{}", self.turn)?;
+        writeln!(out, "score:\t{}", self.game_score)?;
+        let rows_per_chunk = rows_per_chunk.max(1);
+        let mut chunk = String::with_capacity((self.width + 1) * rows_per_chunk);
+        for top in (0..self.height).step_by(rows_per_chunk) {
+            chunk.clear();
+            for y in top..(top + rows_per_chunk).min(self.height) {
+                self.write_row(y, 0, self.width, &mut chunk);
+            }
+            out.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LargeMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "turn:\t{}", self.turn)?;
+        writeln!(f, "score:\t{}", self.game_score)?;
+        let mut row = String::with_capacity(self.width + 1);
+        for y in 0..self.height {
+            row.clear();
+            self.write_row(y, 0, self.width, &mut row);
+            f.write_str(&row)?;
+        }
+        Ok(())
+    }
+}
+
+/// 評価値で比べ、同じなら少ないターン数で着いた方、それも同じならキャラクターの位置で比べる
+impl Ord for LargeMazeState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.evaluated_score
+            .cmp(&other.evaluated_score)
+            .then_with(|| other.turn.cmp(&self.turn))
+            .then_with(|| {
+                (self.character.y, self.character.x).cmp(&(other.character.y, other.character.x))
+            })
+    }
+}
+
+impl PartialOrd for LargeMazeState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for LargeMazeState {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for LargeMazeState {}
+
+impl GameState for LargeMazeState {
+    type Action = Direction;
+
+    fn legal_actions(&self) -> Vec<Direction> {
+        self.neighbors
+            .neighbors(self.cell(self.character))
+            .iter()
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    fn advance(&mut self, action: Direction) {
+        let cell = self.next_cell(action);
+        self.character = self.coord(cell);
+        let point = std::mem::take(&mut self.points[cell]);
+        if point > 0 {
+            self.game_score = self.game_score.add_point(point);
+            self.collected_hash ^= splitmix64(cell as u64);
+        }
+        self.turn += 1;
+    }
+
+    fn is_done(&self) -> bool {
+        self.turn == self.end_turn
+    }
+
+    fn evaluate(&mut self) {
+        self.evaluated_score = self
+            .evaluator
+            .evaluate(self, self.character, self.game_score);
+    }
+
+    fn evaluated_score(&self) -> Eval {
+        self.evaluated_score
+    }
+
+    fn set_evaluated_score(&mut self, score: Eval) -> bool {
+        self.evaluated_score = score;
+        true
+    }
+
+    fn first_action(&self) -> Direction {
+        self.first_action
+    }
+
+    fn set_first_action(&mut self, action: Direction) {
+        self.first_action = action;
+    }
+
+    fn state_hash(&self) -> Option<u64> {
+        Some(self.hash(self.collected_hash, self.cell(self.character), self.turn))
+    }
+
+    /// ポテンシャルは進んだ先のマス自身を見ないので、取った得点を盤面から消さずに評価できる
+    fn preview_child(&self, action: Direction) -> Option<ChildPreview> {
+        let cell = self.next_cell(action);
+        let character = self.coord(cell);
+        let point = self.points[cell];
+        let (game_score, collected_hash) = if point > 0 {
+            (
+                self.game_score.add_point(point),
+                self.collected_hash ^ splitmix64(cell as u64),
+            )
+        } else {
+            (self.game_score, self.collected_hash)
+        };
+        Some(ChildPreview {
+            character,
+            evaluated_score: self.evaluator.evaluate(self, character, game_score),
+            state_hash: self.hash(collected_hash, cell, self.turn + 1),
+        })
+    }
+}
@@ -0,0 +1,156 @@
+//! 1手あたりの思考時間の集計と目標
+
+use core::fmt;
+
+use crate::results::GameResult;
+
+/// 1手あたりの思考時間の分布
+pub struct LatencyReport {
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    pub deadline_overruns: usize,
+}
+
+impl LatencyReport {
+    pub fn new(results: &[GameResult]) -> Self {
+        let mut histogram = LatencyHistogram::default();
+        for result in results {
+            histogram.record_game(result);
+        }
+        histogram.report()
+    }
+}
+
+/// 思考時間の分布を一定のメモリで集計するヒストグラム
+///
+/// 64us未満は1us刻み、それ以上は2の冪ごとに32分割したバケットに数える(誤差は約3%以内)。
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    pub counts: Vec<u64>,
+    pub total: u64,
+    pub max_us: u64,
+    pub deadline_overruns: usize,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; 64 + 58 * 32],
+            total: 0,
+            max_us: 0,
+            deadline_overruns: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn bucket(us: u64) -> usize {
+        if us < 64 {
+            return us as usize;
+        }
+        let shift = 63 - us.leading_zeros() as usize - 5;
+        64 + (shift - 1) * 32 + ((us >> shift) as usize - 32)
+    }
+
+    /// バケットに入る値の上限
+    pub fn bucket_upper_bound(bucket: usize) -> u64 {
+        if bucket < 64 {
+            return bucket as u64;
+        }
+        let shift = (bucket - 64) / 32 + 1;
+        let mantissa = ((bucket - 64) % 32 + 32) as u64;
+        ((mantissa + 1) << shift) - 1
+    }
+
+    pub fn record(&mut self, us: u64) {
+        self.counts[Self::bucket(us)] += 1;
+        self.total += 1;
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn record_game(&mut self, result: &GameResult) {
+        for &us in &result.move_times_us {
+            self.record(us);
+        }
+        self.deadline_overruns += result.deadline_overruns;
+    }
+
+    /// nearest-rank法でのパーセンタイル (バケットの上限で返す)
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((p / 100. * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_upper_bound(bucket).min(self.max_us);
+            }
+        }
+        self.max_us
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        LatencyReport {
+            p50_us: self.percentile(50.),
+            p95_us: self.percentile(95.),
+            p99_us: self.percentile(99.),
+            max_us: self.max_us,
+            deadline_overruns: self.deadline_overruns,
+        }
+    }
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "latency_us: p50={} p95={} p99={} max={} overruns={}",
+            self.p50_us, self.p95_us, self.p99_us, self.max_us, self.deadline_overruns
+        )
+    }
+}
+
+/// 思考時間の目標 (超えた実行は失敗扱いにする)
+#[derive(Clone, Copy, Default)]
+pub struct LatencySlo {
+    pub p99_us: Option<u64>,
+    pub max_us: Option<u64>,
+    pub max_overruns: Option<usize>,
+}
+
+impl LatencySlo {
+    /// 目標を満たしていなければ、満たしていない項目の説明を返す
+    pub fn violations(&self, report: &LatencyReport) -> Vec<String> {
+        let mut violations = vec![];
+        if let Some(p99) = self.p99_us.filter(|&p99| report.p99_us > p99) {
+            violations.push(format!("p99 {}us > {p99}us", report.p99_us));
+        }
+        if let Some(max) = self.max_us.filter(|&max| report.max_us > max) {
+            violations.push(format!("max {}us > {max}us", report.max_us));
+        }
+        if let Some(overruns) = self
+            .max_overruns
+            .filter(|&overruns| report.deadline_overruns > overruns)
+        {
+            violations.push(format!(
+                "{} overruns > {overruns}",
+                report.deadline_overruns
+            ));
+        }
+        violations
+    }
+}
+
+/// 思考時間のレポートを表示し、目標を満たしていればtrueを返す
+pub fn check_latency_slo(report: &LatencyReport, slo: &LatencySlo) -> bool {
+    println!("{report}");
+    let violations = slo.violations(report);
+    for violation in &violations {
+        println!("SLO violated: {violation}");
+    }
+    violations.is_empty()
+}
@@ -0,0 +1,36 @@
+//! [ゲームで学ぶ探索アルゴリズム実践入門](https://gihyo.jp/book/2023/978-4-297-13360-3) の数字集め迷路とその探索アルゴリズム
+#![allow(clippy::needless_range_loop)]
+
+pub mod alternate;
+pub mod calibrate;
+pub mod compose;
+pub mod crash;
+pub mod daemon;
+pub mod dataset;
+pub mod db;
+pub mod fog;
+pub mod game;
+pub mod human;
+pub mod large;
+pub mod latency;
+pub mod play;
+pub mod policy;
+pub mod prelude;
+pub mod repl;
+pub mod report;
+pub mod results;
+pub mod rules;
+pub mod runner;
+pub mod scenario;
+pub mod schema;
+pub mod search;
+pub mod selfcheck;
+pub mod soak;
+pub mod spec;
+pub mod stats;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod util;
+pub mod visualize;
+pub mod wall;
+pub mod warm;
@@ -1,441 +1,1353 @@
 #![allow(clippy::needless_range_loop)]
-#![allow(dead_code, unused_mut, unused_variables)]
-use core::fmt;
-use std::{cmp::Ordering, collections::BinaryHeap, env, time::Instant};
+use std::{cell::RefCell, env};
 
-use rand::{prelude::*, Rng, SeedableRng};
+use clap::{Args, Parser, Subcommand};
+use rand::SeedableRng;
 use rand_chacha::ChaCha12Rng;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct Coord {
-    y: i32,
-    x: i32,
-}
+use num_collect_maze::{
+    alternate::{
+        alpha_beta_action, minimax_action, self_play, AlternateMazeState, MoveOrdering,
+        OrderedAlphaBeta, WinningStatus,
+    },
+    calibrate::{calibrate, CalibrationProfile},
+    compose::PolicyConfig,
+    daemon::{parse_interval, run_daemon, DaemonConfig},
+    dataset::{generate_dataset, write_dataset_jsonl, write_dataset_npz},
+    db::{budget_ms_from_policy_name, ResultDb},
+    fog::{
+        condition_on_observation, determinized_ensemble_action, fog_belief,
+        frontier_exploration_action, FogMazeState,
+    },
+    game::{ActionMask, GameConfig, GameState, MazeState, END_TURN, INSTANCE_FORMAT_VERSION},
+    human::play_human,
+    latency::{check_latency_slo, LatencyReport, LatencySlo},
+    play::{
+        compare, noise_robustness, play_game, refine_sweep_grid, replay_game, replay_game_log,
+        sweep, sweep_table, test_ai_score, PlayConfig,
+    },
+    policy::{parse_policy, Policy},
+    repl::{run_repl, Repl},
+    report::ExperimentReport,
+    results::{
+        read_game_log, read_results_jsonl, render_trajectory_strip, replay_diff, write_game_log,
+        write_manifest, write_results_jsonl, ReplayStats, ResultSink, RunMeta,
+    },
+    rules::RuledState,
+    scenario::Scenario,
+    schema::{migrate_text, Migrated},
+    search::{beam_search_action, DEGRADED_MODE_FLOOR_US},
+    selfcheck::self_check,
+    soak::soak,
+    util::{Clock, FoldedStackLayer, ManualClock, ThreadConfig, PROFILE_ENABLED},
+    visualize::{animate as animate_game, AnimationConfig, FrameClock, WallFrameClock},
+    wall::WallMazeState,
+};
 
-impl Coord {
-    fn new(y: i32, x: i32) -> Self {
-        Self { y, x }
-    }
-}
-
-const H: usize = 30;
-const W: usize = 30;
-const END_TURN: usize = 100;
 const NUM_GAME: usize = 100;
 
-struct TimeKeeper {
-    start_time: std::time::Instant,
-    time_threshold: u128,
+/// 数字集め迷路のAIを動かす (サブコマンドを省略すると `bench`)
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl TimeKeeper {
-    fn new(time_threshold: u128) -> Self {
-        Self {
-            start_time: Instant::now(),
-            time_threshold,
-        }
-    }
-
-    fn is_over(&self) -> bool {
-        let elapsed_msec = self.start_time.elapsed().as_millis();
-        elapsed_msec >= self.time_threshold
-    }
+/// どのサブコマンドにも付けられるオプション
+#[derive(Args)]
+struct GlobalArgs {
+    /// 探索のspanの時間を畳み込んだスタック形式で書き出すパス
+    #[arg(long, global = true)]
+    trace_out: Option<String>,
+    /// 探索の段階ごとの時間を標準エラーに出す
+    #[arg(long, global = true)]
+    profile: bool,
+    /// 1手の時間制限がこれ[us]を下回ったらビームを始めずに2手読みの貪欲法で決める
+    #[arg(long, global = true)]
+    degraded_floor_us: Option<u64>,
+    /// 時間制限をスレッドのCPU時間で測る
+    #[arg(long, global = true)]
+    cpu_time: bool,
+    /// 探索中にpanicしたとき、盤面・AIの指定・探索の統計を書き出すディレクトリ
+    #[arg(long, global = true, default_value = ".")]
+    crash_dir: String,
 }
 
-type State = MazeState;
-
-#[derive(Clone, Eq)]
-struct MazeState {
-    points: Vec<Vec<usize>>,
-    turn: usize,
-    character: Coord,
-    game_score: usize,
-    evaluated_score: usize,
-    dx: [i32; 4],
-    dy: [i32; 4],
-    first_action: usize,
+/// 使うAI (`--algorithm` の指定に `--width` などを書き足す)
+#[derive(Args)]
+struct PolicyArgs {
+    /// AIの指定 (`beam`, `chokudai:num=2`, `fallback(greedy, beam)` など)
+    #[arg(long)]
+    algorithm: Option<String>,
+    /// ビーム幅
+    #[arg(long)]
+    width: Option<usize>,
+    /// ビームの深さ
+    #[arg(long)]
+    depth: Option<usize>,
+    /// 1手の時間制限[ms]
+    #[arg(long, conflicts_with = "time_ms")]
+    ms: Option<usize>,
+    /// 1手の時間制限[ms] (`0.25` のように1ms未満も指定でき、`us=` に直して渡す)
+    #[arg(long)]
+    time_ms: Option<f64>,
+    /// 1手に展開する状態の数 (`--calibration` のプロファイルで時間制限に読み替える)
+    #[arg(long)]
+    nodes: Option<usize>,
+    /// `calibrate` で書き出した探索の速さのプロファイル (`nodes=N` の指定を `ms` に読み替える)
+    #[arg(long)]
+    calibration: Option<String>,
 }
 
-impl MazeState {
-    fn new(seed: u64) -> Self {
-        let mut rng = ChaCha12Rng::seed_from_u64(seed);
-        let character = Coord {
-            y: rng.gen::<i32>().rem_euclid(H as i32),
-            x: rng.gen::<i32>().rem_euclid(W as i32),
+impl PolicyArgs {
+    /// `parse_policy` に渡す指定を作る (`--algorithm` を省略したら `default`)
+    fn spec(&self, default: &str) -> Result<String, String> {
+        let mut spec = self.algorithm.clone().unwrap_or(default.to_string());
+        let time_us = match self.time_ms {
+            Some(ms) if !(ms.is_finite() && ms > 0.) => {
+                return Err(format!("--time-ms must be positive: {ms}"));
+            }
+            time_ms => time_ms.map(|ms| (ms * 1000.).round() as usize),
         };
-
-        let mut points: Vec<Vec<usize>> = vec![vec![0; W]; H];
-        for y in 0..H {
-            for x in 0..W {
-                if y as i32 == character.y && x as i32 == character.x {
-                    continue;
-                }
-                points[y][x] = rng.next_u64() as usize % 10;
+        let params: Vec<String> = [
+            ("width", self.width),
+            ("depth", self.depth),
+            ("ms", self.ms),
+            ("us", time_us),
+            ("nodes", self.nodes),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{key}={}", value?)))
+        .collect();
+        if !params.is_empty() {
+            if spec.contains('(') {
+                return Err(format!(
+                    "--width/--depth/--ms/--time-ms/--nodes cannot be combined with the expression {spec:?}"
+                ));
             }
+            spec.push(if spec.contains(':') { ',' } else { ':' });
+            spec.push_str(&params.join(","));
         }
-        Self {
-            points,
-            turn: 0,
-            character,
-            game_score: 0,
-            evaluated_score: 0,
-            // 0: 右, 1: 左, 2: 下, 3:上
-            dx: [1, -1, 0, 0],
-            dy: [0, 0, 1, -1],
-            first_action: 0,
+        match &self.calibration {
+            Some(path) => CalibrationProfile::load(path)?.resolve(&spec),
+            None if spec.contains("nodes=") => Err(format!(
+                "{spec:?} needs --calibration to turn nodes into a time budget"
+            )),
+            None => Ok(spec),
         }
     }
+}
 
-    /// ゲームの終了判定
-    fn is_done(&self) -> bool {
-        self.turn == END_TURN
-    }
+#[derive(Args)]
+struct ThreadArgs {
+    /// 並列に遊ぶゲーム数の上限 (0で全コア)
+    #[arg(short, long, visible_alias = "threads", default_value_t = 0)]
+    jobs: usize,
+    /// スレッドをコアに固定する
+    #[arg(long)]
+    pin: bool,
+}
 
-    /// 指定したactionでゲームを１ターン進める
-    /// 0: 右, 1: 左, 2: 下, 3:上
-    fn advance(&mut self, action: usize) {
-        self.character.x += self.dx[action];
-        self.character.y += self.dy[action];
-        let point = &mut self.points[self.character.y as usize][self.character.x as usize];
-        if *point > 0 {
-            self.game_score += *point;
-            *point = 0;
-        }
-        self.turn += 1;
+impl ThreadArgs {
+    fn thread_config(&self) -> ThreadConfig {
+        ThreadConfig::new(self.jobs, self.pin)
     }
+}
 
-    /// プレイヤーが可能な行動を全て取得する
-    fn legal_actions(&self) -> Vec<usize> {
-        let mut legal_actions = vec![];
-        for action in 0..4 {
-            let ty = self.character.y + self.dy[action];
-            let tx = self.character.x + self.dx[action];
-            if 0 <= ty && ty < H as i32 && 0 <= tx && tx < W as i32 {
-                legal_actions.push(action);
-            }
-        }
-        legal_actions
-    }
+/// 結果の保存先と注記
+#[derive(Args)]
+struct RecordArgs {
+    /// 結果を記録するSQLiteのパス
+    #[arg(long)]
+    db: Option<String>,
+    /// 結果を書き出すJSONLのパス
+    #[arg(long)]
+    results: Option<String>,
+    /// 実行に付けるタグ (複数指定できる)
+    #[arg(long)]
+    tag: Vec<String>,
+    /// 実行に付けるメモ
+    #[arg(long)]
+    note: Option<String>,
+}
 
-    fn evaluate_score(&mut self) {
-        self.evaluated_score = self.game_score
+impl RecordArgs {
+    fn run_meta(&self) -> RunMeta {
+        RunMeta {
+            tags: self.tag.clone(),
+            note: self.note.clone(),
+        }
     }
 
-    fn greedy_action(&self) -> usize {
-        let legal_actions = self.legal_actions();
-        assert!(!legal_actions.is_empty());
-        let mut best_action = None;
-        let mut highest = None;
-        for action in legal_actions {
-            let next_y = self.character.y + self.dy[action];
-            let next_x = self.character.x + self.dx[action];
-            assert!(0 <= next_y && next_y < H as i32);
-            assert!(0 <= next_x && next_x < W as i32);
-            let next_score = self.points[next_y as usize][next_x as usize];
-            if highest.is_none() || next_score > highest.unwrap() {
-                highest = Some(next_score);
-                best_action = Some(action);
-            }
-        }
-        assert!(best_action.is_some());
-        best_action.unwrap()
+    /// `--db` と `--results` に結果を残す
+    fn save(&self, kind: &str, results: &[num_collect_maze::results::GameResult]) {
+        let run_meta = self.run_meta();
+        if let Some(path) = &self.db {
+            ResultDb::open(path)
+                .unwrap()
+                .record(kind, &run_meta, results)
+                .unwrap();
+        }
+        if let Some(path) = &self.results {
+            write_results_jsonl(results, path).unwrap();
+            write_manifest(path, kind, &run_meta).unwrap();
+        }
     }
 }
 
-impl Ord for MazeState {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.evaluated_score.cmp(&other.evaluated_score)
-    }
+/// ゲームの進め方とルール
+#[derive(Args)]
+struct GameArgs {
+    /// ゲーム開始前に捨ての探索を1回行う
+    #[arg(long = "warmup")]
+    warm_up: bool,
+    /// 最初の1手だけに使う時間制限[ms]
+    #[arg(long)]
+    first_move_ms: Option<u128>,
+    /// これ以上得点できなくなったら探索せずに最後まで進める
+    #[arg(long)]
+    fast_forward: bool,
+    /// 残りターン数がこれ以下になったら終盤の全探索に切り替える
+    #[arg(long)]
+    endgame_turns: Option<usize>,
+    /// ゲームの長さ
+    #[arg(long, default_value_t = END_TURN)]
+    end_turn: usize,
+    /// 進入を禁止する領域 `y0,x0,y1,x1` (複数指定できる)
+    #[arg(long)]
+    forbid_region: Vec<String>,
+    /// 禁止する行動 (複数指定できる)
+    #[arg(long)]
+    forbid_action: Vec<String>,
+    /// 評価値で再訪1回ごとに引く点数
+    #[arg(long, default_value_t = 0)]
+    revisit_penalty: i64,
+    /// 評価値に足すノイズの大きさ
+    #[arg(long, default_value_t = 0)]
+    eval_noise: i64,
+    /// 確率的なAIが手を引いた分布を結果に残す
+    #[arg(long)]
+    action_probs: bool,
+    /// 時間制限付きのAIが使わなかった時間を貯め、次の手の時間制限に上乗せする
+    #[arg(long)]
+    bank_time: bool,
+    /// 終盤の全探索の置換表をゲームの間で共有しない (ゲームを厳密に独立させる)
+    #[arg(long)]
+    isolate_caches: bool,
 }
 
-impl PartialOrd for MazeState {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl GameArgs {
+    fn play_config(&self) -> PlayConfig {
+        let mut mask_rules = vec![];
+        for region in &self.forbid_region {
+            mask_rules.push(ActionMask::parse_region(region).unwrap_or_else(exit_usage));
+        }
+        for action in &self.forbid_action {
+            mask_rules.push(ActionMask::parse_action(action).unwrap_or_else(exit_usage));
+        }
+        let action_mask =
+            (!mask_rules.is_empty()).then(|| std::sync::Arc::new(ActionMask { rules: mask_rules }));
+        PlayConfig {
+            warm_up: self.warm_up,
+            first_move_time_threshold: self.first_move_ms,
+            fast_forward: self.fast_forward,
+            endgame_turns: self.endgame_turns,
+            record_action_probs: self.action_probs,
+            isolate_caches: self.isolate_caches,
+            bank_time: self.bank_time,
+            game: GameConfig {
+                end_turn: self.end_turn,
+                mask: action_mask,
+                revisit_penalty: self.revisit_penalty,
+                eval_noise: self.eval_noise,
+            },
+        }
     }
 }
 
-impl PartialEq for MazeState {
-    fn eq(&self, other: &Self) -> bool {
-        self.evaluated_score == other.evaluated_score
-    }
+/// 思考時間の目標 (満たさなければ終了コード1)
+#[derive(Args)]
+struct SloArgs {
+    #[arg(long)]
+    slo_p99_us: Option<u64>,
+    #[arg(long)]
+    slo_max_us: Option<u64>,
+    #[arg(long)]
+    slo_max_overruns: Option<usize>,
 }
 
-impl fmt::Display for MazeState {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut buf = String::new();
-        buf.push_str(&format!("turn:\t{}\n", self.turn));
-        buf.push_str(&format!("score:\t{}\n", self.game_score));
-        for y in 0..H {
-            for x in 0..W {
-                if self.character.y == y as i32 && self.character.x == x as i32 {
-                    buf.push('@');
-                } else if self.points[y][x] > 0 {
-                    buf.push(char::from_digit(self.points[y][x] as u32, 10).unwrap());
-                } else {
-                    buf.push('.');
-                }
-            }
-            buf.push('\n');
+impl SloArgs {
+    fn slo(&self) -> LatencySlo {
+        LatencySlo {
+            p99_us: self.slo_p99_us,
+            max_us: self.slo_max_us,
+            max_overruns: self.slo_max_overruns,
         }
-        write!(f, "{}", buf)
     }
 }
 
-fn random_action(state: &State, rng: &mut ChaCha12Rng) -> usize {
-    let legal_actions = state.legal_actions();
-    legal_actions[rng.gen::<usize>() % legal_actions.len()]
+#[derive(Args)]
+struct DaemonArgs {
+    /// 固定したベンチマークを `--interval` ごとに繰り返して `--db` に溜め、前回からの有意な変化を表示する
+    #[arg(long, requires = "db")]
+    daemon: bool,
+    /// `--daemon` で実行する間隔 (`24h`, `30m`, `10s` など)
+    #[arg(long, default_value = "24h")]
+    interval: String,
+    /// `--daemon` で実行する回数 (省略したら止めるまで続ける)
+    #[arg(long)]
+    cycles: Option<usize>,
+    /// `--daemon` で遊ばせるAIの一覧のTOML (省略したら `--algorithm` のAIだけ)
+    #[arg(long)]
+    suite: Option<String>,
+    /// `--daemon` でこのp値未満の変化を有意とみなす
+    #[arg(long, default_value_t = 0.05)]
+    drift_alpha: f64,
 }
 
-fn greedy_action(state: &State) -> usize {
-    let legal_actions = state.legal_actions();
-    assert!(!legal_actions.is_empty());
-    let mut best_action = None;
-    let mut highest = None;
-    for action in legal_actions {
-        let mut next_state = state.clone();
-        next_state.advance(action);
-        next_state.evaluate_score();
-        if highest.is_none() || highest.unwrap() < next_state.evaluated_score {
-            highest = Some(next_state.evaluated_score);
-            best_action = Some(action);
-        }
-    }
-    assert!(best_action.is_some());
-    best_action.unwrap()
+#[derive(Subcommand)]
+enum Command {
+    /// 1ゲーム遊ばせて各ターンの盤面を表示する
+    Play {
+        /// 盤面のシード
+        #[arg(default_value_t = 0)]
+        seed: u64,
+        #[command(flatten)]
+        policy: PolicyArgs,
+        /// AIの乱数のシード
+        #[arg(long, default_value_t = 0)]
+        rng_seed: u64,
+        /// 各ターンの盤面・行動・評価値・思考時間をJSONで書き出すパス (`replay` で再生できる)
+        #[arg(long)]
+        log: Option<String>,
+        /// 盤面をANSIの色付きで表示する (得点の大きさで色分けし、キャラクターと直前の移動を強調する)
+        #[arg(long)]
+        color: bool,
+        /// AIの代わりに人がキーボードで遊ぶ (w/a/s/d か矢印キーを押してEnter)
+        #[arg(long)]
+        human: bool,
+        /// `--human` で毎ターン `--algorithm` のAIのおすすめの手を表示し、終局後にAIのスコアと比べる
+        #[arg(long)]
+        hint: bool,
+        /// 端末の全画面に盤面と統計を描き続けながら遊ばせる
+        #[cfg(feature = "tui")]
+        #[arg(long)]
+        tui: bool,
+        /// `--tui` で1ターン進めるごとに待つ時間[ms]
+        #[cfg(feature = "tui")]
+        #[arg(long, default_value_t = 100)]
+        delay_ms: u64,
+    },
+    /// 1つのAIのスコアと思考時間を測る
+    Bench {
+        #[arg(long, default_value_t = NUM_GAME)]
+        games: usize,
+        #[arg(long, default_value_t = 0)]
+        rng_seed: u64,
+        /// 結果をメモリに溜める上限[MB] (超えたらディスクに書き出す)
+        #[arg(long)]
+        memory_cap_mb: Option<usize>,
+        #[command(flatten)]
+        policy: PolicyArgs,
+        #[command(flatten)]
+        threads: ThreadArgs,
+        #[command(flatten)]
+        record: RecordArgs,
+        #[command(flatten)]
+        game: GameArgs,
+        #[command(flatten)]
+        slo: SloArgs,
+        #[command(flatten)]
+        daemon: DaemonArgs,
+    },
+    /// 各探索が1msあたりに展開できる状態の数を測ってプロファイルに書き出す
+    Calibrate {
+        #[arg(long, default_value_t = 3)]
+        games: u64,
+        /// 測るときの1手の時間制限[ms]
+        #[arg(long, default_value_t = 10)]
+        ms: u128,
+        #[arg(long, default_value = "calibration.json")]
+        out: String,
+    },
+    /// 複数のAIを同じシード列で遊ばせて比べる
+    Compare {
+        /// AIの指定
+        specs: Vec<String>,
+        /// AIの式を並べたTOMLの設定ファイル
+        #[arg(long)]
+        config: Option<String>,
+        #[arg(long, default_value_t = NUM_GAME)]
+        games: usize,
+        #[arg(long, default_value_t = 0)]
+        rng_seed: u64,
+        /// 1つ目のAIとのシードごとのスコアの差を表示する
+        #[arg(long)]
+        per_seed: bool,
+        #[command(flatten)]
+        threads: ThreadArgs,
+        #[command(flatten)]
+        record: RecordArgs,
+        #[command(flatten)]
+        game: GameArgs,
+        #[command(flatten)]
+        slo: SloArgs,
+    },
+    /// 結果のJSONLに記録された行動列を再生して、記録どおりになるか確かめる
+    ///
+    /// `play --log` で書き出したJSONなら、各ターンの行動・評価値・思考時間と盤面を表示しながら再生する。
+    Replay {
+        path: String,
+        /// このシードのゲームだけを再生する
+        #[arg(long)]
+        seed: Option<u64>,
+        /// 各ターンの盤面を表示する
+        #[arg(long)]
+        boards: bool,
+        /// 盤面を1ターンずつ描き直して再生する
+        #[arg(long)]
+        animate: bool,
+        /// `--animate` の1秒あたりのフレーム数
+        #[arg(long, default_value_t = 10.)]
+        fps: f64,
+        /// `--animate` で実際には待たずに仮想の時刻を進め、各フレームの時刻を表示する
+        #[arg(long)]
+        virtual_clock: bool,
+        /// `--animate` で盤面をANSIの色付きで描く
+        #[arg(long)]
+        color: bool,
+    },
+    /// 古い版の結果のJSONLや `play --log` のゲームの記録を現在の版に書き換える
+    Migrate {
+        path: String,
+        /// 書き出す先 (省略したら `path` を置き換える)
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// 評価値のノイズの大きさごとにスコアの低下を測る
+    Noise {
+        specs: Vec<String>,
+        #[arg(long, value_delimiter = ',', default_value = "0,5,20,100")]
+        magnitudes: Vec<i64>,
+        #[arg(long, default_value_t = NUM_GAME)]
+        games: usize,
+        #[arg(long, default_value_t = 0)]
+        rng_seed: u64,
+        #[command(flatten)]
+        threads: ThreadArgs,
+        #[command(flatten)]
+        record: RecordArgs,
+        #[command(flatten)]
+        game: GameArgs,
+    },
+    /// ゲームの長さとビーム幅を変えながらスコアを測る
+    Sweep {
+        #[arg(long, value_delimiter = ',', default_values_t = [END_TURN])]
+        end_turns: Vec<usize>,
+        #[arg(long, value_delimiter = ',', default_value = "5")]
+        widths: Vec<usize>,
+        /// 1手の時間制限[ms]
+        #[arg(long)]
+        ms: Option<u128>,
+        #[arg(long, default_value_t = NUM_GAME)]
+        games: usize,
+        /// 前回の結果の最良のビーム幅の周りを細かく測る
+        #[arg(long)]
+        refine: Option<String>,
+        #[command(flatten)]
+        threads: ThreadArgs,
+        #[command(flatten)]
+        record: RecordArgs,
+    },
+    /// 学習用のデータセットを書き出す
+    Dataset {
+        #[arg(default_value = "dataset.jsonl")]
+        path: String,
+        #[arg(default_value_t = NUM_GAME)]
+        games: usize,
+    },
+    /// リプレイの軌跡を並べたSVGを書き出す
+    Strip {
+        out: String,
+        #[arg(required = true)]
+        results: Vec<String>,
+        #[arg(long, default_value_t = 5)]
+        columns: usize,
+    },
+    /// 対話モード
+    Repl {
+        /// このファイルが作られたら思考中の探索を打ち切り、その時点の最善手を使う
+        #[arg(long)]
+        kill_file: Option<String>,
+        /// SIGUSR1 を受け取ったら思考中の探索を打ち切る
+        #[arg(long)]
+        cancel_signal: bool,
+    },
+    /// 同じシードの2つのリプレイを比べる
+    ReplayDiff { a: String, b: String },
+    /// リプレイを横断した統計
+    Stats {
+        #[arg(long, default_value = ".")]
+        replays: String,
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// 実験のディレクトリの結果から1ファイルのレポートを作る (`--out` が `.md` ならMarkdown、それ以外はHTML)
+    Report {
+        /// 結果のJSONLとマニフェストを置いたディレクトリ
+        #[arg(long)]
+        from: String,
+        #[arg(long, default_value = "report.html")]
+        out: String,
+    },
+    /// シナリオのファイルを実行する
+    Scenario {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// 盤面とそのハッシュを表示する
+    Board {
+        #[arg(default_value_t = 0)]
+        seed: u64,
+        /// テキスト形式の盤面のファイル
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// 盤面の生成と乱数列が期待どおりかを確かめる
+    Selfcheck,
+    /// 長時間遊ばせて異常がないかを確かめる
+    Soak {
+        #[arg(long, default_value_t = 2.)]
+        hours: f64,
+        #[arg(long, default_value = "soak_report.txt")]
+        report: String,
+    },
+    /// 2人で交互に行動する迷路で深い探索と浅い探索を対戦させる
+    Alternate {
+        #[arg(long, default_value_t = 4)]
+        deep: usize,
+        #[arg(long, default_value_t = 1)]
+        shallow: usize,
+        #[arg(long, default_value_t = NUM_GAME as u64)]
+        games: u64,
+        /// alpha-beta か minimax
+        #[arg(long, default_value = "alpha-beta")]
+        algorithm: String,
+        /// alpha-beta の手の並べ替え (`all`, `none` か `pv,killers,history` の組み合わせ)。
+        /// 指定するとβカットの統計も表示する
+        #[arg(long)]
+        ordering: Option<MoveOrdering>,
+        /// 反復深化で1つ浅い探索の評価値から上下にこの幅の窓で読み直す
+        /// (窓の外に出た回数も表示する)
+        #[arg(long)]
+        aspiration: Option<i64>,
+        /// 深い方を深さ `deep` ではなく1手あたりこのミリ秒で読めるだけ読ませる
+        #[arg(long)]
+        ms: Option<u128>,
+    },
+    /// 壁のある迷路でビームサーチのスコアを測る
+    Wall {
+        #[arg(long, default_value_t = 5)]
+        width: usize,
+        #[arg(long, default_value_t = 5)]
+        depth: usize,
+        #[arg(long, default_value_t = NUM_GAME as u64)]
+        games: u64,
+        /// distance か score
+        #[arg(long, default_value = "distance")]
+        eval: String,
+    },
+    /// 基本の盤面にルールを重ねた迷路でビームサーチのスコアを測る
+    Rules {
+        /// 重ねるルール (`torus+decay:every=10,amount=1+walls` のように `+` でつなぐ)
+        #[arg(long)]
+        rules: String,
+        #[arg(long, default_value_t = 5)]
+        width: usize,
+        #[arg(long, default_value_t = 5)]
+        depth: usize,
+        #[arg(long, default_value_t = NUM_GAME as u64)]
+        games: u64,
+        /// 最初の盤面を表示する
+        #[arg(long)]
+        show: bool,
+    },
+    /// 霧のかかった迷路でスコアを測る
+    Fog {
+        /// 視界の半径
+        #[arg(long, default_value_t = 2)]
+        radius: i32,
+        /// frontier か ensemble
+        #[arg(long, default_value = "frontier")]
+        policy: String,
+        #[arg(long, default_value_t = 4)]
+        determinizations: usize,
+        #[arg(long, default_value_t = 16)]
+        particles: usize,
+        #[arg(long, default_value_t = 1)]
+        ms: u128,
+    },
+    /// 共有ライブラリの評価関数でビームサーチのスコアを測る
+    #[cfg(feature = "dylib")]
+    Evaluator {
+        path: String,
+        #[arg(default_value_t = NUM_GAME)]
+        games: usize,
+    },
+    /// 結果のデータベースを調べる
+    Query {
+        #[command(subcommand)]
+        query: Query,
+        #[arg(long, global = true, default_value = "results.sqlite")]
+        db: String,
+        #[arg(long, global = true)]
+        tag: Option<String>,
+    },
 }
 
-fn beam_search_action(state: &State, beam_width: usize, beam_depth: usize) -> usize {
-    let mut now_beam = BinaryHeap::new();
-    let mut best_state: Option<State> = None;
-
-    now_beam.push(state.clone());
+#[derive(Subcommand)]
+enum Query {
+    /// 実行の一覧
+    Runs,
+    /// 時間制限ごとの最良のAI
+    Best,
+    /// crateの版ごとのスコアの推移
+    Trend { policy: String },
+    /// 2つのAIの対戦成績
+    H2h { a: String, b: String },
+    /// `bench --daemon` が検出した有意なスコアの変化
+    Drifts,
+}
 
-    for t in 0..beam_depth {
-        let mut next_beam = BinaryHeap::new();
-        for _ in 0..beam_width {
-            if now_beam.is_empty() {
-                break;
-            }
-            let now_state = now_beam.pop().unwrap();
-            let legal_actions = now_state.legal_actions();
-            for action in legal_actions {
-                let mut next_state = now_state.clone();
-                next_state.advance(action);
-                next_state.evaluate_score();
-                if t == 0 {
-                    next_state.first_action = action;
-                }
-                next_beam.push(next_state);
+fn main() {
+    let cli = Cli::parse();
+    // サブコマンドを省略したら既定の設定で bench を実行する
+    let command = cli.command.unwrap_or_else(|| {
+        Cli::parse_from(env::args().chain(["bench".to_string()]))
+            .command
+            .unwrap()
+    });
+    let global = cli.global;
+    num_collect_maze::crash::install_panic_hook(&global.crash_dir);
+    let folded_stack_layer = FoldedStackLayer::default();
+    if global.trace_out.is_some() {
+        use tracing_subscriber::layer::SubscriberExt;
+        tracing::subscriber::set_global_default(
+            tracing_subscriber::registry().with(folded_stack_layer.clone()),
+        )
+        .unwrap();
+    }
+    if global.profile {
+        PROFILE_ENABLED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    if let Some(floor) = global.degraded_floor_us {
+        DEGRADED_MODE_FLOOR_US.store(floor, std::sync::atomic::Ordering::Relaxed);
+    }
+    if global.cpu_time {
+        Clock::set_default(Clock::ThreadCpu);
+    }
+    match command {
+        Command::Play {
+            seed,
+            policy,
+            rng_seed,
+            log,
+            color,
+            human,
+            hint,
+            #[cfg(feature = "tui")]
+            tui,
+            #[cfg(feature = "tui")]
+            delay_ms,
+        } => {
+            let spec = policy
+                .spec("chokudai:width=1,ms=1")
+                .unwrap_or_else(exit_usage);
+            let mut policy = parse_policy(&spec, rng_seed).unwrap_or_else(exit_usage);
+            if human {
+                let hint: Option<&mut dyn Policy> = if hint { Some(policy.as_mut()) } else { None };
+                let stdin = std::io::stdin();
+                play_human(seed, hint, stdin.lock(), std::io::stdout(), color).unwrap();
+                return;
+            }
+            #[cfg(feature = "tui")]
+            if tui {
+                let state = num_collect_maze::tui::play_tui(
+                    seed,
+                    policy.as_mut(),
+                    std::time::Duration::from_millis(delay_ms),
+                )
+                .unwrap();
+                println!("{}\tscore: {}", policy.name(), state.game_score);
+                return;
+            }
+            let game_log = play_game(seed, policy.as_mut(), color);
+            if let Some(path) = log {
+                write_game_log(&game_log, &path).unwrap();
             }
         }
-        now_beam = next_beam;
-        assert!(!now_beam.is_empty());
-        best_state = Some(now_beam.peek().unwrap().clone());
-        if best_state.clone().unwrap().is_done() {
-            break;
+        Command::Bench {
+            games,
+            rng_seed,
+            memory_cap_mb,
+            policy,
+            threads,
+            record,
+            game,
+            slo,
+            daemon,
+        } => {
+            let spec = policy.spec("beam:width=5,ms=10").unwrap_or_else(exit_usage);
+            parse_policy(&spec, 0).unwrap_or_else(exit_usage);
+            if daemon.daemon {
+                let specs = match &daemon.suite {
+                    Some(path) => PolicyConfig::load(path).unwrap_or_else(exit_usage).policies,
+                    None => vec![spec],
+                };
+                let config = DaemonConfig {
+                    specs,
+                    games,
+                    rng_seed,
+                    thread_config: threads.thread_config(),
+                    play_config: game.play_config(),
+                    alpha: daemon.drift_alpha,
+                    meta: record.run_meta(),
+                };
+                let interval = parse_interval(&daemon.interval).unwrap_or_else(exit_usage);
+                let mut db = ResultDb::open(record.db.as_ref().unwrap()).unwrap();
+                run_daemon(&mut db, &config, interval, daemon.cycles).unwrap();
+                return;
+            }
+            let run_meta = record.run_meta();
+            let memory_cap = memory_cap_mb.map(|mb| mb << 20);
+            let spill_path = record
+                .results
+                .clone()
+                .unwrap_or_else(|| "results_spill.jsonl".to_string());
+            let summary = test_ai_score(
+                &spec,
+                games,
+                rng_seed,
+                threads.thread_config(),
+                game.play_config(),
+                match &record.db {
+                    Some(path) => ResultSink::new(memory_cap, spill_path)
+                        .with_db(ResultDb::open(path).unwrap(), "bench", &run_meta)
+                        .unwrap(),
+                    None => ResultSink::new(memory_cap, spill_path),
+                },
+                record.results.is_some(),
+            );
+            if let Some(path) = &record.results {
+                write_manifest(path, "bench", &run_meta).unwrap();
+            }
+            if !check_latency_slo(&summary.latency.report(), &slo.slo()) {
+                std::process::exit(1);
+            }
         }
-    }
-    assert!(best_state.is_some());
-
-    best_state.unwrap().first_action
-}
-
-fn beam_search_action_with_time_threshold(
-    state: &State,
-    beam_width: usize,
-    time_threshold: u128,
-) -> usize {
-    let mut now_beam = BinaryHeap::new();
-    let mut best_state: Option<State> = None;
-    let time_keeper = TimeKeeper::new(time_threshold);
-
-    now_beam.push(state.clone());
-
-    for t in 0.. {
-        let mut next_beam = BinaryHeap::new();
-        for _ in 0..beam_width {
-            #[cfg(debug_assertions)]
+        Command::Compare {
+            mut specs,
+            config,
+            games,
+            rng_seed,
+            per_seed,
+            threads,
+            record,
+            game,
+            slo,
+        } => {
+            if let Some(path) = config {
+                specs.extend(
+                    PolicyConfig::load(&path)
+                        .unwrap_or_else(exit_usage)
+                        .policies,
+                );
+            }
+            if specs.is_empty() {
+                exit_usage::<()>("compare needs at least one policy (or --config)".to_string());
+            }
+            if games == 0 {
+                exit_usage::<()>("compare needs at least one game (--games 1 or more)".to_string());
+            }
+            check_specs(&specs);
+            let all_results = compare(
+                &specs,
+                games,
+                rng_seed,
+                threads.thread_config(),
+                game.play_config(),
+                per_seed,
+            )
+            .concat();
+            record.save("compare", &all_results);
+            if !check_latency_slo(&LatencyReport::new(&all_results), &slo.slo()) {
+                std::process::exit(1);
+            }
+        }
+        Command::Replay {
+            path,
+            seed,
+            boards,
+            animate,
+            fps,
+            virtual_clock,
+            color,
+        } => {
+            if let Some(game_log) =
+                read_game_log(&path).unwrap_or_else(|e| exit_usage(e.to_string()))
             {
-                // eprintln!(
-                //     "elapsed time: {}",
-                //     time_keeper.start_time.elapsed().as_micros()
-                // );
-            }
-            if time_keeper.is_over() {
-                return best_state.unwrap().first_action;
-            }
-            if now_beam.is_empty() {
-                break;
-            }
-            let now_state = now_beam.pop().unwrap();
-            let legal_actions = now_state.legal_actions();
-            for action in legal_actions {
-                let mut next_state = now_state.clone();
-                next_state.advance(action);
-                next_state.evaluate_score();
-                if t == 0 {
-                    next_state.first_action = action;
+                match replay_game_log(&game_log) {
+                    Ok(state) => println!(
+                        "seed {}\t{}\tscore: {}\tok",
+                        game_log.seed, game_log.policy, state.game_score
+                    ),
+                    Err(message) => {
+                        println!("{message}");
+                        std::process::exit(1);
+                    }
                 }
-                next_beam.push(next_state);
+                return;
+            }
+            let mut failed = 0;
+            for result in read_results_jsonl(&path).unwrap_or_else(|e| exit_usage(e.to_string())) {
+                if seed.is_some_and(|seed| seed != result.seed) {
+                    continue;
+                }
+                if animate {
+                    let config = AnimationConfig {
+                        fps,
+                        clear_screen: !virtual_clock,
+                        color,
+                    };
+                    let manual_clock = ManualClock::default();
+                    let wall_clock = WallFrameClock::default();
+                    let clock: &dyn FrameClock = if virtual_clock {
+                        &manual_clock
+                    } else {
+                        &wall_clock
+                    };
+                    match animate_game(&result, &config, clock, &mut std::io::stdout()) {
+                        Ok(frames) => {
+                            if virtual_clock {
+                                for frame in &frames {
+                                    println!("frame {}\tt={:?}", frame.turn, frame.shown);
+                                }
+                            }
+                            println!(
+                                "seed {}\t{}\tframes: {}\tlate: {}",
+                                result.seed,
+                                result.policy,
+                                frames.len(),
+                                frames.iter().filter(|f| f.late).count()
+                            );
+                        }
+                        Err(message) => {
+                            println!("{message}");
+                            failed += 1;
+                        }
+                    }
+                    continue;
+                }
+                match replay_game(&result, boards) {
+                    Ok(state) => println!(
+                        "seed {}\t{}\tscore: {}\tok",
+                        result.seed, result.policy, state.game_score
+                    ),
+                    Err(message) => {
+                        println!("{message}");
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                std::process::exit(1);
             }
         }
-        now_beam = next_beam;
-        assert!(!now_beam.is_empty());
-        best_state = Some(now_beam.peek().unwrap().clone());
-        if best_state.clone().unwrap().is_done() {
-            break;
+        Command::Calibrate { games, ms, out } => {
+            let profile = calibrate(games, ms);
+            for rate in &profile.rates {
+                println!(
+                    "{}\tnodes_per_ms:\t{:.1}\tms={ms} ~ nodes={}",
+                    rate.algorithm,
+                    rate.nodes_per_ms,
+                    profile.nodes_for_ms(&rate.algorithm, ms).unwrap_or(0)
+                );
+            }
+            profile.save(&out).unwrap();
+            println!("wrote {out}");
         }
-    }
-    assert!(best_state.is_some());
-
-    best_state.unwrap().first_action
-}
-
-fn chokudai_search_action(
-    state: &State,
-    beam_width: usize,
-    beam_depth: usize,
-    beam_num: usize,
-) -> usize {
-    let mut beams = vec![BinaryHeap::<State>::new(); beam_depth + 1];
-    beams[0].push(state.clone());
-
-    for _ in 0..beam_num {
-        for t in 0..beam_depth {
-            let (first, second) = beams.split_at_mut(t + 1);
-            let now_beam = &mut first[t];
-            let next_beam = &mut second[0];
-            for i in 0..beam_width {
-                if now_beam.is_empty() {
-                    break;
+        Command::Migrate { path, out } => {
+            let text = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| exit_usage(format!("{path}: {e}")));
+            let (migrated, report) =
+                migrate_text(&text, &path).unwrap_or_else(|e| exit_usage(e.to_string()));
+            let out = out.unwrap_or(path);
+            match migrated {
+                Migrated::Results(results) => write_results_jsonl(&results, &out).unwrap(),
+                Migrated::GameLog(log) => write_game_log(&log, &out).unwrap(),
+            }
+            println!(
+                "{}: migrated {} (already current: {}) -> {out}",
+                report.format, report.migrated, report.current
+            );
+        }
+        Command::Noise {
+            specs,
+            magnitudes,
+            games,
+            rng_seed,
+            threads,
+            record,
+            game,
+        } => {
+            check_specs(&specs);
+            if games == 0 {
+                exit_usage::<()>("noise needs at least one game (--games 1 or more)".to_string());
+            }
+            let all_results = noise_robustness(
+                &specs,
+                &magnitudes,
+                games,
+                rng_seed,
+                threads.thread_config(),
+                game.play_config(),
+            );
+            record.save("noise", &all_results);
+        }
+        Command::Sweep {
+            end_turns,
+            widths,
+            ms,
+            games,
+            refine,
+            threads,
+            record,
+        } => {
+            let thread_config = threads.thread_config();
+            let previous = match &refine {
+                Some(path) => {
+                    read_results_jsonl(path).unwrap_or_else(|e| exit_usage(e.to_string()))
                 }
-                let now_state = now_beam.peek().unwrap().clone();
-                if now_state.is_done() {
-                    break;
+                None => vec![],
+            };
+            let results = if refine.is_some() {
+                let time_threshold = ms.unwrap_or_else(|| {
+                    previous
+                        .iter()
+                        .find_map(|r| budget_ms_from_policy_name(&r.policy))
+                        .unwrap_or(10) as u128
+                });
+                let grid = refine_sweep_grid(&previous, 4);
+                if grid.is_empty() {
+                    println!("no widths left to refine");
                 }
-                now_beam.pop();
-                let legal_actions = now_state.legal_actions();
-                for action in legal_actions {
-                    let mut next_state = now_state.clone();
-                    next_state.advance(action);
-                    next_state.evaluate_score();
-                    if t == 0 {
-                        next_state.first_action = action;
-                    }
-                    #[cfg(debug_assertions)]
-                    {
-                        eprintln!("{next_state}");
+                let mut results = vec![];
+                for (end_turn, beam_widths) in grid {
+                    results.extend(sweep(
+                        &[end_turn],
+                        &beam_widths,
+                        time_threshold,
+                        games,
+                        thread_config,
+                    ));
+                }
+                results
+            } else {
+                sweep(&end_turns, &widths, ms.unwrap_or(10), games, thread_config)
+            };
+            let run_meta = record.run_meta();
+            if let Some(path) = &record.db {
+                ResultDb::open(path)
+                    .unwrap()
+                    .record("sweep", &run_meta, &results)
+                    .unwrap();
+            }
+            let mut merged = previous;
+            merged.extend(results);
+            if refine.is_some() {
+                // 前回の結果と合わせた表 (ゲームの長さごとの最良に * を付ける)
+                let table = sweep_table(&merged);
+                println!("end_turn\tbeam_width\tscore_mean\tgames");
+                for (&(end_turn, beam_width), &(sum, count)) in &table {
+                    let mean = |(sum, count): (usize, usize)| sum as f64 / count as f64;
+                    let best = table
+                        .range((end_turn, 0)..=(end_turn, usize::MAX))
+                        .all(|(_, &other)| mean(other) <= mean((sum, count)));
+                    println!(
+                        "{end_turn}\t{beam_width}\t{:.2}\t{count}{}",
+                        mean((sum, count)),
+                        if best { "\t*" } else { "" }
+                    );
+                }
+            }
+            if let Some(path) = record.results.or(refine) {
+                write_results_jsonl(&merged, &path).unwrap();
+                write_manifest(&path, "sweep", &run_meta).unwrap();
+            }
+        }
+        Command::Dataset { path, games } => {
+            let samples = generate_dataset(games, 5, 10);
+            if path.ends_with(".npz") {
+                write_dataset_npz(&samples, &path).unwrap();
+            } else {
+                write_dataset_jsonl(&samples, &path).unwrap();
+            }
+            println!("wrote {} samples to {path}", samples.len());
+        }
+        Command::Strip {
+            out,
+            results: paths,
+            columns,
+        } => {
+            let mut results = vec![];
+            for path in &paths {
+                results
+                    .extend(read_results_jsonl(path).unwrap_or_else(|e| exit_usage(e.to_string())));
+            }
+            std::fs::write(&out, render_trajectory_strip(&results, columns)).unwrap();
+        }
+        Command::Repl {
+            kill_file,
+            cancel_signal,
+        } => {
+            let mut repl = Repl::default();
+            if let Some(path) = kill_file {
+                repl.cancel_token = repl.cancel_token.with_kill_file(path);
+            }
+            if cancel_signal {
+                repl.cancel_token = repl.cancel_token.with_signal();
+            }
+            let stdin = std::io::stdin();
+            run_repl(repl, stdin.lock(), std::io::stdout()).unwrap();
+        }
+        Command::ReplayDiff { a, b } => {
+            let a = read_results_jsonl(&a).unwrap_or_else(|e| exit_usage(e.to_string()));
+            let b = read_results_jsonl(&b).unwrap_or_else(|e| exit_usage(e.to_string()));
+            for result_a in &a {
+                if let Some(result_b) = b.iter().find(|r| r.seed == result_a.seed) {
+                    print!("{}", replay_diff(result_a, result_b));
+                }
+            }
+        }
+        Command::Report { from, out } => {
+            let report = ExperimentReport::from_dir(&from).unwrap();
+            let text = if out.ends_with(".md") {
+                report.to_markdown()
+            } else {
+                report.to_html()
+            };
+            std::fs::write(&out, text).unwrap();
+            println!(
+                "{out}: {} runs, {} games",
+                report.runs.len(),
+                report.results().count()
+            );
+        }
+        Command::Stats { replays, tag } => {
+            print!(
+                "{}",
+                ReplayStats::from_dir(&replays, tag.as_deref()).unwrap()
+            );
+        }
+        Command::Scenario { paths } => {
+            let mut failed = 0;
+            for path in &paths {
+                let result = std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| Scenario::parse(&text))
+                    .and_then(|scenario| scenario.run());
+                match result {
+                    Ok(()) => println!("PASS {path}"),
+                    Err(message) => {
+                        println!("FAIL {path}: {message}");
+                        failed += 1;
                     }
-                    next_beam.push(next_state);
                 }
             }
+            if failed > 0 {
+                std::process::exit(1);
+            }
         }
-    }
-
-    for t in (0..=beam_depth).rev() {
-        if !beams[t].is_empty() {
-            return beams[t].peek().unwrap().first_action;
+        Command::Board { seed, file } => {
+            let state = match file {
+                Some(path) => {
+                    MazeState::from_text(&std::fs::read_to_string(path).unwrap()).unwrap()
+                }
+                None => MazeState::new(seed),
+            };
+            print!("{state}");
+            println!("instance_hash:\t{}", state.instance_hash());
         }
-    }
-
-    unreachable!()
-}
-
-fn chokudai_search_action_with_time_threshold(
-    state: &State,
-    beam_width: usize,
-    beam_depth: usize,
-    time_threshold: u128,
-) -> usize {
-    let time_keeper = TimeKeeper::new(time_threshold);
-    let mut beams = vec![BinaryHeap::<State>::new(); beam_depth + 1];
-    beams[0].push(state.clone());
-
-    for _ in 0.. {
-        for t in 0..beam_depth {
-            let (first, second) = beams.split_at_mut(t + 1);
-            let now_beam = &mut first[t];
-            let next_beam = &mut second[0];
-            for i in 0..beam_width {
-                if now_beam.is_empty() {
-                    break;
+        Command::Selfcheck => match self_check() {
+            Ok(()) => println!("ok (instance format version {INSTANCE_FORMAT_VERSION})"),
+            Err(message) => {
+                eprintln!("{message}");
+                std::process::exit(1);
+            }
+        },
+        Command::Soak { hours, report } => {
+            soak(hours, &report).unwrap();
+            println!("{}", std::fs::read_to_string(&report).unwrap());
+        }
+        Command::Alternate {
+            deep,
+            shallow,
+            games,
+            algorithm,
+            ordering,
+            aspiration,
+            ms,
+        } => {
+            let ordered = (ordering.is_some() || aspiration.is_some() || ms.is_some()).then(|| {
+                let mut ordered = OrderedAlphaBeta::new(ordering.unwrap_or(MoveOrdering::NONE));
+                ordered.aspiration = aspiration;
+                RefCell::new(ordered)
+            });
+            let search =
+                |state: &AlternateMazeState, depth: usize| match (&ordered, algorithm.as_str()) {
+                    (_, "minimax") => minimax_action(state, depth),
+                    (Some(ordered), _) => ordered.borrow_mut().search(state, depth),
+                    (None, _) => alpha_beta_action(state, depth),
+                };
+            let deep_ai = |state: &AlternateMazeState| match (&ordered, ms) {
+                (Some(ordered), Some(ms)) => {
+                    ordered.borrow_mut().search_with_time_threshold(state, ms)
                 }
-                let now_state = now_beam.peek().unwrap().clone();
-                if now_state.is_done() {
-                    break;
+                _ => search(state, deep),
+            };
+            let shallow_ai = |state: &AlternateMazeState| search(state, shallow);
+            // [深い方の勝ち, 浅い方の勝ち, 引き分け]
+            let mut total = [0; 3];
+            println!("first\tsecond\tfirst_win\tsecond_win\tdraw");
+            let deep_name = match ms {
+                Some(ms) => format!("{algorithm}(ms={ms})"),
+                None => format!("{algorithm}(depth={deep})"),
+            };
+            let shallow_name = format!("{algorithm}(depth={shallow})");
+            for (first_name, second_name, deep_first) in [
+                (&deep_name, &shallow_name, true),
+                (&shallow_name, &deep_name, false),
+            ] {
+                let mut counts = [0; 3];
+                for seed in 0..games {
+                    let status = if deep_first {
+                        self_play(seed, &deep_ai, &shallow_ai)
+                    } else {
+                        self_play(seed, &shallow_ai, &deep_ai)
+                    };
+                    counts[match status {
+                        WinningStatus::Win => 0,
+                        WinningStatus::Lose => 1,
+                        WinningStatus::Draw => 2,
+                    }] += 1;
                 }
-                now_beam.pop();
-                let legal_actions = now_state.legal_actions();
-                for action in legal_actions {
-                    let mut next_state = now_state.clone();
-                    next_state.advance(action);
-                    next_state.evaluate_score();
-                    if t == 0 {
-                        next_state.first_action = action;
-                    }
-                    #[cfg(debug_assertions)]
-                    {
-                        // eprintln!("{next_state}");
-                    }
-                    next_beam.push(next_state);
+                println!(
+                    "{first_name}\t{second_name}\t{}\t{}\t{}",
+                    counts[0], counts[1], counts[2]
+                );
+                let (deep_wins, shallow_wins) = if deep_first {
+                    (counts[0], counts[1])
+                } else {
+                    (counts[1], counts[0])
+                };
+                total[0] += deep_wins;
+                total[1] += shallow_wins;
+                total[2] += counts[2];
+            }
+            let games = (2 * games) as f64;
+            println!("deep_win_rate: {}", total[0] as f64 / games);
+            println!("shallow_win_rate: {}", total[1] as f64 / games);
+            println!("draw_rate: {}", total[2] as f64 / games);
+            if let Some(ordered) = &ordered {
+                let stats = ordered.borrow().stats;
+                println!("nodes: {}", stats.nodes);
+                println!("cutoff_rate: {:.3}", stats.cutoff_rate());
+                println!(
+                    "first_move_cutoff_rate: {:.3}",
+                    stats.first_move_cutoff_rate()
+                );
+                println!("fail_high: {}", stats.fail_highs);
+                println!("fail_low: {}", stats.fail_lows);
+                if ms.is_some() {
+                    println!("average_depth: {:.2}", stats.average_depth());
                 }
             }
         }
-        if time_keeper.is_over() {
-            break;
+        Command::Wall {
+            width,
+            depth,
+            games,
+            eval,
+        } => {
+            let mut score_mean = 0.;
+            for seed in 0..games {
+                let mut state = WallMazeState::new(seed);
+                state.distance_evaluation = eval == "distance";
+                while !state.is_done() {
+                    let Ok(action) = beam_search_action(&state, width, depth) else {
+                        break;
+                    };
+                    state.advance(action);
+                }
+                score_mean += state.game_score.0 as f64;
+            }
+            println!("score_mean: {}", score_mean / games as f64);
         }
-    }
-
-    for t in (0..=beam_depth).rev() {
-        if !beams[t].is_empty() {
-            return beams[t].peek().unwrap().first_action;
+        Command::Rules {
+            rules,
+            width,
+            depth,
+            games,
+            show,
+        } => {
+            let mut score_mean = 0.;
+            for seed in 0..games {
+                let mut state = RuledState::new(seed, &rules, &GameConfig::default())
+                    .unwrap_or_else(exit_usage);
+                if show && seed == 0 {
+                    println!("rules: {}", state.rule_names());
+                    print!("{state}");
+                }
+                // 進めるマスがなくなったら (`NoLegalAction`) そこで終わる
+                while !state.is_done() {
+                    let Ok(action) = beam_search_action(&state, width, depth) else {
+                        break;
+                    };
+                    state.advance(action);
+                }
+                score_mean += state.board.game_score.0 as f64;
+            }
+            println!("score_mean: {}", score_mean / games as f64);
         }
-    }
-
-    unreachable!()
-}
-fn play_game(seed: u64) {
-    let mut state = State::new(seed);
-    println!("{}", state);
-    while !state.is_done() {
-        state.advance(chokudai_search_action_with_time_threshold(
-            &state, 1, END_TURN, 1,
-        ));
-        #[cfg(debug_assertions)]
-        {
-            println!("action determined.");
-            println!("NEXT STATE:");
-            println!("{}", state);
+        Command::Fog {
+            radius,
+            policy,
+            determinizations,
+            particles,
+            ms,
+        } => {
+            let mut score_mean = 0.;
+            for seed in 0..NUM_GAME {
+                let mut rng = ChaCha12Rng::seed_from_u64(seed as u64);
+                let mut fog = FogMazeState::new(seed as u64, radius);
+                let mut belief = fog_belief(&fog, particles, &mut rng);
+                while !fog.is_done() {
+                    let action = match policy.as_str() {
+                        "ensemble" => {
+                            determinized_ensemble_action(&belief, determinizations, 5, ms, &mut rng)
+                        }
+                        _ => frontier_exploration_action(&fog),
+                    };
+                    fog.advance(action);
+                    belief.update(|particle| condition_on_observation(particle, &fog));
+                }
+                score_mean += fog.state.game_score.0 as f64;
+            }
+            score_mean /= NUM_GAME as f64;
+            println!("score_mean: {score_mean}");
+        }
+        #[cfg(feature = "dylib")]
+        Command::Evaluator { path, games } => {
+            use num_collect_maze::{
+                game::State,
+                search::{beam_search_action_with_evaluator, DylibEvaluator},
+            };
+            let evaluator = std::sync::Arc::new(DylibEvaluator::load(&path).unwrap());
+            let mut score_mean = 0.;
+            for seed in 0..games {
+                let mut state = State::new(seed as u64);
+                while !state.is_done() {
+                    let Ok(action) =
+                        beam_search_action_with_evaluator(&state, 5, 10, evaluator.clone())
+                    else {
+                        break;
+                    };
+                    state.advance(action);
+                }
+                score_mean += state.game_score.0 as f64;
+            }
+            score_mean /= games as f64;
+            println!("score_mean: {score_mean}");
+        }
+        Command::Query { query, db, tag } => {
+            let db = ResultDb::open(&db).unwrap();
+            let tag = tag.as_deref();
+            match query {
+                Query::Runs => {
+                    println!("id\tkind\tcrate_version\tcreated_at\tgames\ttags\tnote");
+                    for run in db.runs(tag).unwrap() {
+                        println!(
+                            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                            run.id,
+                            run.kind,
+                            run.crate_version,
+                            run.created_at,
+                            run.games,
+                            run.tags.join(","),
+                            run.note.unwrap_or_default()
+                        );
+                    }
+                }
+                Query::Best => {
+                    println!("budget_ms\tpolicy\tscore_mean\tgames");
+                    for best in db.best_per_budget(tag).unwrap() {
+                        let budget = best.budget_ms.map_or("-".to_string(), |ms| ms.to_string());
+                        println!(
+                            "{budget}\t{}\t{:.2}\t{}",
+                            best.policy, best.score_mean, best.games
+                        );
+                    }
+                }
+                Query::Trend { policy } => {
+                    println!("crate_version\tscore_mean\tgames");
+                    for point in db.score_trend(&policy, tag).unwrap() {
+                        println!(
+                            "{}\t{:.2}\t{}",
+                            point.crate_version, point.score_mean, point.games
+                        );
+                    }
+                }
+                Query::H2h { a, b } => {
+                    let h2h = db.head_to_head(&a, &b, tag).unwrap();
+                    println!(
+                        "{a} vs {b}: {} wins, {} losses, {} draws",
+                        h2h.wins, h2h.losses, h2h.draws
+                    );
+                }
+                Query::Drifts => {
+                    println!("run_id\tprevious_run_id\tpolicy\tprevious_mean\tscore_mean\tp_value");
+                    for drift in db.drifts().unwrap() {
+                        println!(
+                            "{}\t{}\t{}\t{:.2}\t{:.2}\t{:.4}",
+                            drift.run_id,
+                            drift.previous_run_id,
+                            drift.policy,
+                            drift.previous_mean,
+                            drift.score_mean,
+                            drift.p_value
+                        );
+                    }
+                }
+            }
         }
     }
+    if let Some(path) = global.trace_out {
+        folded_stack_layer.write(&path).unwrap();
+    }
 }
 
-fn test_ai_score(num: usize) {
-    let mut rng = ChaCha12Rng::seed_from_u64(0);
-    let mut score_mean = 0.;
-
-    for seed in 0..num {
-        let mut state = State::new(seed as u64);
-        while !state.is_done() {
-            // state.advance(chokudai_search_action_with_time_threshold(
-            //     &state, 2, END_TURN, 10,
-            // ));
-            state.advance(beam_search_action_with_time_threshold(&state, 5, 10));
-        }
-        score_mean += state.game_score as f64;
+/// 使い方の誤りを表示して終了コード2で終了する
+/// AIの指定を全て読んでみて、読めないものがあれば使い方の誤りとして終わる
+fn check_specs(specs: &[String]) {
+    for spec in specs {
+        parse_policy(spec, 0).unwrap_or_else(exit_usage);
     }
-
-    score_mean /= num as f64;
-    println!("score_mean: {score_mean}")
 }
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
-    let seed = if args.len() > 1 {
-        args[1].parse().unwrap()
-    } else {
-        0
-    };
-    // play_game(seed)
-    test_ai_score(NUM_GAME);
+fn exit_usage<T>(message: impl std::fmt::Display) -> T {
+    eprintln!("{message}");
+    std::process::exit(2);
 }
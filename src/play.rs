@@ -0,0 +1,683 @@
+//! ゲームの実行とAIのスコア計測
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Instant,
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::{
+    crash,
+    db::policy_param,
+    game::{Direction, GameConfig, GameState, State, ACTION_NAMES, INSTANCE_FORMAT_VERSION},
+    policy::{derive_policy_seed, parse_policy, Policy, TimedBeamSearchPolicy},
+    results::{board_rows, GameLog, GameResult, ResultSink, RunSummary, TurnLog},
+    runner::{TurnControl, TurnStats},
+    schema::{GAME_LOG_SCHEMA_VERSION, RESULTS_SCHEMA_VERSION},
+    search::{endgame_solver_action, SearchStats, StopRule},
+    stats::{wilcoxon_signed_rank, RunningStats},
+    util::{
+        reproducibility_token, run_games_parallel, run_games_streaming, take_phase_times,
+        GameClock, ThreadConfig, PHASE_NAMES, PROFILE_ENABLED,
+    },
+    warm::with_transposition_table,
+};
+
+/// 盤面 `seed` で `policy` に1ゲーム遊ばせ、各ターンの盤面を表示する (`color` ならANSIの色付きで)
+pub fn play_game(seed: u64, policy: &mut dyn Policy, color: bool) -> GameLog {
+    let mut state = State::new(seed);
+    let _crash_guard = crash::enter_game(seed, policy.name(), &GameConfig::default(), &state);
+    let mut actions = vec![];
+    let mut log = GameLog {
+        schema_version: GAME_LOG_SCHEMA_VERSION,
+        seed,
+        instance_hash: state.instance_hash(),
+        policy: policy.name(),
+        game_score: 0,
+        initial_board: board_rows(&state),
+        turns: vec![],
+    };
+    let mut log_turn = |state: &State, action: Direction, elapsed_us: u64| {
+        let mut evaluated = state.clone();
+        evaluated.evaluate();
+        log.turns.push(TurnLog {
+            turn: state.turn - 1,
+            action: action.name().to_string(),
+            game_score: state.game_score.as_usize(),
+            evaluated_score: evaluated.evaluated_score.0,
+            elapsed_us,
+            board: board_rows(state),
+        });
+    };
+    let show = |state: &State, last_action: Option<Direction>| {
+        if color {
+            println!("{}", state.render_color(last_action));
+        } else {
+            println!("{}", state);
+        }
+    };
+    show(&state, None);
+    while !state.is_done() {
+        if state.future_gain_bound() == 0 {
+            // これ以上得点できないので探索せずに最後まで進める
+            println!("fast-forward from turn {}", state.turn);
+            while !state.is_done() {
+                let action = state.legal_actions()[0];
+                state.advance(action);
+                log_turn(&state, action, 0);
+            }
+            break;
+        }
+        let prev_state = state.clone();
+        crash::note_turn(&state, &actions, policy.search_stats());
+        let start = Instant::now();
+        let action = policy.select_action(&state);
+        let elapsed_us = start.elapsed().as_micros() as u64;
+        actions.push(action.index());
+        state.advance(action);
+        log_turn(&state, action, elapsed_us);
+        println!("action determined: {}", prev_state.diff(&state));
+        show(&state, Some(action));
+    }
+    println!("{}\tscore: {}", policy.name(), state.game_score);
+    log.game_score = state.game_score.as_usize();
+    log
+}
+
+/// `play --log` の記録を1ターンずつ表示しながら再生し、盤面とスコアが記録どおりになるかを確かめる
+pub fn replay_game_log(log: &GameLog) -> Result<State, String> {
+    let mut state = State::new(log.seed);
+    if state.instance_hash() != log.instance_hash {
+        return Err(format!(
+            "seed {}: instance hash mismatch ({} vs recorded {})",
+            log.seed,
+            state.instance_hash(),
+            log.instance_hash
+        ));
+    }
+    println!("{state}");
+    for turn in &log.turns {
+        let action = ACTION_NAMES
+            .iter()
+            .position(|&name| name == turn.action)
+            .map(Direction::from)
+            .ok_or(format!(
+                "seed {}: unknown action {:?}",
+                log.seed, turn.action
+            ))?;
+        if !state.legal_actions().contains(&action) {
+            return Err(format!(
+                "seed {}: illegal action {} at turn {}",
+                log.seed, turn.action, turn.turn
+            ));
+        }
+        state.advance(action);
+        println!(
+            "turn {}\taction: {}\tscore: {}\teval: {}\telapsed: {}us",
+            turn.turn, turn.action, turn.game_score, turn.evaluated_score, turn.elapsed_us
+        );
+        println!("{state}");
+        if state.game_score.as_usize() != turn.game_score || board_rows(&state) != turn.board {
+            return Err(format!(
+                "seed {}: replayed board differs from the log at turn {}",
+                log.seed, turn.turn
+            ));
+        }
+    }
+    Ok(state)
+}
+
+/// 記録された行動列を盤面 `result.seed` で再生し、記録と同じ盤面・スコアになるかを確かめる
+///
+/// `show_boards` なら各ターンの盤面を表示する。
+pub fn replay_game(result: &GameResult, show_boards: bool) -> Result<State, String> {
+    let mut state = State::new(result.seed);
+    if state.instance_hash() != result.instance_hash {
+        return Err(format!(
+            "seed {}: instance hash mismatch ({} vs recorded {})",
+            result.seed,
+            state.instance_hash(),
+            result.instance_hash
+        ));
+    }
+    if show_boards {
+        println!("{state}");
+    }
+    for (turn, &action) in result.actions.iter().enumerate() {
+        let action = Direction::from(action);
+        if !state.legal_actions().contains(&action) {
+            return Err(format!(
+                "seed {}: illegal action {} at turn {}",
+                result.seed,
+                action.name(),
+                state.turn
+            ));
+        }
+        state.advance(action);
+        if show_boards {
+            if let Some(evaluator) = result.evaluators.get(turn).filter(|e| !e.is_empty()) {
+                println!("evaluator:\t{evaluator}");
+            }
+            println!("{state}");
+        }
+    }
+    if state.game_score.as_usize() != result.game_score {
+        return Err(format!(
+            "seed {}: replayed score {} differs from recorded {}",
+            result.seed, state.game_score, result.game_score
+        ));
+    }
+    Ok(state)
+}
+
+/// ゲームの進め方の設定
+#[derive(Clone, Default)]
+pub struct PlayConfig {
+    /// ゲーム開始前に捨ての探索を1回行う
+    pub warm_up: bool,
+    /// 最初の1手だけに使う時間制限[ms]
+    pub first_move_time_threshold: Option<u128>,
+    /// これ以上得点できなくなったら探索せずに最後まで進める
+    pub fast_forward: bool,
+    /// 残りターン数がこれ以下になったらAIの代わりに終盤の全探索で行動を決める
+    pub endgame_turns: Option<usize>,
+    /// 確率的なAIが手を引いた分布を結果に残す
+    pub record_action_probs: bool,
+    /// 終盤の全探索の置換表を他のゲームと共有せず、ゲームごとに空の表から始める
+    pub isolate_caches: bool,
+    /// 時間制限付きのAIが使わなかった時間を `GameClock` に貯め、次の手の時間制限に上乗せする
+    pub bank_time: bool,
+    pub game: GameConfig,
+}
+
+/// 指定したAIで1ゲーム遊ぶ
+pub fn run_game(seed: u64, policy: &mut dyn Policy, config: &PlayConfig) -> GameResult {
+    play_out_with_caches(seed, policy, config, &mut |_, _| TurnControl::Continue)
+        .expect("no action is injected")
+}
+
+/// `config.isolate_caches` なら空の置換表で `play_out` する
+pub(crate) fn play_out_with_caches(
+    seed: u64,
+    policy: &mut dyn Policy,
+    config: &PlayConfig,
+    on_turn: &mut dyn FnMut(&State, &TurnStats) -> TurnControl,
+) -> Result<GameResult, String> {
+    if config.isolate_caches {
+        with_transposition_table(Default::default(), || {
+            play_out(seed, policy, config, on_turn)
+        })
+    } else {
+        play_out(seed, policy, config, on_turn)
+    }
+}
+
+/// 1ゲーム遊ぶ。毎ターン手を決める前に `on_turn` を呼び、手を差し込んだり途中で終えたりする
+///
+/// 差し込まれた手が合法でなければエラーを返す。
+fn play_out(
+    seed: u64,
+    policy: &mut dyn Policy,
+    config: &PlayConfig,
+    on_turn: &mut dyn FnMut(&State, &TurnStats) -> TurnControl,
+) -> Result<GameResult, String> {
+    let mut state = State::with_config(seed, &config.game);
+    // 捨ての探索でpanicしても書き出せるように、その前にゲームに入る
+    let _crash_guard = crash::enter_game(seed, policy.name(), &config.game, &state);
+    if config.warm_up {
+        policy.warm_up(&state);
+    }
+    let instance_hash = state.instance_hash();
+    let mut actions = vec![];
+    let mut action_probs = vec![];
+    let mut evaluators = vec![];
+    let mut move_times_us = vec![];
+    let mut stats = TurnStats::default();
+    let profile = PROFILE_ENABLED.load(std::sync::atomic::Ordering::Relaxed);
+    let mut phase_total = [std::time::Duration::ZERO; 4];
+    let mut phase_max = [std::time::Duration::ZERO; 4];
+    take_phase_times();
+    let _span = tracing::info_span!("run_game").entered();
+    let mut fast_forwarded_from = None;
+    let mut endgame_from = None;
+    let mut clock = policy
+        .time_threshold_us()
+        .filter(|_| config.bank_time)
+        .map(GameClock::new);
+    while !state.is_done() {
+        let injected = match on_turn(&state, &stats) {
+            TurnControl::Continue => None,
+            TurnControl::Stop => break,
+            TurnControl::Play(action) if state.legal_actions().contains(&action) => Some(action),
+            TurnControl::Play(action) => {
+                return Err(format!(
+                    "seed {seed}: illegal injected action {} at turn {}",
+                    action.name(),
+                    state.turn
+                ))
+            }
+        };
+        if config.fast_forward && fast_forwarded_from.is_none() && state.future_gain_bound() == 0 {
+            fast_forwarded_from = Some(state.turn);
+        }
+        let fast_forward = injected.is_none() && fast_forwarded_from.is_some();
+        if let Some(action) = injected.or(fast_forward.then(|| state.legal_actions()[0])) {
+            actions.push(action.index());
+            if config.record_action_probs {
+                action_probs.push(None);
+            }
+            evaluators.push(String::new());
+            move_times_us.push(0);
+            let score_before = state.game_score;
+            state.advance(action);
+            stats.injected_turns += usize::from(!fast_forward);
+            stats.record(action, 0, state.game_score == score_before);
+            continue;
+        }
+        crash::note_turn(&state, &actions, policy.search_stats());
+        let default_time_threshold = policy.time_threshold_us();
+        if let (0, Some(first_move)) = (state.turn, config.first_move_time_threshold) {
+            policy.set_time_threshold_us(first_move * 1000);
+        } else if let Some(clock) = &clock {
+            policy.set_time_threshold_us(clock.allowance_us());
+        }
+        let move_time_threshold = policy.time_threshold_us();
+        let move_start = Instant::now();
+        if endgame_from.is_none()
+            && config
+                .endgame_turns
+                .is_some_and(|turns| state.remaining_turns() <= turns)
+        {
+            endgame_from = Some(state.turn);
+        }
+        let (action, probabilities) = if endgame_from.is_some() {
+            evaluators.push(String::new());
+            // 全探索が手を決められなければAIに任せる
+            let action =
+                endgame_solver_action(&state).unwrap_or_else(|_| policy.select_action(&state));
+            (action, None)
+        } else {
+            let _span = tracing::info_span!("select_action").entered();
+            let sampled = policy.sample_action(&state);
+            evaluators.push(policy.active_evaluator().unwrap_or_default());
+            sampled
+        };
+        if config.record_action_probs {
+            action_probs.push(probabilities);
+        }
+        let elapsed = move_start.elapsed();
+        move_times_us.push(elapsed.as_micros() as u64);
+        if let Some(clock) = &mut clock {
+            clock.record(elapsed.as_micros());
+        }
+        if move_time_threshold.is_some_and(|t| elapsed.as_micros() > t) {
+            stats.deadline_overruns += 1;
+        }
+        if let Some(time_threshold) = default_time_threshold {
+            policy.set_time_threshold_us(time_threshold);
+        }
+        actions.push(action.index());
+        let score_before = state.game_score;
+        state.advance(action);
+        stats.record(
+            action,
+            elapsed.as_micros() as u64,
+            state.game_score == score_before,
+        );
+        if profile {
+            for (phase, elapsed) in take_phase_times().into_iter().enumerate() {
+                phase_total[phase] += elapsed;
+                phase_max[phase] = phase_max[phase].max(elapsed);
+            }
+        }
+    }
+    if profile {
+        let mut line = format!("profile seed={seed}");
+        for phase in 0..4 {
+            line.push_str(&format!(
+                " {}={}us(max/move {}us)",
+                PHASE_NAMES[phase],
+                phase_total[phase].as_micros(),
+                phase_max[phase].as_micros()
+            ));
+        }
+        eprintln!("{line}");
+    }
+    if evaluators.iter().all(String::is_empty) {
+        evaluators.clear();
+    }
+    Ok(GameResult {
+        schema_version: RESULTS_SCHEMA_VERSION,
+        instance_format_version: INSTANCE_FORMAT_VERSION,
+        seed,
+        instance_hash,
+        policy: policy.name(),
+        game_score: state.game_score.as_usize(),
+        wasted_turns: stats.wasted_turns,
+        uncollected_points: state.remaining_sum(),
+        token: reproducibility_token(&actions),
+        actions,
+        action_probs,
+        evaluators,
+        move_times_us,
+        fast_forwarded_from,
+        endgame_from,
+        deadline_overruns: stats.deadline_overruns,
+        search_stats: policy.search_stats(),
+    })
+}
+
+/// `spec` のAIで `num` ゲーム遊ばせ、スコアや思考時間を集計する
+pub fn test_ai_score(
+    spec: &str,
+    num: usize,
+    base_seed: u64,
+    thread_config: ThreadConfig,
+    play_config: PlayConfig,
+    mut sink: ResultSink,
+    write_results: bool,
+) -> RunSummary {
+    let bar = progress_bar(num);
+    run_games_streaming(
+        num,
+        thread_config,
+        |seed| {
+            let rng_seed = derive_policy_seed(base_seed, 0, seed);
+            let mut policy = parse_policy(spec, rng_seed).unwrap();
+            run_game(seed, policy.as_mut(), &play_config)
+        },
+        |result| {
+            sink.push(result).unwrap();
+            bar.set_message(format!("score_mean: {:.2}", sink.summary.score.mean));
+            bar.inc(1);
+        },
+    );
+    bar.finish_and_clear();
+    if write_results {
+        sink.flush().unwrap();
+    }
+
+    let mut summary = sink.summary;
+    let num = summary.num as f64;
+    println!("score_mean: {:.2}", summary.score.mean);
+    println!("score_std: {:.2}", summary.score.std_dev());
+    let ci = summary.score.ci95_half_width();
+    println!(
+        "score_ci95: {:.2}..{:.2} (±{ci:.2})",
+        summary.score.mean - ci,
+        summary.score.mean + ci
+    );
+    println!(
+        "score_quantiles: min={} p5={:.1} p50={:.1} p95={:.1} max={}",
+        summary.score.min,
+        summary.score_quantiles.quantile(0.05),
+        summary.score_quantiles.quantile(0.5),
+        summary.score_quantiles.quantile(0.95),
+        summary.score.max
+    );
+    println!(
+        "first_move_us_mean: {:.1}",
+        summary.first_move_us_sum as f64 / num
+    );
+    println!(
+        "move_us_mean: {:.1}",
+        summary.move_us_sum as f64 / summary.latency.total as f64
+    );
+    println!(
+        "wasted_turns_mean: {}",
+        summary.wasted_turns_sum as f64 / num
+    );
+    println!(
+        "uncollected_points_mean: {}",
+        summary.uncollected_points_sum as f64 / num
+    );
+    if let Some(search_stats) = &summary.search_stats {
+        println!(
+            "nodes_expanded_per_move: {:.1}",
+            search_stats.nodes_per_search()
+        );
+        println!(
+            "states_cloned_per_move: {:.1}",
+            search_stats.clones_per_search()
+        );
+        println!("max_depth_reached: {}", search_stats.max_depth_reached);
+        if search_stats.early_stops > 0 {
+            println!(
+                "early_stop_rate: {:.3}",
+                search_stats.early_stops as f64 / search_stats.searches.max(1) as f64
+            );
+        }
+        if search_stats.eval_cache_lookups > 0 {
+            println!(
+                "eval_cache_hit_rate: {:.3}",
+                search_stats.eval_cache_hit_rate()
+            );
+        }
+    }
+    println!("token: {:016x}", summary.token);
+    if summary.spilled > 0 {
+        println!("spilled {} results to disk", summary.spilled);
+    }
+    summary
+}
+
+/// `num` ゲームの進み具合・残り時間の見込み・それまでのスコアの平均を標準エラー出力に描くバー
+///
+/// 標準エラー出力が端末でなければ何も描かない。
+pub fn progress_bar(num: usize) -> ProgressBar {
+    let bar = ProgressBar::with_draw_target(Some(num as u64), ProgressDrawTarget::stderr());
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{bar:40} {pos}/{len} [{elapsed_precise}<{eta_precise}] {msg}",
+        )
+        .unwrap(),
+    );
+    bar
+}
+
+/// 同じシード列で遊んだ `baseline` と `other` のスコアを、シードごとの差の検定で比べる
+///
+/// 差の平均とその95%信頼区間、勝ち・負け・引き分けの数、Wilcoxonの符号付き順位検定のp値を表示する。
+/// `per_seed` ならシードごとのスコアの差も表示する。`baseline` と `other` は空でないこと。
+pub fn paired_comparison(baseline: &[GameResult], other: &[GameResult], per_seed: bool) {
+    let mut differences = vec![];
+    let mut stats = RunningStats::default();
+    let (mut wins, mut losses, mut draws) = (0, 0, 0);
+    if per_seed {
+        println!("seed	{}	{}	diff", baseline[0].policy, other[0].policy);
+    }
+    for (a, b) in baseline.iter().zip(other) {
+        debug_assert_eq!(a.seed, b.seed);
+        let diff = b.game_score as f64 - a.game_score as f64;
+        match b.game_score.cmp(&a.game_score) {
+            std::cmp::Ordering::Greater => wins += 1,
+            std::cmp::Ordering::Less => losses += 1,
+            std::cmp::Ordering::Equal => draws += 1,
+        }
+        if per_seed {
+            println!("{}	{}	{}	{diff:+}", a.seed, a.game_score, b.game_score);
+        }
+        differences.push(diff);
+        stats.push(diff);
+    }
+    let ci = stats.ci95_half_width();
+    let test = wilcoxon_signed_rank(&differences);
+    println!(
+        "{} vs {}: diff_mean {:+.2} (95% CI {:+.2}..{:+.2}), wins {wins} losses {losses} draws {draws}, wilcoxon W+={} W-={} z={:.2} p={:.4}{}",
+        other[0].policy,
+        baseline[0].policy,
+        stats.mean,
+        stats.mean - ci,
+        stats.mean + ci,
+        test.w_plus,
+        test.w_minus,
+        test.z,
+        test.p_value,
+        if test.p_value < 0.05 { " (significant)" } else { "" }
+    );
+}
+
+/// 複数のAIを同じシード列で遊ばせて比べる
+///
+/// 2つ以上のAIを指定したときは、2つ目以降を1つ目と `paired_comparison` で比べる。`num` は1以上。
+pub fn compare(
+    specs: &[String],
+    num: usize,
+    base_seed: u64,
+    thread_config: ThreadConfig,
+    play_config: PlayConfig,
+    per_seed: bool,
+) -> Vec<Vec<GameResult>> {
+    let mut all_results = vec![];
+    for (policy_index, spec) in specs.iter().enumerate() {
+        let results = run_games_parallel(num, thread_config, |seed| {
+            let rng_seed = derive_policy_seed(base_seed, policy_index, seed);
+            let mut policy = parse_policy(spec, rng_seed).unwrap();
+            run_game(seed, policy.as_mut(), &play_config)
+        });
+        let score_mean = results.iter().map(|r| r.game_score).sum::<usize>() as f64 / num as f64;
+        println!("{}\tscore_mean: {score_mean}", results[0].policy);
+        all_results.push(results);
+    }
+    for results in all_results.iter().skip(1) {
+        paired_comparison(&all_results[0], results, per_seed);
+    }
+    all_results
+}
+
+/// 評価値に大きさ `magnitudes` のノイズを足して各AIを遊ばせ、ノイズなしからのスコアの低下を測る
+///
+/// `magnitudes` の先頭を基準にする (普通は0)。
+pub fn noise_robustness(
+    specs: &[String],
+    magnitudes: &[i64],
+    num: usize,
+    base_seed: u64,
+    thread_config: ThreadConfig,
+    play_config: PlayConfig,
+) -> Vec<GameResult> {
+    println!("policy\tmagnitude\tscore_mean\tdegradation");
+    let mut all_results = vec![];
+    for (policy_index, spec) in specs.iter().enumerate() {
+        let mut baseline = None;
+        for &magnitude in magnitudes {
+            let mut play_config = play_config.clone();
+            play_config.game.eval_noise = magnitude;
+            let results = run_games_parallel(num, thread_config, |seed| {
+                let rng_seed = derive_policy_seed(base_seed, policy_index, seed);
+                let mut policy = parse_policy(spec, rng_seed).unwrap();
+                run_game(seed, policy.as_mut(), &play_config)
+            });
+            let score_mean =
+                results.iter().map(|r| r.game_score).sum::<usize>() as f64 / num as f64;
+            let baseline = *baseline.get_or_insert(score_mean);
+            let degradation = if baseline > 0.0 {
+                (baseline - score_mean) / baseline * 100.0
+            } else {
+                0.0
+            };
+            println!(
+                "{}\t{magnitude}\t{score_mean:.2}\t{degradation:.1}%",
+                results[0].policy
+            );
+            all_results.extend(results);
+        }
+    }
+    all_results
+}
+
+/// スイープの結果を (ゲームの長さ, ビーム幅) ごとに (スコアの合計, ゲーム数) にまとめる
+pub fn sweep_table(results: &[GameResult]) -> BTreeMap<(usize, usize), (usize, usize)> {
+    let mut table = BTreeMap::new();
+    for result in results {
+        let Some(beam_width) = policy_param(&result.policy, "width") else {
+            continue;
+        };
+        let entry = table
+            .entry((result.actions.len(), beam_width as usize))
+            .or_insert((0, 0));
+        entry.0 += result.game_score;
+        entry.1 += 1;
+    }
+    table
+}
+
+/// 前回のスイープで最も平均スコアが高かったビーム幅の周りを細かくした、ゲームの長さごとのビーム幅
+///
+/// 最良の幅とその両隣の幅の間を最大 `points` 個に分けた、まだ測っていない幅を返す。
+/// 両隣との間にもう測る幅がなければ、そのゲームの長さは含めない。
+pub fn refine_sweep_grid(results: &[GameResult], points: usize) -> Vec<(usize, Vec<usize>)> {
+    let table = sweep_table(results);
+    let mut grid = vec![];
+    let end_turns: BTreeSet<usize> = table.keys().map(|&(end_turn, _)| end_turn).collect();
+    for end_turn in end_turns {
+        let widths: Vec<(usize, f64)> = table
+            .range((end_turn, 0)..=(end_turn, usize::MAX))
+            .map(|(&(_, width), &(sum, count))| (width, sum as f64 / count as f64))
+            .collect();
+        let best = (0..widths.len())
+            .max_by(|&a, &b| widths[a].1.total_cmp(&widths[b].1))
+            .unwrap();
+        let best_width = widths[best].0;
+        let low = if best > 0 {
+            widths[best - 1].0
+        } else {
+            (best_width / 2).max(1)
+        };
+        let high = widths
+            .get(best + 1)
+            .map_or(best_width * 2, |&(width, _)| width);
+        let step = ((high - low) / (points + 1)).max(1);
+        let refined: Vec<usize> = (low..=high)
+            .step_by(step)
+            .filter(|width| widths.iter().all(|&(w, _)| w != *width))
+            .take(points)
+            .collect();
+        if !refined.is_empty() {
+            grid.push((end_turn, refined));
+        }
+    }
+    grid
+}
+
+/// ゲームの長さ(END_TURN)とビーム幅を変えながら、時間制限付きビームサーチのスコアを測る
+pub fn sweep(
+    end_turns: &[usize],
+    beam_widths: &[usize],
+    time_threshold: u128,
+    num: usize,
+    thread_config: ThreadConfig,
+) -> Vec<GameResult> {
+    println!("end_turn\tbeam_width\tscore_mean\tscore_per_turn");
+    let mut all_results = vec![];
+    for &end_turn in end_turns {
+        for &beam_width in beam_widths {
+            let play_config = PlayConfig {
+                game: GameConfig {
+                    end_turn,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let results = run_games_parallel(num, thread_config, |seed| {
+                let mut policy = TimedBeamSearchPolicy {
+                    beam_width,
+                    time_threshold_us: time_threshold * 1000,
+                    stop_rule: StopRule::default(),
+                    eval_cache: None,
+                    stats: SearchStats::default(),
+                };
+                run_game(seed, &mut policy, &play_config)
+            });
+            let score_mean =
+                results.iter().map(|r| r.game_score).sum::<usize>() as f64 / num as f64;
+            println!(
+                "{end_turn}\t{beam_width}\t{score_mean:.2}\t{:.3}",
+                score_mean / end_turn as f64
+            );
+            all_results.extend(results);
+        }
+    }
+    all_results
+}
@@ -0,0 +1,1490 @@
+//! ゲームを遊ぶAIの共通インターフェースと各AIの実装
+
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    compose::parse_policy_expr,
+    game::{
+        BoundEvaluator, Direction, Eval, Evaluator, NearestPointEvaluator, PotentialFieldEvaluator,
+        State, END_TURN,
+    },
+    search::{
+        annealed_beam_search_action, arena_beam_search_action_with_stats,
+        beam_search_action_values, beam_search_action_with_attribution_and_stats,
+        beam_search_action_with_eval_cache, chokudai_search_action_with_eval_cache,
+        chokudai_search_action_with_stats_us, endgame_solver_action,
+        fixed_chokudai_search_action_with_stats, greedy_action, iw_action, mcts_action,
+        mcts_root_visits, parallel_chokudai_search_action, primitive_montecarlo_action,
+        random_action, rhea_action, root_parallel_beam_search_action,
+        shared_chokudai_search_action, two_phase_search_action, Attribution, EvalCache,
+        MazeNoveltyFeatures, RheaParams, RootAggregation, SearchError, SearchStats, StopRule,
+        TemperatureSchedule, CHOKUDAI_CHECK_INTERVAL, TURN_EVAL_CACHE_CAPACITY,
+    },
+    spec::SpecError,
+    util::{splitmix64, ThreadConfig},
+};
+
+/// 盤面から行動を決めるAI
+pub trait Policy {
+    /// 結果の表示などに使う設定込みの名前
+    fn name(&self) -> String;
+
+    fn select_action(&mut self, state: &State) -> Direction;
+
+    /// 1手あたりの時間制限[us] (時間で打ち切らないAIはNone)
+    fn time_threshold_us(&self) -> Option<u128> {
+        None
+    }
+
+    /// 1手あたりの時間制限[us]を変更する (時間で打ち切らないAIでは何もしない。ms単位のAIは切り捨てる)
+    fn set_time_threshold_us(&mut self, _time_threshold_us: u128) {}
+
+    /// ゲーム開始前に捨ての探索を行い、アロケータやキャッシュを温めておく
+    fn warm_up(&mut self, state: &State) {
+        self.select_action(state);
+    }
+
+    /// 行動を決める。時間切れなどで決められなかったらNoneを返す
+    ///
+    /// `fallback` で組み合わせたときに使う。既定の実装は常に `select_action` の結果を返す。
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        Some(self.select_action(state))
+    }
+
+    /// 手を決め、確率的に選ぶAIなら選んだ分布 (合法手ごとの確率) も返す
+    ///
+    /// 自己対戦の記録を強化学習に使うためのもの。既定の実装は分布を返さない。
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        (self.select_action(state), None)
+    }
+
+    /// ターンをまたいで覚えている評価値などを捨てる (評価関数が変わったときに呼ぶ)
+    fn clear_caches(&mut self) {}
+
+    /// 直前に手を決めたときに使った評価関数の名前 (評価関数を差し替えるAIだけ)
+    fn active_evaluator(&self) -> Option<String> {
+        None
+    }
+
+    /// これまでの探索で展開・複製した状態の数など (数えている探索のAIだけ)
+    fn search_stats(&self) -> Option<SearchStats> {
+        None
+    }
+
+    /// 手を決めずに、合法手ごとのAIの見積もりを返す
+    ///
+    /// 既定の実装は通常の探索で選んだ手を1、それ以外を0とする。
+    fn evaluate_actions(&mut self, state: &State) -> Vec<(Direction, f64)> {
+        let chosen = self.select_action(state);
+        state
+            .legal_actions()
+            .into_iter()
+            .map(|action| (action, if action == chosen { 1. } else { 0. }))
+            .collect()
+    }
+}
+
+/// 行動ごとの確率 (`Direction::index` の順。合法でない行動は0)
+pub type ActionProbabilities = [f64; 4];
+
+/// 分布 `probabilities` から行動を1つ引く
+///
+/// 確率が正の有限値の行動だけを返す (NaNや無限大は0とみなす)。そのような行動が少なくとも1つ必要。
+fn sample_from(probabilities: &ActionProbabilities, rng: &mut ChaCha12Rng) -> Direction {
+    let weight = |p: f64| if p > 0. && p.is_finite() { p } else { 0. };
+    let mut r = rng.gen::<f64>() * probabilities.iter().map(|&p| weight(p)).sum::<f64>();
+    let mut last = None;
+    for action in Direction::ALL {
+        let p = weight(probabilities[action.index()]);
+        if p <= 0. {
+            continue;
+        }
+        if r < p {
+            return action;
+        }
+        r -= p;
+        last = Some(action);
+    }
+    // 丸め誤差で最後まで残ったら確率が正の最後の行動にする
+    last.unwrap()
+}
+
+/// 評価値 `values` を温度 `temperature` のソフトマックスで確率にする (温度0なら最大の行動に1)
+fn softmax(values: &[(Direction, f64)], temperature: f64) -> ActionProbabilities {
+    let mut probabilities = [0.; 4];
+    let max = values
+        .iter()
+        .map(|&(_, v)| v)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if temperature <= 0. {
+        let &(best, _) = values.iter().find(|&&(_, v)| v == max).unwrap();
+        probabilities[best.index()] = 1.;
+        return probabilities;
+    }
+    let mut sum = 0.;
+    for &(action, value) in values {
+        let weight = ((value - max) / temperature).exp();
+        probabilities[action.index()] = weight;
+        sum += weight;
+    }
+    probabilities.map(|p| p / sum)
+}
+
+/// 探索が手を決められなかったら貪欲法の手で代わりにする
+///
+/// 合法手がなければ貪欲法も手を決められないので、終局した盤面でAIを呼ばないのは呼ぶ側の責任。
+fn or_greedy(state: &State, result: Result<Direction, SearchError>) -> Direction {
+    result
+        .or_else(|_| greedy_action(state))
+        .expect("no legal action")
+}
+
+/// 1手先の評価値
+fn one_step_values(state: &State) -> Vec<(Direction, f64)> {
+    state
+        .legal_actions()
+        .into_iter()
+        .map(|action| {
+            let mut next_state = state.clone();
+            next_state.advance(action);
+            next_state.evaluate_score();
+            (action, next_state.evaluated_score.0 as f64)
+        })
+        .collect()
+}
+
+pub struct RandomPolicy {
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for RandomPolicy {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        random_action(state, &mut self.rng).expect("no legal action")
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        let legal_actions = state.legal_actions();
+        let mut probabilities = [0.; 4];
+        for action in &legal_actions {
+            probabilities[action.index()] = 1. / legal_actions.len() as f64;
+        }
+        (self.select_action(state), Some(probabilities))
+    }
+
+    /// 乱数列を変えないように何もしない
+    fn warm_up(&mut self, _state: &State) {}
+}
+
+pub struct GreedyPolicy;
+
+impl Policy for GreedyPolicy {
+    fn name(&self) -> String {
+        "greedy".to_string()
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        greedy_action(state).expect("no legal action")
+    }
+
+    fn evaluate_actions(&mut self, state: &State) -> Vec<(Direction, f64)> {
+        one_step_values(state)
+    }
+}
+
+/// 1手先の評価値のソフトマックスで確率的に手を選ぶ
+pub struct SoftmaxPolicy {
+    /// 評価値の何点を1とみなすか (0なら貪欲法と同じ)
+    pub temperature: f64,
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for SoftmaxPolicy {
+    fn name(&self) -> String {
+        format!("softmax(temp={})", self.temperature)
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        self.sample_action(state).0
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        let probabilities = softmax(&one_step_values(state), self.temperature);
+        (
+            sample_from(&probabilities, &mut self.rng),
+            Some(probabilities),
+        )
+    }
+
+    fn evaluate_actions(&mut self, state: &State) -> Vec<(Direction, f64)> {
+        let probabilities = softmax(&one_step_values(state), self.temperature);
+        state
+            .legal_actions()
+            .into_iter()
+            .map(|action| (action, probabilities[action.index()]))
+            .collect()
+    }
+
+    /// 乱数列を変えないように何もしない
+    fn warm_up(&mut self, _state: &State) {}
+}
+
+/// 確率 `epsilon` でランダムに、それ以外は貪欲法で手を選ぶ
+pub struct EpsilonGreedyPolicy {
+    pub epsilon: f64,
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for EpsilonGreedyPolicy {
+    fn name(&self) -> String {
+        format!("epsilon-greedy(eps={})", self.epsilon)
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        self.sample_action(state).0
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        let legal_actions = state.legal_actions();
+        let greedy = greedy_action(state).expect("no legal action");
+        let mut probabilities = [0.; 4];
+        for action in &legal_actions {
+            probabilities[action.index()] = self.epsilon / legal_actions.len() as f64;
+        }
+        probabilities[greedy.index()] += 1. - self.epsilon;
+        (
+            sample_from(&probabilities, &mut self.rng),
+            Some(probabilities),
+        )
+    }
+
+    /// 乱数列を変えないように何もしない
+    fn warm_up(&mut self, _state: &State) {}
+}
+
+pub struct BeamSearchPolicy {
+    pub beam_width: usize,
+    pub beam_depth: usize,
+    /// 最後のビームの状態をどの最初の行動の手柄にするか
+    pub attribution: Attribution,
+    /// これまでの探索の記録
+    pub stats: SearchStats,
+}
+
+impl Policy for BeamSearchPolicy {
+    fn name(&self) -> String {
+        match self.attribution {
+            Attribution::Inherit => {
+                format!("beam(width={}, depth={})", self.beam_width, self.beam_depth)
+            }
+            attribution => format!(
+                "beam(width={}, depth={}, attr={attribution})",
+                self.beam_width, self.beam_depth
+            ),
+        }
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = match self.attribution {
+            // 選ぶ手は同じで、複製する状態が少ない
+            Attribution::Inherit => arena_beam_search_action_with_stats(
+                state,
+                self.beam_width,
+                self.beam_depth,
+                &mut self.stats,
+            ),
+            attribution => beam_search_action_with_attribution_and_stats(
+                state,
+                self.beam_width,
+                self.beam_depth,
+                attribution,
+                &mut self.stats,
+            ),
+        };
+        or_greedy(state, result)
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn evaluate_actions(&mut self, state: &State) -> Vec<(Direction, f64)> {
+        if self.attribution != Attribution::Inherit {
+            // 最後のビームの分け方が違うので、選んだ手を1とする既定の見積もりにする
+            let chosen = self.select_action(state);
+            return state
+                .legal_actions()
+                .into_iter()
+                .map(|action| (action, if action == chosen { 1. } else { 0. }))
+                .collect();
+        }
+        beam_search_action_values(state, self.beam_width, self.beam_depth)
+            .into_iter()
+            .map(|(action, value)| (action, value.0 as f64))
+            .collect()
+    }
+}
+
+pub struct TimedBeamSearchPolicy {
+    pub beam_width: usize,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    /// 時間切れの前に打ち切る条件
+    pub stop_rule: StopRule,
+    /// ターンをまたいで子の評価値を覚えておく表 (Noneなら毎ターン評価し直す)
+    pub eval_cache: Option<EvalCache>,
+    /// これまでの探索の記録
+    pub stats: SearchStats,
+}
+
+impl Policy for TimedBeamSearchPolicy {
+    fn name(&self) -> String {
+        let mut name = format!(
+            "beam(width={}, {}",
+            self.beam_width,
+            budget_param(self.time_threshold_us)
+        );
+        if let Some(margin) = self.stop_rule.margin {
+            name.push_str(&format!(", margin={margin}"));
+        }
+        if self.stop_rule.bound {
+            name.push_str(", bound=1");
+        }
+        if self.eval_cache.is_some() {
+            name.push_str(", tt=1");
+        }
+        name + ")"
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        if let Some(eval_cache) = &mut self.eval_cache {
+            eval_cache.begin_turn(state.turn);
+        }
+        let result = beam_search_action_with_eval_cache(
+            state,
+            self.beam_width,
+            self.time_threshold_us,
+            self.stop_rule,
+            self.eval_cache.as_mut(),
+            &mut self.stats,
+        );
+        or_greedy(state, result)
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+
+    fn clear_caches(&mut self) {
+        if let Some(eval_cache) = &mut self.eval_cache {
+            eval_cache.clear();
+        }
+    }
+}
+
+pub struct ChokudaiSearchPolicy {
+    pub beam_width: usize,
+    pub beam_depth: usize,
+    pub beam_num: usize,
+    /// これまでの探索の記録
+    pub stats: SearchStats,
+}
+
+impl Policy for ChokudaiSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "chokudai(width={}, depth={}, num={})",
+            self.beam_width, self.beam_depth, self.beam_num
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = fixed_chokudai_search_action_with_stats(
+            state,
+            self.beam_width,
+            self.beam_depth,
+            self.beam_num,
+            &mut self.stats,
+        );
+        or_greedy(state, result)
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+}
+
+pub struct TimedChokudaiSearchPolicy {
+    pub beam_width: usize,
+    pub beam_depth: usize,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    /// 何段の深さを展開するごとに時間を確認するか
+    pub check_interval: usize,
+    /// ターンをまたいで子の評価値を覚えておく表 (Noneなら探索ごとに作り直す)
+    pub eval_cache: Option<EvalCache>,
+    /// これまでの探索の記録
+    pub stats: SearchStats,
+}
+
+impl TimedChokudaiSearchPolicy {
+    fn search(&mut self, state: &State) -> Result<Direction, SearchError> {
+        let Some(eval_cache) = &mut self.eval_cache else {
+            return chokudai_search_action_with_stats_us(
+                state,
+                self.beam_width,
+                self.beam_depth,
+                self.time_threshold_us,
+                self.check_interval,
+                &mut self.stats,
+            );
+        };
+        eval_cache.begin_turn(state.turn);
+        chokudai_search_action_with_eval_cache(
+            state,
+            self.beam_width,
+            self.beam_depth,
+            self.time_threshold_us,
+            self.check_interval,
+            Some(eval_cache),
+            &mut self.stats,
+        )
+    }
+}
+
+impl Policy for TimedChokudaiSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "chokudai(width={}, depth={}, {}{}{})",
+            self.beam_width,
+            self.beam_depth,
+            budget_param(self.time_threshold_us),
+            if self.check_interval == CHOKUDAI_CHECK_INTERVAL {
+                String::new()
+            } else {
+                format!(", check={}", self.check_interval)
+            },
+            if self.eval_cache.is_some() {
+                ", tt=1"
+            } else {
+                ""
+            }
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = self.search(state);
+        or_greedy(state, result)
+    }
+
+    /// 1段も読めないうちに時間切れになったらNone
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.search(state).ok()
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+
+    fn clear_caches(&mut self) {
+        if let Some(eval_cache) = &mut self.eval_cache {
+            eval_cache.clear();
+        }
+    }
+}
+
+/// 新しい特徴を持たない状態を刈り込んだ幅優先探索で手を決めるAI (Iterated Width)
+pub struct IwPolicy {
+    /// 読む幅の上限 (`search::IW_MAX_WIDTH` まで)
+    pub max_width: usize,
+    /// 特徴に使う、得点が残っているマスの見本の数
+    pub samples: usize,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    /// これまでの探索の記録 (`passes` は読み終えた幅の数の合計)
+    pub stats: SearchStats,
+}
+
+impl IwPolicy {
+    fn search(&mut self, state: &State) -> Result<Direction, SearchError> {
+        iw_action(
+            state,
+            &MazeNoveltyFeatures::new(state, self.samples),
+            self.max_width,
+            self.time_threshold_us,
+            &mut self.stats,
+        )
+    }
+}
+
+impl Policy for IwPolicy {
+    fn name(&self) -> String {
+        format!(
+            "iw(k={}, samples={}, {})",
+            self.max_width,
+            self.samples,
+            budget_param(self.time_threshold_us)
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = self.search(state);
+        or_greedy(state, result)
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.search(state).ok()
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+}
+
+/// 行動列を進化させて手を決めるAI (Rolling Horizon進化的アルゴリズム)
+pub struct RheaPolicy {
+    pub params: RheaParams,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    pub rng: ChaCha12Rng,
+    /// これまでの探索の記録 (`passes` は世代数の合計)
+    pub stats: SearchStats,
+    /// 前のターンの最良の行動列から最初の行動を除いたもの
+    shift_buffer: Vec<u8>,
+    /// `shift_buffer` を作ったときのターン (次のターンでなければ使わない)
+    last_turn: Option<usize>,
+}
+
+impl RheaPolicy {
+    pub fn new(params: RheaParams, time_threshold_us: u128, rng: ChaCha12Rng) -> Self {
+        Self {
+            params,
+            time_threshold_us,
+            rng,
+            stats: SearchStats::default(),
+            shift_buffer: vec![],
+            last_turn: None,
+        }
+    }
+
+    fn search(&mut self, state: &State) -> Result<Direction, SearchError> {
+        // 別のゲームや飛ばしたターンの行動列は今の盤面に合わないので捨てる
+        if self.last_turn.map(|turn| turn + 1) != Some(state.turn) {
+            self.shift_buffer.clear();
+        }
+        self.last_turn = Some(state.turn);
+        rhea_action(
+            state,
+            self.params,
+            self.time_threshold_us,
+            &mut self.shift_buffer,
+            &mut self.rng,
+            &mut self.stats,
+        )
+    }
+}
+
+impl Policy for RheaPolicy {
+    fn name(&self) -> String {
+        let mut name = format!(
+            "rhea(pop={}, len={}, mut={}, {}",
+            self.params.population,
+            self.params.length,
+            self.params.mutation_percent,
+            budget_param(self.time_threshold_us)
+        );
+        if self.params.max_generations > 0 {
+            name.push_str(&format!(", gens={}", self.params.max_generations));
+        }
+        name + ")"
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = self.search(state);
+        or_greedy(state, result)
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.search(state).ok()
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+}
+
+/// 同点の順番を変えた独立なビームサーチを複数のスレッドで回し、最初の行動を多数決などで選ぶAI
+pub struct RootParallelBeamSearchPolicy {
+    pub beam_width: usize,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    pub threads: usize,
+    pub aggregation: RootAggregation,
+    /// 手ごとの同点の順番を決める乱数
+    pub rng: ChaCha12Rng,
+    /// これまでの探索の記録 (全スレッドの合計)
+    pub stats: SearchStats,
+}
+
+impl RootParallelBeamSearchPolicy {
+    fn search(&mut self, state: &State) -> Result<Direction, SearchError> {
+        root_parallel_beam_search_action(
+            state,
+            self.beam_width,
+            self.time_threshold_us,
+            self.threads,
+            self.aggregation,
+            self.rng.gen(),
+            &mut self.stats,
+        )
+    }
+}
+
+impl Policy for RootParallelBeamSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "parallel-beam(width={}, {}, threads={}, agg={})",
+            self.beam_width,
+            budget_param(self.time_threshold_us),
+            self.threads,
+            self.aggregation
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = self.search(state);
+        or_greedy(state, result)
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.search(state).ok()
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+}
+
+/// 同点の順番を変えた独立なchokudaiサーチを複数のスレッドで回すAI
+pub struct ParallelChokudaiSearchPolicy {
+    pub beam_width: usize,
+    pub beam_depth: usize,
+    /// 1手の時間制限[us]
+    pub time_threshold_us: u128,
+    pub threads: usize,
+    /// 深さごとのビームを全スレッドで共有する (`search::shared_chokudai_search_action`)
+    pub shared_beams: bool,
+    /// 手ごとの同点の順番を決める乱数
+    pub rng: ChaCha12Rng,
+    /// これまでの探索の記録 (`passes` は全スレッドの合計)
+    pub stats: SearchStats,
+}
+
+impl ParallelChokudaiSearchPolicy {
+    fn search(&mut self, state: &State) -> Result<Direction, SearchError> {
+        if self.shared_beams {
+            return shared_chokudai_search_action(
+                state,
+                self.beam_width,
+                self.beam_depth,
+                self.time_threshold_us,
+                self.threads,
+                &mut self.stats,
+            );
+        }
+        parallel_chokudai_search_action(
+            state,
+            self.beam_width,
+            self.beam_depth,
+            self.time_threshold_us,
+            self.threads,
+            self.rng.gen(),
+            &mut self.stats,
+        )
+    }
+}
+
+impl Policy for ParallelChokudaiSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "parallel-chokudai(width={}, depth={}, {}, threads={}{})",
+            self.beam_width,
+            self.beam_depth,
+            budget_param(self.time_threshold_us),
+            self.threads,
+            if self.shared_beams { ", share=1" } else { "" }
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = self.search(state);
+        or_greedy(state, result)
+    }
+
+    /// どのスレッドも1段も読めないうちに時間切れになったらNone
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        self.search(state).ok()
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        Some(self.stats)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold_us)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold_us = time_threshold_us;
+    }
+}
+
+pub struct AnnealedBeamSearchPolicy {
+    pub beam_width: usize,
+    pub time_threshold: u128,
+    pub schedule: TemperatureSchedule,
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for AnnealedBeamSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "annealed-beam(width={}, ms={}, temp={}, decay={})",
+            self.beam_width, self.time_threshold, self.schedule.initial, self.schedule.decay
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = annealed_beam_search_action(
+            state,
+            self.beam_width,
+            self.time_threshold,
+            &self.schedule,
+            &mut self.rng,
+        );
+        or_greedy(state, result)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold * 1000)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold = time_threshold_us / 1000;
+    }
+}
+
+/// 原始モンテカルロ法
+pub struct PrimitiveMonteCarloPolicy {
+    pub playout_num: usize,
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for PrimitiveMonteCarloPolicy {
+    fn name(&self) -> String {
+        format!("montecarlo(playouts={})", self.playout_num)
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = primitive_montecarlo_action(state, self.playout_num, &mut self.rng);
+        or_greedy(state, result)
+    }
+}
+
+/// 終盤の全探索 (残りターン数が少ないときだけ使える)
+pub struct EndgameSolverPolicy;
+
+impl Policy for EndgameSolverPolicy {
+    fn name(&self) -> String {
+        "exact".to_string()
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        or_greedy(state, endgame_solver_action(state))
+    }
+
+    fn warm_up(&mut self, _state: &State) {}
+}
+
+/// UCTによるモンテカルロ木探索
+pub struct MctsPolicy {
+    pub playout_budget: usize,
+    pub c: f64,
+    pub expand_threshold: usize,
+    /// 0より大きければ、訪問回数の `1 / temperature` 乗に比例する確率で手を選ぶ
+    pub temperature: f64,
+    pub rng: ChaCha12Rng,
+}
+
+impl Policy for MctsPolicy {
+    fn name(&self) -> String {
+        let mut name = format!(
+            "mcts(playouts={}, c={}, expand={}",
+            self.playout_budget, self.c, self.expand_threshold
+        );
+        if self.temperature > 0. {
+            name.push_str(&format!(", temp={}", self.temperature));
+        }
+        name + ")"
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        if self.temperature > 0. {
+            return self.sample_action(state).0;
+        }
+        let result = mcts_action(
+            state,
+            self.playout_budget,
+            self.c,
+            self.expand_threshold,
+            &mut self.rng,
+        );
+        or_greedy(state, result)
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        if self.temperature <= 0. {
+            return (self.select_action(state), None);
+        }
+        let visits = mcts_root_visits(
+            state,
+            self.playout_budget,
+            self.c,
+            self.expand_threshold,
+            &mut self.rng,
+        );
+        // 最大の訪問回数で割ってから累乗して、温度が低くても無限大にならないようにする。
+        // プレイアウトが0回で訪問回数がなければ合法手から一様に選ぶ
+        let max_count = visits.iter().map(|&(_, count)| count).max().unwrap_or(0);
+        let mut probabilities = [0.; 4];
+        for &(action, count) in &visits {
+            probabilities[action.index()] = if max_count == 0 {
+                1.
+            } else {
+                (count as f64 / max_count as f64).powf(1. / self.temperature)
+            };
+        }
+        let sum: f64 = probabilities.iter().sum();
+        let probabilities = probabilities.map(|p| p / sum);
+        (
+            sample_from(&probabilities, &mut self.rng),
+            Some(probabilities),
+        )
+    }
+}
+
+pub struct TwoPhaseSearchPolicy {
+    pub wide_width: usize,
+    pub wide_depth: usize,
+    pub narrow_width: usize,
+    pub shortlist_size: usize,
+    pub time_threshold: u128,
+    pub split_percent: u128,
+}
+
+impl Policy for TwoPhaseSearchPolicy {
+    fn name(&self) -> String {
+        format!(
+            "two-phase(wide={}x{}, narrow={}, shortlist={}, ms={}, split={}%)",
+            self.wide_width,
+            self.wide_depth,
+            self.narrow_width,
+            self.shortlist_size,
+            self.time_threshold,
+            self.split_percent
+        )
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let result = two_phase_search_action(
+            state,
+            self.wide_width,
+            self.wide_depth,
+            self.narrow_width,
+            self.shortlist_size,
+            self.time_threshold,
+            self.split_percent,
+        );
+        or_greedy(state, result)
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        Some(self.time_threshold * 1000)
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.time_threshold = time_threshold_us / 1000;
+    }
+}
+
+/// 評価関数を切り替える条件
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseStart {
+    /// ゲームの最初から
+    Start,
+    /// ターンがこれ以上になったら
+    Turn(usize),
+    /// 盤面に残っている得点の合計がこれ以下になったら
+    RemainingPoints(usize),
+}
+
+impl PhaseStart {
+    pub fn reached(self, state: &State) -> bool {
+        match self {
+            PhaseStart::Start => true,
+            PhaseStart::Turn(turn) => state.turn >= turn,
+            PhaseStart::RemainingPoints(points) => state.remaining_sum() <= points,
+        }
+    }
+}
+
+impl std::fmt::Display for PhaseStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PhaseStart::Start => Ok(()),
+            PhaseStart::Turn(turn) => write!(f, "@t{turn}"),
+            PhaseStart::RemainingPoints(points) => write!(f, "@p{points}"),
+        }
+    }
+}
+
+/// ゲームの局面ごとの評価関数
+pub struct EvaluatorPhase {
+    pub start: PhaseStart,
+    pub evaluator: Arc<dyn Evaluator>,
+    /// 名前に付ける評価関数の説明
+    pub label: String,
+}
+
+/// `inner` が探索で使う評価関数を局面に応じて差し替える
+///
+/// 手を決めるたびに、根の盤面で条件を満たした最後の局面の評価関数を使う。
+pub struct EvaluatorPolicy {
+    pub inner: Box<dyn Policy>,
+    pub phases: Vec<EvaluatorPhase>,
+    /// 直前に使った局面の番号
+    pub active: Option<usize>,
+}
+
+impl EvaluatorPolicy {
+    /// 常に `evaluator` を使う
+    pub fn single(inner: Box<dyn Policy>, evaluator: Arc<dyn Evaluator>, label: String) -> Self {
+        Self {
+            inner,
+            phases: vec![EvaluatorPhase {
+                start: PhaseStart::Start,
+                evaluator,
+                label,
+            }],
+            active: None,
+        }
+    }
+
+    /// 局面に合った評価関数を設定した盤面
+    ///
+    /// 前の手と違う評価関数になったら、`inner` が前の評価関数で覚えた評価値を捨てる。
+    fn with_phase_evaluator(&mut self, state: &State) -> State {
+        let phase = self
+            .phases
+            .iter()
+            .rposition(|phase| phase.start.reached(state))
+            .unwrap_or(0);
+        if self.active.is_some_and(|active| active != phase) {
+            self.inner.clear_caches();
+        }
+        self.active = Some(phase);
+        state
+            .clone()
+            .with_evaluator(self.phases[phase].evaluator.clone())
+    }
+}
+
+impl Policy for EvaluatorPolicy {
+    fn name(&self) -> String {
+        if let [phase] = &self.phases[..] {
+            return format!("{}+{}", self.inner.name(), phase.label);
+        }
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|phase| format!("{}{}", phase.label, phase.start))
+            .collect();
+        format!("{}+phases({})", self.inner.name(), phases.join(", "))
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        let state = self.with_phase_evaluator(state);
+        self.inner.select_action(&state)
+    }
+
+    fn try_select_action(&mut self, state: &State) -> Option<Direction> {
+        let state = self.with_phase_evaluator(state);
+        self.inner.try_select_action(&state)
+    }
+
+    fn sample_action(&mut self, state: &State) -> (Direction, Option<ActionProbabilities>) {
+        let state = self.with_phase_evaluator(state);
+        self.inner.sample_action(&state)
+    }
+
+    fn warm_up(&mut self, state: &State) {
+        let state = self.with_phase_evaluator(state);
+        self.inner.warm_up(&state);
+    }
+
+    fn evaluate_actions(&mut self, state: &State) -> Vec<(Direction, f64)> {
+        let state = self.with_phase_evaluator(state);
+        self.inner.evaluate_actions(&state)
+    }
+
+    fn active_evaluator(&self) -> Option<String> {
+        self.active.map(|phase| self.phases[phase].label.clone())
+    }
+
+    fn search_stats(&self) -> Option<SearchStats> {
+        self.inner.search_stats()
+    }
+
+    fn time_threshold_us(&self) -> Option<u128> {
+        self.inner.time_threshold_us()
+    }
+
+    fn set_time_threshold_us(&mut self, time_threshold_us: u128) {
+        self.inner.set_time_threshold_us(time_threshold_us);
+    }
+
+    fn clear_caches(&mut self) {
+        self.inner.clear_caches();
+    }
+}
+
+/// AIの名前に書く時間制限 (1msで割り切れれば `ms=10`、割り切れなければ `us=500`)
+pub fn budget_param(time_threshold_us: u128) -> String {
+    if time_threshold_us.is_multiple_of(1000) {
+        format!("ms={}", time_threshold_us / 1000)
+    } else {
+        format!("us={time_threshold_us}")
+    }
+}
+
+/// 比較の中で各AIに渡す乱数のシードを導出する
+///
+/// AIごと(`policy_index`)・ゲームごと(`game_seed`)に独立した乱数列になるので、
+/// あるAIの乱数の消費が他のAIの行動に影響することはない。
+pub fn derive_policy_seed(base_seed: u64, policy_index: usize, game_seed: u64) -> u64 {
+    splitmix64(splitmix64(splitmix64(base_seed) ^ policy_index as u64) ^ game_seed)
+}
+
+/// `parse_policy` で作れるAIの名前と、それぞれが受け付けるパラメータ (`COMMON_PARAMS` 以外)
+pub const POLICY_PARAMS: &[(&str, &[&str])] = &[
+    ("random", &[]),
+    ("greedy", &[]),
+    ("exact", &[]),
+    (
+        "beam",
+        &[
+            "width", "depth", "ms", "us", "attr", "margin", "bound", "tt",
+        ],
+    ),
+    (
+        "chokudai",
+        &["width", "depth", "num", "ms", "us", "check", "tt"],
+    ),
+    (
+        "parallel-chokudai",
+        &["width", "depth", "ms", "us", "threads", "share"],
+    ),
+    ("parallel-beam", &["width", "ms", "us", "threads", "agg"]),
+    ("rhea", &["pop", "len", "mut", "gens", "ms", "us"]),
+    ("iw", &["k", "samples", "ms", "us"]),
+    (
+        "two-phase",
+        &["wide", "wide_depth", "narrow", "shortlist", "ms", "split"],
+    ),
+    ("annealed-beam", &["width", "ms", "temp", "decay"]),
+    ("montecarlo", &["playouts"]),
+    ("mcts", &["playouts", "c", "expand", "temp"]),
+    ("softmax", &["temp"]),
+    ("epsilon-greedy", &["eps"]),
+];
+
+/// どのAIにも付けられるパラメータ
+pub const COMMON_PARAMS: &[&str] = &["dist", "phases"];
+
+/// 割合 [%] を表すので0から100までしか受け付けないパラメータ
+pub const PERCENT_PARAMS: &[&str] = &["split", "eps", "mut"];
+
+/// 時間制限 (`ms`・`us`) の有無で版が分かれるAIの、時間制限付きの版だけ・ない版だけが受け付けるパラメータ
+///
+/// `POLICY_PARAMS` には両方の版のパラメータを並べてあるので、使わない版に付けたものはここで断る。
+pub const TIMED_VARIANT_PARAMS: &[(&str, &[&str], &[&str])] = &[
+    ("beam", &["margin", "bound", "tt"], &["depth", "attr"]),
+    ("chokudai", &["check", "tt"], &["num"]),
+];
+
+/// `parse_policy` で作れるAIの名前
+pub fn policy_names() -> Vec<&'static str> {
+    POLICY_PARAMS.iter().map(|&(name, _)| name).collect()
+}
+
+/// `beam:width=5,ms=10` のような文字列からAIを作る
+///
+/// 指定できるのは `random`, `greedy`, `exact`, `beam:width=W,depth=D,attr=inherit|leaf|vote`,
+/// `beam:width=W,ms=T,margin=M,bound=1` (`margin`, `bound` は `search::StopRule` を参照),
+/// `chokudai:width=W,depth=D,num=N`, `chokudai:width=W,depth=D,ms=T,check=C`
+/// (時間制限付きの `beam` と `chokudai` は `tt=1` で子の評価値をターンをまたいで覚える),
+/// `parallel-chokudai:width=W,depth=D,ms=T,threads=スレッド数(0なら全コア),share=1(ビームを共有する)`,
+/// `parallel-beam:width=W,ms=T,threads=スレッド数(0なら全コア),agg=vote|best`,
+/// `rhea:pop=個体数,len=行動列の長さ,mut=突然変異の確率[%],gens=世代数の上限(0なら時間切れまで),ms=T`,
+/// `iw:k=読む幅の上限,samples=特徴に使うマスの数,ms=T`
+/// (この6つは `ms=T` の代わりに `us=T` で1ms未満の時間制限も指定できる),
+/// `two-phase:wide=W,wide_depth=D,narrow=N,shortlist=K,ms=T,split=P`,
+/// `annealed-beam:width=W,ms=T,temp=初期温度,decay=減衰率[%]`,
+/// `montecarlo:playouts=N`,
+/// `mcts:playouts=N,c=探索項の係数[%],expand=展開するまでの訪問回数,temp=訪問回数の温度[%]`,
+/// `softmax:temp=1手先の評価値の温度[%]`, `epsilon-greedy:eps=ランダムに選ぶ確率[%]`。
+/// どのAIにも `dist=重み[%]` を付けると、評価関数を最寄りの得点までの距離を混ぜた
+/// `NearestPointEvaluator` に差し替える。`phases=potential/dist@t30/bound@t80` を付けると
+/// 局面ごとに評価関数を切り替える (`parse_evaluator_phases` を参照)。
+/// `(` を含む文字列は `fallback(greedy, timeout(beam(width=10), 5ms))` のような組み合わせとして読む
+/// (`compose::parse_policy_expr` を参照)。
+///
+/// 知らない名前やパラメータ、読めない値は `SpecError` で受け付けるものの一覧と綴りの近い候補を返す。
+pub fn parse_policy(spec: &str, rng_seed: u64) -> Result<Box<dyn Policy>, SpecError> {
+    if spec.contains('(') {
+        return parse_policy_expr(spec, rng_seed);
+    }
+    let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+    let Some(&(_, own_params)) = POLICY_PARAMS.iter().find(|&&(n, _)| n == name) else {
+        return Err(SpecError::unknown_name("policy", name, &policy_names()));
+    };
+    let invalid = |param: &str, expected| SpecError::InvalidParam {
+        param: param.to_string(),
+        spec: spec.to_string(),
+        expected,
+    };
+    let mut values = std::collections::HashMap::new();
+    let mut attribution = Attribution::default();
+    let mut aggregation = RootAggregation::default();
+    let mut distance_weight = None;
+    let mut phases = None;
+    let mut keys = vec![];
+    for param in params.split(',').filter(|p| !p.is_empty()) {
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| invalid(param, "key=value"))?;
+        if !own_params.contains(&key) && !COMMON_PARAMS.contains(&key) {
+            return Err(SpecError::UnknownParam {
+                policy: name.to_string(),
+                key: key.to_string(),
+                valid: [own_params, COMMON_PARAMS].concat(),
+            });
+        }
+        keys.push(key);
+        if key == "attr" {
+            attribution = value
+                .parse()
+                .map_err(|_| SpecError::unknown_name("attribution", value, Attribution::NAMES))?;
+            continue;
+        }
+        if key == "agg" {
+            aggregation = value.parse().map_err(|_| {
+                SpecError::unknown_name("aggregation", value, RootAggregation::NAMES)
+            })?;
+            continue;
+        }
+        if key == "phases" {
+            phases = Some(parse_evaluator_phases(value)?);
+            continue;
+        }
+        if key == "dist" {
+            distance_weight = Some(value.parse().map_err(|_| invalid(param, "an integer"))?);
+            continue;
+        }
+        let value: usize = value
+            .parse()
+            .map_err(|_| invalid(param, "a non-negative integer"))?;
+        if PERCENT_PARAMS.contains(&key) && value > 100 {
+            return Err(invalid(param, "a percentage from 0 to 100"));
+        }
+        values.insert(key, value);
+    }
+    let get = |key: &str, default: usize| values.get(key).copied().unwrap_or(default);
+    // `us` があれば `ms` より優先する
+    let timed = values.contains_key("ms") || values.contains_key("us");
+    if let Some(&(_, timed_only, untimed_only)) =
+        TIMED_VARIANT_PARAMS.iter().find(|&&(n, _, _)| n == name)
+    {
+        for &key in &keys {
+            if !timed && timed_only.contains(&key) {
+                return Err(SpecError::Invalid(format!(
+                    "{key} only applies to a timed {name}; add ms= or us= to {spec:?}"
+                )));
+            }
+            if timed && untimed_only.contains(&key) {
+                return Err(SpecError::Invalid(format!(
+                    "{key} does not apply to a timed {name}; remove it or ms=/us= from {spec:?}"
+                )));
+            }
+        }
+    }
+    let time_threshold_us = values
+        .get("us")
+        .map_or(get("ms", 10) as u128 * 1000, |&us| us as u128);
+    let turn_eval_cache =
+        (get("tt", 0) > 0).then(|| EvalCache::with_capacity(TURN_EVAL_CACHE_CAPACITY));
+    let policy: Box<dyn Policy> = match name {
+        "random" => Box::new(RandomPolicy {
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        "greedy" => Box::new(GreedyPolicy),
+        "exact" => Box::new(EndgameSolverPolicy),
+        "beam" if timed => Box::new(TimedBeamSearchPolicy {
+            beam_width: get("width", 5),
+            time_threshold_us,
+            stop_rule: StopRule {
+                margin: values.get("margin").map(|&margin| margin as i64),
+                bound: get("bound", 0) > 0,
+            },
+            eval_cache: turn_eval_cache,
+            stats: SearchStats::default(),
+        }),
+        "beam" => Box::new(BeamSearchPolicy {
+            beam_width: get("width", 5),
+            beam_depth: get("depth", 10),
+            attribution,
+            stats: SearchStats::default(),
+        }),
+        "chokudai" if timed => Box::new(TimedChokudaiSearchPolicy {
+            beam_width: get("width", 1),
+            beam_depth: get("depth", END_TURN),
+            time_threshold_us,
+            check_interval: get("check", CHOKUDAI_CHECK_INTERVAL),
+            eval_cache: turn_eval_cache,
+            stats: SearchStats::default(),
+        }),
+        "chokudai" => Box::new(ChokudaiSearchPolicy {
+            beam_width: get("width", 1),
+            beam_depth: get("depth", END_TURN),
+            beam_num: get("num", 2),
+            stats: SearchStats::default(),
+        }),
+        "parallel-chokudai" => Box::new(ParallelChokudaiSearchPolicy {
+            beam_width: get("width", 1),
+            beam_depth: get("depth", END_TURN),
+            time_threshold_us,
+            threads: ThreadConfig::new(get("threads", 0), false).threads,
+            shared_beams: get("share", 0) > 0,
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+            stats: SearchStats::default(),
+        }),
+        "parallel-beam" => Box::new(RootParallelBeamSearchPolicy {
+            beam_width: get("width", 5),
+            time_threshold_us,
+            threads: ThreadConfig::new(get("threads", 0), false).threads,
+            aggregation,
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+            stats: SearchStats::default(),
+        }),
+        "rhea" => Box::new(RheaPolicy::new(
+            RheaParams {
+                population: get("pop", 10),
+                length: get("len", 20),
+                mutation_percent: get("mut", 10),
+                max_generations: get("gens", 0),
+            },
+            time_threshold_us,
+            ChaCha12Rng::seed_from_u64(rng_seed),
+        )),
+        "iw" => Box::new(IwPolicy {
+            max_width: get("k", 2),
+            samples: get("samples", 16),
+            time_threshold_us,
+            stats: SearchStats::default(),
+        }),
+        "two-phase" => Box::new(TwoPhaseSearchPolicy {
+            wide_width: get("wide", 30),
+            wide_depth: get("wide_depth", 3),
+            narrow_width: get("narrow", 2),
+            shortlist_size: get("shortlist", 2),
+            time_threshold: get("ms", 10) as u128,
+            split_percent: get("split", 30) as u128,
+        }),
+        "annealed-beam" => Box::new(AnnealedBeamSearchPolicy {
+            beam_width: get("width", 5),
+            time_threshold: get("ms", 10) as u128,
+            schedule: TemperatureSchedule {
+                initial: get("temp", 4) as f64,
+                decay: get("decay", 80) as f64 / 100.,
+            },
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        "montecarlo" => Box::new(PrimitiveMonteCarloPolicy {
+            playout_num: get("playouts", 1000),
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        "mcts" => Box::new(MctsPolicy {
+            playout_budget: get("playouts", 1000),
+            c: get("c", 100) as f64 / 100.,
+            expand_threshold: get("expand", 10),
+            temperature: get("temp", 0) as f64 / 100.,
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        "softmax" => Box::new(SoftmaxPolicy {
+            temperature: get("temp", 100) as f64 / 100.,
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        "epsilon-greedy" => Box::new(EpsilonGreedyPolicy {
+            epsilon: get("eps", 10) as f64 / 100.,
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+        }),
+        _ => unreachable!("{name} is listed in POLICY_PARAMS"),
+    };
+    if let Some(phases) = phases {
+        if distance_weight.is_some() {
+            return Err(SpecError::Invalid(format!(
+                "dist and phases cannot be combined in {spec:?}"
+            )));
+        }
+        return Ok(Box::new(EvaluatorPolicy {
+            inner: policy,
+            phases,
+            active: None,
+        }));
+    }
+    if let Some(weight) = distance_weight {
+        return Ok(Box::new(EvaluatorPolicy::single(
+            policy,
+            Arc::new(NearestPointEvaluator { weight }),
+            format!("dist({weight})"),
+        )));
+    }
+    Ok(policy)
+}
+
+/// `parse_evaluator_phases` で使える評価関数の名前
+pub const EVALUATOR_NAMES: &[&str] = &["score", "potential", "dist", "bound"];
+
+/// `potential*100/dist*50@t30/bound@p200` のような局面ごとの評価関数の並び
+///
+/// `/` で区切った各要素は `名前[*重み[%]][@t開始ターン|@p残り得点の上限]` で、最初の要素は
+/// 条件を書かずにゲームの最初から使う。名前は `score` (スコアそのもの)、`potential`
+/// (`PotentialFieldEvaluator`、既定の重み100)、`dist` (`NearestPointEvaluator`、既定の重み50)、
+/// `bound` (`BoundEvaluator`)。
+pub fn parse_evaluator_phases(text: &str) -> Result<Vec<EvaluatorPhase>, SpecError> {
+    let mut phases = vec![];
+    for (i, item) in text.split('/').enumerate() {
+        let (body, start) = match item.split_once('@') {
+            Some((body, condition)) => {
+                let number = |digits: &str| {
+                    digits.parse().map_err(|_| {
+                        SpecError::Invalid(format!(
+                            "invalid phase condition {condition:?} in {text:?}"
+                        ))
+                    })
+                };
+                let start = if let Some(turn) = condition.strip_prefix('t') {
+                    PhaseStart::Turn(number(turn)?)
+                } else if let Some(points) = condition.strip_prefix('p') {
+                    PhaseStart::RemainingPoints(number(points)?)
+                } else {
+                    return Err(SpecError::Invalid(format!(
+                        "invalid phase condition {condition:?} in {text:?} (e.g. t30 or p200)"
+                    )));
+                };
+                (body, start)
+            }
+            None if i == 0 => (item, PhaseStart::Start),
+            None => {
+                return Err(SpecError::Invalid(format!(
+                    "phase {item:?} in {text:?} needs @t<turn> or @p<points>"
+                )))
+            }
+        };
+        let (name, weight) = match body.split_once('*') {
+            Some((name, weight)) => (
+                name,
+                Some(weight.parse::<i64>().map_err(|_| SpecError::InvalidParam {
+                    param: body.to_string(),
+                    spec: text.to_string(),
+                    expected: "name*<integer weight>",
+                })?),
+            ),
+            None => (body, None),
+        };
+        let (evaluator, label): (Arc<dyn Evaluator>, String) = match name {
+            "score" => (
+                Arc::new(|state: &State| Eval::from(state.game_score)),
+                "score".to_string(),
+            ),
+            "potential" => {
+                let weight = weight.unwrap_or(100);
+                (
+                    Arc::new(PotentialFieldEvaluator { weight }),
+                    format!("potential({weight})"),
+                )
+            }
+            "dist" => {
+                let weight = weight.unwrap_or(50);
+                (
+                    Arc::new(NearestPointEvaluator { weight }),
+                    format!("dist({weight})"),
+                )
+            }
+            "bound" => (Arc::new(BoundEvaluator), "bound".to_string()),
+            _ => return Err(SpecError::unknown_name("evaluator", name, EVALUATOR_NAMES)),
+        };
+        phases.push(EvaluatorPhase {
+            start,
+            evaluator,
+            label,
+        });
+    }
+    Ok(phases)
+}
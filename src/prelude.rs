@@ -0,0 +1,29 @@
+//! よく使う型とトレイトをまとめて読み込むためのモジュール
+//!
+//! ```
+//! use num_collect_maze::prelude::*;
+//!
+//! let mut policy = parse_policy("beam:width=3,depth=3", 0).unwrap();
+//! let result = run_game(0, policy.as_mut(), &PlayConfig::default());
+//! assert_eq!(result.actions.len(), END_TURN);
+//! ```
+//!
+//! 中のモジュールの構成を変えても、ここに並べた名前は同じ意味のまま残す。名前を消したり
+//! 意味を変えたりするのは、クレートの版の互換性のない更新 (0.x なら x を上げる) のときだけにする。
+//! 使える名前は `tests/prelude.rs` で確かめている。
+
+pub use crate::{
+    game::{
+        Coord, Direction, Eval, Evaluator, GameConfig, GameScore, GameState, MazeState, State,
+        END_TURN, H, W,
+    },
+    play::{run_game, PlayConfig},
+    policy::{parse_policy, Policy},
+    results::GameResult,
+    runner::{GameRunner, TurnControl, TurnStats},
+    search::{
+        beam_search_action, chokudai_search_action, greedy_action, RheaParams, RootAggregation,
+        SearchError, SearchStats, StopRule, TemperatureSchedule,
+    },
+    spec::SpecError,
+};
@@ -0,0 +1,206 @@
+//! 盤面とAIの設定をその場で変えながら試す対話モード
+
+use std::{
+    io::{BufRead, Write},
+    time::Instant,
+};
+
+use crate::{
+    game::{Direction, MazeState, State},
+    policy::{parse_policy, ActionProbabilities, Policy},
+    util::{with_cancel_token, CancelToken},
+};
+
+const HELP: &str = "\
+commands:
+  load <seed>         シードから盤面を生成する
+  load-file <path>    ファイルから盤面を読み込む
+  show                盤面を表示する
+  policy <spec>       step/play で使うAIを設定する (例: beam:width=10,depth=3)
+  eval [spec]         合法手ごとのAIの見積もりを表示する
+  run [spec]          AIが選ぶ手と思考時間を表示する (盤面は進めない)
+  step [R|L|D|U|spec] 指定した行動かAIの選んだ手で1手進める
+  play [spec]         AIで最後まで進める
+  undo [n]            n手戻す (既定は1)
+  help                この一覧を表示する
+  quit                終了する";
+
+/// 対話モードの状態
+///
+/// 盤面は手を進めるたびに履歴に積むので、`undo` でいつでも戻せる。
+pub struct Repl {
+    pub history: Vec<State>,
+    pub policy_spec: String,
+    /// AIを作るたびに変える乱数のシード
+    pub rng_seed: u64,
+    /// 思考中の探索を打ち切らせるトークン (キルファイルやシグナル)
+    pub cancel_token: CancelToken,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl {
+            history: vec![State::new(0)],
+            policy_spec: "beam:width=5,depth=10".to_string(),
+            rng_seed: 0,
+            cancel_token: CancelToken::default(),
+        }
+    }
+}
+
+impl Repl {
+    pub fn state(&self) -> &State {
+        self.history.last().unwrap()
+    }
+
+    fn policy(&mut self, spec: Option<&str>) -> Result<Box<dyn Policy>, String> {
+        self.rng_seed += 1;
+        parse_policy(spec.unwrap_or(&self.policy_spec), self.rng_seed).map_err(String::from)
+    }
+
+    /// AIに手を選ばせる。キャンセルされたらその時点の最善手を返し、3つ目の値をtrueにする
+    ///
+    /// 確率的なAIなら2つ目の値に手を引いた分布を返す。
+    fn select_action(
+        &self,
+        policy: &mut dyn Policy,
+    ) -> (Direction, Option<ActionProbabilities>, bool) {
+        let (action, probabilities) =
+            with_cancel_token(&self.cancel_token, || policy.sample_action(self.state()));
+        (action, probabilities, self.cancel_token.reset())
+    }
+
+    fn advance(&mut self, action: Direction) -> String {
+        let mut next = self.state().clone();
+        next.advance(action);
+        let line = format!("{action} {}", self.state().diff(&next));
+        self.history.push(next);
+        line
+    }
+
+    /// 1行のコマンドを実行して表示する内容を返す (`quit` ならNone)
+    pub fn execute(&mut self, line: &str) -> Option<Result<String, String>> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next();
+        let result = match command {
+            "" => Ok(String::new()),
+            "quit" | "exit" => return None,
+            "help" => Ok(HELP.to_string()),
+            "load" => arg
+                .unwrap_or("0")
+                .parse()
+                .map_err(|_| "seed must be an integer".to_string())
+                .map(|seed| {
+                    self.history = vec![State::new(seed)];
+                    self.state().to_string()
+                }),
+            "load-file" => std::fs::read_to_string(arg.unwrap_or(""))
+                .map_err(|e| e.to_string())
+                .and_then(|text| MazeState::from_text(&text))
+                .map(|state| {
+                    self.history = vec![state];
+                    self.state().to_string()
+                }),
+            "show" => Ok(self.state().to_string()),
+            "policy" => match arg {
+                Some(spec) => parse_policy(spec, 0).map_err(String::from).map(|policy| {
+                    self.policy_spec = spec.to_string();
+                    format!("policy: {}", policy.name())
+                }),
+                None => Ok(format!("policy: {}", self.policy_spec)),
+            },
+            "eval" => self.policy(arg).map(|mut policy| {
+                let mut values = policy.evaluate_actions(self.state());
+                values.sort_by(|a, b| b.1.total_cmp(&a.1));
+                values
+                    .iter()
+                    .map(|&(action, value)| format!("{action} {value}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+            "run" => self.policy(arg).map(|mut policy| {
+                let start = Instant::now();
+                let (action, probabilities, cancelled) = self.select_action(policy.as_mut());
+                let mut text = format!(
+                    "{}: {action} ({} us){}",
+                    policy.name(),
+                    start.elapsed().as_micros(),
+                    if cancelled { " cancelled" } else { "" }
+                );
+                // 確率的なAIなら引いた分布も表示する
+                if let Some(probabilities) = probabilities {
+                    for action in self.state().legal_actions() {
+                        let p = probabilities[action.index()];
+                        text.push_str(&format!("\n  {action} {p:.3}"));
+                    }
+                }
+                text
+            }),
+            _ if self.state().is_done() && (command == "step" || command == "play") => {
+                Err("the game is over".to_string())
+            }
+            "step" => match arg.and_then(|a| a.parse::<Direction>().ok()) {
+                Some(action) if self.state().legal_actions().contains(&action) => {
+                    Ok(self.advance(action))
+                }
+                Some(_) => Err("illegal action".to_string()),
+                None => self.policy(arg).map(|mut policy| {
+                    let (action, _, cancelled) = self.select_action(policy.as_mut());
+                    let line = self.advance(action);
+                    if cancelled {
+                        format!("{line} (cancelled)")
+                    } else {
+                        line
+                    }
+                }),
+            },
+            "play" => self.policy(arg).map(|mut policy| {
+                let start = self.state().clone();
+                let mut cancelled_moves = 0;
+                while !self.state().is_done() {
+                    let (action, _, cancelled) = self.select_action(policy.as_mut());
+                    cancelled_moves += cancelled as usize;
+                    self.advance(action);
+                }
+                format!(
+                    "{}: {} (cancelled moves: {cancelled_moves})",
+                    policy.name(),
+                    start.diff(self.state())
+                )
+            }),
+            "undo" => arg
+                .unwrap_or("1")
+                .parse::<usize>()
+                .map_err(|_| "n must be an integer".to_string())
+                .map(|n| {
+                    let keep = self.history.len().saturating_sub(n).max(1);
+                    self.history.truncate(keep);
+                    format!("turn {}", self.state().turn)
+                }),
+            _ => Err(format!("unknown command {command:?} (try help)")),
+        };
+        Some(result)
+    }
+}
+
+/// `input` からコマンドを1行ずつ読んで実行する
+pub fn run_repl(
+    mut repl: Repl,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match repl.execute(&line?) {
+            None => break,
+            Some(Ok(text)) if text.is_empty() => {}
+            Some(Ok(text)) => writeln!(output, "{}", text.trim_end())?,
+            Some(Err(message)) => writeln!(output, "error: {message}")?,
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,425 @@
+//! 実験のディレクトリの結果から、共有できる1ファイルのレポート (HTML/Markdown) を作る
+//!
+//! ディレクトリ内の結果のJSONLとマニフェストだけから作るので、AIを動かし直さずに
+//! 表・グラフ・最良の設定・スコアの低いシード・盤面の例をまとめて見せられる。
+
+use std::fmt::Write;
+
+use crate::{
+    game::State,
+    results::{
+        read_manifest, read_results_jsonl, render_trajectory_strip, GameResult, RunManifest,
+    },
+    stats::RunningStats,
+};
+
+/// 結果のJSONL1つ分
+pub struct RunReport {
+    /// ディレクトリ内のファイル名
+    pub file: String,
+    pub manifest: Option<RunManifest>,
+    pub results: Vec<GameResult>,
+}
+
+/// 実験のディレクトリ全体のレポート
+pub struct ExperimentReport {
+    pub dir: String,
+    pub runs: Vec<RunReport>,
+}
+
+/// 表に並べるスコアの低いゲームの数
+pub const WORST_SEEDS: usize = 10;
+/// ヒストグラムの棒の数
+const HISTOGRAM_BINS: usize = 20;
+
+fn score_stats<'a>(results: impl IntoIterator<Item = &'a GameResult>) -> RunningStats {
+    let mut stats = RunningStats::default();
+    for result in results {
+        stats.push(result.game_score as f64);
+    }
+    stats
+}
+
+fn mean_move_us(results: &[&GameResult]) -> f64 {
+    let (sum, moves) = results.iter().fold((0u128, 0usize), |(sum, moves), r| {
+        (
+            sum + r.move_times_us.iter().map(|&t| t as u128).sum::<u128>(),
+            moves + r.move_times_us.len(),
+        )
+    });
+    sum as f64 / moves.max(1) as f64
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 盤面 `seed` で行動列 `actions` を最後まで進めた盤面
+fn final_board(result: &GameResult) -> String {
+    let mut state = State::new(result.seed);
+    for &action in &result.actions {
+        state.advance(action.into());
+    }
+    state.to_string()
+}
+
+impl ExperimentReport {
+    /// ディレクトリ内の `*.jsonl` をファイル名順に全て読み込む
+    pub fn from_dir(dir: &str) -> std::io::Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "jsonl"));
+        paths.sort();
+        let mut runs = vec![];
+        for path in paths {
+            let file = path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string());
+            let path = path.to_string_lossy();
+            runs.push(RunReport {
+                file,
+                manifest: read_manifest(&path),
+                results: read_results_jsonl(&path)?,
+            });
+        }
+        Ok(Self {
+            dir: dir.to_string(),
+            runs,
+        })
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = &GameResult> {
+        self.runs.iter().flat_map(|run| &run.results)
+    }
+
+    /// AIごとのスコアの統計を平均の高い順に並べたもの
+    pub fn policy_ranking(&self) -> Vec<(String, Vec<&GameResult>, RunningStats)> {
+        let mut by_policy: Vec<(String, Vec<&GameResult>)> = vec![];
+        for result in self.results() {
+            match by_policy
+                .iter_mut()
+                .find(|(name, _)| *name == result.policy)
+            {
+                Some((_, results)) => results.push(result),
+                None => by_policy.push((result.policy.clone(), vec![result])),
+            }
+        }
+        let mut ranking: Vec<_> = by_policy
+            .into_iter()
+            .map(|(name, results)| {
+                let stats = score_stats(results.iter().copied());
+                (name, results, stats)
+            })
+            .collect();
+        ranking.sort_by(|a, b| b.2.mean.total_cmp(&a.2.mean).then_with(|| a.0.cmp(&b.0)));
+        ranking
+    }
+
+    /// スコアの低い順に `n` ゲーム (同じスコアならシードの小さい順)
+    pub fn worst_games(&self, n: usize) -> Vec<&GameResult> {
+        let mut results: Vec<_> = self.results().collect();
+        results.sort_by_key(|r| (r.game_score, r.seed));
+        results.truncate(n);
+        results
+    }
+
+    /// 最良のAIの最高と最低のゲーム (盤面の例に使う)
+    fn example_games(&self) -> Vec<&GameResult> {
+        let ranking = self.policy_ranking();
+        let Some((_, results, _)) = ranking.first() else {
+            return vec![];
+        };
+        let best = results
+            .iter()
+            .max_by_key(|r| (r.game_score, std::cmp::Reverse(r.seed)));
+        let worst = results.iter().min_by_key(|r| (r.game_score, r.seed));
+        let mut examples: Vec<&GameResult> = best.into_iter().copied().collect();
+        if let Some(&worst) = worst {
+            if examples.iter().all(|r| !std::ptr::eq(*r, worst)) {
+                examples.push(worst);
+            }
+        }
+        examples
+    }
+
+    /// 全ゲームのスコアのヒストグラム (`(下端, 上端, 数)` の並び)
+    pub fn score_histogram(&self) -> Vec<(usize, usize, usize)> {
+        let stats = score_stats(self.results());
+        if stats.count == 0 {
+            return vec![];
+        }
+        let (min, max) = (stats.min as usize, stats.max as usize);
+        let width = (max - min).div_ceil(HISTOGRAM_BINS).max(1);
+        let mut bins: Vec<_> = (0..=(max - min) / width)
+            .map(|i| (min + i * width, min + (i + 1) * width - 1, 0))
+            .collect();
+        for result in self.results() {
+            bins[(result.game_score - min) / width].2 += 1;
+        }
+        bins
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let total = score_stats(self.results());
+        writeln!(out, "# Experiment report: {}\n", self.dir).unwrap();
+        writeln!(
+            out,
+            "{} runs, {} games, mean score {:.2}\n",
+            self.runs.len(),
+            total.count,
+            total.mean
+        )
+        .unwrap();
+
+        writeln!(out, "## Runs\n").unwrap();
+        writeln!(out, "| file | kind | tags | games | mean | min | max |").unwrap();
+        writeln!(out, "|---|---|---|---:|---:|---:|---:|").unwrap();
+        for run in &self.runs {
+            let stats = score_stats(&run.results);
+            let (kind, tags) = run
+                .manifest
+                .as_ref()
+                .map_or((String::new(), String::new()), |m| {
+                    (m.kind.clone(), m.meta.tags.join(","))
+                });
+            writeln!(
+                out,
+                "| {} | {kind} | {tags} | {} | {:.2} | {} | {} |",
+                run.file, stats.count, stats.mean, stats.min, stats.max
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\n## Best configs\n").unwrap();
+        writeln!(
+            out,
+            "| rank | policy | games | mean | ±95% CI | mean move [us] |"
+        )
+        .unwrap();
+        writeln!(out, "|---:|---|---:|---:|---:|---:|").unwrap();
+        for (rank, (policy, results, stats)) in self.policy_ranking().iter().enumerate() {
+            writeln!(
+                out,
+                "| {} | `{policy}` | {} | {:.2} | {:.2} | {:.0} |",
+                rank + 1,
+                stats.count,
+                stats.mean,
+                stats.ci95_half_width(),
+                mean_move_us(results)
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\n## Score distribution\n").unwrap();
+        writeln!(out, "```").unwrap();
+        let histogram = self.score_histogram();
+        let peak = histogram.iter().map(|bin| bin.2).max().unwrap_or(0).max(1);
+        for (low, high, count) in &histogram {
+            writeln!(
+                out,
+                "{low:>5}-{high:<5} {:<40} {count}",
+                "#".repeat(count * 40 / peak)
+            )
+            .unwrap();
+        }
+        writeln!(out, "```").unwrap();
+
+        writeln!(out, "\n## Worst seeds\n").unwrap();
+        writeln!(
+            out,
+            "| seed | policy | score | wasted turns | uncollected |"
+        )
+        .unwrap();
+        writeln!(out, "|---:|---|---:|---:|---:|").unwrap();
+        for result in self.worst_games(WORST_SEEDS) {
+            writeln!(
+                out,
+                "| {} | `{}` | {} | {} | {} |",
+                result.seed,
+                result.policy,
+                result.game_score,
+                result.wasted_turns,
+                result.uncollected_points
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\n## Example boards\n").unwrap();
+        for result in self.example_games() {
+            writeln!(
+                out,
+                "seed {} `{}` score {} (final board)\n\n```\n{}```\n",
+                result.seed,
+                result.policy,
+                result.game_score,
+                final_board(result)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// 外部のファイルを参照しない1ファイルのHTML (グラフと盤面はインラインのSVG)
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let total = score_stats(self.results());
+        let dir = escape_html(&self.dir);
+        writeln!(
+            out,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Experiment report: {dir}</title>\n\
+             <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 8px}}td.n{{text-align:right}}</style>\n\
+             </head><body>\n<h1>Experiment report: {dir}</h1>\n<p>{} runs, {} games, mean score {:.2}</p>",
+            self.runs.len(),
+            total.count,
+            total.mean
+        )
+        .unwrap();
+
+        writeln!(out, "<h2>Runs</h2>\n<table>\n<tr><th>file</th><th>kind</th><th>tags</th><th>note</th><th>games</th><th>mean</th><th>min</th><th>max</th></tr>").unwrap();
+        for run in &self.runs {
+            let stats = score_stats(&run.results);
+            let (kind, tags, note) = run.manifest.as_ref().map_or(Default::default(), |m| {
+                (
+                    m.kind.clone(),
+                    m.meta.tags.join(","),
+                    m.meta.note.clone().unwrap_or_default(),
+                )
+            });
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{:.2}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+                escape_html(&run.file),
+                escape_html(&kind),
+                escape_html(&tags),
+                escape_html(&note),
+                stats.count,
+                stats.mean,
+                stats.min,
+                stats.max
+            )
+            .unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+
+        let ranking = self.policy_ranking();
+        writeln!(out, "<h2>Best configs</h2>\n<table>\n<tr><th>rank</th><th>policy</th><th>games</th><th>mean</th><th>±95% CI</th><th>mean move [us]</th></tr>").unwrap();
+        for (rank, (policy, results, stats)) in ranking.iter().enumerate() {
+            writeln!(
+                out,
+                "<tr><td class=\"n\">{}</td><td><code>{}</code></td><td class=\"n\">{}</td><td class=\"n\">{:.2}</td><td class=\"n\">{:.2}</td><td class=\"n\">{:.0}</td></tr>",
+                rank + 1,
+                escape_html(policy),
+                stats.count,
+                stats.mean,
+                stats.ci95_half_width(),
+                mean_move_us(results)
+            )
+            .unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+        out.push_str(&self.mean_score_chart(&ranking));
+
+        writeln!(out, "<h2>Score distribution</h2>").unwrap();
+        out.push_str(&self.histogram_chart());
+
+        writeln!(out, "<h2>Worst seeds</h2>\n<table>\n<tr><th>seed</th><th>policy</th><th>score</th><th>wasted turns</th><th>uncollected</th></tr>").unwrap();
+        for result in self.worst_games(WORST_SEEDS) {
+            writeln!(
+                out,
+                "<tr><td class=\"n\">{}</td><td><code>{}</code></td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+                result.seed,
+                escape_html(&result.policy),
+                result.game_score,
+                result.wasted_turns,
+                result.uncollected_points
+            )
+            .unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+
+        let examples: Vec<GameResult> = self.example_games().into_iter().cloned().collect();
+        if !examples.is_empty() {
+            writeln!(out, "<h2>Example boards</h2>").unwrap();
+            out.push_str(&render_trajectory_strip(&examples, examples.len()));
+        }
+        writeln!(out, "</body></html>").unwrap();
+        out
+    }
+
+    /// AIごとの平均スコアと95%信頼区間の横棒グラフ
+    fn mean_score_chart(&self, ranking: &[(String, Vec<&GameResult>, RunningStats)]) -> String {
+        const ROW: usize = 20;
+        const LABEL: usize = 320;
+        const BAR: f64 = 400.;
+        let max = ranking
+            .iter()
+            .map(|(_, _, stats)| stats.max)
+            .fold(1., f64::max);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"11\">\n",
+            LABEL + BAR as usize + 80,
+            ROW * ranking.len() + 4
+        );
+        for (i, (policy, _, stats)) in ranking.iter().enumerate() {
+            let y = i * ROW + 2;
+            let width = stats.mean / max * BAR;
+            let ci = stats.ci95_half_width().min(max);
+            let (low, high) = (
+                LABEL as f64 + ((stats.mean - ci) / max * BAR).max(0.),
+                LABEL as f64 + ((stats.mean + ci) / max * BAR).min(BAR),
+            );
+            let mid = y + ROW / 2;
+            writeln!(
+                svg,
+                "<text x=\"0\" y=\"{}\">{}</text>\n<rect x=\"{LABEL}\" y=\"{}\" width=\"{width:.1}\" height=\"{}\" fill=\"#4a7\"/>\n\
+                 <line x1=\"{low:.1}\" y1=\"{mid}\" x2=\"{high:.1}\" y2=\"{mid}\" stroke=\"#000\"/>\n\
+                 <text x=\"{:.1}\" y=\"{}\">{:.1}</text>",
+                mid + 4,
+                escape_html(policy),
+                y + 3,
+                ROW - 6,
+                LABEL as f64 + BAR + 6.,
+                mid + 4,
+                stats.mean
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// 全ゲームのスコアのヒストグラムの縦棒グラフ
+    fn histogram_chart(&self) -> String {
+        const BIN: usize = 30;
+        const HEIGHT: f64 = 160.;
+        let histogram = self.score_histogram();
+        let peak = histogram.iter().map(|bin| bin.2).max().unwrap_or(0).max(1);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"10\">\n",
+            BIN * histogram.len().max(1),
+            HEIGHT as usize + 30
+        );
+        for (i, (low, high, count)) in histogram.iter().enumerate() {
+            let height = *count as f64 / peak as f64 * HEIGHT;
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{:.1}\" width=\"{}\" height=\"{height:.1}\" fill=\"#06c\"><title>{low}-{high}: {count}</title></rect>\n\
+                 <text x=\"{}\" y=\"{}\">{low}</text>",
+                i * BIN + 1,
+                HEIGHT + 10. - height,
+                BIN - 2,
+                i * BIN,
+                HEIGHT as usize + 24
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
@@ -0,0 +1,587 @@
+//! ゲームの結果の保存・読み込み・集計
+
+use core::fmt;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::ResultDb,
+    game::{Coord, Direction, State, ACTION_NAMES, H, W},
+    latency::LatencyHistogram,
+    policy::ActionProbabilities,
+    schema::{parse_game_log, parse_result_line},
+    search::SearchStats,
+    stats::{RunningStats, TDigest},
+    util::{fnv1a, FNV_OFFSET_BASIS},
+};
+
+/// 1ゲーム分の結果
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameResult {
+    /// 行の形式の版 (`schema::RESULTS_SCHEMA_VERSION`)
+    pub schema_version: u32,
+    pub instance_format_version: u32,
+    pub seed: u64,
+    pub instance_hash: String,
+    pub policy: String,
+    pub game_score: usize,
+    /// 得点のないマスに移動したターン数
+    pub wasted_turns: usize,
+    /// ゲーム終了時に盤面に残っていた得点の合計
+    pub uncollected_points: usize,
+    pub actions: Vec<usize>,
+    /// 各ターンに確率的なAIが手を引いた分布 (`--action-probs` のときだけ記録する)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub action_probs: Vec<Option<ActionProbabilities>>,
+    /// 各ターンに使った評価関数の名前 (評価関数を差し替えるAIのときだけ記録する。AIが決めなかったターンは空)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evaluators: Vec<String>,
+    /// 各ターンの行動決定にかかった時間[us]
+    pub move_times_us: Vec<u64>,
+    /// 得点できなくなり探索せずに進め始めたターン
+    pub fast_forwarded_from: Option<usize>,
+    /// 終盤の全探索に切り替えたターン
+    #[serde(default)]
+    pub endgame_from: Option<usize>,
+    /// 時間制限を1ms以上超過した手の数
+    #[serde(default)]
+    pub deadline_overruns: usize,
+    /// ゲーム全体で探索が展開・複製した状態の数など (数えている探索のAIだけ)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_stats: Option<SearchStats>,
+    /// 選んだ行動列のハッシュ。同じ設定の実行が同一の結果になったかを比べるのに使う
+    pub token: String,
+}
+
+pub fn write_results_jsonl(results: &[GameResult], path: &str) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for result in results {
+        serde_json::to_writer(&mut writer, result)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// 実行に付ける注記 (`--tag`, `--note`)
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct RunMeta {
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// 結果のJSONLと並べて書き出す実行の情報
+#[derive(Serialize, Deserialize)]
+pub struct RunManifest {
+    /// bench/sweep/compare など
+    pub kind: String,
+    pub crate_version: String,
+    /// UNIX時間[s]
+    pub created_at: u64,
+    #[serde(flatten)]
+    pub meta: RunMeta,
+}
+
+/// 結果のJSONL `foo.jsonl` に対応するマニフェストのパス (`foo.manifest.json`)
+pub fn manifest_path(results_path: &str) -> String {
+    let stem = results_path.strip_suffix(".jsonl").unwrap_or(results_path);
+    format!("{stem}.manifest.json")
+}
+
+pub fn write_manifest(results_path: &str, kind: &str, meta: &RunMeta) -> std::io::Result<()> {
+    let manifest = RunManifest {
+        kind: kind.to_string(),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        meta: meta.clone(),
+    };
+    let writer = BufWriter::new(File::create(manifest_path(results_path))?);
+    serde_json::to_writer_pretty(writer, &manifest)?;
+    Ok(())
+}
+
+/// 結果のJSONLに対応するマニフェストを読む (なければNone)
+pub fn read_manifest(results_path: &str) -> Option<RunManifest> {
+    let text = std::fs::read_to_string(manifest_path(results_path)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// 結果を1つずつ足し込んで作る実行全体の集計
+pub struct RunSummary {
+    pub num: usize,
+    pub score: RunningStats,
+    /// スコアの分位点
+    pub score_quantiles: TDigest,
+    pub wasted_turns_sum: usize,
+    pub uncollected_points_sum: usize,
+    pub first_move_us_sum: u64,
+    pub move_us_sum: u64,
+    /// 各ゲームのトークンをシード順にハッシュしたもの
+    pub token: u64,
+    pub latency: LatencyHistogram,
+    /// メモリ上限のためにディスクへ書き出した結果の数
+    pub spilled: usize,
+    /// 全ゲームの探索の記録の合計 (記録のあるゲームがなければNone)
+    pub search_stats: Option<SearchStats>,
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self {
+            num: 0,
+            score: RunningStats::default(),
+            score_quantiles: TDigest::default(),
+            wasted_turns_sum: 0,
+            uncollected_points_sum: 0,
+            first_move_us_sum: 0,
+            move_us_sum: 0,
+            token: FNV_OFFSET_BASIS,
+            latency: LatencyHistogram::default(),
+            spilled: 0,
+            search_stats: None,
+        }
+    }
+}
+
+impl RunSummary {
+    pub fn add(&mut self, result: &GameResult) {
+        self.num += 1;
+        self.score.push(result.game_score as f64);
+        self.score_quantiles.push(result.game_score as f64);
+        self.wasted_turns_sum += result.wasted_turns;
+        self.uncollected_points_sum += result.uncollected_points;
+        self.first_move_us_sum += result.move_times_us.first().copied().unwrap_or(0);
+        self.move_us_sum += result.move_times_us.iter().sum::<u64>();
+        self.token = fnv1a(self.token, result.token.as_bytes());
+        self.latency.record_game(result);
+        if let Some(search_stats) = &result.search_stats {
+            self.search_stats
+                .get_or_insert_with(SearchStats::default)
+                .add(search_stats);
+        }
+    }
+}
+
+impl GameResult {
+    /// メモリ上で占めるおおよそのバイト数
+    pub fn approx_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.instance_hash.len()
+            + self.policy.len()
+            + self.token.len()
+            + self.actions.len() * std::mem::size_of::<usize>()
+            + self.move_times_us.len() * std::mem::size_of::<u64>()
+    }
+}
+
+/// 結果を受け取って集計し、メモリ上限に近づいたらディスクへ書き出す
+pub struct ResultSink {
+    pub results: Vec<GameResult>,
+    pub memory_used: usize,
+    /// メモリに保持する結果の上限[byte] (Noneなら無制限)
+    pub memory_cap: Option<usize>,
+    pub spill_path: String,
+    pub spill: Option<BufWriter<File>>,
+    pub summary: RunSummary,
+    /// 結果を逐次登録するデータベースと実行のid
+    pub db: Option<(ResultDb, i64)>,
+}
+
+impl ResultSink {
+    pub fn new(memory_cap: Option<usize>, spill_path: String) -> Self {
+        Self {
+            results: vec![],
+            memory_used: 0,
+            memory_cap,
+            spill_path,
+            spill: None,
+            summary: RunSummary::default(),
+            db: None,
+        }
+    }
+
+    /// 結果を `kind` の実行としてデータベースにも登録する
+    pub fn with_db(mut self, db: ResultDb, kind: &str, meta: &RunMeta) -> rusqlite::Result<Self> {
+        let run_id = db.start_run(kind, meta)?;
+        self.db = Some((db, run_id));
+        Ok(self)
+    }
+
+    pub fn push(&mut self, result: GameResult) -> std::io::Result<()> {
+        if let Some((db, run_id)) = &self.db {
+            db.insert_game(*run_id, &result)
+                .map_err(std::io::Error::other)?;
+        }
+        self.summary.add(&result);
+        self.memory_used += result.approx_bytes();
+        self.results.push(result);
+        // 上限の9割を超えたら書き出しに切り替える
+        if self
+            .memory_cap
+            .is_some_and(|cap| self.memory_used * 10 >= cap * 9)
+        {
+            self.summary.spilled += self.results.len();
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// メモリ上の結果を全てファイルに追記して手放す
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if self.spill.is_none() {
+            self.spill = Some(BufWriter::new(File::create(&self.spill_path)?));
+        }
+        let writer = self.spill.as_mut().unwrap();
+        for result in self.results.drain(..) {
+            serde_json::to_writer(&mut *writer, &result)?;
+            writeln!(writer)?;
+        }
+        self.memory_used = 0;
+        writer.flush()
+    }
+}
+
+/// 結果のJSONLを読む (各行の版と中身を確かめ、誤りは `path:行` 付きで返す)
+pub fn read_results_jsonl(path: &str) -> std::io::Result<Vec<GameResult>> {
+    let text = std::fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Ok(parse_result_line(line, &format!("{path}:{}", i + 1))?))
+        .collect()
+}
+
+/// `play --log` で書き出す1ゲームの全ターンの記録
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameLog {
+    /// 記録の形式の版 (`schema::GAME_LOG_SCHEMA_VERSION`)
+    pub schema_version: u32,
+    pub seed: u64,
+    pub instance_hash: String,
+    pub policy: String,
+    pub game_score: usize,
+    /// 開始時の盤面 (1行1文字列、`@` がキャラクター)
+    pub initial_board: Vec<String>,
+    pub turns: Vec<TurnLog>,
+}
+
+/// 1ターン分の記録
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurnLog {
+    /// 行動したターン (0始まり)
+    pub turn: usize,
+    /// 選んだ行動 (`R`, `L`, `D`, `U`)
+    pub action: String,
+    /// 行動した後のスコア
+    pub game_score: usize,
+    /// 行動した後の盤面の評価値
+    pub evaluated_score: i64,
+    /// 行動決定にかかった時間[us] (探索せずに進めたターンは0)
+    pub elapsed_us: u64,
+    /// 行動した後の盤面
+    pub board: Vec<String>,
+}
+
+/// 盤面を `GameLog` に書く形 (1行1文字列) にする
+pub fn board_rows(state: &State) -> Vec<String> {
+    state
+        .to_string()
+        .lines()
+        .skip_while(|line| line.contains('\t'))
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn write_game_log(log: &GameLog, path: &str) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, log)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// `path` がJSONのゲームの記録なら版と中身を確かめて読み込む (結果のJSONLなどそれ以外ならNone)
+pub fn read_game_log(path: &str) -> std::io::Result<Option<GameLog>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(parse_game_log(&text, path)?)
+}
+
+/// 同じシードの2つのリプレイを並べて進め、行動が分かれたターンとその後の状態の違いを返す
+pub fn replay_diff(a: &GameResult, b: &GameResult) -> String {
+    let mut out = format!("seed {}: {} vs {}\n", a.seed, a.policy, b.policy);
+    let mut state_a = State::new(a.seed);
+    let mut state_b = State::new(b.seed);
+    let mut diverged = false;
+    for turn in 0..a.actions.len().max(b.actions.len()) {
+        let (action_a, action_b) = (a.actions.get(turn), b.actions.get(turn));
+        if let Some(&action) = action_a {
+            state_a.advance(action.into());
+        }
+        if let Some(&action) = action_b {
+            state_b.advance(action.into());
+        }
+        if !diverged && action_a != action_b {
+            diverged = true;
+            let name = |action: Option<&usize>| action.map_or("-", |&a| Direction::from(a).name());
+            out.push_str(&format!(
+                "  diverged at turn {turn}: {} vs {}\n",
+                name(action_a),
+                name(action_b)
+            ));
+        }
+    }
+    if !diverged {
+        out.push_str("  identical\n");
+    }
+    out.push_str(&format!("  final: {}\n", state_a.diff(&state_b)));
+    out
+}
+
+/// 複数のリプレイ(結果のJSONL)を横断した統計
+pub struct ReplayStats {
+    pub scores: Vec<usize>,
+    pub policies: BTreeMap<String, Vec<usize>>,
+    pub move_time_sum_us: u128,
+    pub moves: usize,
+    pub action_counts: [usize; 4],
+    /// 各マスにキャラクターがいたターン数
+    pub visits: Vec<Vec<usize>>,
+}
+
+impl Default for ReplayStats {
+    fn default() -> Self {
+        ReplayStats {
+            scores: vec![],
+            policies: BTreeMap::new(),
+            move_time_sum_us: 0,
+            moves: 0,
+            action_counts: [0; 4],
+            visits: vec![vec![0; W]; H],
+        }
+    }
+}
+
+impl ReplayStats {
+    pub fn add(&mut self, result: &GameResult) {
+        self.scores.push(result.game_score);
+        self.policies
+            .entry(result.policy.clone())
+            .or_default()
+            .push(result.game_score);
+        self.move_time_sum_us += result
+            .move_times_us
+            .iter()
+            .map(|&t| t as u128)
+            .sum::<u128>();
+        self.moves += result.move_times_us.len();
+        let mut state = State::new(result.seed);
+        for &action in &result.actions {
+            self.action_counts[action] += 1;
+            state.advance(action.into());
+            self.visits[state.character.y as usize][state.character.x as usize] += 1;
+        }
+    }
+
+    /// ディレクトリ内の `*.jsonl` を全て読み込む
+    /// `tag` を指定すると、マニフェストにそのタグが付いた結果だけを集計する
+    pub fn from_dir(dir: &str, tag: Option<&str>) -> std::io::Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "jsonl"));
+        paths.sort();
+        let mut stats = ReplayStats::default();
+        for path in paths {
+            let path = path.to_string_lossy();
+            if let Some(tag) = tag {
+                let tagged = read_manifest(&path)
+                    .is_some_and(|manifest| manifest.meta.tags.iter().any(|t| t == tag));
+                if !tagged {
+                    continue;
+                }
+            }
+            for result in read_results_jsonl(&path)? {
+                stats.add(&result);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "games: {}", self.scores.len())?;
+        if self.scores.is_empty() {
+            return Ok(());
+        }
+        let mut sorted = self.scores.clone();
+        sorted.sort_unstable();
+        let quantile = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+        let mean = |scores: &[usize]| scores.iter().sum::<usize>() as f64 / scores.len() as f64;
+        writeln!(
+            f,
+            "score: mean {:.1} min {} p25 {} p50 {} p75 {} max {}",
+            mean(&self.scores),
+            sorted[0],
+            quantile(0.25),
+            quantile(0.5),
+            quantile(0.75),
+            sorted[sorted.len() - 1]
+        )?;
+        for (policy, scores) in &self.policies {
+            writeln!(
+                f,
+                "  {policy}: {} games, mean {:.1}",
+                scores.len(),
+                mean(scores)
+            )?;
+        }
+
+        writeln!(f, "score histogram:")?;
+        const BINS: usize = 10;
+        let (lo, hi) = (sorted[0], sorted[sorted.len() - 1]);
+        let bin_width = (hi - lo).div_ceil(BINS).max(1);
+        let mut bins = [0; BINS];
+        for &score in &sorted {
+            bins[((score - lo) / bin_width).min(BINS - 1)] += 1;
+        }
+        let max_bin = *bins.iter().max().unwrap();
+        for (i, &count) in bins.iter().enumerate() {
+            let from = lo + i * bin_width;
+            if from > hi {
+                break;
+            }
+            writeln!(
+                f,
+                "  {:>5}-{:<5} {:>5} {}",
+                from,
+                from + bin_width - 1,
+                count,
+                "#".repeat(count * 40 / max_bin)
+            )?;
+        }
+
+        if self.moves > 0 {
+            writeln!(
+                f,
+                "move_us_mean: {:.1}",
+                self.move_time_sum_us as f64 / self.moves as f64
+            )?;
+        }
+        let total_actions = self.action_counts.iter().sum::<usize>().max(1);
+        write!(f, "actions:")?;
+        for (name, &count) in ACTION_NAMES.iter().zip(&self.action_counts) {
+            write!(
+                f,
+                " {name} {count} ({:.1}%)",
+                count as f64 * 100. / total_actions as f64
+            )?;
+        }
+        writeln!(f)?;
+
+        // 訪問回数を濃淡の文字で表したヒートマップ
+        const SHADES: &[u8] = b" .:-=+*#%@";
+        let max_visits = self
+            .visits
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        writeln!(f, "visit heatmap (max {max_visits}):")?;
+        for row in &self.visits {
+            let line: String = row
+                .iter()
+                .map(|&v| {
+                    let shade = if v == 0 {
+                        0
+                    } else {
+                        1 + (v - 1) * (SHADES.len() - 2) / max_visits
+                    };
+                    SHADES[shade] as char
+                })
+                .collect();
+            writeln!(f, "  |{line}|")?;
+        }
+        Ok(())
+    }
+}
+
+/// 1マスの大きさ[px]
+pub const SVG_CELL: usize = 8;
+/// パネル上部のタイトルの高さ[px]
+pub const SVG_TITLE: usize = 16;
+
+/// 複数のゲームの軌跡を横に並べたSVGを作る
+///
+/// 同じAIの複数シード、または同じシードの複数のAIの結果を渡すと、挙動の違いを一目で比べられる。
+pub fn render_trajectory_strip(results: &[GameResult], columns: usize) -> String {
+    let panel_w = W * SVG_CELL + SVG_CELL;
+    let panel_h = H * SVG_CELL + SVG_TITLE + SVG_CELL;
+    let columns = columns.clamp(1, results.len().max(1));
+    let rows = results.len().div_ceil(columns);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"10\">\n",
+        panel_w * columns,
+        panel_h * rows
+    );
+    for (i, result) in results.iter().enumerate() {
+        let ox = (i % columns) * panel_w;
+        let oy = (i / columns) * panel_h + SVG_TITLE;
+        let mut state = State::new(result.seed);
+        svg.push_str(&format!(
+            "<text x=\"{ox}\" y=\"{}\">seed {} {} score {}</text>\n",
+            oy - 4,
+            result.seed,
+            result.policy,
+            result.game_score
+        ));
+        for y in 0..H {
+            for x in 0..W {
+                let point = state.points[y * W + x];
+                if point > 0 {
+                    svg.push_str(&format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{SVG_CELL}\" height=\"{SVG_CELL}\" fill=\"#4a7\" fill-opacity=\"{:.2}\"/>\n",
+                        ox + x * SVG_CELL,
+                        oy + y * SVG_CELL,
+                        point as f64 / 10.
+                    ));
+                }
+            }
+        }
+        let center = |c: Coord| {
+            (
+                ox + c.x as usize * SVG_CELL + SVG_CELL / 2,
+                oy + c.y as usize * SVG_CELL + SVG_CELL / 2,
+            )
+        };
+        let start = center(state.character);
+        let mut path = vec![start];
+        for &action in &result.actions {
+            state.advance(action.into());
+            path.push(center(state.character));
+        }
+        let points: Vec<String> = path.iter().map(|(x, y)| format!("{x},{y}")).collect();
+        svg.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#c33\" stroke-width=\"2\"/>\n",
+            points.join(" ")
+        ));
+        let end = *path.last().unwrap();
+        svg.push_str(&format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"#06c\"/>\n<circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"#000\"/>\n",
+            start.0, start.1, end.0, end.1
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
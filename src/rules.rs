@@ -0,0 +1,492 @@
+//! 基本の盤面に重ねて組み合わせられるルール (トーラス・壁・ワープ・得点の減衰・倍率・エネルギー)
+//!
+//! 変種ごとに状態の型を作る代わりに、`Rule` を基本の盤面 `MazeState` の周りに順に重ねる。
+//! 移動先・得点・ターンの終わり・終了判定をそれぞれのルールが前のルールの結果から決めるので、
+//! `torus+decay+walls` のような組み合わせも型を増やさずに遊べる。
+
+use std::{cmp::Ordering, fmt, sync::Arc};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    db::policy_param,
+    game::{Coord, Direction, Eval, GameConfig, GameScore, GameState, MazeState, H, W},
+    util::splitmix64,
+    wall::stick_down_walls,
+};
+
+/// 基本の盤面に重ねるルール
+///
+/// どのフックも既定では何もしないので、ルールは必要なものだけを実装する。
+/// 重ねた順に呼ばれ、各ルールは前のルールまでで決まった値を受け取って変える。
+/// ゲームごとに変わる値 (エネルギーなど) は状態の `slots` の自分の番の値に持つ。
+pub trait Rule: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// ゲーム開始時に盤面と自分の値を整える
+    fn setup(&self, _board: &mut MazeState, _slot: &mut i64) {}
+
+    /// `from` から `action` で進む先 (`to` は前のルールまでで決まった先。Noneなら進めない)
+    fn target(
+        &self,
+        _board: &MazeState,
+        _from: Coord,
+        _action: Direction,
+        to: Option<Coord>,
+    ) -> Option<Coord> {
+        to
+    }
+
+    /// マス `cell` の得点を取ったときにスコアに足す値 (`gain` は前のルールまでで決まった値)
+    fn gain(&self, _board: &MazeState, _cell: Coord, gain: u64, _slot: &mut i64) -> u64 {
+        gain
+    }
+
+    /// 1ターン進めた後に呼ぶ
+    fn end_turn(&self, _board: &mut MazeState, _slot: &mut i64) {}
+
+    /// ターン数によらずゲームを終わらせるか
+    fn is_done(&self, _board: &MazeState, _slot: i64) -> bool {
+        false
+    }
+
+    /// 盤面の表示でマス `cell` に描く文字 (Noneなら前のルールか得点に任せる)
+    fn symbol(&self, _board: &MazeState, _cell: Coord) -> Option<char> {
+        None
+    }
+
+    /// 盤面の表示に足す1行 (エネルギーの残りなど)
+    fn status(&self, _slot: i64) -> Option<String> {
+        None
+    }
+}
+
+/// 盤面の端どうしがつながっていて、外に出ると反対側に出る
+///
+/// 盤面の外に出る手だけを変えるので、壁などで進めなくするルールはこの後に重ねる。
+pub struct Torus;
+
+impl Rule for Torus {
+    fn name(&self) -> &'static str {
+        "torus"
+    }
+
+    fn target(
+        &self,
+        _board: &MazeState,
+        from: Coord,
+        action: Direction,
+        to: Option<Coord>,
+    ) -> Option<Coord> {
+        if from.offset(action).is_some() {
+            return to;
+        }
+        to.or_else(|| {
+            let (dy, dx) = action.delta();
+            Some(Coord::new(
+                (from.y + dy).rem_euclid(H as i32),
+                (from.x + dx).rem_euclid(W as i32),
+            ))
+        })
+    }
+}
+
+/// 棒倒し法で作った壁には進めない (壁のマスの得点は消す)
+pub struct Walls {
+    pub walls: Vec<Vec<bool>>,
+}
+
+impl Walls {
+    fn is_wall(&self, cell: Coord) -> bool {
+        self.walls[cell.y as usize][cell.x as usize]
+    }
+}
+
+impl Rule for Walls {
+    fn name(&self) -> &'static str {
+        "walls"
+    }
+
+    fn setup(&self, board: &mut MazeState, _slot: &mut i64) {
+        for y in 0..H {
+            for x in 0..W {
+                if self.walls[y][x] {
+                    board.points[y * W + x] = 0;
+                }
+            }
+        }
+    }
+
+    fn target(
+        &self,
+        _board: &MazeState,
+        _from: Coord,
+        _action: Direction,
+        to: Option<Coord>,
+    ) -> Option<Coord> {
+        to.filter(|&cell| !self.is_wall(cell))
+    }
+
+    fn symbol(&self, _board: &MazeState, cell: Coord) -> Option<char> {
+        self.is_wall(cell).then_some('#')
+    }
+}
+
+/// 対になった2マスの片方に入ると、もう片方に飛ぶ (ワープのマスに得点は置かない)
+pub struct Teleporters {
+    pub pairs: Vec<(Coord, Coord)>,
+}
+
+impl Rule for Teleporters {
+    fn name(&self) -> &'static str {
+        "teleporters"
+    }
+
+    fn setup(&self, board: &mut MazeState, _slot: &mut i64) {
+        for &(a, b) in &self.pairs {
+            board.points[a.index()] = 0;
+            board.points[b.index()] = 0;
+        }
+    }
+
+    fn target(
+        &self,
+        _board: &MazeState,
+        _from: Coord,
+        _action: Direction,
+        to: Option<Coord>,
+    ) -> Option<Coord> {
+        let to = to?;
+        Some(
+            self.pairs
+                .iter()
+                .find_map(|&(a, b)| {
+                    if a == to {
+                        Some(b)
+                    } else if b == to {
+                        Some(a)
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(to),
+        )
+    }
+
+    fn symbol(&self, _board: &MazeState, cell: Coord) -> Option<char> {
+        self.pairs
+            .iter()
+            .any(|&(a, b)| a == cell || b == cell)
+            .then_some('T')
+    }
+}
+
+/// `every` ターンごとに盤面の全ての得点が `amount` ずつ減る
+pub struct Decay {
+    pub every: usize,
+    pub amount: u8,
+}
+
+impl Rule for Decay {
+    fn name(&self) -> &'static str {
+        "decay"
+    }
+
+    fn end_turn(&self, board: &mut MazeState, _slot: &mut i64) {
+        if board.turn.is_multiple_of(self.every.max(1)) {
+            for point in board.points.iter_mut() {
+                *point = point.saturating_sub(self.amount);
+            }
+        }
+    }
+}
+
+/// 決まったマスで取った得点は `factor` 倍になる
+pub struct Multiplier {
+    pub factor: u64,
+    pub cells: Vec<Coord>,
+}
+
+impl Rule for Multiplier {
+    fn name(&self) -> &'static str {
+        "multiplier"
+    }
+
+    fn gain(&self, _board: &MazeState, cell: Coord, gain: u64, _slot: &mut i64) -> u64 {
+        if self.cells.contains(&cell) {
+            gain * self.factor
+        } else {
+            gain
+        }
+    }
+
+    fn symbol(&self, board: &MazeState, cell: Coord) -> Option<char> {
+        // 得点が残っている倍率のマスだけ目印を付ける
+        (self.cells.contains(&cell) && board.points[cell.index()] > 0).then_some('*')
+    }
+}
+
+/// 1ターンごとにエネルギーを1使い、得点を取るとその分だけ (`capacity` まで) 回復する。
+/// エネルギーが尽きたらゲームが終わる
+pub struct Energy {
+    pub capacity: i64,
+}
+
+impl Rule for Energy {
+    fn name(&self) -> &'static str {
+        "energy"
+    }
+
+    fn setup(&self, _board: &mut MazeState, slot: &mut i64) {
+        *slot = self.capacity;
+    }
+
+    fn gain(&self, board: &MazeState, cell: Coord, gain: u64, slot: &mut i64) -> u64 {
+        *slot = (*slot + board.points[cell.index()] as i64).min(self.capacity);
+        gain
+    }
+
+    fn end_turn(&self, _board: &mut MazeState, slot: &mut i64) {
+        *slot -= 1;
+    }
+
+    fn is_done(&self, _board: &MazeState, slot: i64) -> bool {
+        slot <= 0
+    }
+
+    fn status(&self, slot: i64) -> Option<String> {
+        Some(format!("energy:\t{slot}"))
+    }
+}
+
+/// 盤面の開始位置以外から重ならないように `n` マス選ぶ
+fn random_cells(rng: &mut ChaCha12Rng, board: &MazeState, n: usize) -> Vec<Coord> {
+    let mut cells = vec![];
+    while cells.len() < n.min(H * W - 1) {
+        let cell = Coord::new(rng.gen_range(0..H as i32), rng.gen_range(0..W as i32));
+        if cell != board.character && !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+}
+
+/// `torus+decay:every=10,amount=1+walls` のようなルールの指定を読み、盤面 `board` 用に作る
+///
+/// ルールは `+` で区切って重ねる順に並べ、パラメータは `:` の後に `key=value` をカンマで並べる。
+/// 壁・ワープ・倍率のマスの配置は盤面のシード `seed` から決める。
+pub fn parse_rules(text: &str, seed: u64, board: &MazeState) -> Result<Vec<Arc<dyn Rule>>, String> {
+    let mut rules: Vec<Arc<dyn Rule>> = vec![];
+    for (i, spec) in text.split('+').map(str::trim).enumerate() {
+        if spec.is_empty() {
+            continue;
+        }
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let params = format!("({params})");
+        let param = |key: &str, default: u64| policy_param(&params, key).unwrap_or(default);
+        let mut rng = ChaCha12Rng::seed_from_u64(splitmix64(seed ^ splitmix64(i as u64 + 1)));
+        rules.push(match name {
+            "torus" => Arc::new(Torus),
+            "walls" => Arc::new(Walls {
+                walls: stick_down_walls(H, W, board.character, &mut rng),
+            }),
+            "teleporters" => {
+                let cells = random_cells(&mut rng, board, 2 * param("pairs", 3) as usize);
+                Arc::new(Teleporters {
+                    pairs: cells.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+                })
+            }
+            "decay" => Arc::new(Decay {
+                every: param("every", 10) as usize,
+                amount: param("amount", 1).min(u8::MAX as u64) as u8,
+            }),
+            "multiplier" => Arc::new(Multiplier {
+                factor: param("factor", 3),
+                cells: random_cells(&mut rng, board, param("cells", 30) as usize),
+            }),
+            "energy" => Arc::new(Energy {
+                capacity: param("capacity", 20) as i64,
+            }),
+            _ => return Err(format!("unknown rule {name:?}")),
+        });
+    }
+    Ok(rules)
+}
+
+/// ルールを重ねた盤面
+#[derive(Clone)]
+pub struct RuledState {
+    pub board: MazeState,
+    pub rules: Arc<[Arc<dyn Rule>]>,
+    /// ルールごとの値 (`rules[i]` は `slots[i]` を使う)
+    pub slots: Vec<i64>,
+}
+
+impl RuledState {
+    /// 盤面 `seed` に `rules` の指定のルールを重ねる
+    pub fn new(seed: u64, rules: &str, config: &GameConfig) -> Result<Self, String> {
+        let board = MazeState::with_config(seed, config);
+        let rules = parse_rules(rules, seed, &board)?;
+        Ok(Self::with_rules(board, rules))
+    }
+
+    pub fn with_rules(mut board: MazeState, rules: Vec<Arc<dyn Rule>>) -> Self {
+        let mut slots = vec![0; rules.len()];
+        for (rule, slot) in rules.iter().zip(&mut slots) {
+            rule.setup(&mut board, slot);
+        }
+        Self {
+            board,
+            rules: rules.into(),
+            slots,
+        }
+    }
+
+    /// 重ねたルールの名前 (`torus+walls` など)
+    pub fn rule_names(&self) -> String {
+        self.rules
+            .iter()
+            .map(|rule| rule.name())
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// `action` で進む先 (進めなければNone)
+    fn target(&self, action: Direction) -> Option<Coord> {
+        let from = self.board.character;
+        self.rules.iter().fold(from.offset(action), |to, rule| {
+            rule.target(&self.board, from, action, to)
+        })
+    }
+}
+
+impl fmt::Display for RuledState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "turn:\t{}", self.board.turn)?;
+        writeln!(f, "score:\t{}", self.board.game_score)?;
+        for (rule, &slot) in self.rules.iter().zip(&self.slots) {
+            if let Some(status) = rule.status(slot) {
+                writeln!(f, "{status}")?;
+            }
+        }
+        for y in 0..H {
+            for x in 0..W {
+                let cell = Coord::new(y as i32, x as i32);
+                let point = self.board.points[cell.index()];
+                let symbol = self
+                    .rules
+                    .iter()
+                    .rev()
+                    .find_map(|rule| rule.symbol(&self.board, cell));
+                if cell == self.board.character {
+                    write!(f, "@")?;
+                } else if let Some(symbol) = symbol {
+                    write!(f, "{symbol}")?;
+                } else if point > 0 {
+                    write!(f, "{point}")?;
+                } else {
+                    write!(f, ".")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for RuledState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.board.cmp(&other.board)
+    }
+}
+
+impl PartialOrd for RuledState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RuledState {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board
+    }
+}
+
+impl Eq for RuledState {}
+
+impl GameState for RuledState {
+    type Action = Direction;
+
+    fn legal_actions(&self) -> Vec<Direction> {
+        Direction::ALL
+            .into_iter()
+            .filter(|&action| self.target(action).is_some())
+            .collect()
+    }
+
+    fn advance(&mut self, action: Direction) {
+        let to = self.target(action).expect("illegal action");
+        self.board.character = to;
+        let cell = to.index();
+        if self.board.points[cell] > 0 {
+            let gain = self
+                .rules
+                .iter()
+                .zip(&mut self.slots)
+                .fold(self.board.points[cell] as u64, |gain, (rule, slot)| {
+                    rule.gain(&self.board, to, gain, slot)
+                });
+            self.board.game_score = GameScore(self.board.game_score.0.saturating_add(gain));
+            self.board.points[cell] = 0;
+            self.board.collected_hash ^= splitmix64(cell as u64);
+        }
+        if !self.board.visited.insert(to) {
+            self.board.revisits += 1;
+        }
+        self.board.turn += 1;
+        for (rule, slot) in self.rules.iter().zip(&mut self.slots) {
+            rule.end_turn(&mut self.board, slot);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.board.is_done()
+            || self
+                .rules
+                .iter()
+                .zip(&self.slots)
+                .any(|(rule, &slot)| rule.is_done(&self.board, slot))
+    }
+
+    fn evaluate(&mut self) {
+        self.board.evaluated_score = Eval::from(self.board.game_score);
+    }
+
+    fn evaluated_score(&self) -> Eval {
+        self.board.evaluated_score
+    }
+
+    fn set_evaluated_score(&mut self, score: Eval) -> bool {
+        self.board.evaluated_score = score;
+        true
+    }
+
+    fn first_action(&self) -> Direction {
+        self.board.first_action
+    }
+
+    fn set_first_action(&mut self, action: Direction) {
+        self.board.first_action = action;
+    }
+
+    /// 基本の盤面のハッシュにルールごとの値を混ぜる
+    fn state_hash(&self) -> Option<u64> {
+        let hash = self.board.state_hash()?;
+        Some(
+            self.slots
+                .iter()
+                .fold(hash, |hash, &slot| splitmix64(hash ^ slot as u64)),
+        )
+    }
+}
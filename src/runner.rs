@@ -0,0 +1,112 @@
+//! ライブラリからゲームの進行に口を挟むための `GameRunner`
+//!
+//! ```
+//! use num_collect_maze::{
+//!     play::PlayConfig,
+//!     policy::parse_policy,
+//!     runner::{GameRunner, TurnControl},
+//! };
+//!
+//! let mut policy = parse_policy("greedy", 0).unwrap();
+//! let mut slow_turns = 0;
+//! let result = GameRunner::new(PlayConfig::default())
+//!     .on_turn(|state, stats| {
+//!         slow_turns += usize::from(stats.last_elapsed_us > 1000);
+//!         match state.turn {
+//!             // 最初の手は人が決める
+//!             0 => TurnControl::Play(state.legal_actions()[0]),
+//!             // 30ターンで打ち切る
+//!             30 => TurnControl::Stop,
+//!             _ => TurnControl::Continue,
+//!         }
+//!     })
+//!     .run(0, policy.as_mut())
+//!     .unwrap();
+//! assert_eq!(result.actions.len(), 30);
+//! ```
+
+use crate::{
+    game::{Direction, State},
+    play::{play_out_with_caches, PlayConfig},
+    policy::Policy,
+    results::GameResult,
+};
+
+/// `GameRunner::on_turn` の戻り値で、そのターンの進め方
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnControl {
+    /// いつもどおりAIに手を決めさせる
+    Continue,
+    /// AIの代わりにこの手を指す (合法でなければ `run` がエラーを返す)
+    Play(Direction),
+    /// ここでゲームを終える (結果の行動列はこのターンの前までになる)
+    Stop,
+}
+
+/// `GameRunner::on_turn` に渡す、そのターンの前までの記録
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TurnStats {
+    /// 直前のターンの行動 (最初のターンはNone)
+    pub last_action: Option<Direction>,
+    /// 直前のターンに手を決めるのにかかった時間[us] (差し込んだ手や早送りは0)
+    pub last_elapsed_us: u64,
+    /// 手を決めるのにかかった時間の合計[us]
+    pub total_elapsed_us: u64,
+    /// 得点できなかったターン数
+    pub wasted_turns: usize,
+    /// 1手の時間制限を超過したターン数
+    pub deadline_overruns: usize,
+    /// `TurnControl::Play` で手を差し込んだターン数
+    pub injected_turns: usize,
+}
+
+impl TurnStats {
+    pub(crate) fn record(&mut self, action: Direction, elapsed_us: u64, wasted: bool) {
+        self.last_action = Some(action);
+        self.last_elapsed_us = elapsed_us;
+        self.total_elapsed_us += elapsed_us;
+        self.wasted_turns += usize::from(wasted);
+    }
+}
+
+/// 毎ターン呼ぶ関数
+type TurnHook<'a> = Box<dyn FnMut(&State, &TurnStats) -> TurnControl + 'a>;
+
+/// `PlayConfig` どおりにゲームを進めながら、毎ターン手を決める前に `on_turn` の関数を呼ぶ
+///
+/// 途中で打ち切る・決めた手を差し込む・独自の指標を集めるといったゲームの進め方を、
+/// `run_game` を書き直さずに作れる。`on_turn` を何度も呼ぶと登録した順に全て呼び、
+/// 最初に `TurnControl::Continue` 以外を返したものに従う。
+pub struct GameRunner<'a> {
+    config: PlayConfig,
+    hooks: Vec<TurnHook<'a>>,
+}
+
+impl<'a> GameRunner<'a> {
+    pub fn new(config: PlayConfig) -> Self {
+        Self {
+            config,
+            hooks: vec![],
+        }
+    }
+
+    /// 毎ターン手を決める前に呼ぶ関数を足す
+    pub fn on_turn(mut self, hook: impl FnMut(&State, &TurnStats) -> TurnControl + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// 盤面 `seed` で `policy` に1ゲーム遊ばせる (関数を足していなければ `run_game` と同じ結果)
+    pub fn run(&mut self, seed: u64, policy: &mut dyn Policy) -> Result<GameResult, String> {
+        let hooks = &mut self.hooks;
+        play_out_with_caches(seed, policy, &self.config, &mut |state, stats| {
+            hooks.iter_mut().map(|hook| hook(state, stats)).fold(
+                TurnControl::Continue,
+                |control, next| match control {
+                    TurnControl::Continue => next,
+                    control => control,
+                },
+            )
+        })
+    }
+}
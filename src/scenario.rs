@@ -0,0 +1,149 @@
+//! 回帰テスト用のシナリオ
+
+use crate::{
+    game::{Direction, State, W},
+    policy::parse_policy,
+};
+
+/// シナリオで確かめる性質
+#[derive(Debug)]
+pub enum Expectation {
+    /// `(y, x)` の得点を `turns` ターン以内に取る
+    Collect { y: usize, x: usize, turns: usize },
+    /// `turns` ターン以内にスコアが `score` 以上になる
+    ScoreAtLeast { score: usize, turns: usize },
+}
+
+/// 過去に悪手を指した局面などを固定した回帰テスト用のシナリオ
+///
+/// ```text
+/// # コメント
+/// policy: beam:width=5,depth=10
+/// seed: 0            (seed の代わりに board: の後に盤面を書いてもよい)
+/// prefix: R R D      (AIに任せる前に強制する行動)
+/// expect: collect 3,4 within 5
+/// expect: score >= 20 within 5
+/// ```
+pub struct Scenario {
+    pub state: State,
+    pub policy: String,
+    pub prefix: Vec<Direction>,
+    pub expectations: Vec<Expectation>,
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut state = None;
+        let mut policy = "greedy".to_string();
+        let mut prefix = vec![];
+        let mut expectations = vec![];
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(format!("expected `key: value`, found {line:?}"))?;
+            let value = value.trim();
+            match key.trim() {
+                "policy" => policy = value.to_string(),
+                "seed" => {
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("invalid seed {value:?}"))?;
+                    state = Some(State::new(seed));
+                }
+                "board" => {
+                    let board: Vec<&str> = lines
+                        .by_ref()
+                        .take_while(|l| !l.trim().is_empty())
+                        .collect();
+                    state = Some(State::from_text(&board.join("\n"))?);
+                }
+                "prefix" => {
+                    for action in value.split_whitespace() {
+                        prefix.push(action.parse()?);
+                    }
+                }
+                "expect" => expectations.push(Self::parse_expectation(value)?),
+                key => return Err(format!("unknown key {key:?}")),
+            }
+        }
+        Ok(Self {
+            state: state.ok_or("scenario needs `seed:` or `board:`")?,
+            policy,
+            prefix,
+            expectations,
+        })
+    }
+
+    pub fn parse_expectation(text: &str) -> Result<Expectation, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let invalid = || format!("invalid expectation {text:?}");
+        match words[..] {
+            ["collect", at, "within", turns] => {
+                let (y, x) = at.split_once(',').ok_or_else(invalid)?;
+                Ok(Expectation::Collect {
+                    y: y.parse().map_err(|_| invalid())?,
+                    x: x.parse().map_err(|_| invalid())?,
+                    turns: turns.parse().map_err(|_| invalid())?,
+                })
+            }
+            ["score", ">=", score, "within", turns] => Ok(Expectation::ScoreAtLeast {
+                score: score.parse().map_err(|_| invalid())?,
+                turns: turns.parse().map_err(|_| invalid())?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// 強制手順の後をAIに指させ、全ての性質を満たすか確かめる
+    pub fn run(&self) -> Result<(), String> {
+        let mut state = self.state.clone();
+        for &action in &self.prefix {
+            if !state.legal_actions().contains(&action) {
+                return Err(format!(
+                    "illegal prefix action {action} at turn {}",
+                    state.turn
+                ));
+            }
+            state.advance(action);
+        }
+        let mut policy = parse_policy(&self.policy, 0)?;
+        let horizon =
+            self.expectations
+                .iter()
+                .map(|e| match e {
+                    Expectation::Collect { turns, .. }
+                    | Expectation::ScoreAtLeast { turns, .. } => *turns,
+                })
+                .max()
+                .unwrap_or(0);
+        let mut history = vec![state.clone()];
+        for _ in 0..horizon {
+            if state.is_done() {
+                break;
+            }
+            state.advance(policy.select_action(&state));
+            history.push(state.clone());
+        }
+        for expectation in &self.expectations {
+            let ok = match *expectation {
+                Expectation::Collect { y, x, turns } => history
+                    .iter()
+                    .take(turns + 1)
+                    .any(|s| s.points[y * W + x] == 0),
+                Expectation::ScoreAtLeast { score, turns } => history
+                    .iter()
+                    .take(turns + 1)
+                    .any(|s| s.game_score.as_usize() >= score),
+            };
+            if !ok {
+                return Err(format!("{} failed: {expectation:?}", self.policy));
+            }
+        }
+        Ok(())
+    }
+}
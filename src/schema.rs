@@ -0,0 +1,361 @@
+//! ファイル形式のバージョン・読み込み時の検証・古い形式からの移行
+//!
+//! 結果のJSONL (1行1ゲーム)、`play --log` のゲームの記録、AIの設定ファイル (TOML)、
+//! `calibrate` の探索の速さのプロファイルはそれぞれ `schema_version` を持つ。読み込むときに
+//! 版と中身を確かめ、古い版なら `migrate` での更新を、新しすぎる版ならcrateの更新を促すエラーにする。盤面の生成形式 (`INSTANCE_FORMAT_VERSION`) が
+//! 違う結果は盤面そのものが変わってしまうので移行できない。
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{
+    game::{Direction, State, H, INSTANCE_FORMAT_VERSION, W},
+    results::{GameLog, GameResult},
+    util::reproducibility_token,
+};
+
+/// 結果のJSONLの1行の形式の版
+///
+/// 1: `schema_version` のない行 (`seed`・`policy`・`game_score`・`actions`・`token` だけの古い行を含む)
+/// 2: `schema_version` を持ち、盤面のハッシュや無駄なターン数などが全て揃った行
+pub const RESULTS_SCHEMA_VERSION: u32 = 2;
+
+/// `play --log` のゲームの記録の形式の版 (1: `schema_version` のない記録)
+pub const GAME_LOG_SCHEMA_VERSION: u32 = 2;
+
+/// AIの設定ファイル (`policies = [...]`) の形式の版 (`schema_version` を省略したら1)
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// `calibrate` で書き出す探索の速さのプロファイルの形式の版
+pub const CALIBRATION_SCHEMA_VERSION: u32 = 1;
+
+/// 読み込んだファイルの形式の誤り (`location` は `path:行` など)
+#[derive(Debug)]
+pub enum SchemaError {
+    /// 形式として読めない、または中身が矛盾している
+    Invalid { location: String, message: String },
+    /// 古い版 (`migrate` で更新できる)
+    Outdated {
+        location: String,
+        format: &'static str,
+        found: u32,
+    },
+    /// このcrateが知らない新しい版
+    TooNew {
+        location: String,
+        format: &'static str,
+        found: u32,
+        supported: u32,
+    },
+    /// 盤面の生成形式が違う (同じシードでも盤面が違うので移行できない)
+    InstanceMismatch { location: String, found: u32 },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Invalid { location, message } => write!(f, "{location}: {message}"),
+            SchemaError::Outdated {
+                location,
+                format,
+                found,
+            } => write!(
+                f,
+                "{location}: {format} schema version {found} is outdated; upgrade the file with `migrate`"
+            ),
+            SchemaError::TooNew {
+                location,
+                format,
+                found,
+                supported,
+            } => write!(
+                f,
+                "{location}: {format} schema version {found} is newer than this build supports ({supported}); update the crate"
+            ),
+            SchemaError::InstanceMismatch { location, found } => write!(
+                f,
+                "{location}: boards were generated with instance format version {found}, \
+                 but this build generates version {INSTANCE_FORMAT_VERSION}; the results cannot be migrated"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<SchemaError> for std::io::Error {
+    fn from(error: SchemaError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+fn invalid(location: &str, message: impl Into<String>) -> SchemaError {
+    SchemaError::Invalid {
+        location: location.to_string(),
+        message: message.into(),
+    }
+}
+
+/// JSONの `schema_version` (なければ1)
+fn schema_version(value: &Value, location: &str) -> Result<u32, SchemaError> {
+    match value.get("schema_version") {
+        None => Ok(1),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| invalid(location, format!("invalid schema_version {version}"))),
+    }
+}
+
+/// 版が `current` と同じか確かめる
+fn check_version(
+    found: u32,
+    current: u32,
+    format: &'static str,
+    location: &str,
+) -> Result<(), SchemaError> {
+    match found.cmp(&current) {
+        std::cmp::Ordering::Equal => Ok(()),
+        std::cmp::Ordering::Less => Err(SchemaError::Outdated {
+            location: location.to_string(),
+            format,
+            found,
+        }),
+        std::cmp::Ordering::Greater => Err(SchemaError::TooNew {
+            location: location.to_string(),
+            format,
+            found,
+            supported: current,
+        }),
+    }
+}
+
+/// 結果の中身が矛盾していないか確かめる (盤面の生成形式・行動の番号・トークン)
+pub fn validate_result(result: &GameResult, location: &str) -> Result<(), SchemaError> {
+    if result.instance_format_version != INSTANCE_FORMAT_VERSION {
+        return Err(SchemaError::InstanceMismatch {
+            location: location.to_string(),
+            found: result.instance_format_version,
+        });
+    }
+    if let Some(&action) = result.actions.iter().find(|&&action| action >= 4) {
+        return Err(invalid(location, format!("unknown action {action}")));
+    }
+    if result.token != reproducibility_token(&result.actions) {
+        return Err(invalid(
+            location,
+            format!("token {} does not match the actions", result.token),
+        ));
+    }
+    if result.move_times_us.len() > result.actions.len() {
+        return Err(invalid(location, "more move times than actions"));
+    }
+    Ok(())
+}
+
+/// 結果のJSONLの1行を読み、版と中身を確かめる
+pub fn parse_result_line(line: &str, location: &str) -> Result<GameResult, SchemaError> {
+    let value: Value = serde_json::from_str(line).map_err(|e| invalid(location, e.to_string()))?;
+    check_version(
+        schema_version(&value, location)?,
+        RESULTS_SCHEMA_VERSION,
+        "results",
+        location,
+    )?;
+    let result: GameResult =
+        serde_json::from_value(value).map_err(|e| invalid(location, e.to_string()))?;
+    validate_result(&result, location)?;
+    Ok(result)
+}
+
+/// ゲームの記録の中身が矛盾していないか確かめる (行動の名前・ターンの連番・盤面の大きさ)
+pub fn validate_game_log(log: &GameLog, location: &str) -> Result<(), SchemaError> {
+    let check_board = |board: &[String], what: &str| {
+        if board.len() != H || board.iter().any(|row| row.chars().count() != W) {
+            return Err(invalid(location, format!("{what} is not a {H}x{W} board")));
+        }
+        Ok(())
+    };
+    check_board(&log.initial_board, "initial_board")?;
+    for (i, turn) in log.turns.iter().enumerate() {
+        if turn.turn != i {
+            return Err(invalid(
+                location,
+                format!("turn {} is recorded at position {i}", turn.turn),
+            ));
+        }
+        turn.action
+            .parse::<Direction>()
+            .map_err(|e| invalid(location, format!("turn {i}: {e}")))?;
+        check_board(&turn.board, &format!("board of turn {i}"))?;
+    }
+    Ok(())
+}
+
+/// `play --log` のゲームの記録を読み、版と中身を確かめる
+///
+/// JSONの1つのオブジェクトで `turns` を持たないもの (結果のJSONLなど) はNoneを返す。
+pub fn parse_game_log(text: &str, location: &str) -> Result<Option<GameLog>, SchemaError> {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return Ok(None);
+    };
+    if value.get("turns").is_none() {
+        return Ok(None);
+    }
+    check_version(
+        schema_version(&value, location)?,
+        GAME_LOG_SCHEMA_VERSION,
+        "game log",
+        location,
+    )?;
+    let log: GameLog =
+        serde_json::from_value(value).map_err(|e| invalid(location, e.to_string()))?;
+    validate_game_log(&log, location)?;
+    Ok(Some(log))
+}
+
+/// AIの設定ファイルの `schema_version` を確かめる (省略は1)
+pub fn check_config_version(version: u32, location: &str) -> Result<(), SchemaError> {
+    check_version(version, CONFIG_SCHEMA_VERSION, "config", location)
+}
+
+/// 探索の速さのプロファイルの `schema_version` を確かめる
+pub fn check_calibration_version(version: u32, location: &str) -> Result<(), SchemaError> {
+    check_version(version, CALIBRATION_SCHEMA_VERSION, "calibration", location)
+}
+
+/// 古い版の結果の1行を現在の版にする
+///
+/// 版1の行に欠けている項目は、盤面を作り直して行動列を再生して埋める。
+/// 記録された行動が盤面の外に出るときや、再生したスコアが記録と違うときはエラーにする。
+pub fn migrate_result(mut value: Value, location: &str) -> Result<GameResult, SchemaError> {
+    let version = schema_version(&value, location)?;
+    if version > RESULTS_SCHEMA_VERSION {
+        check_version(version, RESULTS_SCHEMA_VERSION, "results", location)?;
+    }
+    if version == 1 {
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| invalid(location, "a result must be a JSON object"))?;
+        let seed = object
+            .get("seed")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid(location, "missing seed"))?;
+        let actions: Vec<usize> = object
+            .get("actions")
+            .cloned()
+            .and_then(|actions| serde_json::from_value(actions).ok())
+            .ok_or_else(|| invalid(location, "missing actions"))?;
+        let mut state = State::new(seed);
+        let instance_hash = state.instance_hash();
+        let mut wasted_turns = 0;
+        for (turn, &action) in actions.iter().enumerate() {
+            let action = Direction::ALL
+                .get(action)
+                .copied()
+                .filter(|action| state.legal_actions().contains(action))
+                .ok_or_else(|| invalid(location, format!("illegal action at turn {turn}")))?;
+            let score_before = state.game_score;
+            state.advance(action);
+            if state.game_score == score_before {
+                wasted_turns += 1;
+            }
+        }
+        if let Some(recorded) = object.get("game_score").and_then(Value::as_u64) {
+            if recorded as usize != state.game_score.as_usize() {
+                return Err(invalid(
+                    location,
+                    format!(
+                        "recorded score {recorded} does not match the replayed score {}",
+                        state.game_score
+                    ),
+                ));
+            }
+        }
+        let mut fill = |key: &str, default: Value| {
+            object.entry(key).or_insert(default);
+        };
+        fill("instance_format_version", INSTANCE_FORMAT_VERSION.into());
+        fill("instance_hash", instance_hash.into());
+        fill("game_score", state.game_score.as_usize().into());
+        fill("wasted_turns", wasted_turns.into());
+        fill("uncollected_points", state.remaining_sum().into());
+        fill("move_times_us", Value::Array(vec![]));
+        fill("fast_forwarded_from", Value::Null);
+        fill("token", reproducibility_token(&actions).into());
+        object.insert("schema_version".to_string(), RESULTS_SCHEMA_VERSION.into());
+    }
+    let result: GameResult =
+        serde_json::from_value(value).map_err(|e| invalid(location, e.to_string()))?;
+    validate_result(&result, location)?;
+    Ok(result)
+}
+
+/// 古い版のゲームの記録を現在の版にする (版1は項目が同じなので版を付けるだけ)
+pub fn migrate_game_log(mut value: Value, location: &str) -> Result<GameLog, SchemaError> {
+    let version = schema_version(&value, location)?;
+    if version > GAME_LOG_SCHEMA_VERSION {
+        check_version(version, GAME_LOG_SCHEMA_VERSION, "game log", location)?;
+    }
+    value
+        .as_object_mut()
+        .ok_or_else(|| invalid(location, "a game log must be a JSON object"))?
+        .insert("schema_version".to_string(), GAME_LOG_SCHEMA_VERSION.into());
+    let log: GameLog =
+        serde_json::from_value(value).map_err(|e| invalid(location, e.to_string()))?;
+    validate_game_log(&log, location)?;
+    Ok(log)
+}
+
+/// `migrate` で更新したファイルの内容
+pub enum Migrated {
+    Results(Vec<GameResult>),
+    GameLog(GameLog),
+}
+
+/// `migrate` の結果
+pub struct MigrationReport {
+    pub format: &'static str,
+    /// 更新した行 (ゲームの記録なら1つ) の数
+    pub migrated: usize,
+    /// すでに現在の版だった行の数
+    pub current: usize,
+}
+
+/// 結果のJSONLかゲームの記録 `text` を読み、古い版の行を現在の版にする
+pub fn migrate_text(text: &str, path: &str) -> Result<(Migrated, MigrationReport), SchemaError> {
+    if let Ok(value) = serde_json::from_str::<Value>(text) {
+        if value.get("turns").is_some() {
+            let outdated = schema_version(&value, path)? < GAME_LOG_SCHEMA_VERSION;
+            let log = migrate_game_log(value, path)?;
+            let report = MigrationReport {
+                format: "game log",
+                migrated: outdated as usize,
+                current: !outdated as usize,
+            };
+            return Ok((Migrated::GameLog(log), report));
+        }
+    }
+    let mut results = vec![];
+    let mut report = MigrationReport {
+        format: "results",
+        migrated: 0,
+        current: 0,
+    };
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{path}:{}", i + 1);
+        let value: Value =
+            serde_json::from_str(line).map_err(|e| invalid(&location, e.to_string()))?;
+        if schema_version(&value, &location)? < RESULTS_SCHEMA_VERSION {
+            report.migrated += 1;
+        } else {
+            report.current += 1;
+        }
+        results.push(migrate_result(value, &location)?);
+    }
+    Ok((Migrated::Results(results), report))
+}
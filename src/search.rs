@@ -0,0 +1,2650 @@
+//! 1手を決める探索アルゴリズム
+
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    game::{ChildPreview, Direction, Eval, Evaluator, GameState, State, W},
+    util::{profiled, splitmix64, Phase, TimeKeeper},
+    warm::{neighbor_table, transposition_table, TranspositionTable},
+};
+
+/// 探索が行動を決められなかった理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchError {
+    /// 今の状態に合法手がない
+    NoLegalAction,
+    /// 1段も読み終わらないうちに時間制限を使い切った (キャンセルされた)
+    TimeBudgetExhausted,
+    /// ビームに状態が残らなかった (ビーム幅や深さが0のときなど)
+    EmptyBeam,
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::NoLegalAction => write!(f, "no legal action"),
+            SearchError::TimeBudgetExhausted => {
+                write!(f, "time budget exhausted before any depth was searched")
+            }
+            SearchError::EmptyBeam => write!(f, "beam became empty"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// ビームに最初の行動を取り出せる状態が残らなかったときのエラー
+///
+/// 合法手がないならそれを優先し、`exhausted` なら時間切れ、そうでなければビームが空になったとみなす。
+fn no_candidate<S: GameState>(state: &S, exhausted: bool) -> SearchError {
+    if state.legal_actions().is_empty() {
+        SearchError::NoLegalAction
+    } else if exhausted {
+        SearchError::TimeBudgetExhausted
+    } else {
+        SearchError::EmptyBeam
+    }
+}
+
+pub fn random_action<S: GameState>(
+    state: &S,
+    rng: &mut ChaCha12Rng,
+) -> Result<S::Action, SearchError> {
+    let legal_actions = state.legal_actions();
+    if legal_actions.is_empty() {
+        return Err(SearchError::NoLegalAction);
+    }
+    Ok(legal_actions[rng.gen::<usize>() % legal_actions.len()])
+}
+
+pub fn greedy_action<S: GameState>(state: &S) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("greedy").entered();
+    let legal_actions = state.legal_actions();
+    let mut best_action = None;
+    let mut highest = None;
+    for action in legal_actions {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        next_state.evaluate();
+        if highest.is_none() || highest.unwrap() < next_state.evaluated_score() {
+            highest = Some(next_state.evaluated_score());
+            best_action = Some(action);
+        }
+    }
+    best_action.ok_or(SearchError::NoLegalAction)
+}
+
+/// 2手先まで全探索して、2手後の評価値が最大になる最初の行動を返す
+pub fn two_ply_greedy_action<S: GameState>(state: &S) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("two_ply_greedy").entered();
+    let legal_actions = state.legal_actions();
+    let Some(&first) = legal_actions.first() else {
+        return Err(SearchError::NoLegalAction);
+    };
+    let mut best_action = first;
+    let mut highest = None;
+    for action in legal_actions {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        next_state.evaluate();
+        let mut score = next_state.evaluated_score();
+        if !next_state.is_done() {
+            for next_action in next_state.legal_actions() {
+                let mut next_next_state = next_state.clone();
+                next_next_state.advance(next_action);
+                next_next_state.evaluate();
+                score = score.max(next_next_state.evaluated_score());
+            }
+        }
+        if highest.is_none() || highest.unwrap() < score {
+            highest = Some(score);
+            best_action = action;
+        }
+    }
+    Ok(best_action)
+}
+
+/// 1手あたりの時間制限がこれ[us]を下回ると、時間制限付きの探索はビームを始めずに
+/// `two_ply_greedy_action` で行動を決める
+pub static DEGRADED_MODE_FLOOR_US: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(200);
+
+pub fn is_degraded_budget(time_threshold: u128) -> bool {
+    is_degraded_budget_us(time_threshold * 1000)
+}
+
+/// 時間制限を[us]で指定する `is_degraded_budget`
+pub fn is_degraded_budget_us(time_threshold_us: u128) -> bool {
+    time_threshold_us < DEGRADED_MODE_FLOOR_US.load(std::sync::atomic::Ordering::Relaxed) as u128
+}
+
+/// 評価値の高い順に状態を取り出すビーム
+///
+/// 評価値が同じ状態は `Ord` の残りのキー (ターン・位置など) で比べ、それも同じなら先に入れた方を
+/// 先に取り出すので、取り出す順番はビルドや `BinaryHeap` の実装によらずに決まる。
+/// 入れながら取り出すchokudaiサーチで使う (深さごとのビームサーチは `LevelBeam`)。
+#[derive(Clone)]
+struct Beam<S> {
+    heap: BinaryHeap<TieBroken<S>>,
+    pushed: u64,
+}
+
+impl<S: Ord> Beam<S> {
+    fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            pushed: 0,
+        }
+    }
+
+    fn push(&mut self, state: S) {
+        // 先に入れた方が大きくなるように、入れた順番を反転させたものをキーにする
+        self.heap.push(TieBroken {
+            state,
+            key: u64::MAX - self.pushed,
+        });
+        self.pushed += 1;
+    }
+
+    fn pop(&mut self) -> Option<S> {
+        self.heap.pop().map(|entry| entry.state)
+    }
+
+    fn peek(&self) -> Option<&S> {
+        self.heap.peek().map(|entry| &entry.state)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+/// 深さごとのビームサーチで、次の深さの子を全て入れてから評価値の高い順に取り出すビーム
+///
+/// 取り出すのは `width` 個程度なので、全ての子を `BinaryHeap` に並べる代わりに、最初に取り出すときに
+/// 上位 `width` 個の位置だけを `select_nth_unstable_by` で選んで並べる (子が N 個なら O(N + width log width))。
+/// 状態は入れた場所から動かさない。重複を読み飛ばして足りなくなったら残りから次の `width` 個を選ぶので、
+/// 取り出す順番は `Beam` と同じ。取り出し始めたら子を入れない。
+struct LevelBeam<S> {
+    /// 入れた順の子 (取り出したものはNone)
+    states: Vec<Option<S>>,
+    /// `states` で最も良い子の位置
+    best: Option<usize>,
+    /// まだ並べていない子の位置 (取り出し始めるまでは空)
+    pending: Vec<usize>,
+    /// 選んで並べた子の位置 (末尾ほど良い)
+    ready: Vec<usize>,
+    selecting: bool,
+    width: usize,
+}
+
+impl<S: Ord> LevelBeam<S> {
+    fn new(width: usize) -> Self {
+        Self {
+            states: Vec::new(),
+            best: None,
+            pending: Vec::new(),
+            ready: Vec::new(),
+            selecting: false,
+            width: width.max(1),
+        }
+    }
+
+    /// 位置 `a` と `b` の子の比較 (同点なら先に入れた方が大きい)
+    fn compare(&self, a: usize, b: usize) -> std::cmp::Ordering {
+        self.states[a].cmp(&self.states[b]).then(b.cmp(&a))
+    }
+
+    fn push(&mut self, state: S) {
+        debug_assert!(!self.selecting, "pushed into a beam being popped");
+        self.states.push(Some(state));
+        let index = self.states.len() - 1;
+        if self
+            .best
+            .is_none_or(|best| self.compare(index, best).is_gt())
+        {
+            self.best = Some(index);
+        }
+    }
+
+    fn pop(&mut self) -> Option<S> {
+        if self.ready.is_empty() {
+            self.select();
+        }
+        let index = self.ready.pop()?;
+        self.states[index].take()
+    }
+
+    /// まだ並べていない子から上位 `width` 個を選んで `ready` に並べる
+    fn select(&mut self) {
+        let mut pending = std::mem::take(&mut self.pending);
+        if !self.selecting {
+            self.selecting = true;
+            pending.extend(0..self.states.len());
+        }
+        let split = pending.len().saturating_sub(self.width);
+        if split > 0 {
+            pending.select_nth_unstable_by(split, |&a, &b| self.compare(a, b));
+        }
+        let mut ready: Vec<usize> = pending.drain(split..).collect();
+        ready.sort_unstable_by(|&a, &b| self.compare(a, b));
+        self.ready = ready;
+        self.pending = pending;
+    }
+
+    fn peek(&self) -> Option<&S> {
+        let index = if !self.selecting {
+            self.best
+        } else if let Some(&index) = self.ready.last() {
+            Some(index)
+        } else {
+            self.pending
+                .iter()
+                .copied()
+                .max_by(|&a, &b| self.compare(a, b))
+        };
+        index.and_then(|index| self.states[index].as_ref())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &S> {
+        self.states.iter().flatten()
+    }
+}
+
+/// ビームから評価値の高い順に状態を取り出す。同じ深さで取り出し済みの状態と同じものは読み飛ばす
+fn pop_unseen<S: GameState>(beam: &mut LevelBeam<S>, seen: &mut HashSet<u64>) -> Option<S> {
+    pop_unseen_by(beam, seen, S::state_hash)
+}
+
+/// 状態を包んだ `T` のビームで、包んだ状態の `state_hash` を `state_hash` で取り出す `pop_unseen`
+fn pop_unseen_by<T: Ord>(
+    beam: &mut LevelBeam<T>,
+    seen: &mut HashSet<u64>,
+    state_hash: impl Fn(&T) -> Option<u64>,
+) -> Option<T> {
+    loop {
+        let state = profiled(Phase::Heap, || beam.pop())?;
+        let is_new = profiled(Phase::Dedup, || {
+            state_hash(&state).is_none_or(|hash| seen.insert(hash))
+        });
+        if is_new {
+            return Some(state);
+        }
+    }
+}
+
+pub fn beam_search_action<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_stats(state, beam_width, beam_depth, &mut SearchStats::default())
+}
+
+/// 展開した状態の数などを `stats` に足し込むビームサーチ
+pub fn beam_search_action_with_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("beam_search").entered();
+    let start = Instant::now();
+    let mut now_beam = LevelBeam::new(beam_width);
+    let mut best_state: Option<S> = None;
+
+    now_beam.push(state.clone());
+    stats.states_cloned += 1;
+
+    for t in 0..beam_depth {
+        let _depth_span = tracing::info_span!("depth").entered();
+        let mut next_beam = LevelBeam::new(beam_width);
+        let mut seen = HashSet::new();
+        for _ in 0..beam_width {
+            let Some(now_state) = pop_unseen(&mut now_beam, &mut seen) else {
+                break;
+            };
+            let legal_actions = now_state.legal_actions();
+            stats.expand(t, legal_actions.len());
+            for action in legal_actions {
+                let mut next_state = profiled(Phase::ChildGeneration, || {
+                    let mut next_state = now_state.clone();
+                    next_state.advance(action);
+                    next_state
+                });
+                profiled(Phase::Evaluation, || next_state.evaluate());
+                if t == 0 {
+                    next_state.set_first_action(action);
+                }
+                profiled(Phase::Heap, || next_beam.push(next_state));
+            }
+        }
+        now_beam = next_beam;
+        let Some(best) = now_beam.peek() else {
+            break;
+        };
+        best_state = Some(best.clone());
+        stats.states_cloned += 1;
+        if best.is_done() {
+            break;
+        }
+    }
+    stats.record_elapsed(start.elapsed());
+
+    best_state
+        .map(|s| s.first_action())
+        .ok_or_else(|| no_candidate(state, false))
+}
+
+/// 子の評価値と親のビーム上の位置だけを持つ、ビームサーチの探索木の節
+struct ArenaNode<A> {
+    /// 親の `survivors` での位置
+    parent: usize,
+    action: A,
+    preview: ChildPreview,
+}
+
+/// 状態の複製をビームに残る状態だけに絞ったビームサーチ (`beam_search_action` と同じ手を返す)
+pub fn arena_beam_search_action<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+) -> Result<S::Action, SearchError> {
+    arena_beam_search_action_with_stats(state, beam_width, beam_depth, &mut SearchStats::default())
+}
+
+/// 展開した状態の数などを `stats` に足し込む `arena_beam_search_action`
+///
+/// 子は `GameState::preview_child` で評価値と重複判定のハッシュだけを計算した節として
+/// `nodes` に並べ、ビームに残る `beam_width` 個だけ親を複製して盤面を作る。
+/// 同点の順番もビームと同じ (評価値・位置・生成した順) なので、選ぶ手は `beam_search_action` と変わらない。
+/// 子を作らずに評価できない状態 (評価関数を差し替えた `MazeState` など) は
+/// `beam_search_action_with_stats` で探索する。
+pub fn arena_beam_search_action_with_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let legal_actions = state.legal_actions();
+    if legal_actions
+        .first()
+        .is_some_and(|&action| state.preview_child(action).is_none())
+    {
+        return beam_search_action_with_stats(state, beam_width, beam_depth, stats);
+    }
+    let _span = tracing::info_span!("arena_beam_search").entered();
+    let start = Instant::now();
+    let mut survivors = vec![state.clone()];
+    let mut nodes: Vec<ArenaNode<S::Action>> = Vec::new();
+    let mut order: Vec<usize> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut best_action = None;
+    stats.states_cloned += 1;
+
+    for t in 0..beam_depth {
+        let _depth_span = tracing::info_span!("depth").entered();
+        nodes.clear();
+        for (parent, now_state) in survivors.iter().enumerate().take(beam_width) {
+            let legal_actions = now_state.legal_actions();
+            // 子は複製せずに見積もるだけなので、複製はビームに残った子を作るときに数える
+            stats.expand(t, 0);
+            for action in legal_actions {
+                let preview = profiled(Phase::Evaluation, || now_state.preview_child(action))
+                    .expect("the root could be previewed");
+                nodes.push(ArenaNode {
+                    parent,
+                    action,
+                    preview,
+                });
+            }
+        }
+        profiled(Phase::Heap, || {
+            order.clear();
+            order.extend(0..nodes.len());
+            // 評価値・位置の大きい順、同じなら先に生成した順
+            order.sort_unstable_by_key(|&index| {
+                let preview = &nodes[index].preview;
+                std::cmp::Reverse((
+                    preview.evaluated_score,
+                    (preview.character.y, preview.character.x),
+                    std::cmp::Reverse(index),
+                ))
+            });
+        });
+        profiled(Phase::Dedup, || {
+            seen.clear();
+            order.retain(|&index| seen.insert(nodes[index].preview.state_hash));
+            order.truncate(beam_width);
+        });
+        if order.is_empty() {
+            break;
+        }
+        let next_survivors: Vec<S> = profiled(Phase::ChildGeneration, || {
+            order
+                .iter()
+                .map(|&index| {
+                    let node = &nodes[index];
+                    let mut next_state = survivors[node.parent].clone();
+                    next_state.advance(node.action);
+                    next_state.evaluate();
+                    if t == 0 {
+                        next_state.set_first_action(node.action);
+                    }
+                    next_state
+                })
+                .collect()
+        });
+        stats.states_cloned += next_survivors.len() as u64;
+        survivors = next_survivors;
+        best_action = Some(survivors[0].first_action());
+        if survivors[0].is_done() {
+            break;
+        }
+    }
+    stats.record_elapsed(start.elapsed());
+
+    best_action.ok_or_else(|| no_candidate(state, false))
+}
+
+/// ビームサーチで見つけた状態をどの最初の行動の手柄にするか
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Attribution {
+    /// 子孫の状態が最初の行動をそのまま引き継ぐ
+    #[default]
+    Inherit,
+    /// 最後のビームで最も評価値が高い状態から親をたどる
+    BestLeaf,
+    /// 最後のビームに残った状態の数が最も多い最初の行動を選ぶ (同数なら評価値が高い方)
+    Vote,
+}
+
+impl Attribution {
+    /// `attr=` に書ける名前
+    pub const NAMES: &'static [&'static str] = &["inherit", "leaf", "vote"];
+}
+
+impl std::str::FromStr for Attribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inherit" => Ok(Attribution::Inherit),
+            "leaf" => Ok(Attribution::BestLeaf),
+            "vote" => Ok(Attribution::Vote),
+            _ => Err(format!("unknown attribution {s:?} (inherit, leaf, vote)")),
+        }
+    }
+}
+
+impl std::fmt::Display for Attribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Attribution::Inherit => "inherit",
+            Attribution::BestLeaf => "leaf",
+            Attribution::Vote => "vote",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// 探索木の節点 (根は含めない)
+struct SearchNode<A> {
+    /// 親の節点の番号 (根の子ならNone)
+    parent: Option<usize>,
+    action: A,
+}
+
+/// 節点から親をたどって、根の子になっている節点の行動を返す
+fn root_action<A: Copy>(tree: &[SearchNode<A>], mut index: usize) -> A {
+    while let Some(parent) = tree[index].parent {
+        index = parent;
+    }
+    tree[index].action
+}
+
+/// 探索木の節点の番号を付けた状態 (`Ord` は状態だけで比べる)
+struct Linked<S> {
+    state: S,
+    /// 状態に対応する節点の番号 (根ならNone)
+    node: Option<usize>,
+}
+
+impl<S: Ord> Ord for Linked<S> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.state.cmp(&other.state)
+    }
+}
+
+impl<S: Ord> PartialOrd for Linked<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: Ord> PartialEq for Linked<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<S: Ord> Eq for Linked<S> {}
+
+/// 最初の行動の決め方を選べるビームサーチ
+///
+/// `Attribution::Inherit` 以外では状態に最初の行動を持たせず、親へのリンクを持った木を作って後からたどる。
+/// ビームの取り出し方 (同点の順番と重複した状態の読み飛ばし) は `beam_search_action` と同じ。
+pub fn beam_search_action_with_attribution<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    attribution: Attribution,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_attribution_and_stats(
+        state,
+        beam_width,
+        beam_depth,
+        attribution,
+        &mut SearchStats::default(),
+    )
+}
+
+/// 展開した状態の数などを `stats` に足し込む `beam_search_action_with_attribution`
+pub fn beam_search_action_with_attribution_and_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    attribution: Attribution,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    if attribution == Attribution::Inherit {
+        return beam_search_action_with_stats(state, beam_width, beam_depth, stats);
+    }
+    let _span = tracing::info_span!("beam_search_with_attribution").entered();
+    let start = Instant::now();
+    let linked_hash = |linked: &Linked<S>| linked.state.state_hash();
+    let mut tree: Vec<SearchNode<S::Action>> = Vec::new();
+    let mut now_beam = LevelBeam::new(beam_width);
+    now_beam.push(Linked {
+        state: state.clone(),
+        node: None,
+    });
+    stats.states_cloned += 1;
+
+    for t in 0..beam_depth {
+        let _depth_span = tracing::info_span!("depth").entered();
+        let mut next_beam = LevelBeam::new(beam_width);
+        let mut seen = HashSet::new();
+        for _ in 0..beam_width {
+            let Some(Linked {
+                state: now_state,
+                node: parent,
+            }) = pop_unseen_by(&mut now_beam, &mut seen, linked_hash)
+            else {
+                break;
+            };
+            let legal_actions = now_state.legal_actions();
+            stats.expand(t, legal_actions.len());
+            for action in legal_actions {
+                let mut next_state = profiled(Phase::ChildGeneration, || {
+                    let mut next_state = now_state.clone();
+                    next_state.advance(action);
+                    next_state
+                });
+                profiled(Phase::Evaluation, || next_state.evaluate());
+                tree.push(SearchNode { parent, action });
+                profiled(Phase::Heap, || {
+                    next_beam.push(Linked {
+                        state: next_state,
+                        node: Some(tree.len() - 1),
+                    })
+                });
+            }
+        }
+        // 展開できる状態がなくなったら1つ前の深さのビームで決める
+        let Some(best) = next_beam.peek() else {
+            break;
+        };
+        let done = best.state.is_done();
+        now_beam = next_beam;
+        if done {
+            break;
+        }
+    }
+
+    stats.record_elapsed(start.elapsed());
+    // 最後のビームの状態を良い順に、同じ状態は1つだけ並べる
+    let mut seen = HashSet::new();
+    let leaves: Vec<usize> =
+        std::iter::from_fn(|| pop_unseen_by(&mut now_beam, &mut seen, linked_hash))
+            .filter_map(|leaf| leaf.node)
+            .collect();
+    let &best_leaf = leaves.first().ok_or_else(|| no_candidate(state, false))?;
+    Ok(match attribution {
+        Attribution::Inherit | Attribution::BestLeaf => root_action(&tree, best_leaf),
+        Attribution::Vote => {
+            let mut votes: Vec<(S::Action, usize)> = Vec::new();
+            for &index in &leaves {
+                let action = root_action(&tree, index);
+                match votes.iter_mut().find(|(a, _)| *a == action) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((action, 1)),
+                }
+            }
+            let mut best = votes[0];
+            for &vote in &votes[1..] {
+                if vote.1 > best.1 {
+                    best = vote;
+                }
+            }
+            best.0
+        }
+    })
+}
+
+/// ビームサーチを行い、最初の行動ごとにビームに残った状態の最大の評価値を返す
+///
+/// ビームに残る状態は `beam_search_action` と同じく、同じ状態を読み飛ばして評価値の高い順に選んだもの。
+/// 途中でビームから落ちた行動は、最後に残っていた深さでの最大の評価値になる。
+/// 評価値が深くなるほど下がらなければ、最大の評価値を持つ行動に `beam_search_action` が選ぶ手が含まれる。
+pub fn beam_search_action_values(
+    state: &State,
+    beam_width: usize,
+    beam_depth: usize,
+) -> Vec<(Direction, Eval)> {
+    let _span = tracing::info_span!("beam_search_values").entered();
+    let legal_actions = state.legal_actions();
+    let mut values = vec![None; legal_actions.len()];
+    let index = |action: Direction| legal_actions.iter().position(|&a| a == action).unwrap();
+    let mut survivors = vec![state.clone()];
+
+    for t in 0..beam_depth {
+        let mut next_beam = LevelBeam::new(beam_width);
+        for now_state in &survivors {
+            for action in now_state.legal_actions() {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate();
+                if t == 0 {
+                    next_state.set_first_action(action);
+                }
+                next_beam.push(next_state);
+            }
+        }
+        let mut seen = HashSet::new();
+        survivors = std::iter::from_fn(|| pop_unseen(&mut next_beam, &mut seen))
+            .take(beam_width)
+            .collect();
+        let Some(best) = survivors.first() else {
+            break;
+        };
+        let is_done = best.is_done();
+        let mut depth_values = vec![None; legal_actions.len()];
+        for s in &survivors {
+            let v: &mut Option<Eval> = &mut depth_values[index(s.first_action())];
+            *v = Some(v.map_or(s.evaluated_score, |v| v.max(s.evaluated_score)));
+        }
+        for (value, depth_value) in values.iter_mut().zip(depth_values) {
+            if depth_value.is_some() {
+                *value = depth_value;
+            }
+        }
+        if is_done {
+            break;
+        }
+    }
+
+    legal_actions
+        .iter()
+        .zip(values)
+        .map(|(&action, value)| (action, value.unwrap_or(Eval::MIN)))
+        .collect()
+}
+
+/// 評価関数を差し替えてビームサーチを行う
+pub fn beam_search_action_with_evaluator(
+    state: &State,
+    beam_width: usize,
+    beam_depth: usize,
+    evaluator: Arc<dyn Evaluator>,
+) -> Result<Direction, SearchError> {
+    let _span = tracing::info_span!("beam_search_with_evaluator").entered();
+    beam_search_action(
+        &state.clone().with_evaluator(evaluator),
+        beam_width,
+        beam_depth,
+    )
+}
+
+/// 評価関数を差し替えて時間制限付きのchokudaiサーチを行う
+pub fn chokudai_search_action_with_evaluator(
+    state: &State,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold: u128,
+    evaluator: Arc<dyn Evaluator>,
+) -> Result<Direction, SearchError> {
+    chokudai_search_action_with_time_threshold(
+        &state.clone().with_evaluator(evaluator),
+        beam_width,
+        beam_depth,
+        time_threshold,
+    )
+}
+
+/// 共有ライブラリから読み込んだ評価関数
+///
+/// ライブラリは次のC ABIの関数をエクスポートする必要がある。
+///
+/// ```c
+/// int64_t score(const int32_t *state_buffer, size_t len);
+/// ```
+///
+/// `state_buffer`の中身は[`MazeState::to_buffer`]を参照。
+#[cfg(feature = "dylib")]
+pub struct DylibEvaluator {
+    pub score: unsafe extern "C" fn(*const i32, usize) -> i64,
+    // scoreより長生きさせるために保持しておく
+    pub _library: libloading::Library,
+}
+
+#[cfg(feature = "dylib")]
+impl DylibEvaluator {
+    pub fn load(path: &str) -> Result<Self, libloading::Error> {
+        // 読み込むライブラリが上記のABIに従っていることは利用者が保証する
+        unsafe {
+            let library = libloading::Library::new(path)?;
+            let score =
+                *library.get::<unsafe extern "C" fn(*const i32, usize) -> i64>(b"score\0")?;
+            Ok(Self {
+                score,
+                _library: library,
+            })
+        }
+    }
+
+    pub fn score(&self, state: &State) -> i64 {
+        let buf = state.to_buffer();
+        unsafe { (self.score)(buf.as_ptr(), buf.len()) }
+    }
+}
+
+#[cfg(feature = "dylib")]
+impl Evaluator for DylibEvaluator {
+    fn evaluate(&self, state: &State) -> Eval {
+        Eval(self.score(state))
+    }
+}
+
+/// 1手 `time_threshold` [ms] の時間制限付きビームサーチ
+///
+/// 根の子の1手読みだけは時間を見ずに行うので、時間制限をすぐに使い切っても合法手があれば必ず行動を返す。
+pub fn beam_search_action_with_time_threshold<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold: u128,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_time_threshold_us(state, beam_width, time_threshold * 1000)
+}
+
+/// 時間制限を[us]で指定する `beam_search_action_with_time_threshold` (1ms未満の時間制限にも使える)
+pub fn beam_search_action_with_time_threshold_us<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold_us: u128,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_time_threshold_us_and_stats(
+        state,
+        beam_width,
+        time_threshold_us,
+        &mut SearchStats::default(),
+    )
+}
+
+/// 展開した状態の数や時間制限の超過を `stats` に足し込む `beam_search_action_with_time_threshold`
+pub fn beam_search_action_with_time_threshold_and_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold: u128,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_time_threshold_us_and_stats(
+        state,
+        beam_width,
+        time_threshold * 1000,
+        stats,
+    )
+}
+
+/// 展開した状態の数や時間制限の超過を `stats` に足し込む `beam_search_action_with_time_threshold_us`
+pub fn beam_search_action_with_time_threshold_us_and_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold_us: u128,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_stop_rule_us_and_stats(
+        state,
+        beam_width,
+        time_threshold_us,
+        StopRule::default(),
+        stats,
+    )
+}
+
+/// 時間制限付きの探索を、時間切れの前でも手が決まったとみなして打ち切る条件
+///
+/// 簡単な局面で時間を使い切らずに済ませ、`play::PlayConfig::bank_time` と組み合わせると
+/// 余った時間を難しい局面に回せる。既定値はどちらの条件も見ない (時間切れまで探索する)。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StopRule {
+    /// ビームの中で最良の最初の行動の評価値が、2番目の行動の評価値をこれ以上上回ったら打ち切る
+    /// (ビームに最初の行動が1種類しか残っていなければ常に満たす)
+    pub margin: Option<i64>,
+    /// 最良の状態の評価値が根の `GameState::evaluation_bound` に達したら打ち切る
+    pub bound: bool,
+}
+
+impl StopRule {
+    /// 根の評価値の上界が `root_bound` のとき、ビーム `beam` で打ち切ってよいか
+    fn is_met<S: GameState>(&self, root_bound: Option<Eval>, beam: &LevelBeam<S>) -> bool {
+        let Some(best) = beam.peek() else {
+            return false;
+        };
+        if self.bound && root_bound.is_some_and(|bound| best.evaluated_score() >= bound) {
+            return true;
+        }
+        let Some(margin) = self.margin else {
+            return false;
+        };
+        let best_action = best.first_action();
+        let runner_up = beam
+            .iter()
+            .filter(|s| s.first_action() != best_action)
+            .map(|s| s.evaluated_score())
+            .max();
+        runner_up.is_none_or(|runner_up| best.evaluated_score().0 - runner_up.0 >= margin)
+    }
+}
+
+/// 深さを読み終えるたびに `stop_rule` を確かめ、満たしたら時間切れの前でも打ち切る
+/// `beam_search_action_with_time_threshold_us_and_stats`
+pub fn beam_search_action_with_stop_rule_us_and_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold_us: u128,
+    stop_rule: StopRule,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    beam_search_action_with_eval_cache(state, beam_width, time_threshold_us, stop_rule, None, stats)
+}
+
+/// 子の評価値を `eval_cache` で引く `beam_search_action_with_stop_rule_us_and_stats`
+///
+/// ターンをまたいで同じ表を渡すと、前のターンに読んだ局面の子を評価し直さずに済む。
+pub fn beam_search_action_with_eval_cache<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_threshold_us: u128,
+    stop_rule: StopRule,
+    mut eval_cache: Option<&mut EvalCache>,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("beam_search_with_time_threshold").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let mut now_beam = LevelBeam::new(beam_width);
+    let mut best_state: Option<S> = None;
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let root_bound = state.evaluation_bound();
+
+    now_beam.push(state.clone());
+    stats.states_cloned += 1;
+
+    for t in 0.. {
+        let _depth_span = tracing::info_span!("depth").entered();
+        let mut next_beam = LevelBeam::new(beam_width);
+        let mut seen = HashSet::new();
+        for _ in 0..beam_width {
+            #[cfg(debug_assertions)]
+            {
+                // eprintln!(
+                //     "elapsed time: {}",
+                //     time_keeper.elapsed().as_micros()
+                // );
+            }
+            // 深さ0 (根の子の1手読み) は時間を見ずに必ず展開するので、
+            // 時間制限をすぐに使い切っても1手読みの貪欲法の手を返せる
+            if t > 0 && time_keeper.is_over() {
+                stats.record(&time_keeper);
+                return best_state
+                    .map(|s| s.first_action())
+                    .ok_or_else(|| no_candidate(state, true));
+            }
+            let Some(now_state) = pop_unseen(&mut now_beam, &mut seen) else {
+                break;
+            };
+            let legal_actions = now_state.legal_actions();
+            stats.expand(t, legal_actions.len());
+            let parent_hash = now_state.eval_cache_hash();
+            for (index, action) in legal_actions.into_iter().enumerate() {
+                let mut next_state = profiled(Phase::ChildGeneration, || {
+                    let mut next_state = now_state.clone();
+                    next_state.advance(action);
+                    next_state
+                });
+                profiled(Phase::Evaluation, || match eval_cache.as_deref_mut() {
+                    Some(eval_cache) => {
+                        eval_cache.evaluate(parent_hash, index, &mut next_state, stats)
+                    }
+                    None => next_state.evaluate(),
+                });
+                if t == 0 {
+                    next_state.set_first_action(action);
+                }
+                profiled(Phase::Heap, || next_beam.push(next_state));
+            }
+        }
+        now_beam = next_beam;
+        let Some(best) = now_beam.peek() else {
+            break;
+        };
+        best_state = Some(best.clone());
+        stats.states_cloned += 1;
+        if best.is_done() {
+            break;
+        }
+        if stop_rule.is_met(root_bound, &now_beam) {
+            stats.early_stops += 1;
+            break;
+        }
+    }
+    stats.record(&time_keeper);
+
+    best_state
+        .map(|s| s.first_action())
+        .ok_or_else(|| no_candidate(state, false))
+}
+
+/// ビームの深さごとの温度 `initial * decay^depth`
+#[derive(Clone, Copy, Debug)]
+pub struct TemperatureSchedule {
+    pub initial: f64,
+    pub decay: f64,
+}
+
+impl TemperatureSchedule {
+    pub fn temperature(&self, depth: usize) -> f64 {
+        self.initial * self.decay.powi(depth as i32)
+    }
+}
+
+/// 浅い深さほど大きなノイズを評価値に混ぜて順位付けする時間制限付きビームサーチ
+///
+/// ビームが早い段階で1つの経路に収束してしまうのを防ぐ。ノイズは `[0, 温度)` の一様乱数で、
+/// 子の評価値は `evaluate_score` で計算し直すので親のノイズは引き継がれない。
+pub fn annealed_beam_search_action(
+    state: &State,
+    beam_width: usize,
+    time_threshold: u128,
+    schedule: &TemperatureSchedule,
+    rng: &mut ChaCha12Rng,
+) -> Result<Direction, SearchError> {
+    let _span = tracing::info_span!("annealed_beam_search").entered();
+    if is_degraded_budget(time_threshold) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new(time_threshold);
+    let mut now_beam = LevelBeam::new(beam_width);
+    now_beam.push(state.clone());
+    let mut best_state: Option<State> = None;
+    for t in 0.. {
+        let temperature = schedule.temperature(t);
+        let mut next_beam = LevelBeam::new(beam_width);
+        for _ in 0..beam_width {
+            let Some(now_state) = now_beam.pop() else {
+                break;
+            };
+            for action in now_state.legal_actions() {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate_score();
+                if temperature > 0. {
+                    next_state.evaluated_score = next_state
+                        .evaluated_score
+                        .saturating_add((rng.gen::<f64>() * temperature) as i64);
+                }
+                if t == 0 {
+                    next_state.first_action = action;
+                }
+                next_beam.push(next_state);
+            }
+        }
+        now_beam = next_beam;
+        let Some(best) = now_beam.peek() else {
+            break;
+        };
+        // 最終的な手はノイズを除いた実際のスコアで選ぶ
+        if best_state
+            .as_ref()
+            .is_none_or(|b| b.turn < best.turn || b.game_score < best.game_score)
+        {
+            best_state = now_beam.iter().max_by_key(|s| s.game_score).cloned();
+        }
+        if best.is_done() || time_keeper.is_over() {
+            break;
+        }
+    }
+    best_state
+        .map(|s| s.first_action)
+        .ok_or_else(|| no_candidate(state, false))
+}
+
+/// 2段階探索: 広く浅いビームで有望な最初の行動を絞り込み、残りの時間で狭く深いビームを行う
+///
+/// 全体の時間制限 `time_threshold` [ms] のうち `split_percent` [%] を1段階目に使う (100を超えたら100とみなす)。
+pub fn two_phase_search_action(
+    state: &State,
+    wide_width: usize,
+    wide_depth: usize,
+    narrow_width: usize,
+    shortlist_size: usize,
+    time_threshold: u128,
+    split_percent: u128,
+) -> Result<Direction, SearchError> {
+    let _span = tracing::info_span!("two_phase_search").entered();
+    if is_degraded_budget(time_threshold) {
+        return two_ply_greedy_action(state);
+    }
+    let coarse_threshold = time_threshold * split_percent.min(100) / 100;
+    let time_keeper = TimeKeeper::new(coarse_threshold);
+
+    // 1段階目: 最初の行動ごとに到達できた最良の評価値を集める
+    let mut best_by_action: [Option<Eval>; 4] = [None; 4];
+    let mut now_beam = LevelBeam::new(wide_width);
+    now_beam.push(state.clone());
+    for t in 0..wide_depth {
+        let mut next_beam = LevelBeam::new(wide_width);
+        for _ in 0..wide_width {
+            let Some(now_state) = now_beam.pop() else {
+                break;
+            };
+            for action in now_state.legal_actions() {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate_score();
+                if t == 0 {
+                    next_state.first_action = action;
+                }
+                let best = &mut best_by_action[next_state.first_action.index()];
+                *best = (*best).max(Some(next_state.evaluated_score));
+                next_beam.push(next_state);
+            }
+        }
+        now_beam = next_beam;
+        if time_keeper.is_over() || now_beam.peek().is_none_or(|s| s.is_done()) {
+            break;
+        }
+    }
+    let mut shortlist: Vec<Direction> = Direction::ALL
+        .into_iter()
+        .filter(|&a| best_by_action[a.index()].is_some())
+        .collect();
+    shortlist.sort_by_key(|&a| std::cmp::Reverse(best_by_action[a.index()]));
+    shortlist.truncate(shortlist_size.max(1));
+    match shortlist[..] {
+        [] => return Err(no_candidate(state, false)),
+        [action] => return Ok(action),
+        _ => {}
+    }
+
+    // 2段階目: 絞り込んだ行動だけから始まる狭く深いビーム
+    let time_keeper = TimeKeeper::new(time_threshold.saturating_sub(coarse_threshold));
+    let mut now_beam = LevelBeam::new(narrow_width);
+    for &action in &shortlist {
+        let mut next_state = state.clone();
+        next_state.advance(action);
+        next_state.evaluate_score();
+        next_state.first_action = action;
+        now_beam.push(next_state);
+    }
+    let mut best_state = now_beam.peek().unwrap().clone();
+    while !best_state.is_done() && !time_keeper.is_over() {
+        let mut next_beam = LevelBeam::new(narrow_width);
+        for _ in 0..narrow_width {
+            let Some(now_state) = now_beam.pop() else {
+                break;
+            };
+            for action in now_state.legal_actions() {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate_score();
+                next_beam.push(next_state);
+            }
+        }
+        now_beam = next_beam;
+        let Some(best) = now_beam.peek() else {
+            break;
+        };
+        best_state = best.clone();
+    }
+    Ok(best_state.first_action)
+}
+
+pub fn chokudai_search_action<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    beam_num: usize,
+) -> Result<S::Action, SearchError> {
+    fixed_chokudai_search_action_with_stats(
+        state,
+        beam_width,
+        beam_depth,
+        beam_num,
+        &mut SearchStats::default(),
+    )
+}
+
+/// 一巡する回数を固定したchokudaiサーチで、展開した状態の数などを `stats` に足し込む
+pub fn fixed_chokudai_search_action_with_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    beam_num: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("chokudai_search").entered();
+    let start = Instant::now();
+    let mut beams = vec![Beam::<S>::new(); beam_depth + 1];
+    beams[0].push(state.clone());
+    let mut eval_cache = EvalCache::default();
+    stats.states_cloned += 1;
+
+    for _ in 0..beam_num {
+        for t in 0..beam_depth {
+            let _depth_span = tracing::info_span!("depth").entered();
+            let (first, second) = beams.split_at_mut(t + 1);
+            let now_beam = &mut first[t];
+            let next_beam = &mut second[0];
+            for _ in 0..beam_width {
+                if now_beam.is_empty() {
+                    break;
+                }
+                let now_state = now_beam.peek().unwrap().clone();
+                stats.states_cloned += 1;
+                if now_state.is_done() {
+                    break;
+                }
+                profiled(Phase::Heap, || now_beam.pop());
+                let legal_actions = now_state.legal_actions();
+                stats.expand(t, legal_actions.len());
+                let parent_hash = now_state.eval_cache_hash();
+                for (index, action) in legal_actions.into_iter().enumerate() {
+                    let mut next_state = profiled(Phase::ChildGeneration, || {
+                        let mut next_state = now_state.clone();
+                        next_state.advance(action);
+                        next_state
+                    });
+                    profiled(Phase::Evaluation, || {
+                        eval_cache.evaluate(parent_hash, index, &mut next_state, stats)
+                    });
+                    if t == 0 {
+                        next_state.set_first_action(action);
+                    }
+                    profiled(Phase::Heap, || next_beam.push(next_state));
+                }
+            }
+        }
+        stats.passes += 1;
+    }
+    stats.record_elapsed(start.elapsed());
+
+    deepest_first_action(state, &beams, false)
+}
+
+/// chokudaiサーチの一番深いビームの先頭の状態の最初の行動 (根しか残っていなければエラー)
+fn deepest_first_action<S: GameState>(
+    state: &S,
+    beams: &[Beam<S>],
+    exhausted: bool,
+) -> Result<S::Action, SearchError> {
+    beams[1..]
+        .iter()
+        .rev()
+        .find_map(|beam| beam.peek())
+        .map(|s| s.first_action())
+        .ok_or_else(|| no_candidate(state, exhausted))
+}
+
+/// 探索で行った仕事の記録 (探索を繰り返すと足し込む)
+///
+/// 時計の速さによらずアルゴリズムどうしを比べられるように、展開した状態と複製した状態を数える。
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SearchStats {
+    /// 探索の回数
+    pub searches: usize,
+    /// 全ての深さを一巡した回数 (chokudaiサーチだけ。RHEAでは進めた世代数、IWでは読み終えた幅の数)
+    pub passes: usize,
+    /// 子を生成した状態の数
+    pub nodes_expanded: u64,
+    /// 複製した状態の数 (子の生成とビームの先頭の取り出し)
+    pub states_cloned: u64,
+    /// 根からの最大の深さ (子の深さで数える)
+    pub max_depth_reached: usize,
+    pub elapsed_us: u128,
+    /// 時間制限を超えて探索した時間の合計
+    pub overshoot_us: u128,
+    pub max_overshoot_us: u128,
+    /// `StopRule` を満たして時間切れの前に打ち切った探索の回数
+    pub early_stops: usize,
+    /// 子の評価値を `EvalCache` で引いた回数
+    pub eval_cache_lookups: u64,
+    /// そのうち計算済みの評価値を使い回せた回数
+    pub eval_cache_hits: u64,
+}
+
+impl SearchStats {
+    fn record(&mut self, time_keeper: &TimeKeeper) {
+        let elapsed_us = time_keeper.elapsed().as_micros();
+        let overshoot_us = elapsed_us.saturating_sub(time_keeper.time_threshold_us);
+        self.searches += 1;
+        self.elapsed_us += elapsed_us;
+        self.overshoot_us += overshoot_us;
+        self.max_overshoot_us = self.max_overshoot_us.max(overshoot_us);
+    }
+
+    /// 時間制限のない探索1回分の時間を足す
+    fn record_elapsed(&mut self, elapsed: Duration) {
+        self.searches += 1;
+        self.elapsed_us += elapsed.as_micros();
+    }
+
+    /// 深さ `depth` の状態から `children` 個の子を生成した
+    fn expand(&mut self, depth: usize, children: usize) {
+        self.nodes_expanded += 1;
+        self.states_cloned += children as u64;
+        self.max_depth_reached = self.max_depth_reached.max(depth + 1);
+    }
+
+    pub fn add(&mut self, other: &SearchStats) {
+        self.searches += other.searches;
+        self.passes += other.passes;
+        self.nodes_expanded += other.nodes_expanded;
+        self.states_cloned += other.states_cloned;
+        self.max_depth_reached = self.max_depth_reached.max(other.max_depth_reached);
+        self.elapsed_us += other.elapsed_us;
+        self.overshoot_us += other.overshoot_us;
+        self.max_overshoot_us = self.max_overshoot_us.max(other.max_overshoot_us);
+        self.early_stops += other.early_stops;
+        self.eval_cache_lookups += other.eval_cache_lookups;
+        self.eval_cache_hits += other.eval_cache_hits;
+    }
+
+    /// 子の評価値を使い回せた割合 (引いていなければ0)
+    pub fn eval_cache_hit_rate(&self) -> f64 {
+        self.eval_cache_hits as f64 / self.eval_cache_lookups.max(1) as f64
+    }
+
+    /// 探索1回あたりに展開した状態の数
+    pub fn nodes_per_search(&self) -> f64 {
+        self.nodes_expanded as f64 / self.searches.max(1) as f64
+    }
+
+    /// 探索1回あたりに複製した状態の数
+    pub fn clones_per_search(&self) -> f64 {
+        self.states_cloned as f64 / self.searches.max(1) as f64
+    }
+}
+
+/// 同じ親から同じ行動で進んだ子の評価値を覚えておく表
+///
+/// chokudaiサーチは一巡ごとに同じ状態を別の経路からまた展開することがあるので、評価関数が重いときに
+/// 計算し直さずに済む。キーは親の `GameState::eval_cache_hash` と行動の `legal_actions` での位置で、
+/// ハッシュを持たない状態や `GameState::set_evaluated_score` に対応しない状態は毎回 `evaluate` する。
+///
+/// ふつうは1回の探索の中だけで使うが、`beam_search_action_with_eval_cache` や
+/// `chokudai_search_action_with_eval_cache` に同じ表を渡し続けると、前のターンに読んだ局面を
+/// 次のターンの探索でも評価し直さずに済む (ハッシュにターンを含むので、同じゲームの中なら取り違えない)。
+#[derive(Clone, Debug)]
+pub struct EvalCache {
+    evals: HashMap<(u64, usize), Eval>,
+    /// 覚える子の数の上限 (超えそうになったら全て忘れる)
+    capacity: usize,
+    /// 最後に `begin_turn` したターン
+    last_turn: Option<usize>,
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        Self::with_capacity(usize::MAX)
+    }
+}
+
+/// ターンをまたいで使う `EvalCache` の既定の上限
+pub const TURN_EVAL_CACHE_CAPACITY: usize = 1 << 20;
+
+impl EvalCache {
+    /// 子を `capacity` 個まで覚える表
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            evals: HashMap::new(),
+            capacity: capacity.max(1),
+            last_turn: None,
+        }
+    }
+
+    /// 覚えている子の数
+    pub fn len(&self) -> usize {
+        self.evals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.evals.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.evals.clear();
+    }
+
+    /// ターン `turn` の手を決める前に呼ぶ
+    ///
+    /// 前に呼んだときの次のターンでなければ、別のゲームの盤面かもしれないので全て忘れる。
+    pub fn begin_turn(&mut self, turn: usize) {
+        if self.last_turn.map(|last| last + 1) != Some(turn) {
+            self.evals.clear();
+        }
+        self.last_turn = Some(turn);
+    }
+
+    /// 親のハッシュが `parent_hash` の状態から `index` 番目の行動で進んだ `child` を評価する
+    fn evaluate<S: GameState>(
+        &mut self,
+        parent_hash: Option<u64>,
+        index: usize,
+        child: &mut S,
+        stats: &mut SearchStats,
+    ) {
+        let Some(parent_hash) = parent_hash else {
+            child.evaluate();
+            return;
+        };
+        stats.eval_cache_lookups += 1;
+        let key = (parent_hash, index);
+        if self.evals.len() >= self.capacity && !self.evals.contains_key(&key) {
+            self.evals.clear();
+        }
+        match self.evals.entry(key) {
+            Entry::Occupied(entry) if child.set_evaluated_score(*entry.get()) => {
+                stats.eval_cache_hits += 1;
+            }
+            Entry::Occupied(_) => child.evaluate(),
+            Entry::Vacant(entry) => {
+                child.evaluate();
+                entry.insert(child.evaluated_score());
+            }
+        }
+    }
+}
+
+/// 時間制限付きのchokudaiサーチで、何段の深さを展開するごとに時間を確認するか
+pub const CHOKUDAI_CHECK_INTERVAL: usize = 1;
+
+/// 1手 `time_threshold` [ms] の時間制限付きchokudaiサーチ
+pub fn chokudai_search_action_with_time_threshold<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold: u128,
+) -> Result<S::Action, SearchError> {
+    chokudai_search_action_with_time_threshold_us(
+        state,
+        beam_width,
+        beam_depth,
+        time_threshold * 1000,
+    )
+}
+
+/// 時間制限を[us]で指定する `chokudai_search_action_with_time_threshold` (1ms未満の時間制限にも使える)
+pub fn chokudai_search_action_with_time_threshold_us<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold_us: u128,
+) -> Result<S::Action, SearchError> {
+    chokudai_search_action_with_stats_us(
+        state,
+        beam_width,
+        beam_depth,
+        time_threshold_us,
+        CHOKUDAI_CHECK_INTERVAL,
+        &mut SearchStats::default(),
+    )
+}
+
+/// 時間制限付きのchokudaiサーチ
+///
+/// 深さを `check_interval` 段展開するごとに時間を確認し、一巡の途中でも時間切れなら打ち切る。
+/// 時間制限を超過した時間は `stats` に記録する。
+pub fn chokudai_search_action_with_stats<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold: u128,
+    check_interval: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    chokudai_search_action_with_stats_us(
+        state,
+        beam_width,
+        beam_depth,
+        time_threshold * 1000,
+        check_interval,
+        stats,
+    )
+}
+
+/// 時間制限を[us]で指定する `chokudai_search_action_with_stats`
+pub fn chokudai_search_action_with_stats_us<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold_us: u128,
+    check_interval: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    chokudai_search_action_with_eval_cache(
+        state,
+        beam_width,
+        beam_depth,
+        time_threshold_us,
+        check_interval,
+        Some(&mut EvalCache::default()),
+        stats,
+    )
+}
+
+/// 子の評価値を探索の外から渡した `eval_cache` で引く `chokudai_search_action_with_stats_us`
+/// (Noneなら子を毎回 `evaluate` する)
+pub fn chokudai_search_action_with_eval_cache<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold_us: u128,
+    check_interval: usize,
+    mut eval_cache: Option<&mut EvalCache>,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("chokudai_search_with_time_threshold").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let check_interval = check_interval.max(1);
+    let mut beams = vec![Beam::<S>::new(); beam_depth + 1];
+    beams[0].push(state.clone());
+
+    let mut expanded_depths = 0;
+    'search: loop {
+        for t in 0..beam_depth {
+            let _depth_span = tracing::info_span!("depth").entered();
+            let (first, second) = beams.split_at_mut(t + 1);
+            let now_beam = &mut first[t];
+            let next_beam = &mut second[0];
+            for _ in 0..beam_width {
+                if now_beam.is_empty() {
+                    break;
+                }
+                let now_state = now_beam.peek().unwrap().clone();
+                stats.states_cloned += 1;
+                if now_state.is_done() {
+                    break;
+                }
+                profiled(Phase::Heap, || now_beam.pop());
+                let legal_actions = now_state.legal_actions();
+                stats.expand(t, legal_actions.len());
+                let parent_hash = now_state.eval_cache_hash();
+                for (index, action) in legal_actions.into_iter().enumerate() {
+                    let mut next_state = profiled(Phase::ChildGeneration, || {
+                        let mut next_state = now_state.clone();
+                        next_state.advance(action);
+                        next_state
+                    });
+                    profiled(Phase::Evaluation, || match eval_cache.as_deref_mut() {
+                        Some(eval_cache) => {
+                            eval_cache.evaluate(parent_hash, index, &mut next_state, stats)
+                        }
+                        None => next_state.evaluate(),
+                    });
+                    if t == 0 {
+                        next_state.set_first_action(action);
+                    }
+                    #[cfg(debug_assertions)]
+                    {
+                        // eprintln!("{next_state}");
+                    }
+                    profiled(Phase::Heap, || next_beam.push(next_state));
+                }
+            }
+            expanded_depths += 1;
+            if expanded_depths % check_interval == 0 && time_keeper.is_over() {
+                break 'search;
+            }
+        }
+        stats.passes += 1;
+        if time_keeper.is_over() {
+            break;
+        }
+    }
+    stats.record(&time_keeper);
+
+    deepest_first_action(state, &beams, time_keeper.is_over())
+}
+
+/// 評価値が同じ状態の順序を `key` で決めるための包み
+///
+/// `Beam` では入れた順番を、並列chokudaiサーチではスレッドごとに違う乱数を `key` に使い、
+/// 後者では同点の状態を展開する順番を散らす。
+#[derive(Clone)]
+struct TieBroken<S> {
+    state: S,
+    key: u64,
+}
+
+impl<S: Ord> Ord for TieBroken<S> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.state.cmp(&other.state).then(self.key.cmp(&other.key))
+    }
+}
+
+impl<S: Ord> PartialOrd for TieBroken<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: Ord> PartialEq for TieBroken<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<S: Ord> Eq for TieBroken<S> {}
+
+/// 同点の順番を `tie_break_seed` で散らしたchokudaiサーチを時間切れまで回し、深さごとの最良の状態と探索の記録を返す
+fn chokudai_worker<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_keeper: &TimeKeeper,
+    tie_break_seed: u64,
+) -> (Vec<Option<S>>, SearchStats) {
+    let mut key = tie_break_seed;
+    let mut next_key = || {
+        key = splitmix64(key);
+        key
+    };
+    let mut beams = vec![BinaryHeap::<TieBroken<S>>::new(); beam_depth + 1];
+    beams[0].push(TieBroken {
+        state: state.clone(),
+        key: 0,
+    });
+
+    let mut stats = SearchStats {
+        states_cloned: 1,
+        ..SearchStats::default()
+    };
+    'search: loop {
+        for t in 0..beam_depth {
+            let (first, second) = beams.split_at_mut(t + 1);
+            let now_beam = &mut first[t];
+            let next_beam = &mut second[0];
+            for _ in 0..beam_width {
+                let Some(now) = now_beam.peek() else {
+                    break;
+                };
+                if now.state.is_done() {
+                    break;
+                }
+                let now_state = now_beam.pop().unwrap().state;
+                let legal_actions = now_state.legal_actions();
+                stats.expand(t, legal_actions.len());
+                for action in legal_actions {
+                    let mut next_state = now_state.clone();
+                    next_state.advance(action);
+                    next_state.evaluate();
+                    if t == 0 {
+                        next_state.set_first_action(action);
+                    }
+                    next_beam.push(TieBroken {
+                        state: next_state,
+                        key: next_key(),
+                    });
+                }
+            }
+            if time_keeper.is_over() {
+                break 'search;
+            }
+        }
+        stats.passes += 1;
+        // 深さ0なら上の段の中では時間を確かめないので、一巡ごとにも確かめる
+        if time_keeper.is_over() {
+            break;
+        }
+    }
+    let bests = beams
+        .into_iter()
+        .map(|beam| beam.peek().map(|best| best.state.clone()))
+        .collect();
+    (bests, stats)
+}
+
+/// `time_keeper` と同じ時計・時間制限・取り消しで、このスレッドで測り始める `TimeKeeper`
+///
+/// スレッドCPU時間の時計はスレッドごとに測り始めないといけないので、スレッドの中で起点を取る。
+fn thread_time_keeper(time_keeper: &TimeKeeper) -> TimeKeeper {
+    TimeKeeper {
+        source: time_keeper.source.clone(),
+        start_time: time_keeper.source.now(),
+        time_threshold_us: time_keeper.time_threshold_us,
+        cancel_token: time_keeper.cancel_token.clone(),
+    }
+}
+
+/// 同点の順番を変えた独立なchokudaiサーチを `threads` 個のスレッドで同時に回す並列chokudaiサーチ
+///
+/// 時間切れになったら全スレッドの深さごとの最良の状態をまとめ、最も深い段で最も良い状態の最初の行動を返す。
+/// スレッド `i` は `splitmix64(tie_break_seed ^ i)` で同点の順番を決めるので、1スレッドなら
+/// 普通の時間制限付きchokudaiサーチとほぼ同じ探索になる。時間制限 `time_threshold_us` [us] は各スレッドで測り始める。
+pub fn parallel_chokudai_search_action<S: GameState + Send + Sync>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold_us: u128,
+    threads: usize,
+    tie_break_seed: u64,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("parallel_chokudai_search").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let results: Vec<(Vec<Option<S>>, SearchStats)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1) as u64)
+            .map(|i| {
+                let time_keeper = &time_keeper;
+                scope.spawn(move || {
+                    chokudai_worker(
+                        state,
+                        beam_width,
+                        beam_depth,
+                        &thread_time_keeper(time_keeper),
+                        splitmix64(tie_break_seed ^ i),
+                    )
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("chokudai worker panicked"))
+            .collect()
+    });
+    stats.record(&time_keeper);
+
+    let mut merged: Vec<Option<S>> = vec![None; beam_depth + 1];
+    for (bests, worker_stats) in results {
+        stats.passes += worker_stats.passes;
+        stats.nodes_expanded += worker_stats.nodes_expanded;
+        stats.states_cloned += worker_stats.states_cloned;
+        stats.max_depth_reached = stats.max_depth_reached.max(worker_stats.max_depth_reached);
+        for (merged, best) in merged.iter_mut().zip(bests) {
+            if let Some(best) = best {
+                if merged.as_ref().is_none_or(|current| best > *current) {
+                    *merged = Some(best);
+                }
+            }
+        }
+    }
+    // 深さ0の段は根なので最初の行動を持たない
+    merged
+        .into_iter()
+        .skip(1)
+        .rev()
+        .flatten()
+        .next()
+        .map(|best| best.first_action())
+        .ok_or_else(|| no_candidate(state, time_keeper.is_over()))
+}
+
+/// 深さごとのビームを全スレッドで共有するchokudaiサーチを、時間切れまで回す1スレッド分
+fn shared_chokudai_worker<S: GameState>(
+    beams: &[Mutex<Beam<S>>],
+    beam_width: usize,
+    time_keeper: &TimeKeeper,
+) -> SearchStats {
+    let mut stats = SearchStats::default();
+    'search: loop {
+        for t in 0..beams.len() - 1 {
+            for _ in 0..beam_width {
+                // ロックは取り出すときと子を入れるときだけ持ち、展開は他のスレッドと同時に行う
+                let now_state = {
+                    let mut now_beam = beams[t].lock().unwrap();
+                    match now_beam.peek() {
+                        Some(now) if !now.is_done() => now_beam.pop().unwrap(),
+                        _ => break,
+                    }
+                };
+                let legal_actions = now_state.legal_actions();
+                stats.expand(t, legal_actions.len());
+                let children: Vec<S> = legal_actions
+                    .into_iter()
+                    .map(|action| {
+                        let mut next_state = now_state.clone();
+                        next_state.advance(action);
+                        next_state.evaluate();
+                        if t == 0 {
+                            next_state.set_first_action(action);
+                        }
+                        next_state
+                    })
+                    .collect();
+                let mut next_beam = beams[t + 1].lock().unwrap();
+                for child in children {
+                    next_beam.push(child);
+                }
+            }
+            if time_keeper.is_over() {
+                break 'search;
+            }
+        }
+        stats.passes += 1;
+        // `chokudai_worker` と同じく、深さ0でも止まるように一巡ごとにも確かめる
+        if time_keeper.is_over() {
+            break;
+        }
+    }
+    stats
+}
+
+/// 深さごとのビームを `threads` 個のスレッドで共有する並列chokudaiサーチ
+///
+/// 各スレッドは独立に深さを一巡し、深さ `t` のビームから状態を取り出して展開した子を深さ `t + 1` の
+/// ビームに入れる。`parallel_chokudai_search_action` と違って同じ状態を複数のスレッドで展開しないので、
+/// スレッドを増やした分だけ深く・広く読める。子を入れる順番がスレッドの進み方で変わるので、
+/// 2スレッド以上では同点の扱いが実行ごとに変わりうる。時間制限 `time_threshold_us` [us] は各スレッドで測り始める。
+pub fn shared_chokudai_search_action<S: GameState + Send + Sync>(
+    state: &S,
+    beam_width: usize,
+    beam_depth: usize,
+    time_threshold_us: u128,
+    threads: usize,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("shared_chokudai_search").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let beams: Vec<Mutex<Beam<S>>> = (0..=beam_depth).map(|_| Mutex::new(Beam::new())).collect();
+    beams[0].lock().unwrap().push(state.clone());
+    stats.states_cloned += 1;
+    let results: Vec<SearchStats> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                let (beams, time_keeper) = (&beams, &time_keeper);
+                scope.spawn(move || {
+                    shared_chokudai_worker(beams, beam_width, &thread_time_keeper(time_keeper))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("chokudai worker panicked"))
+            .collect()
+    });
+    stats.record(&time_keeper);
+    for worker_stats in results {
+        stats.passes += worker_stats.passes;
+        stats.nodes_expanded += worker_stats.nodes_expanded;
+        stats.states_cloned += worker_stats.states_cloned;
+        stats.max_depth_reached = stats.max_depth_reached.max(worker_stats.max_depth_reached);
+    }
+    let beams: Vec<Beam<S>> = beams
+        .into_iter()
+        .map(|beam| beam.into_inner().unwrap())
+        .collect();
+    deepest_first_action(state, &beams, time_keeper.is_over())
+}
+
+/// 根並列のビームサーチで、スレッドごとの結果から最初の行動を選ぶ方法
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootAggregation {
+    /// 最良の状態の最初の行動として最も多くのスレッドが選んだ行動 (同数なら評価値が高い方)
+    #[default]
+    Vote,
+    /// 全スレッドで最も評価値が高い状態の最初の行動
+    Best,
+}
+
+impl RootAggregation {
+    /// `agg=` に書ける名前
+    pub const NAMES: &'static [&'static str] = &["vote", "best"];
+}
+
+impl std::str::FromStr for RootAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vote" => Ok(RootAggregation::Vote),
+            "best" => Ok(RootAggregation::Best),
+            _ => Err(format!("unknown aggregation {s:?} (vote, best)")),
+        }
+    }
+}
+
+impl std::fmt::Display for RootAggregation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RootAggregation::Vote => "vote",
+            RootAggregation::Best => "best",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// 同点の順番を `tie_break_seed` で散らした時間制限付きビームサーチを1スレッド分回し、
+/// 読み終えた一番深い段の最良の状態と探索の記録を返す
+fn beam_worker<S: GameState>(
+    state: &S,
+    beam_width: usize,
+    time_keeper: &TimeKeeper,
+    tie_break_seed: u64,
+) -> (Option<S>, SearchStats) {
+    let mut key = tie_break_seed;
+    let mut next_key = || {
+        key = splitmix64(key);
+        key
+    };
+    let mut now_beam = BinaryHeap::from([TieBroken {
+        state: state.clone(),
+        key: 0,
+    }]);
+    let mut best_state: Option<S> = None;
+    let mut stats = SearchStats {
+        states_cloned: 1,
+        ..SearchStats::default()
+    };
+    for t in 0.. {
+        let mut next_beam = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        let mut expanded = 0;
+        while expanded < beam_width {
+            // 深さ0は時間を見ずに必ず展開する (`beam_search_action_with_stop_rule_us_and_stats` と同じ)
+            if t > 0 && time_keeper.is_over() {
+                return (best_state, stats);
+            }
+            let Some(TieBroken {
+                state: now_state, ..
+            }) = now_beam.pop()
+            else {
+                break;
+            };
+            if profiled(Phase::Dedup, || {
+                now_state
+                    .state_hash()
+                    .is_some_and(|hash| !seen.insert(hash))
+            }) {
+                continue;
+            }
+            expanded += 1;
+            let legal_actions = now_state.legal_actions();
+            stats.expand(t, legal_actions.len());
+            for action in legal_actions {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate();
+                if t == 0 {
+                    next_state.set_first_action(action);
+                }
+                next_beam.push(TieBroken {
+                    state: next_state,
+                    key: next_key(),
+                });
+            }
+        }
+        now_beam = next_beam;
+        let Some(best) = now_beam.peek() else {
+            break;
+        };
+        best_state = Some(best.state.clone());
+        stats.states_cloned += 1;
+        if best.state.is_done() {
+            break;
+        }
+    }
+    (best_state, stats)
+}
+
+/// 同点の順番だけを変えた時間制限付きビームサーチを `threads` 個のスレッドで同時に回す根並列のビームサーチ
+///
+/// 各スレッドの最良の状態の最初の行動を `aggregation` でまとめる。どれか1つのスレッドが同点の選び方で
+/// 外れた手を選んでも多数決で打ち消せるので、時間制限が短くて読みが浅いときに手が安定する。
+/// スレッド `i` は `splitmix64(tie_break_seed ^ i)` で同点の順番を決め、結果をまとめる順番もスレッドの番号順
+/// なので、時間で打ち切られる深さが同じなら実行ごとに同じ手になる。時間制限 `time_threshold_us` [us] は
+/// 各スレッドで測り始める。
+pub fn root_parallel_beam_search_action<S: GameState + Send + Sync>(
+    state: &S,
+    beam_width: usize,
+    time_threshold_us: u128,
+    threads: usize,
+    aggregation: RootAggregation,
+    tie_break_seed: u64,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("root_parallel_beam_search").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let results: Vec<(Option<S>, SearchStats)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1) as u64)
+            .map(|i| {
+                let time_keeper = &time_keeper;
+                scope.spawn(move || {
+                    beam_worker(
+                        state,
+                        beam_width,
+                        &thread_time_keeper(time_keeper),
+                        splitmix64(tie_break_seed ^ i),
+                    )
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("beam worker panicked"))
+            .collect()
+    });
+    stats.record(&time_keeper);
+
+    // 最初の行動ごとの (票数, 最良の評価値)
+    let mut tally: Vec<(S::Action, usize, Eval)> = Vec::new();
+    for (best, worker_stats) in results {
+        stats.nodes_expanded += worker_stats.nodes_expanded;
+        stats.states_cloned += worker_stats.states_cloned;
+        stats.max_depth_reached = stats.max_depth_reached.max(worker_stats.max_depth_reached);
+        let Some(best) = best else {
+            continue;
+        };
+        let (action, eval) = (best.first_action(), best.evaluated_score());
+        match tally.iter_mut().find(|(a, _, _)| *a == action) {
+            Some((_, votes, best_eval)) => {
+                *votes += 1;
+                *best_eval = (*best_eval).max(eval);
+            }
+            None => tally.push((action, 1, eval)),
+        }
+    }
+    // 同点なら先に現れた (番号の小さいスレッドが選んだ) 行動
+    let key = |&(_, votes, eval): &(S::Action, usize, Eval)| match aggregation {
+        RootAggregation::Vote => (votes, eval),
+        RootAggregation::Best => (0, eval),
+    };
+    tally
+        .iter()
+        .rev()
+        .max_by_key(|entry| key(entry))
+        .map(|&(action, _, _)| action)
+        .ok_or_else(|| no_candidate(state, time_keeper.is_over()))
+}
+
+/// 状態からランダムに最後まで進めたときの評価値
+pub fn playout<S: GameState>(state: &S, rng: &mut ChaCha12Rng) -> Eval {
+    let mut state = state.clone();
+    while !state.is_done() {
+        let Ok(action) = random_action(&state, rng) else {
+            break;
+        };
+        state.advance(action);
+    }
+    state.evaluate();
+    state.evaluated_score()
+}
+
+/// 原始モンテカルロ法
+///
+/// 合法手に順番に `playout_num` 回のプレイアウトを割り振り、平均の評価値が最も高い行動を返す。
+pub fn primitive_montecarlo_action<S: GameState>(
+    state: &S,
+    playout_num: usize,
+    rng: &mut ChaCha12Rng,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("primitive_montecarlo").entered();
+    let legal_actions = state.legal_actions();
+    if legal_actions.is_empty() {
+        return Err(SearchError::NoLegalAction);
+    }
+    let mut totals = vec![0i64; legal_actions.len()];
+    let mut counts = vec![0usize; legal_actions.len()];
+    for i in 0..playout_num {
+        let index = i % legal_actions.len();
+        let mut next_state = state.clone();
+        next_state.advance(legal_actions[index]);
+        totals[index] = totals[index].saturating_add(playout(&next_state, rng).0);
+        counts[index] += 1;
+    }
+    let mut best_action = legal_actions[0];
+    let mut best_mean = f64::NEG_INFINITY;
+    for index in 0..legal_actions.len() {
+        if counts[index] == 0 {
+            continue;
+        }
+        let mean = totals[index] as f64 / counts[index] as f64;
+        if mean > best_mean {
+            best_mean = mean;
+            best_action = legal_actions[index];
+        }
+    }
+    Ok(best_action)
+}
+
+/// MCTSの木の節点
+struct MctsNode<S: GameState> {
+    state: S,
+    /// プレイアウトの評価値の合計
+    total: f64,
+    visits: usize,
+    /// 子の行動と節点の番号
+    children: Vec<(S::Action, usize)>,
+}
+
+/// MCTSの木
+///
+/// 評価値の範囲はゲームによって違うので、これまでに見た評価値の最小・最大で[0, 1]に正規化してUCB1を計算する。
+struct MctsTree<S: GameState> {
+    nodes: Vec<MctsNode<S>>,
+    c: f64,
+    expand_threshold: usize,
+    min_value: f64,
+    max_value: f64,
+}
+
+impl<S: GameState> MctsTree<S> {
+    fn push(&mut self, state: S) -> usize {
+        self.nodes.push(MctsNode {
+            state,
+            total: 0.,
+            visits: 0,
+            children: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    fn expand(&mut self, index: usize) {
+        let state = self.nodes[index].state.clone();
+        for action in state.legal_actions() {
+            let mut next_state = state.clone();
+            next_state.advance(action);
+            let child = self.push(next_state);
+            self.nodes[index].children.push((action, child));
+        }
+    }
+
+    fn select(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        if let Some(&(_, child)) = node
+            .children
+            .iter()
+            .find(|&&(_, c)| self.nodes[c].visits == 0)
+        {
+            return child;
+        }
+        let scale = (self.max_value - self.min_value).max(1.);
+        let log_visits = (node.visits as f64).ln();
+        let ucb1 = |child: usize| {
+            let child = &self.nodes[child];
+            let mean = (child.total / child.visits as f64 - self.min_value) / scale;
+            mean + self.c * (log_visits / child.visits as f64).sqrt()
+        };
+        node.children
+            .iter()
+            .map(|&(_, child)| child)
+            .max_by(|&a, &b| ucb1(a).total_cmp(&ucb1(b)))
+            .unwrap()
+    }
+
+    /// 節点から1回プレイアウトして、通った節点に評価値を足す
+    fn visit(&mut self, index: usize, rng: &mut ChaCha12Rng) -> f64 {
+        let value = if self.nodes[index].state.is_done() || self.nodes[index].children.is_empty() {
+            let value = playout(&self.nodes[index].state, rng).0 as f64;
+            self.min_value = self.min_value.min(value);
+            self.max_value = self.max_value.max(value);
+            if !self.nodes[index].state.is_done()
+                && self.nodes[index].visits + 1 >= self.expand_threshold
+            {
+                self.expand(index);
+            }
+            value
+        } else {
+            let child = self.select(index);
+            self.visit(child, rng)
+        };
+        self.nodes[index].total += value;
+        self.nodes[index].visits += 1;
+        value
+    }
+}
+
+/// `rhea_action` のパラメータ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RheaParams {
+    /// 個体の数
+    pub population: usize,
+    /// 1個体の行動列の長さ
+    pub length: usize,
+    /// 1つの遺伝子を突然変異させる確率[%]
+    pub mutation_percent: usize,
+    /// 世代数の上限 (0なら時間切れまで)
+    pub max_generations: usize,
+}
+
+impl Default for RheaParams {
+    fn default() -> Self {
+        Self {
+            population: 10,
+            length: 20,
+            mutation_percent: 10,
+            max_generations: 0,
+        }
+    }
+}
+
+/// 遺伝子 `genome` の行動列を `state` から指したときの評価値
+///
+/// 遺伝子はその時点の合法手の中の番号 (合法手の数で割った余り) なので、どんな遺伝子も合法な行動列になる。
+/// 途中で終局したら残りの遺伝子は使わない。
+fn rhea_fitness<S: GameState>(state: &S, genome: &[u8], stats: &mut SearchStats) -> Eval {
+    let mut state = state.clone();
+    stats.states_cloned += 1;
+    for &gene in genome {
+        if state.is_done() {
+            break;
+        }
+        let legal_actions = state.legal_actions();
+        if legal_actions.is_empty() {
+            break;
+        }
+        state.advance(legal_actions[gene as usize % legal_actions.len()]);
+        stats.nodes_expanded += 1;
+    }
+    state.evaluate();
+    state.evaluated_score()
+}
+
+/// 時間制限付きのRolling Horizon進化的アルゴリズム (RHEA)
+///
+/// 長さ `params.length` の行動列を `params.population` 個の個体として、最良の個体を残す選択・
+/// 2個体のトーナメント選択・一様交叉・突然変異で時間切れ (か `params.max_generations` 世代) まで進化させ、
+/// 最良の個体の最初の行動を返す。`shift_buffer` に前のターンの最良の個体から最初の行動を除いたものを
+/// 渡すと最初の世代に入れ、返すときに今回の最良の個体から最初の行動を除いたものに置き換える。
+pub fn rhea_action<S: GameState>(
+    state: &S,
+    params: RheaParams,
+    time_threshold_us: u128,
+    shift_buffer: &mut Vec<u8>,
+    rng: &mut ChaCha12Rng,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("rhea").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let legal_actions = state.legal_actions();
+    if legal_actions.is_empty() {
+        return Err(SearchError::NoLegalAction);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let (population, length) = (params.population.max(2), params.length.max(1));
+    let random_genome =
+        |rng: &mut ChaCha12Rng| -> Vec<u8> { (0..length).map(|_| rng.gen()).collect() };
+
+    let mut genomes: Vec<Vec<u8>> = (0..population).map(|_| random_genome(rng)).collect();
+    if !shift_buffer.is_empty() {
+        let mut shifted = random_genome(rng);
+        let kept = shift_buffer.len().min(length);
+        shifted[..kept].copy_from_slice(&shift_buffer[..kept]);
+        genomes[0] = shifted;
+    }
+    let mut fitness: Vec<Eval> = genomes
+        .iter()
+        .map(|genome| rhea_fitness(state, genome, stats))
+        .collect();
+    let mut generations = 0;
+    while (params.max_generations == 0 || generations < params.max_generations)
+        && !time_keeper.is_over()
+    {
+        let elite = (0..population)
+            .max_by_key(|&i| (fitness[i], Reverse(i)))
+            .unwrap();
+        let mut next_genomes = vec![genomes[elite].clone()];
+        let mut next_fitness = vec![fitness[elite]];
+        while next_genomes.len() < population {
+            if time_keeper.is_over() {
+                break;
+            }
+            let mut tournament = || {
+                let (a, b) = (rng.gen_range(0..population), rng.gen_range(0..population));
+                if fitness[a] >= fitness[b] {
+                    a
+                } else {
+                    b
+                }
+            };
+            let (mother, father) = (tournament(), tournament());
+            let child: Vec<u8> = (0..length)
+                .map(|i| {
+                    let gene = if rng.gen_bool(0.5) {
+                        genomes[mother][i]
+                    } else {
+                        genomes[father][i]
+                    };
+                    if rng.gen_range(0..100) < params.mutation_percent {
+                        rng.gen()
+                    } else {
+                        gene
+                    }
+                })
+                .collect();
+            next_fitness.push(rhea_fitness(state, &child, stats));
+            next_genomes.push(child);
+        }
+        // 時間切れで埋まらなかった分は前の世代から引き継ぐ
+        let filled = next_genomes.len();
+        genomes.truncate(population);
+        next_genomes.extend(genomes.drain(..).skip(filled));
+        next_fitness.extend(fitness.drain(..).skip(filled));
+        genomes = next_genomes;
+        fitness = next_fitness;
+        generations += 1;
+    }
+    stats.passes += generations;
+    stats.max_depth_reached = stats.max_depth_reached.max(length);
+    stats.record(&time_keeper);
+
+    let best = (0..population)
+        .max_by_key(|&i| (fitness[i], Reverse(i)))
+        .unwrap();
+    let genome = &genomes[best];
+    shift_buffer.clear();
+    shift_buffer.extend_from_slice(&genome[1..]);
+    Ok(legal_actions[genome[0] as usize % legal_actions.len()])
+}
+
+/// IW (Iterated Width) で状態の新しさを測る特徴
+///
+/// 状態を原子 (`u64`) の集まりとして書き出す。種類の違う特徴が同じ原子にならないように、
+/// 上位のビットに特徴の種類を入れておく。`Fn(&S, &mut Vec<u64>)` の関数もそのまま使える。
+pub trait NoveltyFeatures<S> {
+    /// `state` の原子を `atoms` に足す (同じ状態なら同じ順番で)
+    fn atoms(&self, state: &S, atoms: &mut Vec<u64>);
+}
+
+impl<S, F: Fn(&S, &mut Vec<u64>)> NoveltyFeatures<S> for F {
+    fn atoms(&self, state: &S, atoms: &mut Vec<u64>) {
+        self(state, atoms)
+    }
+}
+
+/// 数字集め迷路の特徴: キャラクターの位置と、根で得点が残っていたマスのうち見本に選んだものを取ったかどうか
+#[derive(Clone, Debug)]
+pub struct MazeNoveltyFeatures {
+    /// 見本に選んだマス (`y * W + x`)
+    cells: Vec<usize>,
+}
+
+impl MazeNoveltyFeatures {
+    /// 根 `root` で得点が残っているマスから、等間隔に `samples` 個を見本に選ぶ
+    pub fn new(root: &State, samples: usize) -> Self {
+        let remaining: Vec<usize> = (0..root.points.len())
+            .filter(|&cell| root.points[cell] > 0)
+            .collect();
+        let step = (remaining.len() / samples.max(1)).max(1);
+        Self {
+            cells: remaining.into_iter().step_by(step).take(samples).collect(),
+        }
+    }
+
+    /// 見本に選んだマス
+    pub fn cells(&self) -> &[usize] {
+        &self.cells
+    }
+}
+
+impl NoveltyFeatures<State> for MazeNoveltyFeatures {
+    fn atoms(&self, state: &State, atoms: &mut Vec<u64>) {
+        atoms.push((state.character.y as usize * W + state.character.x as usize) as u64);
+        for (i, &cell) in self.cells.iter().enumerate() {
+            if state.points[cell] == 0 {
+                atoms.push(1 << 32 | i as u64);
+            }
+        }
+    }
+}
+
+/// IWが読む幅の上限 (3以上を指定しても2までしか読まない)
+pub const IW_MAX_WIDTH: usize = 2;
+
+/// 幅 `width` の新しさの表に評価値 `score` の状態の原子 `atoms` の組を入れ、
+/// 1つでも初めて見た組か、その組をこれまでより高い評価値で持つ組があればtrue
+///
+/// 幅1では原子そのもの、幅2では原子の対を見る (1つの原子は同じ原子との対として入れる)。
+/// 評価値も比べるので、同じマスに着いた状態でも多く得点した方は刈り込まない。
+fn insert_novel(
+    table: &mut HashMap<(u64, u64), Eval>,
+    atoms: &[u64],
+    width: usize,
+    score: Eval,
+) -> bool {
+    let mut novel = false;
+    let mut insert = |tuple| match table.entry(tuple) {
+        Entry::Occupied(mut entry) if *entry.get() < score => {
+            entry.insert(score);
+            true
+        }
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(score);
+            true
+        }
+    };
+    for (i, &a) in atoms.iter().enumerate() {
+        novel |= insert((a, a));
+        if width >= 2 {
+            for &b in &atoms[i + 1..] {
+                novel |= insert((a, b));
+            }
+        }
+    }
+    novel
+}
+
+/// IW(1), IW(2), ... と幅を広げながら、新しい特徴を持たない状態を刈り込んだ幅優先探索で手を決める
+///
+/// 幅 `k` の探索では、それまでに生成したどの状態にもなかった `k` 個以下の原子の組か、
+/// これまでより高い評価値で持つ組がある子だけを展開する。
+/// 刈り込みで状態の数が特徴の数で抑えられるので、短い時間制限でも遠くまで読める。
+/// 幅 `max_width` (`IW_MAX_WIDTH` まで) を読み終えるか時間切れになったら、生成した中で
+/// 評価値が最も高い状態 (同点なら浅い方) の最初の行動を返す。根の子は時間を見ずに必ず生成する。
+pub fn iw_action<S: GameState, F: NoveltyFeatures<S>>(
+    state: &S,
+    features: &F,
+    max_width: usize,
+    time_threshold_us: u128,
+    stats: &mut SearchStats,
+) -> Result<S::Action, SearchError> {
+    let _span = tracing::info_span!("iw").entered();
+    if is_degraded_budget_us(time_threshold_us) {
+        return two_ply_greedy_action(state);
+    }
+    let time_keeper = TimeKeeper::new_us(time_threshold_us);
+    let mut best: Option<S> = None;
+    let mut atoms = vec![];
+    let mut timed_out = false;
+    'widths: for width in 1..=max_width.clamp(1, IW_MAX_WIDTH) {
+        let _width_span = tracing::info_span!("width").entered();
+        let mut table = HashMap::new();
+        atoms.clear();
+        features.atoms(state, &mut atoms);
+        insert_novel(&mut table, &atoms, width, state.evaluated_score());
+        let mut queue = VecDeque::from([(state.clone(), 0)]);
+        stats.states_cloned += 1;
+        while let Some((now_state, depth)) = queue.pop_front() {
+            if depth > 0 && time_keeper.is_over() {
+                timed_out = true;
+                break 'widths;
+            }
+            let legal_actions = now_state.legal_actions();
+            stats.expand(depth, legal_actions.len());
+            for action in legal_actions {
+                let mut next_state = profiled(Phase::ChildGeneration, || {
+                    let mut next_state = now_state.clone();
+                    next_state.advance(action);
+                    next_state
+                });
+                profiled(Phase::Evaluation, || next_state.evaluate());
+                atoms.clear();
+                features.atoms(&next_state, &mut atoms);
+                if !insert_novel(&mut table, &atoms, width, next_state.evaluated_score()) {
+                    continue;
+                }
+                if depth == 0 {
+                    next_state.set_first_action(action);
+                }
+                if best
+                    .as_ref()
+                    .is_none_or(|best| next_state.evaluated_score() > best.evaluated_score())
+                {
+                    best = Some(next_state.clone());
+                    stats.states_cloned += 1;
+                }
+                if !next_state.is_done() {
+                    queue.push_back((next_state, depth + 1));
+                }
+            }
+        }
+        stats.passes += 1;
+    }
+    stats.record(&time_keeper);
+
+    best.map(|s| s.first_action())
+        .ok_or_else(|| no_candidate(state, timed_out))
+}
+
+/// UCTによるモンテカルロ木探索
+///
+/// プレイアウトを `playout_budget` 回行い、最も多く訪れた子の行動を返す。
+/// 節点は `expand_threshold` 回訪れたら展開する。`c` はUCB1の探索項の係数。
+pub fn mcts_action<S: GameState>(
+    state: &S,
+    playout_budget: usize,
+    c: f64,
+    expand_threshold: usize,
+    rng: &mut ChaCha12Rng,
+) -> Result<S::Action, SearchError> {
+    mcts_root_visits(state, playout_budget, c, expand_threshold, rng)
+        .into_iter()
+        .max_by_key(|&(_, visits)| visits)
+        .map(|(action, _)| action)
+        .ok_or(SearchError::NoLegalAction)
+}
+
+/// `mcts_action` と同じ探索を行い、根の子の行動ごとの訪問回数を返す
+pub fn mcts_root_visits<S: GameState>(
+    state: &S,
+    playout_budget: usize,
+    c: f64,
+    expand_threshold: usize,
+    rng: &mut ChaCha12Rng,
+) -> Vec<(S::Action, usize)> {
+    let _span = tracing::info_span!("mcts").entered();
+    let mut tree = MctsTree {
+        nodes: Vec::new(),
+        c,
+        expand_threshold,
+        min_value: f64::INFINITY,
+        max_value: f64::NEG_INFINITY,
+    };
+    let root = tree.push(state.clone());
+    tree.expand(root);
+    for _ in 0..playout_budget {
+        tree.visit(root, rng);
+    }
+    tree.nodes[root]
+        .children
+        .iter()
+        .map(|&(action, child)| (action, tree.nodes[child].visits))
+        .collect()
+}
+
+/// 終盤の全探索で、残り `remaining` ターンで取れる得点の上界
+///
+/// 残りターン数以内に到達できる得点を大きい順に並べた累積和。得点は減る一方なので探索中も上界になる。
+fn endgame_bounds(state: &State) -> Vec<usize> {
+    let remaining_turns = state.remaining_turns();
+    let mut points: Vec<usize> = state.reachable_points().iter().map(|&(_, p)| p).collect();
+    points.sort_unstable_by(|a, b| b.cmp(a));
+    let mut bounds = vec![0; remaining_turns + 1];
+    for r in 1..=remaining_turns {
+        bounds[r] = bounds[r - 1] + points.get(r - 1).copied().unwrap_or(0);
+    }
+    bounds
+}
+
+/// 得点の高い順に並べた合法手
+fn actions_by_gain(state: &State) -> Vec<Direction> {
+    if state.mask.is_some() {
+        let mut actions = state.legal_actions();
+        actions.sort_by_key(|&action| {
+            let next = state.character.offset(action).unwrap();
+            std::cmp::Reverse(state.points[next.index()])
+        });
+        return actions;
+    }
+    // 禁止された行動がなければ、合法手は隣接するマスの表から引ける
+    let mut neighbors = neighbor_table().neighbors(state.character.index()).to_vec();
+    neighbors.sort_by_key(|&(_, next)| std::cmp::Reverse(state.points[next]));
+    neighbors.into_iter().map(|(action, _)| action).collect()
+}
+
+/// `path` は根からの行動列で、これまでの最善を更新したらその手順を `best_path` に写す
+fn endgame_dfs(
+    state: &mut State,
+    bounds: &[usize],
+    gained: usize,
+    best: &mut usize,
+    path: &mut Vec<Direction>,
+    best_path: &mut Vec<Direction>,
+) {
+    if state.is_done() {
+        if gained > *best {
+            *best = gained;
+            best_path.clone_from(path);
+        }
+        return;
+    }
+    if gained + bounds[state.remaining_turns()] <= *best {
+        return;
+    }
+    for action in actions_by_gain(state) {
+        let (character, game_score, visited, revisits, collected_hash) = (
+            state.character,
+            state.game_score,
+            state.visited,
+            state.revisits,
+            state.collected_hash,
+        );
+        let next = character.offset(action).unwrap();
+        let point = state.points[next.index()];
+        state.advance(action);
+        path.push(action);
+        endgame_dfs(
+            state,
+            bounds,
+            gained + point as usize,
+            best,
+            path,
+            best_path,
+        );
+        path.pop();
+        state.points[next.index()] = point;
+        state.character = character;
+        state.game_score = game_score;
+        state.visited = visited;
+        state.revisits = revisits;
+        state.collected_hash = collected_hash;
+        state.turn -= 1;
+    }
+}
+
+/// 残りターンで取れる得点が最大になる行動を分枝限定法の全探索で求める
+///
+/// 残りターン数が少ない終盤(十数ターン)でしか現実的な時間で終わらない。
+/// 解いた最善の手順上の局面は置換表 (`warm::transposition_table`) に残し、次からは解き直さない。
+pub fn endgame_solver_action(state: &State) -> Result<Direction, SearchError> {
+    let _span = tracing::info_span!("endgame_solver").entered();
+    // 禁止された行動と差し替えた評価関数は置換表のキーに含まれないので、その盤面では表を使わない
+    if state.mask.is_some() || state.evaluator.is_some() {
+        return solve_endgame(state).map(|(action, _)| action);
+    }
+    let table = transposition_table();
+    let key = TranspositionTable::key(state);
+    if let Some(action) = table.get(key) {
+        return Ok(action);
+    }
+    let (best_action, best_path) = solve_endgame(state)?;
+    table.insert(key, best_action);
+    // 最善の手順の途中の局面からも、残りの手順を辿るのが最善
+    let mut next = state.clone();
+    for &action in best_path.iter() {
+        next.advance(action);
+        if next.is_done() {
+            break;
+        }
+        if let Some(&following) = best_path.get(next.turn - state.turn) {
+            table.insert(TranspositionTable::key(&next), following);
+        }
+    }
+    Ok(best_action)
+}
+
+/// 終盤の全探索で最善手と、見つかれば最善の手順を求める
+fn solve_endgame(state: &State) -> Result<(Direction, Vec<Direction>), SearchError> {
+    let actions = actions_by_gain(state);
+    let Some(&first) = actions.first() else {
+        return Err(SearchError::NoLegalAction);
+    };
+    let bounds = endgame_bounds(state);
+    let mut greedy_state = state.clone();
+    while !greedy_state.is_done() {
+        let action = greedy_state.greedy_action();
+        greedy_state.advance(action);
+    }
+    // 貪欲法と同じ得点の手順も見つけられるように、上界の比較は1点下げた値から始める
+    let mut best =
+        (greedy_state.game_score.as_usize() - state.game_score.as_usize()).saturating_sub(1);
+    let mut best_action = first;
+    let mut best_path = vec![];
+    for action in actions {
+        let before = best;
+        let next = state.character.offset(action).unwrap();
+        let point = state.points[next.index()] as usize;
+        let mut child = state.clone();
+        child.advance(action);
+        let mut path = vec![action];
+        endgame_dfs(
+            &mut child,
+            &bounds,
+            point,
+            &mut best,
+            &mut path,
+            &mut best_path,
+        );
+        if best > before {
+            best_action = action;
+        }
+    }
+    Ok((best_action, best_path))
+}
@@ -0,0 +1,61 @@
+//! 盤面生成と乱数列の再現性の確認
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    game::{MazeState, INSTANCE_FORMAT_VERSION},
+    play::{run_game, PlayConfig},
+    policy::RandomPolicy,
+    util::{fnv1a, FNV_OFFSET_BASIS},
+};
+
+/// シード0..16の初期盤面のフィンガープリント
+pub const EXPECTED_INSTANCE_FINGERPRINT: &str = "2bd61eae2fcd6185";
+/// シード0..16でランダムAIが選ぶ行動列のフィンガープリント
+pub const EXPECTED_POLICY_RNG_FINGERPRINT: &str = "58f47b94425d8dfb";
+
+/// 初期盤面のフィンガープリント
+pub fn instance_fingerprint() -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    for seed in 0..16 {
+        hash = fnv1a(hash, MazeState::new(seed).to_string().as_bytes());
+    }
+    format!("{hash:016x}")
+}
+
+/// ランダムAIの乱数列のフィンガープリント
+pub fn policy_rng_fingerprint() -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    for seed in 0..16 {
+        let mut policy = RandomPolicy {
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        };
+        hash = fnv1a(
+            hash,
+            run_game(seed, &mut policy, &PlayConfig::default())
+                .token
+                .as_bytes(),
+        );
+    }
+    format!("{hash:016x}")
+}
+
+/// 盤面生成とAIの乱数列が期待どおりか確かめる
+///
+/// プラットフォームや依存クレートの更新で結果が変わっていないかの確認に使う。
+pub fn self_check() -> Result<(), String> {
+    let instance = instance_fingerprint();
+    if instance != EXPECTED_INSTANCE_FINGERPRINT {
+        return Err(format!(
+            "instance fingerprint mismatch (format version {INSTANCE_FORMAT_VERSION}): expected {EXPECTED_INSTANCE_FINGERPRINT}, got {instance}"
+        ));
+    }
+    let policy_rng = policy_rng_fingerprint();
+    if policy_rng != EXPECTED_POLICY_RNG_FINGERPRINT {
+        return Err(format!(
+            "policy rng fingerprint mismatch: expected {EXPECTED_POLICY_RNG_FINGERPRINT}, got {policy_rng}"
+        ));
+    }
+    Ok(())
+}
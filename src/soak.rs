@@ -0,0 +1,137 @@
+//! ソークテスト
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::Instant,
+};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    crash,
+    game::{GameConfig, State, END_TURN},
+    policy::{
+        BeamSearchPolicy, ChokudaiSearchPolicy, GreedyPolicy, Policy, RandomPolicy,
+        TimedBeamSearchPolicy, TimedChokudaiSearchPolicy,
+    },
+    search::{Attribution, SearchStats, StopRule, CHOKUDAI_CHECK_INTERVAL},
+};
+
+/// 時間制限の超過とみなすまでの猶予[ms]
+pub const SOAK_OVERRUN_TOLERANCE: u128 = 5;
+
+/// ソークテスト用にランダムな設定のAIを作る
+pub fn random_policy(rng: &mut ChaCha12Rng) -> Box<dyn Policy> {
+    match rng.gen_range(0..6) {
+        0 => Box::new(RandomPolicy {
+            rng: ChaCha12Rng::seed_from_u64(rng.gen()),
+        }),
+        1 => Box::new(GreedyPolicy),
+        2 => Box::new(BeamSearchPolicy {
+            beam_width: rng.gen_range(1..=10),
+            beam_depth: rng.gen_range(1..=END_TURN),
+            attribution: Attribution::default(),
+            stats: SearchStats::default(),
+        }),
+        3 => Box::new(TimedBeamSearchPolicy {
+            beam_width: rng.gen_range(1..=10),
+            time_threshold_us: rng.gen_range(0..=10) * 1000,
+            stop_rule: StopRule::default(),
+            eval_cache: None,
+            stats: SearchStats::default(),
+        }),
+        4 => Box::new(ChokudaiSearchPolicy {
+            beam_width: rng.gen_range(1..=3),
+            beam_depth: rng.gen_range(1..=END_TURN),
+            beam_num: rng.gen_range(1..=3),
+            stats: SearchStats::default(),
+        }),
+        _ => Box::new(TimedChokudaiSearchPolicy {
+            beam_width: rng.gen_range(1..=3),
+            beam_depth: rng.gen_range(1..=END_TURN),
+            time_threshold_us: rng.gen_range(0..=10) * 1000,
+            check_interval: CHOKUDAI_CHECK_INTERVAL,
+            eval_cache: None,
+            stats: SearchStats::default(),
+        }),
+    }
+}
+
+/// 現在の常駐メモリ量[KiB] (取得できない環境ではNone)
+pub fn resident_memory_kib() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4)
+}
+
+/// ランダムな設定でゲームを遊び続け、panic・時間超過・メモリ増加を記録する
+pub fn soak(hours: f64, report_path: &str) -> std::io::Result<()> {
+    let mut rng = ChaCha12Rng::seed_from_u64(0);
+    let start_time = Instant::now();
+    let duration = std::time::Duration::from_secs_f64(hours * 3600.);
+    let initial_memory = resident_memory_kib();
+    let mut max_memory = initial_memory;
+    let mut num_game = 0;
+    let mut failures = vec![];
+    let mut overruns = vec![];
+
+    while start_time.elapsed() < duration {
+        let seed = rng.gen::<u64>();
+        let mut policy = random_policy(&mut rng);
+        let name = policy.name();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut state = State::new(seed);
+            let _crash_guard =
+                crash::enter_game(seed, name.clone(), &GameConfig::default(), &state);
+            let mut actions = vec![];
+            let mut worst = 0;
+            while !state.is_done() {
+                crash::note_turn(&state, &actions, policy.search_stats());
+                let move_start = Instant::now();
+                let action = policy.select_action(&state);
+                worst = worst.max(move_start.elapsed().as_millis());
+                actions.push(action.index());
+                state.advance(action);
+            }
+            worst
+        }));
+        match result {
+            Ok(worst) => {
+                if let Some(time_threshold_us) = policy.time_threshold_us() {
+                    if worst > time_threshold_us / 1000 + SOAK_OVERRUN_TOLERANCE {
+                        overruns.push(format!("seed={seed} {name} worst={worst}ms"));
+                    }
+                }
+            }
+            Err(err) => {
+                let message = err
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| err.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                failures.push(format!("seed={seed} {name} panic: {message}"));
+            }
+        }
+        num_game += 1;
+        max_memory = max_memory.max(resident_memory_kib());
+    }
+
+    let mut report = BufWriter::new(File::create(report_path)?);
+    writeln!(
+        report,
+        "elapsed:\t{:.1}s",
+        start_time.elapsed().as_secs_f64()
+    )?;
+    writeln!(report, "games:\t{num_game}")?;
+    writeln!(report, "panics:\t{}", failures.len())?;
+    writeln!(report, "overruns:\t{}", overruns.len())?;
+    if let (Some(initial), Some(max)) = (initial_memory, max_memory) {
+        writeln!(report, "memory:\t{initial}KiB -> max {max}KiB")?;
+    }
+    for line in failures.iter().chain(&overruns) {
+        writeln!(report, "{line}")?;
+    }
+    report.flush()
+}
@@ -0,0 +1,132 @@
+//! AIや評価関数の指定を読めなかったときのエラー
+//!
+//! 知らない名前やパラメータには受け付ける名前の一覧を添え、綴りの近い名前があれば
+//! `did you mean` で候補を示す。
+
+use std::fmt;
+
+/// 指定を読めなかった理由
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpecError {
+    /// 知らない名前 (`kind` は `policy`・`evaluator`・`attribution` など)
+    UnknownName {
+        kind: &'static str,
+        name: String,
+        valid: Vec<&'static str>,
+    },
+    /// AI `policy` が受け付けないパラメータ
+    UnknownParam {
+        policy: String,
+        key: String,
+        valid: Vec<&'static str>,
+    },
+    /// `キー=値` の形になっていない、または値を読めないパラメータ
+    InvalidParam {
+        param: String,
+        spec: String,
+        expected: &'static str,
+    },
+    /// 式の書き方の誤りなど、上のどれにも当たらないもの
+    Invalid(String),
+}
+
+impl SpecError {
+    /// 知らない名前 `name` のエラー
+    pub fn unknown_name(kind: &'static str, name: &str, valid: &[&'static str]) -> Self {
+        SpecError::UnknownName {
+            kind,
+            name: name.to_string(),
+            valid: valid.to_vec(),
+        }
+    }
+
+    /// 綴りの近い候補 (なければNone)
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self {
+            SpecError::UnknownName { name, valid, .. } => did_you_mean(name, valid),
+            SpecError::UnknownParam { key, valid, .. } => did_you_mean(key, valid),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::UnknownName { kind, name, .. } => write!(f, "unknown {kind} {name:?}")?,
+            SpecError::UnknownParam { policy, key, .. } => {
+                write!(f, "unknown parameter {key:?} for {policy}")?
+            }
+            SpecError::InvalidParam {
+                param,
+                spec,
+                expected,
+            } => {
+                return write!(
+                    f,
+                    "invalid parameter {param:?} in {spec:?} (expected {expected})"
+                )
+            }
+            SpecError::Invalid(message) => return write!(f, "{message}"),
+        }
+        if let Some(suggestion) = self.suggestion() {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        match self {
+            SpecError::UnknownParam { policy, valid, .. } if valid.is_empty() => {
+                write!(f, "; {policy} takes no parameters")
+            }
+            SpecError::UnknownName { valid, .. } | SpecError::UnknownParam { valid, .. } => {
+                write!(f, "; expected one of: {}", valid.join(", "))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+/// 文字列のエラーを返す関数の中から `?` で使えるように
+impl From<SpecError> for String {
+    fn from(error: SpecError) -> Self {
+        error.to_string()
+    }
+}
+
+/// `candidates` の中で `name` に最も綴りの近いもの
+///
+/// 編集距離が名前の長さの1/3 (最低1) を超えるものは候補にしない。
+pub fn did_you_mean(name: &str, candidates: &[&'static str]) -> Option<&'static str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|&candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// 1文字の挿入・削除・置換と、隣り合う2文字の入れ替えをそれぞれ1とした編集距離
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distance[i][j] は a[..i] と b[..j] の距離
+    let mut distance = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..=a.len() {
+        distance[i][0] = i;
+    }
+    for j in 0..=b.len() {
+        distance[0][j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distance[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            distance[i][j] = substitution
+                .min(distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance[i][j] = distance[i][j].min(distance[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    distance[a.len()][b.len()]
+}
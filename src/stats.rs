@@ -0,0 +1,329 @@
+//! 値を溜めずに1つずつ足し込んで求める統計量
+//!
+//! 何百万ゲーム分のスコアでも、全部をメモリに持たずに平均・分散・分位点を出せる。
+
+use std::f64::consts::PI;
+
+/// 平均と分散をWelfordの方法で逐次求める
+#[derive(Clone, Copy, Debug)]
+pub struct RunningStats {
+    pub count: u64,
+    pub mean: f64,
+    /// 平均からの偏差の2乗和
+    pub m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for RunningStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.,
+            m2: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl RunningStats {
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    /// 別に集計したものを合わせる (スレッドごとの集計をまとめるのに使う)
+    pub fn merge(&mut self, other: &RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// 不偏分散 (2個未満なら0)
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// 平均の標準誤差
+    pub fn std_error(&self) -> f64 {
+        if self.count == 0 {
+            return 0.;
+        }
+        self.std_dev() / (self.count as f64).sqrt()
+    }
+
+    /// 平均の95%信頼区間の半幅 (t分布による。2個未満なら無限大)
+    pub fn ci95_half_width(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        student_t_975(self.count - 1) * self.std_error()
+    }
+}
+
+/// 自由度 `df` のt分布の97.5%点
+///
+/// 自由度5以上は正規分布の97.5%点からのCornish-Fisher展開による近似で、誤差は0.2%未満。
+pub fn student_t_975(df: u64) -> f64 {
+    match df {
+        0 => f64::INFINITY,
+        1 => 12.706,
+        2 => 4.303,
+        3 => 3.182,
+        4 => 2.776,
+        _ => {
+            let z: f64 = 1.959_964;
+            let df = df as f64;
+            let (z3, z5, z7) = (z.powi(3), z.powi(5), z.powi(7));
+            z + (z3 + z) / (4. * df)
+                + (5. * z5 + 16. * z3 + 3. * z) / (96. * df * df)
+                + (3. * z7 + 19. * z5 + 17. * z3 - 15. * z) / (384. * df.powi(3))
+        }
+    }
+}
+
+/// 分位点を一定のメモリで近似するt-digest
+///
+/// 値を重み付きの重心にまとめ、分布の端ほど重心を小さく保つので、中央より p1 や p99 の方が正確になる。
+/// 重心の数はおよそ `compression` 個に収まる。
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    pub compression: f64,
+    /// (平均, 重み) を平均の昇順に並べたもの
+    pub centroids: Vec<(f64, f64)>,
+    /// まだ重心にまとめていない値
+    pub buffer: Vec<f64>,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(100.)
+    }
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        Self {
+            compression,
+            centroids: vec![],
+            buffer: vec![],
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        self.buffer.push(x);
+        self.count += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        if self.buffer.len() as f64 >= self.compression * 5. {
+            self.compress();
+        }
+    }
+
+    /// 別に集計したものを合わせる
+    pub fn merge(&mut self, other: &TDigest) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend(&other.buffer);
+        self.centroids.extend(&other.centroids);
+        self.centroids.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.compress();
+    }
+
+    /// 分位点 `q` (0.0〜1.0) を与えるスケール関数 `k(q) = δ / 2π * asin(2q - 1)`
+    fn scale(&self, q: f64) -> f64 {
+        self.compression / (2. * PI) * (2. * q - 1.).asin()
+    }
+
+    fn inverse_scale(&self, k: f64) -> f64 {
+        ((k * 2. * PI / self.compression).sin() + 1.) / 2.
+    }
+
+    /// 溜めた値と重心を平均の順に並べ、スケール関数で1つ分の幅に収まる範囲ずつまとめ直す
+    pub fn compress(&mut self) {
+        if self.buffer.is_empty() && self.centroids.len() as f64 <= self.compression * 2. {
+            return;
+        }
+        let mut points: Vec<(f64, f64)> = self.buffer.drain(..).map(|x| (x, 1.)).collect();
+        points.append(&mut self.centroids);
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if points.is_empty() {
+            return;
+        }
+        let total: f64 = points.iter().map(|&(_, w)| w).sum();
+
+        let mut merged = Vec::with_capacity(self.compression as usize * 2);
+        let mut current = points[0];
+        let mut weight_before = 0.;
+        let mut limit = total * self.inverse_scale(self.scale(0.) + 1.);
+        for &(mean, weight) in &points[1..] {
+            if weight_before + current.1 + weight <= limit {
+                current.1 += weight;
+                current.0 += (mean - current.0) * weight / current.1;
+            } else {
+                weight_before += current.1;
+                merged.push(current);
+                limit = total * self.inverse_scale(self.scale(weight_before / total) + 1.);
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// 分位点 `q` (0.0〜1.0) の近似値 (空ならNaN)
+    ///
+    /// まだ一度も重心にまとめていない (値が `5 * compression` 個未満の) ときは、並べ替えて正確に求める。
+    pub fn quantile(&mut self, q: f64) -> f64 {
+        if self.centroids.is_empty() && !self.buffer.is_empty() {
+            self.buffer.sort_by(f64::total_cmp);
+            let position = q.clamp(0., 1.) * (self.buffer.len() - 1) as f64;
+            let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+            let t = position - lower as f64;
+            return self.buffer[lower] + (self.buffer[upper] - self.buffer[lower]) * t;
+        }
+        self.compress();
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+        let total: f64 = self.centroids.iter().map(|&(_, w)| w).sum();
+        let target = q.clamp(0., 1.) * total;
+        // 重心の値はその重みの中央にあるとみなして、隣の重心との間を線形補間する
+        let mut previous = (self.min, 0.);
+        let mut cumulative = 0.;
+        for &(mean, weight) in &self.centroids {
+            let center = cumulative + weight / 2.;
+            if target < center {
+                let (previous_mean, previous_center) = previous;
+                if center == previous_center {
+                    return mean;
+                }
+                let t = (target - previous_center) / (center - previous_center);
+                return previous_mean + (mean - previous_mean) * t;
+            }
+            previous = (mean, center);
+            cumulative += weight;
+        }
+        let (last_mean, last_center) = previous;
+        if total == last_center {
+            return self.max;
+        }
+        let t = (target - last_center) / (total - last_center);
+        last_mean + (self.max - last_mean) * t
+    }
+}
+
+/// 標準正規分布の累積分布関数
+///
+/// 相補誤差関数のChebyshev近似 (Numerical Recipes の `erfcc`、相対誤差1.2e-7未満) による。
+pub fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1. / (1. + 0.5 * z);
+    let erfc = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0. {
+        1. - erfc / 2.
+    } else {
+        erfc / 2.
+    }
+}
+
+/// Wilcoxonの符号付き順位検定の結果
+#[derive(Clone, Copy, Debug)]
+pub struct WilcoxonResult {
+    /// 差が0でない組の数
+    pub n: usize,
+    /// 差が正の組の順位和
+    pub w_plus: f64,
+    /// 差が負の組の順位和
+    pub w_minus: f64,
+    /// 正規近似の検定統計量 (正なら差が正に偏っている)
+    pub z: f64,
+    /// 両側のp値
+    pub p_value: f64,
+}
+
+/// 対応のある差 `differences` がゼロを中心に分布しているかをWilcoxonの符号付き順位検定で調べる
+///
+/// 差が0の組は除き、絶対値が同じ組には平均の順位を付ける。p値は同順位の補正と連続性の補正を
+/// した正規近似で求めるので、差が0でない組が20組程度以上あるときに使う。
+pub fn wilcoxon_signed_rank(differences: &[f64]) -> WilcoxonResult {
+    let mut nonzero: Vec<f64> = differences.iter().copied().filter(|&d| d != 0.).collect();
+    nonzero.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+    let n = nonzero.len();
+    let (mut w_plus, mut w_minus) = (0., 0.);
+    // 同順位の補正項 Σ(t^3 - t) / 48
+    let mut tie_correction = 0.;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j < n && nonzero[j].abs() == nonzero[i].abs() {
+            j += 1;
+        }
+        let rank = (i + 1 + j) as f64 / 2.;
+        for &d in &nonzero[i..j] {
+            if d > 0. {
+                w_plus += rank;
+            } else {
+                w_minus += rank;
+            }
+        }
+        let t = (j - i) as f64;
+        tie_correction += (t * t * t - t) / 48.;
+        i = j;
+    }
+    let nf = n as f64;
+    let mean = nf * (nf + 1.) / 4.;
+    let variance = nf * (nf + 1.) * (2. * nf + 1.) / 24. - tie_correction;
+    let (z, p_value) = if variance > 0. {
+        let diff = w_plus - mean;
+        let corrected = (diff.abs() - 0.5).max(0.) * diff.signum();
+        let z = corrected / variance.sqrt();
+        (z, (2. * (1. - normal_cdf(z.abs()))).min(1.))
+    } else {
+        (0., 1.)
+    };
+    WilcoxonResult {
+        n,
+        w_plus,
+        w_minus,
+        z,
+        p_value,
+    }
+}
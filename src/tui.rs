@@ -0,0 +1,246 @@
+//! AIが遊ぶ様子を端末に描き続ける表示 (`tui` フィーチャー)
+//!
+//! 左に盤面、右にターン・スコア・選んだ行動・思考時間を描く。スペースで一時停止、
+//! 一時停止中は `n` で1ターンずつ進め、`+`・`-` で速さを変え、`q` で終わる。
+
+use std::{io, time::Duration, time::Instant};
+
+use ratatui::{
+    backend::Backend,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame, Terminal,
+};
+
+use crate::{
+    game::{Direction, GameState, State, H, W},
+    policy::Policy,
+};
+
+/// 再生中・一時停止中に受け付ける操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Controls {
+    pub paused: bool,
+    /// 1ターン進めるごとに待つ時間
+    pub delay: Duration,
+    pub quit: bool,
+    /// 一時停止中に1ターンだけ進める
+    step: bool,
+}
+
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+/// 一時停止中や終局後にキー入力を待つ間隔
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+impl Controls {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            paused: false,
+            delay: delay.clamp(MIN_DELAY, MAX_DELAY),
+            quit: false,
+            step: false,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.paused = !self.paused,
+            KeyCode::Char('n') | KeyCode::Right => {
+                self.paused = true;
+                self.step = true;
+            }
+            KeyCode::Char('+') | KeyCode::Up => self.delay = (self.delay / 2).max(MIN_DELAY),
+            KeyCode::Char('-') | KeyCode::Down => self.delay = (self.delay * 2).min(MAX_DELAY),
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            _ => {}
+        }
+    }
+
+    /// 次のターンに進めてよいか (コマ送りの指示は1回で使い切る)
+    pub fn take_advance(&mut self) -> bool {
+        std::mem::take(&mut self.step) || !self.paused
+    }
+}
+
+/// サイドバーに表示する、これまでの手の記録
+#[derive(Clone, Debug, Default)]
+pub struct PlayStats {
+    pub policy: String,
+    pub last_action: Option<Direction>,
+    pub last_elapsed_us: u64,
+    pub max_elapsed_us: u64,
+    pub total_elapsed_us: u64,
+    /// AIに手を決めさせたターン数 (早送りしたターンは数えない)
+    pub searched_turns: usize,
+    /// 1手あたりの時間制限[us]
+    pub time_threshold_us: Option<u128>,
+}
+
+impl PlayStats {
+    pub fn average_elapsed_us(&self) -> f64 {
+        self.total_elapsed_us as f64 / self.searched_turns.max(1) as f64
+    }
+}
+
+fn board_lines(state: &State) -> Vec<Line<'static>> {
+    (0..H)
+        .map(|y| {
+            Line::from(
+                (0..W)
+                    .map(|x| {
+                        let point = state.points[y * W + x];
+                        if state.character.y == y as i32 && state.character.x == x as i32 {
+                            Span::styled(
+                                "@",
+                                Style::default()
+                                    .fg(Color::Yellow)
+                                    .add_modifier(Modifier::BOLD),
+                            )
+                        } else if point > 0 {
+                            Span::raw(point.to_string())
+                        } else {
+                            Span::styled(".", Style::default().fg(Color::DarkGray))
+                        }
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+fn sidebar_lines(state: &State, stats: &PlayStats, controls: &Controls) -> Vec<Line<'static>> {
+    let mut evaluated = state.clone();
+    evaluated.evaluate();
+    let status = if state.is_done() {
+        "done"
+    } else if controls.paused {
+        "paused"
+    } else {
+        "playing"
+    };
+    let mut lines = vec![
+        format!("policy: {}", stats.policy),
+        format!("turn: {}/{}", state.turn, state.end_turn),
+        format!("score: {}", state.game_score),
+        format!("evaluated: {}", evaluated.evaluated_score.0),
+        format!(
+            "action: {}",
+            stats.last_action.map_or("-", |action| action.name())
+        ),
+        format!("think: {}us", stats.last_elapsed_us),
+        format!("think(avg): {:.0}us", stats.average_elapsed_us()),
+        format!("think(max): {}us", stats.max_elapsed_us),
+    ];
+    if let Some(us) = stats.time_threshold_us {
+        lines.push(if us.is_multiple_of(1000) {
+            format!("budget: {}ms", us / 1000)
+        } else {
+            format!("budget: {us}us")
+        });
+    }
+    lines.extend([
+        format!("delay: {}ms", controls.delay.as_millis()),
+        format!("status: {status}"),
+        String::new(),
+        "space: pause  n: step".to_string(),
+        "+/-: speed  q: quit".to_string(),
+    ]);
+    lines.into_iter().map(Line::from).collect()
+}
+
+/// 盤面とサイドバーを1フレーム描く
+pub fn draw(frame: &mut Frame, state: &State, stats: &PlayStats, controls: &Controls) {
+    let [board_area, sidebar_area] =
+        Layout::horizontal([Constraint::Length(W as u16 + 2), Constraint::Min(24)])
+            .areas(frame.area());
+    frame.render_widget(
+        Paragraph::new(board_lines(state)).block(Block::default().borders(Borders::ALL)),
+        board_area,
+    );
+    frame.render_widget(
+        Paragraph::new(sidebar_lines(state, stats, controls))
+            .block(Block::default().borders(Borders::ALL)),
+        sidebar_area,
+    );
+}
+
+/// 盤面 `seed` で `policy` に遊ばせながら `terminal` に描き続け、`q` で終わったときの記録を返す
+///
+/// `next_key` は最大で引数の時間だけキー入力を待ち、押されたキーを返す。
+/// 終局後も `q` が押されるまで最後の盤面を描き続ける。
+pub fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    seed: u64,
+    policy: &mut dyn Policy,
+    controls: &mut Controls,
+    next_key: &mut dyn FnMut(Duration) -> io::Result<Option<KeyCode>>,
+) -> io::Result<(State, PlayStats)> {
+    let mut state = State::new(seed);
+    let mut stats = PlayStats {
+        policy: policy.name(),
+        time_threshold_us: policy.time_threshold_us(),
+        ..PlayStats::default()
+    };
+    while !controls.quit {
+        terminal.draw(|frame| draw(frame, &state, &stats, controls))?;
+        let timeout = if controls.paused || state.is_done() {
+            IDLE_POLL.max(controls.delay)
+        } else {
+            controls.delay
+        };
+        if let Some(key) = next_key(timeout)? {
+            controls.handle_key(key);
+        }
+        if controls.quit || state.is_done() || !controls.take_advance() {
+            continue;
+        }
+        if state.future_gain_bound() == 0 {
+            // これ以上得点できないので探索せずに進める
+            let action = state.legal_actions()[0];
+            state.advance(action);
+            stats.last_action = Some(action);
+            stats.last_elapsed_us = 0;
+            continue;
+        }
+        let start = Instant::now();
+        let action = policy.select_action(&state);
+        let elapsed_us = start.elapsed().as_micros() as u64;
+        state.advance(action);
+        stats.last_action = Some(action);
+        stats.last_elapsed_us = elapsed_us;
+        stats.max_elapsed_us = stats.max_elapsed_us.max(elapsed_us);
+        stats.total_elapsed_us += elapsed_us;
+        stats.searched_turns += 1;
+    }
+    Ok((state, stats))
+}
+
+/// 端末から押されたキーを読む (離したときのイベントは読み飛ばす)
+fn read_key(timeout: Duration) -> io::Result<Option<KeyCode>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    Ok(match event::read()? {
+        Event::Key(key) if key.kind == KeyEventKind::Press => Some(key.code),
+        _ => None,
+    })
+}
+
+/// 端末を全画面表示に切り替えて `run` し、終わったら元に戻す
+pub fn play_tui(seed: u64, policy: &mut dyn Policy, delay: Duration) -> io::Result<State> {
+    let mut terminal = ratatui::init();
+    let result = run(
+        &mut terminal,
+        seed,
+        policy,
+        &mut Controls::new(delay),
+        &mut read_key,
+    );
+    ratatui::restore();
+    let (state, _) = result?;
+    Ok(state)
+}
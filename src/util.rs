@@ -0,0 +1,607 @@
+//! 時間計測・プロファイル・ハッシュ・スレッドなどの共通処理
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    time::Instant,
+};
+
+/// 時間制限の計測に使う時計
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Clock {
+    /// 実時間
+    Wall,
+    /// 呼び出したスレッドが消費したCPU時間
+    ThreadCpu,
+}
+
+/// `TimeKeeper::new` が使う時計 (trueならスレッドCPU時間)
+pub static USE_THREAD_CPU_CLOCK: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+impl Clock {
+    /// 以降に作る `TimeKeeper` の既定の時計を変更する
+    pub fn set_default(clock: Clock) {
+        USE_THREAD_CPU_CLOCK.store(
+            clock == Clock::ThreadCpu,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    pub fn default_clock() -> Clock {
+        if USE_THREAD_CPU_CLOCK.load(std::sync::atomic::Ordering::Relaxed) {
+            Clock::ThreadCpu
+        } else {
+            Clock::Wall
+        }
+    }
+
+    /// この時計の時刻を返す `TimeSource`
+    pub fn source(self) -> std::sync::Arc<dyn TimeSource> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            Clock::Wall => std::sync::Arc::new(SystemClock::default()),
+            // wasm32-unknown-unknown では Instant が使えないので performance.now() で測る
+            #[cfg(target_arch = "wasm32")]
+            Clock::Wall => std::sync::Arc::new(PerformanceNowClock::default()),
+            Clock::ThreadCpu => std::sync::Arc::new(ThreadCpuClock),
+        }
+    }
+}
+
+/// 呼び出したスレッドがこれまでに消費したCPU時間
+#[cfg(not(target_arch = "wasm32"))]
+pub fn thread_cpu_time() -> std::time::Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts);
+    }
+    std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// wasm32 にはスレッドのCPU時間がないので実時間で代用する
+#[cfg(target_arch = "wasm32")]
+pub fn thread_cpu_time() -> std::time::Duration {
+    PerformanceNowClock::default().now()
+}
+
+/// 時間制限の計測に使う、単調に進む時刻の源
+pub trait TimeSource: Send + Sync {
+    /// 任意の起点からの経過時間
+    fn now(&self) -> std::time::Duration;
+}
+
+/// `Instant` による実時間 (作った時点が起点)
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> std::time::Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// 呼び出したスレッドが消費したCPU時間
+pub struct ThreadCpuClock;
+
+impl TimeSource for ThreadCpuClock {
+    fn now(&self) -> std::time::Duration {
+        thread_cpu_time()
+    }
+}
+
+/// 手で進める時計
+///
+/// `advance` で進めるほか、`ticking` で作ると時刻を読むたびに一定時間進むので、
+/// 時間制限付きの探索を実時間によらず決定的に動かせる。clone した時計は時刻を共有する。
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    nanos: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    step_nanos: u64,
+}
+
+impl ManualClock {
+    /// 時刻を読むたびに `step` 進む時計
+    pub fn ticking(step: std::time::Duration) -> Self {
+        Self {
+            nanos: Default::default(),
+            step_nanos: step.as_nanos() as u64,
+        }
+    }
+
+    pub fn advance(&self, duration: std::time::Duration) {
+        self.nanos.fetch_add(
+            duration.as_nanos() as u64,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(
+            self.nanos
+                .fetch_add(self.step_nanos, std::sync::atomic::Ordering::Relaxed),
+        )
+    }
+}
+
+/// `performance.now()` のようにミリ秒を小数で返す関数による時計
+///
+/// wasm32 では `Default` がホストから `env.performance_now` として渡された
+/// `performance.now()` を使う。
+pub struct PerformanceNowClock {
+    pub now_ms: fn() -> f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "env")]
+extern "C" {
+    fn performance_now() -> f64;
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for PerformanceNowClock {
+    fn default() -> Self {
+        Self {
+            now_ms: || unsafe { performance_now() },
+        }
+    }
+}
+
+impl TimeSource for PerformanceNowClock {
+    fn now(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64((self.now_ms)().max(0.) / 1000.)
+    }
+}
+
+thread_local! {
+    static TIME_SOURCE: std::cell::RefCell<Option<std::sync::Arc<dyn TimeSource>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// このスレッドで `f` の中で作る `TimeKeeper` に時計 `source` を使わせる
+///
+/// 時間制限付きの探索はどれも `TimeKeeper::new` で時間を測るので、
+/// 探索の呼び出しをこれで包むと時計を差し替えられる。
+pub fn with_time_source<T>(source: std::sync::Arc<dyn TimeSource>, f: impl FnOnce() -> T) -> T {
+    let previous = TIME_SOURCE.with(|current| current.replace(Some(source)));
+    let result = f();
+    TIME_SOURCE.with(|current| current.replace(previous));
+    result
+}
+
+/// SIGUSR1 を受け取ったら立てるフラグ
+static CANCEL_SIGNAL_RECEIVED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_cancel_signal(_: libc::c_int) {
+    CANCEL_SIGNAL_RECEIVED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// キルファイルの有無を確かめる間隔 (`is_cancelled` の呼び出し回数)
+const KILL_FILE_POLL_INTERVAL: u32 = 64;
+
+/// 進行中の時間制限付きの探索を外から打ち切らせるトークン
+///
+/// `cancel` を呼ぶか、キルファイルが作られるか、SIGUSR1 を受け取るとキャンセルされたことになり、
+/// `with_cancel_token` で包んだ探索は時間切れと同じように打ち切ってその時点の最善手を返す。
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    kill_file: Option<std::sync::Arc<std::path::PathBuf>>,
+    watch_signal: bool,
+    polls: std::sync::Arc<std::sync::atomic::AtomicU32>,
+}
+
+impl CancelToken {
+    /// `path` にファイルが作られたらキャンセルする
+    pub fn with_kill_file(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.kill_file = Some(std::sync::Arc::new(path.into()));
+        self
+    }
+
+    /// SIGUSR1 を受け取ったらキャンセルする (シグナルハンドラを登録する)
+    #[cfg(unix)]
+    pub fn with_signal(mut self) -> Self {
+        let handler: extern "C" fn(libc::c_int) = on_cancel_signal;
+        unsafe {
+            libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+        }
+        self.watch_signal = true;
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        use std::sync::atomic::Ordering::Relaxed;
+        if self.cancelled.load(Relaxed) {
+            return true;
+        }
+        if self.watch_signal && CANCEL_SIGNAL_RECEIVED.load(Relaxed) {
+            self.cancel();
+            return true;
+        }
+        if let Some(path) = &self.kill_file {
+            if self
+                .polls
+                .fetch_add(1, Relaxed)
+                .is_multiple_of(KILL_FILE_POLL_INTERVAL)
+                && path.exists()
+            {
+                self.cancel();
+                return true;
+            }
+        }
+        false
+    }
+
+    /// キャンセルされていたかを返し、次の探索のために解除する (キルファイルは消す)
+    pub fn reset(&self) -> bool {
+        use std::sync::atomic::Ordering::Relaxed;
+        let cancelled = self.is_cancelled();
+        self.cancelled.store(false, Relaxed);
+        self.polls.store(0, Relaxed);
+        if self.watch_signal {
+            CANCEL_SIGNAL_RECEIVED.store(false, Relaxed);
+        }
+        if let Some(path) = &self.kill_file {
+            let _ = std::fs::remove_file(path.as_ref());
+        }
+        cancelled
+    }
+}
+
+thread_local! {
+    static CANCEL_TOKEN: std::cell::RefCell<Option<CancelToken>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// このスレッドで `f` の中で作る `TimeKeeper` を `token` でキャンセルできるようにする
+pub fn with_cancel_token<T>(token: &CancelToken, f: impl FnOnce() -> T) -> T {
+    let previous = CANCEL_TOKEN.with(|current| current.replace(Some(token.clone())));
+    let result = f();
+    CANCEL_TOKEN.with(|current| current.replace(previous));
+    result
+}
+
+pub struct TimeKeeper {
+    pub source: std::sync::Arc<dyn TimeSource>,
+    pub start_time: std::time::Duration,
+    /// 時間制限[us]
+    pub time_threshold_us: u128,
+    /// キャンセルされたら時間切れとみなす
+    pub cancel_token: Option<CancelToken>,
+}
+
+impl TimeKeeper {
+    /// `with_time_source` で差し替えた時計、なければ既定の時計で `time_threshold` [ms] を測る
+    pub fn new(time_threshold: u128) -> Self {
+        Self::new_us(time_threshold * 1000)
+    }
+
+    /// 時間制限を[us]で指定する `new`
+    pub fn new_us(time_threshold_us: u128) -> Self {
+        let source = TIME_SOURCE
+            .with(|current| current.borrow().clone())
+            .unwrap_or_else(|| Clock::default_clock().source());
+        Self::with_source_us(time_threshold_us, source)
+    }
+
+    pub fn with_clock(time_threshold: u128, clock: Clock) -> Self {
+        Self::with_source(time_threshold, clock.source())
+    }
+
+    pub fn with_source(time_threshold: u128, source: std::sync::Arc<dyn TimeSource>) -> Self {
+        Self::with_source_us(time_threshold * 1000, source)
+    }
+
+    pub fn with_source_us(time_threshold_us: u128, source: std::sync::Arc<dyn TimeSource>) -> Self {
+        Self {
+            start_time: source.now(),
+            source,
+            time_threshold_us,
+            cancel_token: CANCEL_TOKEN.with(|current| current.borrow().clone()),
+        }
+    }
+
+    /// 計測を始めてからの経過時間
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.source.now().saturating_sub(self.start_time)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.elapsed().as_micros() >= self.time_threshold_us
+            || self
+                .cancel_token
+                .as_ref()
+                .is_some_and(CancelToken::is_cancelled)
+    }
+}
+
+/// 1ゲーム分の持ち時間
+///
+/// 1手の時間制限 `per_move_us` のうち使わなかった時間を貯めておき、次の手の時間制限に上乗せする。
+/// 時間切れの前に打ち切れる探索 (`search::StopRule`) が簡単な局面で余らせた時間を、
+/// 打ち切れなかった難しい局面で使える。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameClock {
+    pub per_move_us: u128,
+    /// 貯めた時間[us]
+    pub bank_us: u128,
+}
+
+impl GameClock {
+    pub fn new(per_move_us: u128) -> Self {
+        Self {
+            per_move_us,
+            bank_us: 0,
+        }
+    }
+
+    /// 次の手に使える時間[us] (1手の時間制限と貯めた時間の合計)
+    pub fn allowance_us(&self) -> u128 {
+        self.per_move_us + self.bank_us
+    }
+
+    /// 次の手に `elapsed_us` かかったことを記録し、使わなかった分を貯める
+    pub fn record(&mut self, elapsed_us: u128) {
+        self.bank_us = self.allowance_us().saturating_sub(elapsed_us);
+    }
+}
+
+/// `--profile` で計測する探索のフェーズ
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// 状態のcloneとadvance
+    ChildGeneration,
+    Evaluation,
+    /// ビームへのpush/pop
+    Heap,
+    /// 重複状態の除去
+    Dedup,
+}
+
+pub const PHASE_NAMES: [&str; 4] = ["child_generation", "evaluation", "heap", "dedup"];
+
+pub static PROFILE_ENABLED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+thread_local! {
+    static PHASE_TIMES: std::cell::Cell<[std::time::Duration; 4]> =
+        const { std::cell::Cell::new([std::time::Duration::ZERO; 4]) };
+}
+
+/// `--profile` が有効なときだけ `f` の実行時間をフェーズごとに積算する
+pub fn profiled<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !PROFILE_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    PHASE_TIMES.with(|times| {
+        let mut t = times.get();
+        t[phase as usize] += elapsed;
+        times.set(t);
+    });
+    result
+}
+
+/// このスレッドで積算したフェーズごとの時間を取り出してリセットする
+pub fn take_phase_times() -> [std::time::Duration; 4] {
+    PHASE_TIMES.with(|times| times.replace([std::time::Duration::ZERO; 4]))
+}
+
+/// spanの入れ子をfolded stacks形式 (`a;b;c <ナノ秒>`) で集計するtracingのレイヤー
+///
+/// 出力はinfernoやflamegraph.plにそのまま渡せる。
+#[derive(Clone, Default)]
+pub struct FoldedStackLayer {
+    pub folded: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, u128>>>,
+}
+
+/// span毎に保持する計測用の情報
+pub struct SpanTiming {
+    pub entered: Option<Instant>,
+    /// 子spanで使われた時間[ns]
+    pub children: u128,
+}
+
+impl<S> tracing_subscriber::Layer<S> for FoldedStackLayer
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<SpanTiming>() {
+            Some(timing) => timing.entered = Some(Instant::now()),
+            None => extensions.insert(SpanTiming {
+                entered: Some(Instant::now()),
+                children: 0,
+            }),
+        }
+    }
+
+    fn on_exit(&self, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let elapsed = {
+            let mut extensions = span.extensions_mut();
+            let timing = extensions.get_mut::<SpanTiming>().unwrap();
+            let elapsed = timing.entered.take().unwrap().elapsed().as_nanos();
+            let self_time = elapsed.saturating_sub(timing.children);
+            timing.children = 0;
+            let stack: Vec<_> = span.scope().from_root().map(|s| s.name()).collect();
+            *self
+                .folded
+                .lock()
+                .unwrap()
+                .entry(stack.join(";"))
+                .or_default() += self_time;
+            elapsed
+        };
+        if let Some(parent) = span.parent() {
+            if let Some(timing) = parent.extensions_mut().get_mut::<SpanTiming>() {
+                timing.children += elapsed;
+            }
+        }
+    }
+}
+
+impl FoldedStackLayer {
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let folded = self.folded.lock().unwrap();
+        let mut stacks: Vec<_> = folded.iter().collect();
+        stacks.sort();
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (stack, nanos) in stacks {
+            writeln!(writer, "{stack} {nanos}")?;
+        }
+        writer.flush()
+    }
+}
+
+/// 並列実行の設定
+#[derive(Clone, Copy)]
+pub struct ThreadConfig {
+    /// 同時に進めるゲームの数の上限 (1ならスレッドを作らず呼び出し元で順番に実行する)
+    pub threads: usize,
+    /// ワーカーiをCPUコアiに固定するか (Linuxのみ)
+    pub pin: bool,
+}
+
+impl ThreadConfig {
+    /// `threads == 0` のときは利用可能なコア数を使う
+    pub fn new(threads: usize, pin: bool) -> Self {
+        let threads = if threads == 0 {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        Self { threads, pin }
+    }
+}
+
+/// 呼び出したスレッドを指定したCPUコアに固定する
+pub fn pin_current_thread(core: usize) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core % libc::CPU_SETSIZE as usize, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+/// `0..num` のシードそれぞれで `run` を呼び、結果をシード順に並べて返す
+pub fn run_games_parallel<T: Send>(
+    num: usize,
+    config: ThreadConfig,
+    run: impl Fn(u64) -> T + Sync,
+) -> Vec<T> {
+    let mut results = Vec::with_capacity(num);
+    run_games_streaming(num, config, run, |result| results.push(result));
+    results
+}
+
+/// `0..num` のシードそれぞれで `run` を呼び、終わった結果をシード順に `on_result` へ渡す
+///
+/// 結果を全て溜めずに済むので、ゲーム数が多くてもメモリ使用量が増えない。
+/// 2スレッド以上ならrayonのスレッドプールでゲームを並列に進める。
+pub fn run_games_streaming<T: Send>(
+    num: usize,
+    config: ThreadConfig,
+    run: impl Fn(u64) -> T + Sync,
+    mut on_result: impl FnMut(T),
+) {
+    if config.threads <= 1 {
+        for seed in 0..num {
+            on_result(run(seed as u64));
+        }
+        return;
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .start_handler(move |worker| {
+            if config.pin {
+                pin_current_thread(worker);
+            }
+        })
+        .build()
+        .expect("failed to build the thread pool");
+    let next_seed = std::sync::atomic::AtomicUsize::new(0);
+    // ワーカーは小さいシードから順に取っていくので、シード順を揃えるために待たせる結果は少なく済む
+    pool.in_place_scope(|scope| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for _ in 0..config.threads {
+            let next_seed = &next_seed;
+            let run = &run;
+            let sender = sender.clone();
+            scope.spawn(move |_| loop {
+                let seed = next_seed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                if seed >= num {
+                    break;
+                }
+                sender.send((seed, run(seed as u64))).unwrap();
+            });
+        }
+        drop(sender);
+        // 先に終わった結果はシード順が揃うまで待たせておく
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (seed, result) in receiver {
+            pending.insert(seed, result);
+            while let Some(result) = pending.remove(&next) {
+                on_result(result);
+                next += 1;
+            }
+        }
+    });
+}
+
+/// FNV-1a (64bit)
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    let mut hash = hash;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+pub const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 行動列から再現性確認用のトークンを作る
+pub fn reproducibility_token(actions: &[usize]) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    for &action in actions {
+        hash = fnv1a(hash, &[action as u8]);
+    }
+    format!("{hash:016x}")
+}
+
+/// SplitMix64の1ステップ
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
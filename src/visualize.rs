@@ -0,0 +1,129 @@
+//! 記録したゲームを1ターンずつ盤面を描き直して見せる再生表示
+//!
+//! フレームの間隔は `FrameClock` で待つので、`ManualClock` を渡すと実際には眠らずに
+//! 仮想の時刻だけを進めて早送りでき、テストでフレームの時刻を確かめられる。
+
+use std::{io::Write, time::Duration};
+
+use crate::{
+    game::{Direction, State},
+    play::replay_game,
+    results::GameResult,
+    util::{ManualClock, SystemClock, TimeSource},
+};
+
+/// フレームの間隔を測って待つ時計
+pub trait FrameClock {
+    /// 任意の起点からの経過時間
+    fn now(&self) -> Duration;
+
+    /// `duration` だけ待つ
+    fn sleep(&self, duration: Duration);
+}
+
+/// 実時間で眠る時計
+#[derive(Default)]
+pub struct WallFrameClock {
+    clock: SystemClock,
+}
+
+impl FrameClock for WallFrameClock {
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// 眠る代わりに時刻を進める仮想の時計
+impl FrameClock for ManualClock {
+    fn now(&self) -> Duration {
+        TimeSource::now(self)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// 再生表示の設定
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationConfig {
+    /// 1秒あたりのフレーム数 (1フレームで1ターン進む)
+    pub fps: f64,
+    /// フレームごとに画面を消してから描く (端末向け)
+    pub clear_screen: bool,
+    /// 盤面をANSIの色付きで描く (`MazeState::render_color`)
+    pub color: bool,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            fps: 10.,
+            clear_screen: true,
+            color: false,
+        }
+    }
+}
+
+/// 描いたフレームの記録
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub turn: usize,
+    /// 描く予定だった時刻 (再生開始からの経過時間)
+    pub due: Duration,
+    /// 実際に描いた時刻
+    pub shown: Duration,
+    /// 予定より半フレーム以上遅れて描いたか
+    pub late: bool,
+}
+
+/// 記録 `result` を再生し、初期盤面と各ターンの盤面を1フレームずつ `out` に描く
+///
+/// フレーム `i` の予定時刻は再生開始から `i / fps` 秒後で、それより早ければ `clock` で待つ。
+/// 予定時刻は開始時刻から決めるので、描くのが遅れても後のフレームに遅れが積み重ならない。
+pub fn animate(
+    result: &GameResult,
+    config: &AnimationConfig,
+    clock: &dyn FrameClock,
+    out: &mut dyn Write,
+) -> Result<Vec<Frame>, String> {
+    replay_game(result, false)?;
+    let interval = Duration::from_secs_f64(1. / config.fps.max(f64::MIN_POSITIVE));
+    let start = clock.now();
+    let mut frames = vec![];
+    let mut state = State::new(result.seed);
+    for turn in 0..=result.actions.len() {
+        if turn > 0 {
+            state.advance(Direction::from(result.actions[turn - 1]));
+        }
+        let due = interval * turn as u32;
+        let elapsed = clock.now().saturating_sub(start);
+        if elapsed < due {
+            clock.sleep(due - elapsed);
+        }
+        let shown = clock.now().saturating_sub(start);
+        if config.clear_screen {
+            write!(out, "\x1b[2J\x1b[H").map_err(|e| e.to_string())?;
+        }
+        if config.color {
+            let last_action = turn
+                .checked_sub(1)
+                .map(|t| Direction::from(result.actions[t]));
+            write!(out, "{}", state.render_color(last_action)).map_err(|e| e.to_string())?;
+        } else {
+            write!(out, "{state}").map_err(|e| e.to_string())?;
+        }
+        out.flush().map_err(|e| e.to_string())?;
+        frames.push(Frame {
+            turn,
+            due,
+            shown,
+            late: shown.saturating_sub(due) >= interval / 2,
+        });
+    }
+    Ok(frames)
+}
@@ -0,0 +1,224 @@
+//! 壁のある数字集め迷路の変種
+
+use std::{cmp::Ordering, collections::VecDeque, fmt};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::game::{Coord, Direction, Eval, GameScore, GameState};
+
+pub const WALL_H: usize = 7;
+pub const WALL_W: usize = 7;
+pub const WALL_END_TURN: usize = 49;
+
+/// 棒倒し法で `h`×`w` の盤面の壁を作る (`character` のマスには壁を置かない)
+///
+/// 1つおきの柱から右・左・下(最初の行だけ上も)のいずれかに壁を倒すので、
+/// 壁のないマスどうしは必ずつながっている。最後の行と列に柱は立てない。
+pub fn stick_down_walls(
+    h: usize,
+    w: usize,
+    character: Coord,
+    rng: &mut impl Rng,
+) -> Vec<Vec<bool>> {
+    let mut walls = vec![vec![false; w]; h];
+    for y in (1..h - 1).step_by(2) {
+        for x in (1..w - 1).step_by(2) {
+            let mut wall = Coord::new(y as i32, x as i32);
+            if wall == character {
+                continue;
+            }
+            walls[y][x] = true;
+            let directions = if y == 1 { 4 } else { 3 };
+            let (dy, dx) = Direction::ALL[rng.gen_range(0..directions)].delta();
+            wall = Coord::new(wall.y + dy, wall.x + dx);
+            if wall == character {
+                continue;
+            }
+            walls[wall.y as usize][wall.x as usize] = true;
+        }
+    }
+    walls
+}
+
+/// 壁のある迷路
+///
+/// 壁は棒倒し法で作るので、壁のないマスどうしは必ずつながっている。
+#[derive(Clone)]
+pub struct WallMazeState {
+    pub points: Vec<Vec<usize>>,
+    pub walls: Vec<Vec<bool>>,
+    pub turn: usize,
+    pub character: Coord,
+    pub game_score: GameScore,
+    pub evaluated_score: Eval,
+    pub first_action: Direction,
+    /// 評価値に最寄りの得点までの最短距離を使う
+    pub distance_evaluation: bool,
+}
+
+impl WallMazeState {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let character = Coord::new(
+            rng.gen_range(0..WALL_H as i32),
+            rng.gen_range(0..WALL_W as i32),
+        );
+        let walls = stick_down_walls(WALL_H, WALL_W, character, &mut rng);
+        let mut points = vec![vec![0; WALL_W]; WALL_H];
+        for y in 0..WALL_H {
+            for x in 0..WALL_W {
+                if walls[y][x] || Coord::new(y as i32, x as i32) == character {
+                    continue;
+                }
+                points[y][x] = rng.gen_range(0..10);
+            }
+        }
+        Self {
+            points,
+            walls,
+            turn: 0,
+            character,
+            game_score: GameScore(0),
+            evaluated_score: Eval(0),
+            first_action: Direction::Right,
+            distance_evaluation: true,
+        }
+    }
+
+    fn next_coord(&self, action: Direction) -> Option<Coord> {
+        let (dy, dx) = action.delta();
+        let (y, x) = (self.character.y + dy, self.character.x + dx);
+        let inside = (0..WALL_H as i32).contains(&y) && (0..WALL_W as i32).contains(&x);
+        (inside && !self.walls[y as usize][x as usize]).then_some(Coord { y, x })
+    }
+
+    /// 壁を避けて、キャラクターから得点のある最寄りのマスまでの最短距離をBFSで求める
+    ///
+    /// 得点のあるマスがなければ `WALL_H * WALL_W` を返す。
+    pub fn distance_to_nearest_point(&self) -> usize {
+        let mut distance = vec![vec![usize::MAX; WALL_W]; WALL_H];
+        let mut queue = VecDeque::new();
+        distance[self.character.y as usize][self.character.x as usize] = 0;
+        queue.push_back(self.character);
+        while let Some(now) = queue.pop_front() {
+            let d = distance[now.y as usize][now.x as usize];
+            if self.points[now.y as usize][now.x as usize] > 0 {
+                return d;
+            }
+            for (dy, dx) in Direction::ALL.map(Direction::delta) {
+                let (y, x) = (now.y + dy, now.x + dx);
+                if !(0..WALL_H as i32).contains(&y) || !(0..WALL_W as i32).contains(&x) {
+                    continue;
+                }
+                let (uy, ux) = (y as usize, x as usize);
+                if self.walls[uy][ux] || distance[uy][ux] != usize::MAX {
+                    continue;
+                }
+                distance[uy][ux] = d + 1;
+                queue.push_back(Coord { y, x });
+            }
+        }
+        WALL_H * WALL_W
+    }
+}
+
+impl fmt::Display for WallMazeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "turn:\t{}", self.turn)?;
+        writeln!(f, "score:\t{}", self.game_score)?;
+        for y in 0..WALL_H {
+            for x in 0..WALL_W {
+                if Coord::new(y as i32, x as i32) == self.character {
+                    write!(f, "@")?;
+                } else if self.walls[y][x] {
+                    write!(f, "#")?;
+                } else if self.points[y][x] > 0 {
+                    write!(f, "{}", self.points[y][x])?;
+                } else {
+                    write!(f, ".")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// 評価値で比べ、同じなら少ないターン数で着いた方、それも同じならキャラクターの位置で比べる
+impl Ord for WallMazeState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.evaluated_score
+            .cmp(&other.evaluated_score)
+            .then_with(|| other.turn.cmp(&self.turn))
+            .then_with(|| {
+                (self.character.y, self.character.x).cmp(&(other.character.y, other.character.x))
+            })
+    }
+}
+
+impl PartialOrd for WallMazeState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for WallMazeState {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for WallMazeState {}
+
+impl GameState for WallMazeState {
+    type Action = Direction;
+
+    /// 盤面の外と壁には進めない
+    fn legal_actions(&self) -> Vec<Direction> {
+        Direction::ALL
+            .into_iter()
+            .filter(|&action| self.next_coord(action).is_some())
+            .collect()
+    }
+
+    fn advance(&mut self, action: Direction) {
+        self.character = self.next_coord(action).expect("illegal action");
+        let point = &mut self.points[self.character.y as usize][self.character.x as usize];
+        self.game_score = self.game_score.add_point(*point as u8);
+        *point = 0;
+        self.turn += 1;
+    }
+
+    fn is_done(&self) -> bool {
+        self.turn == WALL_END_TURN
+    }
+
+    /// スコアを優先し、同じスコアなら最寄りの得点に近いほど高くする
+    fn evaluate(&mut self) {
+        let cells = (WALL_H * WALL_W) as i64;
+        self.evaluated_score = Eval(Eval::from(self.game_score).0 * cells);
+        if self.distance_evaluation {
+            self.evaluated_score = self
+                .evaluated_score
+                .saturating_add(cells - self.distance_to_nearest_point() as i64);
+        }
+    }
+
+    fn evaluated_score(&self) -> Eval {
+        self.evaluated_score
+    }
+
+    fn set_evaluated_score(&mut self, score: Eval) -> bool {
+        self.evaluated_score = score;
+        true
+    }
+
+    fn first_action(&self) -> Direction {
+        self.first_action
+    }
+
+    fn set_first_action(&mut self, action: Direction) {
+        self.first_action = action;
+    }
+}
@@ -0,0 +1,208 @@
+//! ゲームの間で使い回す表
+//!
+//! 隣接するマスの表と、ポテンシャル場の評価関数が使う重みの表は盤面の大きさだけで決まるので、
+//! プロセスで1つだけ作って全てのゲームで読み取り専用に共有する。
+//! 終盤の全探索の置換表は盤面そのものをキーにするので別の盤面の結果を取り違えることはなく、
+//! 既定では同じプロセスで遊ぶ全てのゲームで共有する (同じシードを複数のAIで遊ぶ `compare` などで効く)。
+//! ただし同点の手順のどれを選ぶかが前に遊んだゲームに左右されるので、ゲームを厳密に独立させたいときは
+//! `PlayConfig::isolate_caches` でゲームごとに空の置換表を使う。
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use crate::{
+    game::{Coord, Direction, State, H, W},
+    util::{fnv1a, FNV_OFFSET_BASIS},
+};
+
+/// マスごとの、盤面の中に進める行動とその先のマスの番号 (`Direction::ALL` の順)
+pub struct NeighborTable {
+    cells: Vec<Vec<(Direction, usize)>>,
+}
+
+impl NeighborTable {
+    fn new() -> Self {
+        let cells = (0..H * W)
+            .map(|cell| {
+                let coord = Coord::new((cell / W) as i32, (cell % W) as i32);
+                Direction::ALL
+                    .into_iter()
+                    .filter_map(|action| Some((action, coord.offset(action)?.index())))
+                    .collect()
+            })
+            .collect();
+        Self { cells }
+    }
+
+    /// マス `cell` (`Coord::index`) から進める行動と、その先のマス
+    pub fn neighbors(&self, cell: usize) -> &[(Direction, usize)] {
+        &self.cells[cell]
+    }
+}
+
+/// ポテンシャル場の評価関数が足す `100 * 得点 / (距離 + 1)^2` の表
+pub struct PotentialTable {
+    /// `距離 * POTENTIAL_POINTS + 得点` 番目が値
+    weights: Vec<i64>,
+}
+
+/// `PotentialTable` に載せる得点の種類 (盤面の得点は0〜9)
+const POTENTIAL_POINTS: usize = 10;
+
+impl PotentialTable {
+    fn new() -> Self {
+        let weights = (0..H + W)
+            .flat_map(|distance| {
+                (0..POTENTIAL_POINTS).map(move |point| Self::compute(distance as i64, point as i64))
+            })
+            .collect();
+        Self { weights }
+    }
+
+    fn compute(distance: i64, point: i64) -> i64 {
+        100 * point / ((distance + 1) * (distance + 1))
+    }
+
+    /// 距離 `distance` にある得点 `point` のマスが作るポテンシャル
+    pub fn potential(&self, distance: usize, point: usize) -> i64 {
+        if point < POTENTIAL_POINTS {
+            self.weights[distance * POTENTIAL_POINTS + point]
+        } else {
+            Self::compute(distance as i64, point as i64)
+        }
+    }
+}
+
+/// 隣接するマスの表 (プロセスで共有する)
+pub fn neighbor_table() -> &'static NeighborTable {
+    static TABLE: OnceLock<NeighborTable> = OnceLock::new();
+    TABLE.get_or_init(NeighborTable::new)
+}
+
+/// ポテンシャルの表 (プロセスで共有する)
+pub fn potential_table() -> &'static PotentialTable {
+    static TABLE: OnceLock<PotentialTable> = OnceLock::new();
+    TABLE.get_or_init(PotentialTable::new)
+}
+
+/// 置換表を分ける数 (別々のスレッドのゲームがロックを取り合わないように)
+const TRANSPOSITION_SHARDS: usize = 16;
+
+/// 1つの区画に溜める局面の上限 (超えたらその区画を空にする)
+const TRANSPOSITION_SHARD_CAPACITY: usize = 1 << 16;
+
+/// 終盤の全探索で求めた局面ごとの最善手
+///
+/// 全探索の最善の手順の途中の局面は、そこから残りの手順を辿るのがやはり最善なので、
+/// 1回解くと手順上の全ての局面の最善手が分かる。次のターン以降は解き直さずに表を引く。
+pub struct TranspositionTable {
+    shards: Vec<Mutex<HashMap<u64, Direction>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self {
+            shards: (0..TRANSPOSITION_SHARDS)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TranspositionTable {
+    /// 局面のキー (盤面の得点・キャラクターの位置・ターン・終了ターンと、再訪のペナルティ・評価値のノイズのハッシュ)
+    ///
+    /// 評価の設定が違うゲームとは表を分ける。差し替えた評価関数はキーにできないので、
+    /// その盤面では `search::endgame_solver_action` が表を使わない。
+    pub fn key(state: &State) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &state.points);
+        hash = fnv1a(hash, &state.character.y.to_le_bytes());
+        hash = fnv1a(hash, &state.character.x.to_le_bytes());
+        hash = fnv1a(hash, &(state.turn as u64).to_le_bytes());
+        hash = fnv1a(hash, &(state.end_turn as u64).to_le_bytes());
+        hash = fnv1a(hash, &state.revisit_penalty.to_le_bytes());
+        let noise = state
+            .eval_noise
+            .map_or((0, 0), |noise| (noise.magnitude, noise.seed));
+        hash = fnv1a(hash, &noise.0.to_le_bytes());
+        fnv1a(hash, &noise.1.to_le_bytes())
+    }
+
+    fn shard(&self, key: u64) -> &Mutex<HashMap<u64, Direction>> {
+        &self.shards[key as usize % TRANSPOSITION_SHARDS]
+    }
+
+    pub fn get(&self, key: u64) -> Option<Direction> {
+        let found = self.shard(key).lock().unwrap().get(&key).copied();
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    pub fn insert(&self, key: u64, action: Direction) {
+        let mut shard = self.shard(key).lock().unwrap();
+        if shard.len() >= TRANSPOSITION_SHARD_CAPACITY {
+            shard.clear();
+        }
+        shard.insert(key, action);
+    }
+
+    /// 溜めている局面の数
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 表を引いて見つかった回数
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// 表を引いて見つからなかった回数
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+thread_local! {
+    static TRANSPOSITION_TABLE: RefCell<Option<Arc<TranspositionTable>>> =
+        const { RefCell::new(None) };
+}
+
+/// プロセスで共有する置換表
+pub fn shared_transposition_table() -> Arc<TranspositionTable> {
+    static TABLE: OnceLock<Arc<TranspositionTable>> = OnceLock::new();
+    TABLE.get_or_init(Default::default).clone()
+}
+
+/// このスレッドで使う置換表 (`with_transposition_table` の中ならそれ、外なら共有の置換表)
+pub fn transposition_table() -> Arc<TranspositionTable> {
+    TRANSPOSITION_TABLE
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(shared_transposition_table)
+}
+
+/// このスレッドで `f` の中で解く終盤の全探索に置換表 `table` を使わせる
+pub fn with_transposition_table<T>(table: Arc<TranspositionTable>, f: impl FnOnce() -> T) -> T {
+    let previous = TRANSPOSITION_TABLE.with(|current| current.replace(Some(table)));
+    let result = f();
+    TRANSPOSITION_TABLE.with(|current| current.replace(previous));
+    result
+}
@@ -0,0 +1,105 @@
+use std::process::Command;
+
+/// `alternate` で深い探索と浅い探索を先後入れ替えて対戦させた出力
+fn self_play_report(algorithm: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .args([
+            "alternate",
+            "--deep",
+            "4",
+            "--shallow",
+            "1",
+            "--games",
+            "20",
+        ])
+        .args(["--algorithm", algorithm])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn rate(report: &str, name: &str) -> f64 {
+    report
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{name}: ")))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn deeper_search_wins_more_often() {
+    let report = self_play_report("alpha-beta");
+    println!("{report}");
+    assert!(rate(&report, "deep_win_rate") > rate(&report, "shallow_win_rate"));
+}
+
+#[test]
+fn alpha_beta_plays_the_same_moves_as_minimax() {
+    let alpha_beta = self_play_report("alpha-beta");
+    let minimax = self_play_report("minimax");
+    assert_eq!(alpha_beta.replace("alpha-beta", "minimax"), minimax);
+}
+
+/// `--ordering` を付けた alpha-beta の出力 (手の並べ替えの統計を含む)
+fn ordered_report(ordering: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .args([
+            "alternate",
+            "--deep",
+            "6",
+            "--shallow",
+            "1",
+            "--games",
+            "10",
+        ])
+        .args(["--ordering", ordering])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn move_ordering_keeps_moves_and_expands_fewer_nodes() {
+    let none = ordered_report("none");
+    let all = ordered_report("all");
+    let results = |report: &str| {
+        report
+            .lines()
+            .take_while(|line| !line.starts_with("nodes"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    assert_eq!(results(&none), results(&all));
+    assert!(rate(&all, "nodes") < rate(&none, "nodes"), "{none}\n{all}");
+    assert!(rate(&all, "first_move_cutoff_rate") > rate(&none, "first_move_cutoff_rate"));
+}
+
+#[test]
+fn aspiration_windows_keep_moves_and_count_re_searches() {
+    let full = ordered_report("all");
+    let narrow = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .args([
+            "alternate",
+            "--deep",
+            "6",
+            "--shallow",
+            "1",
+            "--games",
+            "10",
+        ])
+        .args(["--ordering", "all", "--aspiration", "1"])
+        .output()
+        .unwrap();
+    assert!(narrow.status.success());
+    let narrow = String::from_utf8(narrow.stdout).unwrap();
+    let results = |report: &str| report.lines().take(6).collect::<Vec<_>>().join("\n");
+    assert_eq!(results(&full), results(&narrow));
+    assert_eq!(rate(&full, "fail_high") + rate(&full, "fail_low"), 0.);
+    assert!(
+        rate(&narrow, "fail_high") + rate(&narrow, "fail_low") > 0.,
+        "{narrow}"
+    );
+}
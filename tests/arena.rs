@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use num_collect_maze::{
+    game::{ActionMask, GameConfig, GameState, State},
+    search::{
+        arena_beam_search_action, arena_beam_search_action_with_stats, beam_search_action,
+        beam_search_action_with_stats, SearchStats,
+    },
+};
+
+#[test]
+fn previews_match_the_advanced_child() {
+    let config = GameConfig {
+        revisit_penalty: 3,
+        eval_noise: 5,
+        ..GameConfig::default()
+    };
+    let mut state = State::with_config(2, &config);
+    for turn in 0..20 {
+        for action in state.legal_actions() {
+            let preview = state.preview_child(action).unwrap();
+            let mut child = state.clone();
+            child.advance(action);
+            child.evaluate();
+            assert_eq!(preview.evaluated_score, child.evaluated_score);
+            assert_eq!(Some(preview.state_hash), child.state_hash());
+            assert!(preview.character == child.character);
+        }
+        // 行ったり来たりして再訪のペナルティも確かめる
+        let actions = state.legal_actions();
+        state.advance(actions[turn % actions.len()]);
+    }
+}
+
+#[test]
+fn arena_beam_chooses_the_same_actions_as_the_beam() {
+    let configs = [
+        GameConfig::default(),
+        GameConfig {
+            revisit_penalty: 2,
+            eval_noise: 3,
+            ..GameConfig::default()
+        },
+    ];
+    for config in &configs {
+        for seed in 0..5 {
+            let state = State::with_config(seed, config);
+            for (width, depth) in [(1, 1), (3, 5), (5, 10), (0, 3)] {
+                assert_eq!(
+                    arena_beam_search_action(&state, width, depth),
+                    beam_search_action(&state, width, depth),
+                    "seed={seed} width={width} depth={depth}"
+                );
+            }
+        }
+    }
+    // 最後まで遊んでも同じ
+    let mut state = State::new(7);
+    while !state.is_done() {
+        let action = beam_search_action(&state, 4, 6).unwrap();
+        assert_eq!(arena_beam_search_action(&state, 4, 6), Ok(action));
+        state.advance(action);
+    }
+}
+
+#[test]
+fn arena_beam_clones_only_the_survivors() {
+    let state = State::new(0);
+    let (mut arena, mut beam) = (SearchStats::default(), SearchStats::default());
+    arena_beam_search_action_with_stats(&state, 5, 10, &mut arena).unwrap();
+    beam_search_action_with_stats(&state, 5, 10, &mut beam).unwrap();
+    assert_eq!(arena.nodes_expanded, beam.nodes_expanded);
+    assert_eq!(arena.max_depth_reached, beam.max_depth_reached);
+    // 根と各深さのビームに残る状態だけを複製する
+    assert!(arena.states_cloned <= 1 + 5 * 10);
+    // 展開したのは根と最後の深さ以外で残った状態なので、差は最後の深さに残った5個
+    assert_eq!(arena.states_cloned, arena.nodes_expanded + 5);
+    assert!(arena.states_cloned < beam.states_cloned);
+}
+
+#[test]
+fn masked_states_are_supported() {
+    let config = GameConfig {
+        mask: Some(Arc::new(ActionMask {
+            rules: vec![
+                ActionMask::parse_action("U@0-20").unwrap(),
+                ActionMask::parse_region("0,0,10,10").unwrap(),
+            ],
+        })),
+        ..GameConfig::default()
+    };
+    let state = State::with_config(1, &config);
+    assert_eq!(
+        arena_beam_search_action(&state, 3, 8),
+        beam_search_action(&state, 3, 8)
+    );
+}
@@ -0,0 +1,74 @@
+use num_collect_maze::{
+    calibrate::{calibrate, AlgorithmRate, CalibrationProfile, CALIBRATED_ALGORITHMS},
+    schema::CALIBRATION_SCHEMA_VERSION,
+    search::SearchStats,
+};
+
+/// beamが1msに100、chokudaiが1msに40の状態を展開するプロファイル
+fn profile() -> CalibrationProfile {
+    let rate = |algorithm: &str, nodes_per_ms| AlgorithmRate {
+        algorithm: algorithm.to_string(),
+        nodes_per_ms,
+        stats: SearchStats::default(),
+    };
+    CalibrationProfile {
+        schema_version: CALIBRATION_SCHEMA_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: 0,
+        budget_ms: 10,
+        rates: vec![rate("beam", 100.), rate("chokudai", 40.)],
+    }
+}
+
+#[test]
+fn node_budgets_translate_to_time_budgets_and_back() {
+    let profile = profile();
+    assert_eq!(profile.ms_for_nodes("beam", 2000).unwrap(), 20);
+    // 切り上げて、少なくとも1ms
+    assert_eq!(profile.ms_for_nodes("chokudai", 100).unwrap(), 3);
+    assert_eq!(profile.ms_for_nodes("beam", 1).unwrap(), 1);
+    assert_eq!(profile.nodes_for_ms("chokudai", 5).unwrap(), 200);
+    assert!(profile.ms_for_nodes("mcts", 100).is_err());
+}
+
+#[test]
+fn resolve_rewrites_nodes_into_ms() {
+    let profile = profile();
+    assert_eq!(
+        profile.resolve("beam:width=5,nodes=2000").unwrap(),
+        "beam:width=5,ms=20"
+    );
+    // 元の時間制限はnodesで置き換える
+    assert_eq!(
+        profile.resolve("chokudai:ms=10,width=1,nodes=400").unwrap(),
+        "chokudai:width=1,ms=10"
+    );
+    assert_eq!(profile.resolve("greedy").unwrap(), "greedy");
+    assert!(profile.resolve("fallback(greedy, beam:nodes=10)").is_err());
+    assert!(profile.resolve("mcts:nodes=10").is_err());
+}
+
+#[test]
+fn profiles_round_trip_and_reject_newer_versions() {
+    let path = std::env::temp_dir().join(format!("calibration_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    profile().save(path).unwrap();
+    let loaded = CalibrationProfile::load(path).unwrap();
+    assert_eq!(loaded.nodes_per_ms("chokudai").unwrap(), 40.);
+
+    let mut newer = profile();
+    newer.schema_version = CALIBRATION_SCHEMA_VERSION + 1;
+    newer.save(path).unwrap();
+    assert!(CalibrationProfile::load(path).is_err_and(|e| e.contains("newer")));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn calibration_measures_every_algorithm() {
+    let profile = calibrate(1, 1);
+    assert_eq!(profile.rates.len(), CALIBRATED_ALGORITHMS.len());
+    for rate in &profile.rates {
+        assert!(rate.stats.nodes_expanded > 0);
+        assert!(rate.nodes_per_ms > 0.);
+    }
+}
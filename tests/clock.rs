@@ -0,0 +1,40 @@
+use std::{sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{MazeState, END_TURN},
+    search::{chokudai_search_action_with_stats, SearchStats, CHOKUDAI_CHECK_INTERVAL},
+    util::{with_time_source, ManualClock},
+};
+
+/// 時刻を読むたびに1ms進む時計で、時間制限10msのchokudaiサーチを1回行う
+fn ticking_chokudai(seed: u64) -> (usize, SearchStats) {
+    let state = MazeState::new(seed);
+    let mut stats = SearchStats::default();
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_millis(1))),
+        || {
+            chokudai_search_action_with_stats(
+                &state,
+                1,
+                END_TURN,
+                10,
+                CHOKUDAI_CHECK_INTERVAL,
+                &mut stats,
+            )
+        },
+    );
+    (action.unwrap().index(), stats)
+}
+
+#[test]
+fn manual_clock_makes_timed_search_deterministic() {
+    for seed in 0..5 {
+        let (action_a, stats_a) = ticking_chokudai(seed);
+        let (action_b, stats_b) = ticking_chokudai(seed);
+        assert_eq!(action_a, action_b);
+        assert_eq!(stats_a.passes, stats_b.passes);
+        assert_eq!(stats_a.elapsed_us, stats_b.elapsed_us);
+        // 10ms目の確認で打ち切り、記録のための読み取りで1ms進む (実時間にはよらない)
+        assert_eq!(stats_a.elapsed_us, 11_000);
+    }
+}
@@ -0,0 +1,81 @@
+use num_collect_maze::{
+    crash::{current_context, install_panic_hook},
+    game::{Direction, State},
+    play::{play_game, run_game, PlayConfig},
+    policy::Policy,
+};
+
+/// 決まったターンでpanicするAI
+struct PanicAt(usize);
+
+impl Policy for PanicAt {
+    fn name(&self) -> String {
+        format!("panic-at-{}", self.0)
+    }
+
+    fn select_action(&mut self, state: &State) -> Direction {
+        assert!(state.turn < self.0, "search failed");
+        state.legal_actions()[0]
+    }
+}
+
+#[test]
+fn panics_during_a_game_are_dumped_with_their_context() {
+    let dir = std::env::temp_dir().join(format!("crash_{}", std::process::id()));
+    install_panic_hook(&dir);
+    assert!(current_context().is_none());
+
+    let result = std::panic::catch_unwind(|| {
+        run_game(7, &mut PanicAt(3), &PlayConfig::default());
+    });
+    assert!(result.is_err());
+    // ゲームを抜けたら状況を忘れる
+    assert!(current_context().is_none());
+
+    let path = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            name.starts_with("crash-7-3-")
+        })
+        .expect("no crash dump was written");
+    let dump = std::fs::read_to_string(&path).unwrap();
+    assert!(dump.starts_with("message:\tsearch failed\n"), "{dump}");
+    assert!(dump.contains("seed:\t7\npolicy:\tpanic-at-3\n"), "{dump}");
+    assert!(dump.contains("turn:\t3\n"), "{dump}");
+
+    // 書き出した行動列で同じ盤面を再現できる
+    let actions: Vec<usize> = dump
+        .lines()
+        .find_map(|line| line.strip_prefix("actions:\t"))
+        .unwrap()
+        .split_whitespace()
+        .map(|action| action.parse().unwrap())
+        .collect();
+    let mut state = State::new(7);
+    for &action in &actions {
+        state.advance(Direction::from(action));
+    }
+    assert_eq!(actions.len(), 3);
+    assert!(dump.ends_with(&state.to_string()));
+
+    // 盤面を表示しながら遊ぶ `play` でも書き出す
+    let result = std::panic::catch_unwind(|| {
+        play_game(8, &mut PanicAt(2), false);
+    });
+    assert!(result.is_err());
+    assert!(current_context().is_none());
+    let dump = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            name.starts_with("crash-8-2-")
+        })
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .expect("no crash dump was written by play_game");
+    assert!(dump.contains("seed:\t8\npolicy:\tpanic-at-2\n"), "{dump}");
+    assert!(dump.contains("turn:\t2\n"), "{dump}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use num_collect_maze::{
+    daemon::{parse_interval, run_cycle, DaemonConfig},
+    db::ResultDb,
+    play::PlayConfig,
+    results::RunMeta,
+    util::ThreadConfig,
+};
+
+#[test]
+fn intervals_parse_with_units() {
+    assert_eq!(parse_interval("24h"), Ok(Duration::from_secs(24 * 3600)));
+    assert_eq!(parse_interval("30m"), Ok(Duration::from_secs(1800)));
+    assert_eq!(parse_interval("2d"), Ok(Duration::from_secs(2 * 86400)));
+    assert_eq!(parse_interval("10"), Ok(Duration::from_secs(10)));
+    assert!(parse_interval("1w").is_err());
+    assert!(parse_interval("h").is_err());
+}
+
+#[test]
+fn daemon_flags_only_significant_drifts() {
+    let path = std::env::temp_dir().join(format!("daemon_{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut db = ResultDb::open(path.to_str().unwrap()).unwrap();
+    let config = DaemonConfig {
+        specs: vec!["greedy".to_string()],
+        games: 30,
+        rng_seed: 0,
+        thread_config: ThreadConfig {
+            threads: 1,
+            pin: false,
+        },
+        play_config: PlayConfig::default(),
+        alpha: 0.05,
+        meta: RunMeta::default(),
+    };
+
+    // 初回は比べる相手がない
+    assert!(run_cycle(&mut db, &config).unwrap().is_empty());
+    // 同じ設定なら同じスコアになり、変化はない
+    let drifts = run_cycle(&mut db, &config).unwrap();
+    assert_eq!(drifts.len(), 1);
+    assert!(!drifts[0].significant);
+    assert_eq!(drifts[0].record.previous_run_id, 1);
+    assert!(db.drifts().unwrap().is_empty());
+
+    // 前回の実行のスコアを全シードで50点上げておくと、次の実行で下がったとみなされる
+    db.conn
+        .execute(
+            "UPDATE games SET game_score = game_score + 50 WHERE run_id = 2",
+            [],
+        )
+        .unwrap();
+    let drifts = run_cycle(&mut db, &config).unwrap();
+    assert!(drifts[0].significant);
+    assert!(drifts[0].record.score_mean < drifts[0].record.previous_mean);
+    let recorded = db.drifts().unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!((recorded[0].run_id, recorded[0].previous_run_id), (3, 2));
+    std::fs::remove_file(path).unwrap();
+}
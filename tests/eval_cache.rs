@@ -0,0 +1,284 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use num_collect_maze::{
+    game::{Direction, Eval, GameConfig, GameState, MazeState},
+    policy::{
+        parse_policy, EvaluatorPhase, EvaluatorPolicy, PhaseStart, Policy, TimedBeamSearchPolicy,
+    },
+    search::{
+        beam_search_action_with_eval_cache, chokudai_search_action,
+        chokudai_search_action_with_eval_cache, fixed_chokudai_search_action_with_stats, EvalCache,
+        SearchStats, StopRule, CHOKUDAI_CHECK_INTERVAL,
+    },
+    util::{with_time_source, ManualClock},
+};
+
+/// 評価関数を呼んだ回数を数える盤面
+fn counting_state(seed: u64, calls: &Arc<AtomicU64>) -> MazeState {
+    let mut state = MazeState::new(seed);
+    let calls = calls.clone();
+    state.evaluator = Some(Arc::new(move |state: &MazeState| {
+        calls.fetch_add(1, Ordering::Relaxed);
+        Eval::from(state.game_score)
+    }));
+    state
+}
+
+#[test]
+fn chokudai_reuses_the_evaluations_of_revisited_parents() {
+    let mut total = SearchStats::default();
+    for seed in 0..5 {
+        let calls = Arc::new(AtomicU64::new(0));
+        let state = counting_state(seed, &calls);
+        let mut stats = SearchStats::default();
+        let action = fixed_chokudai_search_action_with_stats(&state, 2, 30, 20, &mut stats);
+        // 使い回しても選ぶ手は変わらない
+        assert_eq!(
+            action,
+            chokudai_search_action(&MazeState::new(seed), 2, 30, 20)
+        );
+        // 使い回せなかった子だけ評価関数を呼ぶ
+        assert!(stats.eval_cache_lookups > 0);
+        assert_eq!(
+            calls.load(Ordering::Relaxed),
+            stats.eval_cache_lookups - stats.eval_cache_hits
+        );
+        total.add(&stats);
+    }
+    assert!(total.eval_cache_hits > 0);
+    assert!(total.eval_cache_hit_rate() > 0. && total.eval_cache_hit_rate() < 1.);
+}
+
+#[test]
+fn set_evaluated_score_replaces_the_evaluation() {
+    let mut state = MazeState::new(0);
+    let mut child = state.clone();
+    child.advance(child.legal_actions()[0]);
+    assert!(child.set_evaluated_score(Eval(42)));
+    assert_eq!(child.evaluated_score(), Eval(42));
+    state.evaluate();
+    assert_eq!(SearchStats::default().eval_cache_hit_rate(), 0.);
+}
+
+/// 評価関数を数える盤面で1ゲーム遊び、評価関数を呼んだ回数と探索の記録を返す
+/// (`eval_cache` がSomeならその表をターンをまたいで使い続ける)
+fn play_with_cache(
+    seed: u64,
+    chokudai: bool,
+    mut eval_cache: Option<EvalCache>,
+) -> (Vec<usize>, u64, SearchStats) {
+    let calls = Arc::new(AtomicU64::new(0));
+    let mut state = counting_state(seed, &calls);
+    let mut stats = SearchStats::default();
+    let mut actions = vec![];
+    with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+        || {
+            while !state.is_done() {
+                let action = match (&mut eval_cache, chokudai) {
+                    (Some(eval_cache), true) => {
+                        eval_cache.begin_turn(state.turn);
+                        chokudai_search_action_with_eval_cache(
+                            &state,
+                            1,
+                            5,
+                            300,
+                            CHOKUDAI_CHECK_INTERVAL,
+                            Some(eval_cache),
+                            &mut stats,
+                        )
+                    }
+                    (None, true) => chokudai_search_action_with_eval_cache(
+                        &state,
+                        1,
+                        5,
+                        300,
+                        CHOKUDAI_CHECK_INTERVAL,
+                        Some(&mut EvalCache::default()),
+                        &mut stats,
+                    ),
+                    (eval_cache, false) => {
+                        if let Some(eval_cache) = eval_cache {
+                            eval_cache.begin_turn(state.turn);
+                        }
+                        beam_search_action_with_eval_cache(
+                            &state,
+                            3,
+                            300,
+                            StopRule::default(),
+                            eval_cache.as_mut(),
+                            &mut stats,
+                        )
+                    }
+                }
+                .unwrap();
+                actions.push(
+                    state
+                        .legal_actions()
+                        .iter()
+                        .position(|&a| a == action)
+                        .unwrap(),
+                );
+                state.advance(action);
+            }
+        },
+    );
+    (actions, calls.load(Ordering::Relaxed), stats)
+}
+
+#[test]
+fn a_cache_kept_between_turns_skips_positions_read_on_earlier_turns() {
+    for chokudai in [false, true] {
+        let (actions, calls, _) = play_with_cache(3, chokudai, None);
+        let (cached_actions, cached_calls, stats) =
+            play_with_cache(3, chokudai, Some(EvalCache::default()));
+        // 覚えた評価値は評価し直した値と同じなので、指す手は変わらない
+        assert_eq!(cached_actions, actions, "chokudai={chokudai}");
+        assert!(stats.eval_cache_hits > 0, "chokudai={chokudai}");
+        assert!(cached_calls < calls, "chokudai={chokudai}");
+        assert_eq!(
+            cached_calls,
+            stats.eval_cache_lookups - stats.eval_cache_hits
+        );
+    }
+}
+
+#[test]
+fn a_revisit_penalty_keeps_the_moves_of_chokudai_with_the_cache() {
+    let config = GameConfig {
+        revisit_penalty: 5,
+        ..GameConfig::default()
+    };
+    // 同じマス・ターン・取ったマスでも再訪の回数が違えば、子の評価値も引くハッシュも違う
+    let walk = |actions: &[Direction]| {
+        let mut state = MazeState::with_config(15, &config);
+        for &action in actions {
+            state.advance(action);
+        }
+        state
+    };
+    let (r, l) = (Direction::Right, Direction::Left);
+    let (rrll, rlrl) = (walk(&[r, r, l, l]), walk(&[r, l, r, l]));
+    assert_eq!(rrll.state_hash(), rlrl.state_hash());
+    assert_ne!(rrll.revisits, rlrl.revisits);
+    assert_ne!(rrll.eval_cache_hash(), rlrl.eval_cache_hash());
+
+    let mut total = SearchStats::default();
+    for seed in 0..3 {
+        let mut state = MazeState::with_config(seed, &config);
+        let mut eval_cache = EvalCache::default();
+        with_time_source(
+            Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+            || {
+                while !state.is_done() {
+                    let search = |eval_cache, stats: &mut SearchStats| {
+                        chokudai_search_action_with_eval_cache(
+                            &state,
+                            2,
+                            5,
+                            300,
+                            CHOKUDAI_CHECK_INTERVAL,
+                            eval_cache,
+                            stats,
+                        )
+                        .unwrap()
+                    };
+                    eval_cache.begin_turn(state.turn);
+                    let cached = search(Some(&mut eval_cache), &mut total);
+                    let action = search(None, &mut SearchStats::default());
+                    assert_eq!(cached, action, "seed={seed} turn={}", state.turn);
+                    state.advance(action);
+                }
+            },
+        );
+    }
+    assert!(total.eval_cache_hits > 0);
+}
+
+#[test]
+fn switching_the_evaluator_forgets_the_evaluations_of_the_old_one() {
+    // 途中から得点を避ける評価関数に切り替える
+    let phased = |eval_cache: Option<EvalCache>| EvaluatorPolicy {
+        inner: Box::new(TimedBeamSearchPolicy {
+            beam_width: 3,
+            time_threshold_us: 300,
+            stop_rule: StopRule::default(),
+            eval_cache,
+            stats: SearchStats::default(),
+        }),
+        phases: vec![
+            EvaluatorPhase {
+                start: PhaseStart::Start,
+                evaluator: Arc::new(|state: &MazeState| Eval::from(state.game_score)),
+                label: "score".to_string(),
+            },
+            EvaluatorPhase {
+                start: PhaseStart::Turn(10),
+                evaluator: Arc::new(|state: &MazeState| Eval(-Eval::from(state.game_score).0)),
+                label: "avoid".to_string(),
+            },
+        ],
+        active: None,
+    };
+    let play = |mut policy: EvaluatorPolicy| {
+        let mut state = MazeState::new(4);
+        let mut actions = vec![];
+        with_time_source(
+            Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+            || {
+                while state.turn < 20 {
+                    let action = policy.select_action(&state);
+                    actions.push(action);
+                    state.advance(action);
+                }
+            },
+        );
+        actions
+    };
+    assert_eq!(play(phased(Some(EvalCache::default()))), play(phased(None)));
+}
+
+#[test]
+fn the_cache_forgets_other_games_and_stays_within_its_capacity() {
+    let state = MazeState::new(0);
+    let mut eval_cache = EvalCache::with_capacity(10);
+    eval_cache.begin_turn(0);
+    let mut stats = SearchStats::default();
+    with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+        || {
+            beam_search_action_with_eval_cache(
+                &state,
+                3,
+                300,
+                StopRule::default(),
+                Some(&mut eval_cache),
+                &mut stats,
+            )
+        },
+    )
+    .unwrap();
+    assert!(!eval_cache.is_empty());
+    assert!(eval_cache.len() <= 10);
+    // 次のターンなら覚えたまま、そうでなければ別のゲームとみなして忘れる
+    eval_cache.begin_turn(1);
+    assert!(!eval_cache.is_empty());
+    eval_cache.begin_turn(0);
+    assert!(eval_cache.is_empty());
+}
+
+#[test]
+fn timed_policies_accept_the_cache_parameter() {
+    let policy = parse_policy("beam:width=3,ms=2,tt=1", 0).unwrap();
+    assert_eq!(policy.name(), "beam(width=3, ms=2, tt=1)");
+    let policy = parse_policy("chokudai:width=1,depth=5,us=500,tt=1", 0).unwrap();
+    assert_eq!(policy.name(), "chokudai(width=1, depth=5, us=500, tt=1)");
+    assert!(parse_policy("beam:width=3,tt=1", 0).is_err());
+    assert!(parse_policy("chokudai:width=1,num=2,tt=1", 0).is_err());
+}
@@ -0,0 +1,62 @@
+use num_collect_maze::{
+    game::{Direction, State},
+    human::{parse_key, play_human},
+    policy::parse_policy,
+    search::greedy_action,
+};
+
+fn key(action: Direction) -> &'static str {
+    match action {
+        Direction::Up => "w",
+        Direction::Left => "a",
+        Direction::Down => "s",
+        Direction::Right => "d",
+    }
+}
+
+#[test]
+fn keys_and_arrows_map_to_directions() {
+    assert_eq!(parse_key("w"), Some(Direction::Up));
+    assert_eq!(parse_key(" D \n"), Some(Direction::Right));
+    assert_eq!(parse_key("\x1b[A"), Some(Direction::Up));
+    assert_eq!(parse_key("\x1b[B"), Some(Direction::Down));
+    assert_eq!(parse_key("\x1b[C"), Some(Direction::Right));
+    assert_eq!(parse_key("\x1b[D"), Some(Direction::Left));
+    assert_eq!(parse_key("x"), None);
+}
+
+#[test]
+fn following_the_hints_reproduces_the_ai_game() {
+    let mut state = State::new(5);
+    let mut input = String::from("x\n");
+    while !state.is_done() {
+        let action = greedy_action(&state).unwrap();
+        input.push_str(key(action));
+        input.push('\n');
+        state.advance(action);
+    }
+    let mut hint = parse_policy("greedy", 0).unwrap();
+    let mut output = vec![];
+    let game = play_human(5, Some(hint.as_mut()), input.as_bytes(), &mut output, false).unwrap();
+    assert!(!game.quit);
+    assert_eq!(game.state.game_score, state.game_score);
+    assert_eq!(game.hinted, game.actions.len());
+    assert_eq!(game.agreed, game.hinted);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("unknown key \"x\""));
+    assert!(output.contains(&format!("you\tscore: {}", state.game_score)));
+}
+
+#[test]
+fn illegal_moves_are_rejected_and_q_quits() {
+    // 盤面0の開始位置から上に出られなくなるまで進み、さらに上を押す
+    let ups = State::new(0).character.y as usize;
+    let input = format!("{}w\nq\n", "w\n".repeat(ups));
+    let mut output = vec![];
+    let game = play_human(0, None, input.as_bytes(), &mut output, false).unwrap();
+    assert!(game.quit);
+    assert_eq!(game.actions.len(), ups);
+    assert_eq!(game.hinted, 0);
+    assert_eq!(game.state.character.y, 0);
+    assert!(String::from_utf8(output).unwrap().contains("cannot move U"));
+}
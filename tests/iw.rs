@@ -0,0 +1,114 @@
+use std::{cell::Cell, sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{Coord, Direction, GameConfig, MazeState},
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::{iw_action, MazeNoveltyFeatures, NoveltyFeatures, SearchStats},
+    util::{with_time_source, ManualClock},
+};
+
+/// 止まった時計で、幅 `max_width` まで読み終えるIWを1回行う
+fn frozen_iw<F: NoveltyFeatures<MazeState>>(
+    state: &MazeState,
+    features: &F,
+    max_width: usize,
+) -> (Direction, SearchStats) {
+    let mut stats = SearchStats::default();
+    let action = with_time_source(Arc::new(ManualClock::default()), || {
+        iw_action(state, features, max_width, 10_000, &mut stats)
+    });
+    (action.unwrap(), stats)
+}
+
+/// 20ターンで終わる盤面 (IW(2)を最後まで読める大きさ)
+fn short_game(seed: u64) -> MazeState {
+    MazeState::with_config(
+        seed,
+        &GameConfig {
+            end_turn: 20,
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn widths_are_read_to_the_end_reproducibly() {
+    let state = short_game(3);
+    let features = MazeNoveltyFeatures::new(&state, 8);
+    let (action, stats) = frozen_iw(&state, &features, 2);
+    assert_eq!(frozen_iw(&state, &features, 2), (action, stats));
+    assert!(state.legal_actions().contains(&action));
+    assert_eq!(stats.passes, 2);
+    assert_eq!(stats.searches, 1);
+    // 幅2は幅1の組も見るので、幅1だけより多く展開する
+    let (_, narrow) = frozen_iw(&state, &features, 1);
+    assert_eq!(narrow.passes, 1);
+    assert!(stats.nodes_expanded > narrow.nodes_expanded);
+    // 3以上は2までしか読まない
+    assert_eq!(frozen_iw(&state, &features, 5).1.passes, 2);
+}
+
+#[test]
+fn the_search_finds_a_row_of_points() {
+    let mut state = short_game(0);
+    state.points.fill(0);
+    state.character = Coord::new(10, 10);
+    for x in 11..20 {
+        state.points[10 * 30 + x] = 9;
+    }
+    let features = MazeNoveltyFeatures::new(&state, 4);
+    assert_eq!(features.cells().len(), 4);
+    assert!(features.cells().iter().all(|&cell| state.points[cell] > 0));
+    assert_eq!(frozen_iw(&state, &features, 1).0, Direction::Right);
+}
+
+#[test]
+fn the_feature_set_is_pluggable() {
+    let state = short_game(1);
+    let calls = Cell::new(0);
+    // ターンだけを特徴にすると、各ターンでそれまでの最高の評価値を超えた状態しか展開しない
+    let turn_only = |state: &MazeState, atoms: &mut Vec<u64>| {
+        calls.set(calls.get() + 1);
+        atoms.push(state.turn as u64);
+    };
+    let (action, stats) = frozen_iw(&state, &turn_only, 1);
+    assert!(state.legal_actions().contains(&action));
+    assert!(calls.get() > 0);
+    let (_, maze) = frozen_iw(&state, &MazeNoveltyFeatures::new(&state, 8), 1);
+    assert!(stats.nodes_expanded < maze.nodes_expanded);
+}
+
+#[test]
+fn the_time_limit_still_reads_the_children_of_the_root() {
+    let state = MazeState::new(0);
+    let mut stats = SearchStats::default();
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_millis(1))),
+        || {
+            iw_action(
+                &state,
+                &MazeNoveltyFeatures::new(&state, 8),
+                2,
+                1000,
+                &mut stats,
+            )
+        },
+    );
+    assert!(state.legal_actions().contains(&action.unwrap()));
+    assert_eq!(stats.passes, 0);
+    assert_eq!(stats.max_depth_reached, 1);
+}
+
+#[test]
+fn the_policy_plays_a_whole_game() {
+    let mut policy = parse_policy("iw:k=1,samples=4,us=300", 0).unwrap();
+    assert_eq!(policy.name(), "iw(k=1, samples=4, us=300)");
+    let result = run_game(0, policy.as_mut(), &PlayConfig::default());
+    assert_eq!(result.actions.len(), 100);
+    assert_eq!(policy.search_stats().unwrap().searches, 100);
+    assert_eq!(
+        parse_policy("iw", 0).unwrap().name(),
+        "iw(k=2, samples=16, ms=10)"
+    );
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use num_collect_maze::{
+    game::{GameState, State, END_TURN, H, W},
+    large::{default_end_turn, LargeEvaluator, LargeMazeState},
+    search::{
+        arena_beam_search_action, arena_beam_search_action_with_stats, beam_search_action,
+        SearchStats,
+    },
+};
+
+#[test]
+fn a_30x30_board_is_the_same_game_as_maze_state() {
+    assert_eq!(default_end_turn(H, W), END_TURN);
+    for seed in 0..3 {
+        let mut large = LargeMazeState::new(seed, H, W);
+        let mut state = State::new(seed);
+        assert_eq!(&large.points[..], &state.points[..]);
+        assert!(large.character == state.character);
+        assert_eq!(large.to_string(), state.to_string());
+        while !state.is_done() {
+            let action = beam_search_action(&state, 3, 5).unwrap();
+            assert_eq!(arena_beam_search_action(&large, 3, 5), Ok(action));
+            state.advance(action);
+            large.advance(action);
+        }
+        assert!(large.is_done());
+        assert_eq!(large.game_score, state.game_score);
+    }
+}
+
+#[test]
+fn boards_of_the_same_size_share_the_neighbor_table() {
+    let (a, b) = (
+        LargeMazeState::new(0, 100, 120),
+        LargeMazeState::new(1, 100, 120),
+    );
+    assert!(Arc::ptr_eq(a.neighbor_table(), b.neighbor_table()));
+    let c = LargeMazeState::new(0, 120, 100);
+    assert!(!Arc::ptr_eq(a.neighbor_table(), c.neighbor_table()));
+    // 角のマスからは2方向、内側のマスからは4方向に進める
+    assert_eq!(a.neighbor_table().neighbors(0).len(), 2);
+    assert_eq!(a.neighbor_table().neighbors(120 + 1).len(), 4);
+}
+
+#[test]
+fn previews_match_the_advanced_child() {
+    let mut state = LargeMazeState::new(4, 100, 100);
+    state.evaluator = LargeEvaluator::sampled_potential(64, 10, 50);
+    for turn in 0..30 {
+        for action in state.legal_actions() {
+            let preview = state.preview_child(action).unwrap();
+            let mut child = state.clone();
+            child.advance(action);
+            child.evaluate();
+            assert_eq!(preview.evaluated_score, child.evaluated_score);
+            assert_eq!(Some(preview.state_hash), child.state_hash());
+            assert!(preview.character == child.character);
+        }
+        let actions = state.legal_actions();
+        state.advance(actions[turn % actions.len()]);
+    }
+}
+
+#[test]
+fn bounded_beams_play_a_200x200_game() {
+    let mut state = LargeMazeState::new(0, 200, 200);
+    state.evaluator = LargeEvaluator::sampled_potential(32, 8, 50);
+    state.end_turn = 50;
+    let mut stats = SearchStats::default();
+    while !state.is_done() {
+        let action = arena_beam_search_action_with_stats(&state, 4, 5, &mut stats).unwrap();
+        state.advance(action);
+    }
+    assert!(state.game_score.0 > 0);
+    // 複製するのは根とビームに残る状態だけ
+    assert!(stats.states_cloned <= 50 * (1 + 4 * 5));
+}
+
+#[test]
+fn rendering_can_be_windowed_or_chunked() {
+    let state = LargeMazeState::new(5, 100, 100);
+    let full = state.to_string();
+    let mut chunked = vec![];
+    state.write_chunked(&mut chunked, 7).unwrap();
+    assert_eq!(String::from_utf8(chunked).unwrap(), full);
+
+    let rows: Vec<&str> = full.lines().skip(2).collect();
+    let window = state.render_window(10, 95, 3, 10);
+    let expected: Vec<&str> = rows[10..13].iter().map(|row| &row[95..]).collect();
+    assert_eq!(window.lines().collect::<Vec<_>>(), expected);
+    assert_eq!(state.render_window(100, 0, 5, 5), "");
+}
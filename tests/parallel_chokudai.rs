@@ -0,0 +1,85 @@
+use std::{sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{MazeState, END_TURN},
+    policy::parse_policy,
+    search::{
+        parallel_chokudai_search_action, shared_chokudai_search_action, SearchError, SearchStats,
+    },
+    util::{with_time_source, ManualClock},
+};
+
+/// 時刻を読むたびに100us進む時計で、ビームを共有するchokudaiサーチを1回行う
+fn ticking_shared(seed: u64, threads: usize) -> (usize, SearchStats) {
+    let state = MazeState::new(seed);
+    let mut stats = SearchStats::default();
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(100))),
+        || shared_chokudai_search_action(&state, 1, END_TURN, 20_000, threads, &mut stats),
+    );
+    (action.unwrap().index(), stats)
+}
+
+#[test]
+fn a_single_thread_is_deterministic() {
+    for seed in 0..3 {
+        let (action, stats) = ticking_shared(seed, 1);
+        assert_eq!(ticking_shared(seed, 1), (action, stats));
+        assert!(stats.nodes_expanded > 0);
+        assert!(stats.passes >= 1);
+    }
+}
+
+#[test]
+fn threads_share_the_beams() {
+    let state = MazeState::new(0);
+    let mut stats = SearchStats::default();
+    let action = shared_chokudai_search_action(&state, 2, 20, 5_000, 4, &mut stats).unwrap();
+    assert!(state.legal_actions().contains(&action));
+    assert!(stats.nodes_expanded > 0);
+    // 全てのスレッドが少なくとも1回は時間を確かめて止まる
+    assert!(stats.elapsed_us > 0);
+}
+
+#[test]
+fn the_policy_takes_a_share_parameter() {
+    let state = MazeState::new(1);
+    let mut policy = parse_policy("parallel-chokudai:width=2,ms=2,threads=2,share=1", 0).unwrap();
+    assert_eq!(
+        policy.name(),
+        "parallel-chokudai(width=2, depth=100, ms=2, threads=2, share=1)"
+    );
+    let action = policy.select_action(&state);
+    assert!(state.legal_actions().contains(&action));
+    assert!(policy.search_stats().unwrap().nodes_expanded > 0);
+    let policy = parse_policy("parallel-chokudai:ms=2,threads=2", 0).unwrap();
+    assert!(!policy.name().contains("share"));
+}
+
+/// `f` が実時間で1秒以内に終わるか
+fn finishes_promptly(f: impl FnOnce() + Send + 'static) -> bool {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        f();
+        let _ = sender.send(());
+    });
+    receiver.recv_timeout(Duration::from_secs(1)).is_ok()
+}
+
+#[test]
+fn depth_zero_stops_at_the_deadline() {
+    for shared in [false, true] {
+        let search = move || {
+            let state = MazeState::new(0);
+            let mut stats = SearchStats::default();
+            let result = if shared {
+                shared_chokudai_search_action(&state, 1, 0, 1000, 2, &mut stats)
+            } else {
+                parallel_chokudai_search_action(&state, 1, 0, 1000, 2, 0, &mut stats)
+            };
+            // 深さ0では根しか残らないので手を決められない
+            assert_eq!(result, Err(SearchError::TimeBudgetExhausted));
+        };
+        assert!(finishes_promptly(search), "shared={shared}");
+    }
+}
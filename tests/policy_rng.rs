@@ -0,0 +1,37 @@
+use std::process::Command;
+
+/// `compare` を実行して、結果のJSONLから各ゲームの (AIの名前, トークン) を取り出す
+fn compare_tokens(policies: &[&str], name: &str) -> Vec<(String, String)> {
+    let path = std::env::temp_dir().join(format!("num_collect_maze_{name}.jsonl"));
+    let status = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .arg("compare")
+        .args(policies)
+        .args(["--games", "3", "--results"])
+        .arg(&path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let text = std::fs::read_to_string(&path).unwrap();
+    text.lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                value["policy"].as_str().unwrap().to_string(),
+                value["token"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn adding_a_policy_does_not_perturb_another_policys_rng() {
+    let alone = compare_tokens(&["random"], "alone");
+    let with_others = compare_tokens(&["random", "random", "greedy"], "with_others");
+    assert_eq!(alone[..], with_others[..3]);
+}
+
+#[test]
+fn each_policy_gets_its_own_stream() {
+    let tokens = compare_tokens(&["random", "random"], "two_random");
+    assert_ne!(tokens[..3], tokens[3..]);
+}
@@ -0,0 +1,128 @@
+//! `prelude` の名前を消したり別の物にしたりしたら、ここがビルドできなくなる
+
+use std::sync::Arc;
+
+use num_collect_maze::prelude::*;
+
+#[test]
+fn the_prelude_covers_a_whole_game() {
+    let config = PlayConfig {
+        game: GameConfig {
+            end_turn: 20,
+            ..GameConfig::default()
+        },
+        ..PlayConfig::default()
+    };
+    let mut policy: Box<dyn Policy> = parse_policy("beam:width=2,depth=2", 0).unwrap();
+    let result: GameResult = run_game(0, policy.as_mut(), &config);
+    assert_eq!(result.actions.len(), 20);
+
+    let mut turns = 0;
+    let result = GameRunner::new(config)
+        .on_turn(|_: &State, stats: &TurnStats| {
+            turns += 1;
+            if stats.wasted_turns > 100 {
+                TurnControl::Stop
+            } else {
+                TurnControl::Continue
+            }
+        })
+        .run(0, policy.as_mut())
+        .unwrap();
+    assert_eq!(result.actions.len(), turns);
+
+    let error: SpecError = parse_policy("beam:widht=2", 0).err().unwrap();
+    assert!(error.to_string().contains("width"));
+}
+
+#[test]
+fn the_prelude_covers_states_and_searches() {
+    let state: MazeState = MazeState::new(0);
+    assert_eq!((state.points.len(), END_TURN), (H * W, 100));
+    let evaluator: Arc<dyn Evaluator> = Arc::new(|state: &MazeState| Eval::from(state.game_score));
+    let state = state.with_evaluator(evaluator);
+    let action: Direction = beam_search_action(&state, 2, 2).unwrap();
+    assert!(state.legal_actions().contains(&action));
+    assert!(chokudai_search_action(&state, 1, 2, 2).is_ok());
+    assert!(greedy_action(&state).is_ok());
+    let _: Coord = state.character;
+    assert_eq!(state.game_score, GameScore(0));
+    assert!(matches!(
+        beam_search_action(&state, 0, 2),
+        Err(SearchError::EmptyBeam)
+    ));
+
+    // 探索の設定の型
+    let _ = (
+        StopRule::default(),
+        RheaParams::default(),
+        RootAggregation::Vote,
+        TemperatureSchedule {
+            initial: 1.,
+            decay: 0.5,
+        },
+        SearchStats::default(),
+    );
+}
+
+/// `GameState` を実装すれば汎用の探索がそのまま使える
+#[test]
+fn the_prelude_is_enough_to_implement_a_game() {
+    use std::fmt;
+
+    /// 0から始めて、1か2を足して10を超えないようにする
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Count {
+        evaluated_score: Eval,
+        value: u8,
+        first_action: u8,
+    }
+
+    impl fmt::Display for Count {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.value)
+        }
+    }
+
+    impl GameState for Count {
+        type Action = u8;
+
+        fn legal_actions(&self) -> Vec<u8> {
+            [1, 2]
+                .into_iter()
+                .filter(|&a| self.value + a <= 10)
+                .collect()
+        }
+
+        fn advance(&mut self, action: u8) {
+            self.value += action;
+        }
+
+        fn is_done(&self) -> bool {
+            self.value >= 9
+        }
+
+        fn evaluate(&mut self) {
+            self.evaluated_score = Eval(self.value as i64);
+        }
+
+        fn evaluated_score(&self) -> Eval {
+            self.evaluated_score
+        }
+
+        fn first_action(&self) -> u8 {
+            self.first_action
+        }
+
+        fn set_first_action(&mut self, action: u8) {
+            self.first_action = action;
+        }
+    }
+
+    let state = Count {
+        evaluated_score: Eval(0),
+        value: 0,
+        first_action: 0,
+    };
+    assert_eq!(greedy_action(&state), Ok(2));
+}
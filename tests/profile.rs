@@ -0,0 +1,21 @@
+use std::sync::atomic::Ordering;
+
+use num_collect_maze::{
+    game::MazeState,
+    search::{arena_beam_search_action, beam_search_action},
+    util::{take_phase_times, Phase, PHASE_NAMES, PROFILE_ENABLED},
+};
+
+#[test]
+fn beam_searches_time_the_dedup_apart_from_the_heap() {
+    PROFILE_ENABLED.store(true, Ordering::Relaxed);
+    let state = MazeState::new(0);
+    assert_eq!(PHASE_NAMES[Phase::Dedup as usize], "dedup");
+    for search in [beam_search_action, arena_beam_search_action] {
+        take_phase_times();
+        search(&state, 10, 10).unwrap();
+        let times = take_phase_times();
+        assert!(!times[Phase::Dedup as usize].is_zero());
+        assert!(!times[Phase::Heap as usize].is_zero());
+    }
+}
@@ -0,0 +1,56 @@
+use num_collect_maze::{
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    report::ExperimentReport,
+    results::{write_manifest, write_results_jsonl, RunMeta},
+};
+
+/// `specs` のAIを10シードずつ遊ばせた結果を1AI1ファイルで置いた実験のディレクトリ
+fn experiment_dir(specs: &[&str]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("num_collect_maze_report_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (i, spec) in specs.iter().enumerate() {
+        let mut policy = parse_policy(spec, 0).unwrap();
+        let results: Vec<_> = (0..10)
+            .map(|seed| run_game(seed, policy.as_mut(), &PlayConfig::default()))
+            .collect();
+        let path = dir.join(format!("run{i}.jsonl"));
+        let path = path.to_str().unwrap();
+        write_results_jsonl(&results, path).unwrap();
+        let meta = RunMeta {
+            tags: vec![format!("tag{i}")],
+            note: Some("<note>".to_string()),
+        };
+        write_manifest(path, "bench", &meta).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn report_ranks_policies_and_renders_self_contained_html() {
+    let dir = experiment_dir(&["greedy", "beam:width=3,depth=3"]);
+    let report = ExperimentReport::from_dir(dir.to_str().unwrap()).unwrap();
+    assert_eq!(report.runs.len(), 2);
+
+    let ranking = report.policy_ranking();
+    assert_eq!(ranking.len(), 2);
+    assert!(ranking[0].2.mean >= ranking[1].2.mean);
+    let worst = report.worst_games(5);
+    assert!(worst.windows(2).all(|w| w[0].game_score <= w[1].game_score));
+    let min = report.results().map(|r| r.game_score).min().unwrap();
+    assert_eq!(worst[0].game_score, min);
+    let histogram = report.score_histogram();
+    assert_eq!(histogram.iter().map(|bin| bin.2).sum::<usize>(), 20);
+
+    let html = report.to_html();
+    assert!(html.contains("<svg"));
+    assert!(html.contains("tag1"));
+    assert!(html.contains("&lt;note&gt;"));
+    // 外部のファイルを読まない
+    assert!(!html.contains("src=") && !html.contains("href="));
+
+    let markdown = report.to_markdown();
+    assert!(markdown.contains("## Best configs"));
+    assert!(markdown.contains(&format!("| 1 | `{}` |", ranking[0].0)));
+    std::fs::remove_dir_all(dir).unwrap();
+}
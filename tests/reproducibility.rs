@@ -0,0 +1,55 @@
+use std::process::Command;
+
+fn run(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned()
+            + &String::from_utf8_lossy(&output.stderr),
+    )
+}
+
+#[test]
+fn instances_and_policy_rng_streams_are_stable() {
+    let (ok, output) = run(&["selfcheck"]);
+    assert!(ok, "{output}");
+}
+
+#[test]
+fn game_log_replays_turn_by_turn() {
+    let path = std::env::temp_dir().join(format!("game_log_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let (ok, output) = run(&["play", "3", "--algorithm", "greedy", "--log", path]);
+    assert!(ok, "{output}");
+    let score = output
+        .lines()
+        .next_back()
+        .unwrap()
+        .rsplit('\t')
+        .next()
+        .unwrap();
+
+    let (ok, output) = run(&["replay", path]);
+    std::fs::remove_file(path).unwrap();
+    assert!(ok, "{output}");
+    assert!(output.contains("turn 99\taction: "), "{output}");
+    assert!(
+        output.trim_end().ends_with(&format!("{score}\tok")),
+        "{output}"
+    );
+}
+
+#[test]
+fn compare_without_games_is_a_usage_error() {
+    let (ok, output) = run(&["compare", "greedy", "random", "--games", "0"]);
+    assert!(!ok);
+    assert!(output.contains("at least one game"), "{output}");
+    assert!(!output.contains("panicked"), "{output}");
+
+    let (ok, output) = run(&["noise", "greedy", "--games", "0"]);
+    assert!(!ok);
+    assert!(output.contains("at least one game"), "{output}");
+}
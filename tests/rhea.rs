@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use num_collect_maze::{
+    game::{Coord, Direction, MazeState},
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::{rhea_action, RheaParams, SearchStats},
+    util::{with_time_source, ManualClock},
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+/// 止まった時計で、世代数だけで打ち切るRHEAを1回行う
+fn frozen_rhea(
+    state: &MazeState,
+    params: RheaParams,
+    shift_buffer: &mut Vec<u8>,
+    seed: u64,
+) -> (Direction, SearchStats) {
+    let mut stats = SearchStats::default();
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let action = with_time_source(Arc::new(ManualClock::default()), || {
+        rhea_action(state, params, 10_000, shift_buffer, &mut rng, &mut stats)
+    });
+    (action.unwrap(), stats)
+}
+
+#[test]
+fn generations_are_reproducible() {
+    let params = RheaParams {
+        max_generations: 5,
+        ..RheaParams::default()
+    };
+    let state = MazeState::new(3);
+    let (action, stats) = frozen_rhea(&state, params, &mut vec![], 1);
+    assert_eq!(frozen_rhea(&state, params, &mut vec![], 1), (action, stats));
+    assert!(state.legal_actions().contains(&action));
+    assert_eq!(stats.passes, 5);
+    // 最初の世代と、各世代で最良の個体以外の子を評価する
+    assert_eq!(stats.states_cloned as usize, 10 + 5 * 9);
+    assert_eq!(stats.max_depth_reached, 20);
+}
+
+#[test]
+fn evolution_finds_a_row_of_points() {
+    let mut state = MazeState::new(0);
+    state.points.fill(0);
+    state.character = Coord::new(10, 10);
+    for x in 11..20 {
+        state.points[10 * 30 + x] = 9;
+    }
+    let params = RheaParams {
+        population: 10,
+        length: 10,
+        mutation_percent: 10,
+        max_generations: 50,
+    };
+    for seed in 0..3 {
+        let mut shift_buffer = vec![];
+        let (action, _) = frozen_rhea(&state, params, &mut shift_buffer, seed);
+        assert_eq!(action, Direction::Right, "seed={seed}");
+        // 次のターンに渡す行動列は最初の行動を除いたもの
+        assert_eq!(shift_buffer.len(), 9);
+    }
+}
+
+#[test]
+fn the_policy_plays_a_whole_game() {
+    let mut policy = parse_policy("rhea:pop=4,len=5,gens=2", 0).unwrap();
+    assert_eq!(policy.name(), "rhea(pop=4, len=5, mut=10, ms=10, gens=2)");
+    let result = run_game(0, policy.as_mut(), &PlayConfig::default());
+    assert_eq!(result.actions.len(), 100);
+    assert_eq!(policy.search_stats().unwrap().passes, 2 * 100);
+    assert_eq!(
+        parse_policy("rhea:us=500", 0).unwrap().name(),
+        "rhea(pop=10, len=20, mut=10, us=500)"
+    );
+}
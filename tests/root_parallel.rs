@@ -0,0 +1,68 @@
+use std::{sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::MazeState,
+    policy::parse_policy,
+    search::{root_parallel_beam_search_action, RootAggregation, SearchStats},
+    util::{with_time_source, ManualClock},
+};
+
+/// 時刻を読むたびに10us進む時計で、1スレッドの根並列ビームサーチを1回行う
+fn ticking_search(seed: u64, aggregation: RootAggregation) -> (usize, SearchStats) {
+    let state = MazeState::new(seed);
+    let mut stats = SearchStats::default();
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(10))),
+        || root_parallel_beam_search_action(&state, 3, 2_000, 1, aggregation, 7, &mut stats),
+    );
+    (action.unwrap().index(), stats)
+}
+
+#[test]
+fn a_single_thread_is_a_deterministic_beam_search() {
+    for seed in 0..3 {
+        let (vote, stats) = ticking_search(seed, RootAggregation::Vote);
+        assert_eq!(ticking_search(seed, RootAggregation::Vote), (vote, stats));
+        // 1スレッドなら多数決も最良の評価値も同じ行動を選ぶ
+        assert_eq!(ticking_search(seed, RootAggregation::Best).0, vote);
+        assert_eq!(stats.searches, 1);
+        assert!(stats.max_depth_reached > 1);
+    }
+}
+
+#[test]
+fn threads_vote_on_the_first_action() {
+    let state = MazeState::new(2);
+    for aggregation in [RootAggregation::Vote, RootAggregation::Best] {
+        let mut stats = SearchStats::default();
+        let action =
+            root_parallel_beam_search_action(&state, 3, 3_000, 4, aggregation, 0, &mut stats)
+                .unwrap();
+        assert!(state.legal_actions().contains(&action));
+        // 全てのスレッドが少なくとも根の子を展開する
+        assert!(stats.nodes_expanded >= 4);
+    }
+}
+
+#[test]
+fn the_policy_parses_the_aggregation() {
+    let mut policy = parse_policy("parallel-beam:width=3,ms=2,threads=2,agg=best", 0).unwrap();
+    assert_eq!(
+        policy.name(),
+        "parallel-beam(width=3, ms=2, threads=2, agg=best)"
+    );
+    let state = MazeState::new(0);
+    assert!(state
+        .legal_actions()
+        .contains(&policy.select_action(&state)));
+    let policy = parse_policy("parallel-beam:us=1500,threads=1", 0).unwrap();
+    assert_eq!(
+        policy.name(),
+        "parallel-beam(width=5, us=1500, threads=1, agg=vote)"
+    );
+    let error = match parse_policy("parallel-beam:agg=votes", 0) {
+        Ok(policy) => panic!("parsed as {}", policy.name()),
+        Err(error) => error,
+    };
+    assert_eq!(error.suggestion(), Some("vote"));
+}
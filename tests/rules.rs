@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use num_collect_maze::{
+    game::{Coord, Direction, GameConfig, GameState, MazeState, H, W},
+    rules::{Decay, Energy, Multiplier, RuledState, Teleporters, Torus},
+    search::beam_search_action,
+};
+
+/// 左上の隅に置いた盤面
+fn corner_board() -> MazeState {
+    let mut board = MazeState::new(0);
+    board.character = Coord::new(0, 0);
+    board
+}
+
+#[test]
+fn torus_wraps_moves_off_the_board() {
+    let plain = RuledState::with_rules(corner_board(), vec![]);
+    assert_eq!(plain.legal_actions().len(), 2);
+    let mut state = RuledState::with_rules(corner_board(), vec![Arc::new(Torus)]);
+    assert_eq!(state.legal_actions().len(), 4);
+    state.advance(Direction::Up);
+    assert!(state.board.character == Coord::new(H as i32 - 1, 0));
+    state.advance(Direction::Left);
+    assert!(state.board.character == Coord::new(H as i32 - 1, W as i32 - 1));
+}
+
+#[test]
+fn walls_block_moves_and_clear_points() {
+    for seed in 0..5 {
+        let state = RuledState::new(seed, "torus+walls", &GameConfig::default()).unwrap();
+        // 1行目はターン、2行目はスコア
+        let rows: Vec<Vec<char>> = state
+            .to_string()
+            .lines()
+            .skip(2)
+            .map(|line| line.chars().collect())
+            .collect();
+        let cell_char = |cell: Coord| rows[cell.y as usize][cell.x as usize];
+        assert!(rows.iter().flatten().any(|&c| c == '#'));
+        for y in 0..H {
+            for x in 0..W {
+                let cell = Coord::new(y as i32, x as i32);
+                if cell_char(cell) == '#' {
+                    assert_eq!(state.board.points[cell.index()], 0);
+                }
+            }
+        }
+        for action in state.legal_actions() {
+            let mut next = state.clone();
+            next.advance(action);
+            assert_ne!(cell_char(next.board.character), '#');
+        }
+    }
+}
+
+#[test]
+fn teleporters_move_to_the_paired_cell() {
+    let pair = (Coord::new(0, 1), Coord::new(10, 10));
+    let mut state = RuledState::with_rules(
+        corner_board(),
+        vec![Arc::new(Teleporters { pairs: vec![pair] })],
+    );
+    state.advance(Direction::Right);
+    assert!(state.board.character == pair.1);
+    assert_eq!(state.board.game_score.0, 0);
+}
+
+#[test]
+fn decay_lowers_every_point() {
+    let mut state = RuledState::with_rules(
+        corner_board(),
+        vec![Arc::new(Decay {
+            every: 2,
+            amount: 1,
+        })],
+    );
+    let before: u64 = state.board.points.iter().map(|&p| p as u64).sum();
+    state.advance(Direction::Right);
+    state.advance(Direction::Left);
+    let after: u64 = state.board.points.iter().map(|&p| p as u64).sum();
+    let nonzero = state.board.points.iter().filter(|&&p| p > 0).count() as u64;
+    assert!(after + nonzero <= before);
+}
+
+#[test]
+fn multiplier_scales_points_on_its_cells() {
+    let mut board = corner_board();
+    board.points[Coord::new(0, 1).index()] = 4;
+    let mut state = RuledState::with_rules(
+        board,
+        vec![Arc::new(Multiplier {
+            factor: 3,
+            cells: vec![Coord::new(0, 1)],
+        })],
+    );
+    state.advance(Direction::Right);
+    assert_eq!(state.board.game_score.0, 12);
+}
+
+#[test]
+fn energy_ends_the_game_when_it_runs_out() {
+    let mut board = corner_board();
+    board.points.fill(0);
+    let mut state = RuledState::with_rules(board, vec![Arc::new(Energy { capacity: 3 })]);
+    for _ in 0..3 {
+        assert!(!state.is_done());
+        let action = state.legal_actions()[0];
+        state.advance(action);
+    }
+    assert!(state.is_done());
+    assert!(state.to_string().contains("energy:\t0"));
+}
+
+#[test]
+fn combined_rules_can_be_searched() {
+    let mut state = RuledState::new(
+        1,
+        "torus+decay:every=10,amount=1+walls",
+        &GameConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(state.rule_names(), "torus+decay+walls");
+    while !state.is_done() {
+        let action = beam_search_action(&state, 3, 3).unwrap();
+        state.advance(action);
+    }
+    assert_eq!(state.board.turn, state.board.end_turn);
+    assert!(state.board.game_score.0 > 0);
+    assert!(RuledState::new(1, "torus+gravity", &GameConfig::default()).is_err());
+}
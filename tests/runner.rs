@@ -0,0 +1,95 @@
+use num_collect_maze::{
+    game::Direction,
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    runner::{GameRunner, TurnControl},
+};
+
+#[test]
+fn runner_without_hooks_matches_run_game() {
+    let config = PlayConfig::default();
+    let expected = run_game(
+        3,
+        parse_policy("beam:width=3,depth=3", 0).unwrap().as_mut(),
+        &config,
+    );
+    let mut turns = 0;
+    let result = GameRunner::new(config)
+        .on_turn(|_, _| {
+            turns += 1;
+            TurnControl::Continue
+        })
+        .run(3, parse_policy("beam:width=3,depth=3", 0).unwrap().as_mut())
+        .unwrap();
+    assert_eq!(result.token, expected.token);
+    assert_eq!(result.game_score, expected.game_score);
+    assert_eq!(result.wasted_turns, expected.wasted_turns);
+    assert_eq!(turns, expected.actions.len());
+}
+
+#[test]
+fn stop_ends_the_game_early() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let mut last_seen = None;
+    let result = GameRunner::new(PlayConfig::default())
+        .on_turn(|state, stats| {
+            last_seen = stats.last_action;
+            if state.turn == 10 {
+                TurnControl::Stop
+            } else {
+                TurnControl::Continue
+            }
+        })
+        .run(0, policy.as_mut())
+        .unwrap();
+    assert_eq!(result.actions.len(), 10);
+    assert_eq!(
+        last_seen.map(Direction::index),
+        result.actions.last().copied()
+    );
+}
+
+#[test]
+fn injected_moves_replace_the_policy() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let mut injected = None;
+    let mut injected_turns = 0;
+    let result = GameRunner::new(PlayConfig::default())
+        // 先に足した関数の指示が優先されるので、Stopは2ターン目から効く
+        .on_turn(|state, _| match state.turn {
+            0 => {
+                let action = *state.legal_actions().last().unwrap();
+                injected = Some(action);
+                TurnControl::Play(action)
+            }
+            _ => TurnControl::Continue,
+        })
+        .on_turn(|_, stats| {
+            injected_turns = stats.injected_turns;
+            TurnControl::Stop
+        })
+        .run(0, policy.as_mut())
+        .unwrap();
+    assert_eq!(result.actions[0], injected.unwrap().index());
+    assert_eq!(injected_turns, 1);
+}
+
+#[test]
+fn illegal_injections_are_errors() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let result = GameRunner::new(PlayConfig::default())
+        .on_turn(|state, _| {
+            let illegal = Direction::ALL
+                .into_iter()
+                .find(|action| !state.legal_actions().contains(action));
+            match illegal {
+                Some(action) => TurnControl::Play(action),
+                None => TurnControl::Continue,
+            }
+        })
+        .run(0, policy.as_mut());
+    let Err(error) = result else {
+        panic!("an illegal action was accepted");
+    };
+    assert!(error.contains("illegal injected action"), "{error}");
+}
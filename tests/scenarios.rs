@@ -0,0 +1,20 @@
+use std::process::Command;
+
+#[test]
+fn regression_scenarios_pass() {
+    let mut paths: Vec<_> = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    let output = Command::new(env!("CARGO_BIN_EXE_num_collect_maze"))
+        .arg("scenario")
+        .args(&paths)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
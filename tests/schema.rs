@@ -0,0 +1,125 @@
+use num_collect_maze::{
+    compose::PolicyConfig,
+    game::INSTANCE_FORMAT_VERSION,
+    play::{play_game, run_game, PlayConfig},
+    policy::parse_policy,
+    schema::{
+        migrate_text, parse_game_log, parse_result_line, Migrated, SchemaError,
+        RESULTS_SCHEMA_VERSION,
+    },
+};
+use serde_json::Value;
+
+/// 現在の版の結果の1行
+fn current_line(seed: u64) -> Value {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let result = run_game(seed, policy.as_mut(), &PlayConfig::default());
+    serde_json::to_value(&result).unwrap()
+}
+
+/// 版1の頃の結果の1行 (`seed`・`policy`・`game_score`・`actions`・`token` だけ)
+fn legacy_line(seed: u64) -> Value {
+    let current = current_line(seed);
+    let mut legacy = serde_json::Map::new();
+    for key in ["seed", "policy", "game_score", "actions", "token"] {
+        legacy.insert(key.to_string(), current[key].clone());
+    }
+    Value::Object(legacy)
+}
+
+#[test]
+fn current_results_load_and_old_ones_ask_for_migration() {
+    let current = current_line(0);
+    assert!(parse_result_line(&current.to_string(), "a.jsonl:1").is_ok());
+
+    let Err(error) = parse_result_line(&legacy_line(0).to_string(), "a.jsonl:1") else {
+        panic!("expected an error");
+    };
+    assert!(matches!(error, SchemaError::Outdated { found: 1, .. }));
+    assert!(error.to_string().contains("migrate"));
+
+    let mut newer = current.clone();
+    newer["schema_version"] = (RESULTS_SCHEMA_VERSION + 1).into();
+    assert!(matches!(
+        parse_result_line(&newer.to_string(), "a.jsonl:1"),
+        Err(SchemaError::TooNew { .. })
+    ));
+}
+
+#[test]
+fn results_are_validated_on_load() {
+    let mut other_boards = current_line(0);
+    other_boards["instance_format_version"] = (INSTANCE_FORMAT_VERSION + 1).into();
+    assert!(matches!(
+        parse_result_line(&other_boards.to_string(), "a.jsonl:1"),
+        Err(SchemaError::InstanceMismatch { .. })
+    ));
+
+    let mut tampered = current_line(0);
+    tampered["actions"][0] = ((tampered["actions"][0].as_u64().unwrap() + 1) % 4).into();
+    let Err(error) = parse_result_line(&tampered.to_string(), "a.jsonl:7") else {
+        panic!("expected an error");
+    };
+    assert!(error.to_string().starts_with("a.jsonl:7: token"));
+
+    let mut unknown = current_line(0);
+    unknown["colour"] = "red".into();
+    assert!(matches!(
+        parse_result_line(&unknown.to_string(), "a.jsonl:1"),
+        Err(SchemaError::Invalid { .. })
+    ));
+}
+
+#[test]
+fn migrating_legacy_results_restores_the_current_lines() {
+    let text = format!("{}\n{}\n", legacy_line(0), current_line(1));
+    let (migrated, report) = migrate_text(&text, "old.jsonl").unwrap();
+    assert_eq!((report.migrated, report.current), (1, 1));
+    let Migrated::Results(results) = migrated else {
+        panic!("expected results");
+    };
+    let mut expected = current_line(0);
+    // 思考時間は古い行に残っていないので空になる
+    expected["move_times_us"] = Value::Array(vec![]);
+    assert_eq!(serde_json::to_value(&results[0]).unwrap(), expected);
+    assert_eq!(results[1].seed, 1);
+
+    let mut wrong_score = legacy_line(0);
+    wrong_score["game_score"] = 1.into();
+    assert!(migrate_text(&wrong_score.to_string(), "old.jsonl").is_err());
+}
+
+#[test]
+fn game_logs_are_versioned() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let log = play_game(0, policy.as_mut(), false);
+    let mut value = serde_json::to_value(&log).unwrap();
+    assert!(parse_game_log(&value.to_string(), "log.json")
+        .unwrap()
+        .is_some());
+    assert!(parse_game_log(&current_line(0).to_string(), "a.jsonl")
+        .unwrap()
+        .is_none());
+
+    value.as_object_mut().unwrap().remove("schema_version");
+    assert!(matches!(
+        parse_game_log(&value.to_string(), "log.json"),
+        Err(SchemaError::Outdated { .. })
+    ));
+    let (migrated, report) = migrate_text(&value.to_string(), "log.json").unwrap();
+    assert_eq!(report.migrated, 1);
+    assert!(matches!(migrated, Migrated::GameLog(_)));
+}
+
+#[test]
+fn configs_reject_newer_schema_versions() {
+    let path = std::env::temp_dir().join(format!("schema_config_{}.toml", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "policies = [\"greedy\"]\n").unwrap();
+    assert_eq!(PolicyConfig::load(path).unwrap().schema_version, 1);
+    std::fs::write(path, "schema_version = 2\npolicies = [\"greedy\"]\n").unwrap();
+    assert!(PolicyConfig::load(path).is_err_and(|e| e.contains("newer")));
+    std::fs::write(path, "policy = [\"greedy\"]\n").unwrap();
+    assert!(PolicyConfig::load(path).is_err());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,169 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{Eval, GameState, MazeState},
+    policy::{parse_policy, Policy, TimedBeamSearchPolicy},
+    search::{
+        beam_search_action, beam_search_action_with_attribution,
+        beam_search_action_with_time_threshold,
+        beam_search_action_with_time_threshold_us_and_stats, chokudai_search_action, greedy_action,
+        mcts_action, primitive_montecarlo_action, random_action, two_ply_greedy_action,
+        Attribution, SearchError, SearchStats, StopRule,
+    },
+    util::{with_cancel_token, with_time_source, CancelToken, ManualClock},
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+/// 1手も動けないまま終わらない状態
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Stuck;
+
+impl fmt::Display for Stuck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stuck")
+    }
+}
+
+impl GameState for Stuck {
+    type Action = usize;
+
+    fn legal_actions(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    fn advance(&mut self, _action: usize) {}
+
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    fn evaluate(&mut self) {}
+
+    fn evaluated_score(&self) -> Eval {
+        Eval(0)
+    }
+
+    fn first_action(&self) -> usize {
+        0
+    }
+
+    fn set_first_action(&mut self, _action: usize) {}
+}
+
+#[test]
+fn states_without_legal_actions_are_reported() {
+    let mut rng = ChaCha12Rng::seed_from_u64(0);
+    assert_eq!(
+        random_action(&Stuck, &mut rng),
+        Err(SearchError::NoLegalAction)
+    );
+    assert_eq!(greedy_action(&Stuck), Err(SearchError::NoLegalAction));
+    assert_eq!(
+        two_ply_greedy_action(&Stuck),
+        Err(SearchError::NoLegalAction)
+    );
+    assert_eq!(
+        beam_search_action(&Stuck, 5, 5),
+        Err(SearchError::NoLegalAction)
+    );
+    assert_eq!(
+        chokudai_search_action(&Stuck, 5, 5, 2),
+        Err(SearchError::NoLegalAction)
+    );
+    assert_eq!(
+        primitive_montecarlo_action(&Stuck, 10, &mut rng),
+        Err(SearchError::NoLegalAction)
+    );
+    assert_eq!(
+        mcts_action(&Stuck, 10, 1., 1, &mut rng),
+        Err(SearchError::NoLegalAction)
+    );
+}
+
+#[test]
+fn searches_without_any_depth_report_an_empty_beam() {
+    let state = MazeState::new(0);
+    assert_eq!(
+        beam_search_action(&state, 5, 0),
+        Err(SearchError::EmptyBeam)
+    );
+    assert_eq!(
+        beam_search_action(&state, 0, 5),
+        Err(SearchError::EmptyBeam)
+    );
+    assert_eq!(
+        beam_search_action_with_attribution(&state, 5, 0, Attribution::Vote),
+        Err(SearchError::EmptyBeam)
+    );
+    assert_eq!(
+        chokudai_search_action(&state, 5, 5, 0),
+        Err(SearchError::EmptyBeam)
+    );
+    assert!(beam_search_action(&state, 5, 5).is_ok());
+}
+
+#[test]
+fn expired_budgets_still_return_the_one_ply_greedy_action() {
+    let state = MazeState::new(0);
+    // 同点の手の選び方もビームと揃えるため、1手読みのビームサーチを貪欲法の手とする
+    let greedy = beam_search_action(&state, 5, 1).unwrap();
+    let gain = |action| {
+        let mut next = state.clone();
+        next.advance(action);
+        next.game_score
+    };
+    assert_eq!(gain(greedy), gain(greedy_action(&state).unwrap()));
+
+    // 打ち切られていても根の子の1手読みは済ませる
+    let token = CancelToken::default();
+    token.cancel();
+    let result = with_cancel_token(&token, || {
+        beam_search_action_with_time_threshold(&state, 5, 10)
+    });
+    assert_eq!(result, Ok(greedy));
+
+    // 時刻を読むたびに1秒進む時計では、最初の確認で時間制限を使い切っている
+    let mut stats = SearchStats::default();
+    let result = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_secs(1))),
+        || beam_search_action_with_time_threshold_us_and_stats(&state, 5, 1000, &mut stats),
+    );
+    assert_eq!(result, Ok(greedy));
+    assert_eq!(stats.nodes_expanded, 1);
+    assert_eq!(stats.max_depth_reached, 1);
+
+    let mut policy = TimedBeamSearchPolicy {
+        beam_width: 5,
+        time_threshold_us: 10_000,
+        stop_rule: StopRule::default(),
+        eval_cache: None,
+        stats: SearchStats::default(),
+    };
+    with_cancel_token(&token, || {
+        assert_eq!(policy.try_select_action(&state), Some(greedy));
+    });
+}
+
+#[test]
+fn sampled_mcts_moves_stay_legal_without_playouts_or_at_low_temperature() {
+    // 温度0.01で訪問回数をそのまま100乗すると無限大になる
+    for (spec, turns) in [
+        ("mcts:playouts=0,temp=100", usize::MAX),
+        ("mcts:playouts=2000,temp=1", 3),
+    ] {
+        let mut policy = parse_policy(spec, 0).unwrap();
+        let mut state = MazeState::new(0);
+        for _ in 0..turns {
+            if state.is_done() {
+                break;
+            }
+            let (action, probabilities) = policy.sample_action(&state);
+            assert!(state.legal_actions().contains(&action), "{spec}");
+            let probabilities = probabilities.unwrap();
+            assert!(probabilities.iter().all(|p| p.is_finite()), "{spec}");
+            assert!(probabilities[action.index()] > 0., "{spec}");
+            state.advance(action);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use num_collect_maze::{
+    game::State,
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::{
+        beam_search_action, beam_search_action_with_stats, chokudai_search_action,
+        fixed_chokudai_search_action_with_stats, SearchStats,
+    },
+};
+
+#[test]
+fn counting_does_not_change_the_chosen_action() {
+    for seed in 0..5 {
+        let state = State::new(seed);
+        let mut stats = SearchStats::default();
+        assert_eq!(
+            beam_search_action_with_stats(&state, 5, 10, &mut stats),
+            beam_search_action(&state, 5, 10)
+        );
+        assert_eq!(
+            fixed_chokudai_search_action_with_stats(&state, 1, 20, 2, &mut stats),
+            chokudai_search_action(&state, 1, 20, 2)
+        );
+    }
+}
+
+#[test]
+fn beam_search_counts_are_bounded_by_its_parameters() {
+    let state = State::new(0);
+    let mut stats = SearchStats::default();
+    beam_search_action_with_stats(&state, 5, 10, &mut stats).unwrap();
+    let first = stats;
+    assert_eq!(first.searches, 1);
+    assert_eq!(first.max_depth_reached, 10);
+    assert!(first.nodes_expanded > 10 && first.nodes_expanded <= 5 * 10);
+    // 子は合法手の数 (4つ以下) だけ複製し、ほかに根と各深さの最良の状態を複製する
+    assert!(first.states_cloned <= 4 * first.nodes_expanded + 1 + 10);
+
+    // 同じ探索は同じだけ数え、繰り返すと足し込む
+    beam_search_action_with_stats(&state, 5, 10, &mut stats).unwrap();
+    assert_eq!(stats.searches, 2);
+    assert_eq!(stats.nodes_expanded, 2 * first.nodes_expanded);
+    assert_eq!(stats.states_cloned, 2 * first.states_cloned);
+}
+
+#[test]
+fn wider_search_does_more_work() {
+    let state = State::new(0);
+    let (mut narrow, mut wide) = (SearchStats::default(), SearchStats::default());
+    beam_search_action_with_stats(&state, 2, 10, &mut narrow).unwrap();
+    beam_search_action_with_stats(&state, 8, 10, &mut wide).unwrap();
+    assert!(wide.nodes_expanded > narrow.nodes_expanded);
+    assert!(wide.states_cloned > narrow.states_cloned);
+}
+
+#[test]
+fn game_results_carry_the_policy_search_stats() {
+    let config = PlayConfig::default();
+    let mut policy = parse_policy("chokudai:width=1,depth=10,num=2", 0).unwrap();
+    let result = run_game(0, policy.as_mut(), &config);
+    let stats = result.search_stats.unwrap();
+    assert!(stats.searches > 0);
+    assert_eq!(stats.passes, 2 * stats.searches);
+    assert!(stats.nodes_expanded >= stats.searches as u64);
+
+    let mut greedy = parse_policy("greedy", 0).unwrap();
+    assert!(run_game(0, greedy.as_mut(), &config).search_stats.is_none());
+}
+
+#[test]
+fn evaluator_phases_pass_through_the_inner_policy() {
+    let config = PlayConfig::default();
+    let mut policy =
+        parse_policy("chokudai:width=1,depth=10,num=2,phases=score/dist@t50", 0).unwrap();
+    let result = run_game(0, policy.as_mut(), &config);
+    let stats = policy.search_stats().unwrap();
+    assert_eq!(result.search_stats, Some(stats));
+    assert_eq!(stats.searches, 100);
+    assert!(stats.eval_cache_lookups > 0);
+
+    // 捨ての探索も評価関数を差し替えたAIに任せる
+    policy.warm_up(&State::new(1));
+    assert_eq!(policy.search_stats().unwrap().searches, 101);
+
+    // 合法手ごとの見積もりは中のビームサーチの評価値 (100倍したスコアから距離を引いたもの)
+    let mut policy = parse_policy("beam:width=3,depth=3,dist=50", 0).unwrap();
+    let values = policy.evaluate_actions(&State::new(0));
+    assert!(values.iter().any(|&(_, value)| value > 1.));
+}
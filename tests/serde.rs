@@ -0,0 +1,47 @@
+#![cfg(feature = "serde")]
+
+use num_collect_maze::{
+    game::{GameRecord, GameState, MazeState},
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::greedy_action,
+};
+
+#[test]
+fn maze_state_round_trips_through_json() {
+    let mut state = MazeState::new(7);
+    for _ in 0..10 {
+        state.advance(greedy_action(&state).unwrap());
+    }
+    let json = serde_json::to_string(&state).unwrap();
+    let mut loaded: MazeState = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.to_string(), state.to_string());
+    assert_eq!(loaded.state_hash(), state.state_hash());
+    while !state.is_done() {
+        let action = greedy_action(&state).unwrap();
+        state.advance(action);
+        loaded.advance(action);
+    }
+    assert_eq!(loaded.game_score, state.game_score);
+}
+
+#[test]
+fn game_record_round_trips_and_detects_tampering() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let result = run_game(11, policy.as_mut(), &PlayConfig::default());
+    let actions: Vec<_> = result.actions.iter().map(|&a| a.into()).collect();
+    let record = GameRecord::from_actions(result.seed, &actions).unwrap();
+    assert_eq!(record.scores.last().unwrap().as_usize(), result.game_score);
+
+    let json = serde_json::to_string(&record).unwrap();
+    let loaded: GameRecord = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, record);
+    assert_eq!(
+        loaded.replay().unwrap().game_score.as_usize(),
+        result.game_score
+    );
+
+    let mut tampered = loaded;
+    tampered.scores[5].0 += 1;
+    assert!(tampered.replay().is_err());
+}
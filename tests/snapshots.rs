@@ -0,0 +1,147 @@
+//! 盤面を描く関数ごとのスナップショット
+//!
+//! 小さく固定した盤面を描いた結果を `tests/snapshots/<名前>.snap` と比べる。描き方を変えたときは
+//! `UPDATE_SNAPSHOTS=1 cargo test --all-features --test snapshots` で書き直し、差分をレビューする。
+//! 一致しないときは隣に `<名前>.snap.new` を書き出すので、`diff` で見比べられる。
+
+use std::path::PathBuf;
+
+use num_collect_maze::{
+    game::{Direction, GameConfig, MazeState},
+    large::LargeMazeState,
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    results::render_trajectory_strip,
+    rules::RuledState,
+    search::greedy_action,
+    wall::WallMazeState,
+};
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let path = dir.join(format!("{name}.snap"));
+    let new_path = dir.join(format!("{name}.snap.new"));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        let _ = std::fs::remove_file(&new_path);
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_default();
+    if expected == actual {
+        let _ = std::fs::remove_file(&new_path);
+        return;
+    }
+    std::fs::write(&new_path, actual).unwrap();
+    let first_difference = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or(expected.lines().count().min(actual.lines().count()));
+    panic!(
+        "snapshot {name} changed at line {} (wrote {}; rerun with UPDATE_SNAPSHOTS=1 to accept)\nexpected: {:?}\n  actual: {:?}",
+        first_difference + 1,
+        new_path.display(),
+        expected.lines().nth(first_difference),
+        actual.lines().nth(first_difference),
+    );
+}
+
+/// 盤面0を貪欲法で5手進めた盤面
+fn fixed_state() -> (MazeState, Direction) {
+    let mut state = MazeState::new(0);
+    let mut action = Direction::Right;
+    for _ in 0..5 {
+        action = greedy_action(&state).unwrap();
+        state.advance(action);
+    }
+    (state, action)
+}
+
+#[test]
+fn plain() {
+    assert_snapshot("plain", &fixed_state().0.to_string());
+}
+
+#[test]
+fn colored() {
+    let (state, action) = fixed_state();
+    // エスケープ文字をそのまま残すとレビューで見えないので記号に置き換える
+    let rendered = state.render_color(Some(action)).replace('\x1b', "␛");
+    assert_snapshot("colored", &rendered);
+}
+
+#[test]
+fn wall() {
+    assert_snapshot("wall", &WallMazeState::new(0).to_string());
+}
+
+#[test]
+fn rules() {
+    let state =
+        RuledState::new(0, "torus+walls+teleporters:pairs=2", &GameConfig::default()).unwrap();
+    assert_snapshot("rules", &state.to_string());
+}
+
+#[test]
+fn large_window() {
+    let state = LargeMazeState::new(0, 100, 100);
+    let top = (state.character.y as usize).saturating_sub(5);
+    let left = (state.character.x as usize).saturating_sub(10);
+    assert_snapshot("large_window", &state.render_window(top, left, 10, 20));
+}
+
+#[test]
+fn trajectory_svg() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let config = PlayConfig {
+        game: GameConfig {
+            end_turn: 10,
+            ..GameConfig::default()
+        },
+        ..PlayConfig::default()
+    };
+    let result = run_game(0, policy.as_mut(), &config);
+    assert_snapshot("trajectory_svg", &render_trajectory_strip(&[result], 1));
+}
+
+#[cfg(feature = "tui")]
+#[test]
+fn tui_frame() {
+    use std::time::Duration;
+
+    use num_collect_maze::tui::{draw, Controls, PlayStats};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    let (state, action) = fixed_state();
+    let stats = PlayStats {
+        policy: "greedy".to_string(),
+        last_action: Some(action),
+        last_elapsed_us: 12,
+        max_elapsed_us: 30,
+        total_elapsed_us: 80,
+        searched_turns: 5,
+        time_threshold_us: Some(2_000),
+    };
+    let mut terminal = Terminal::new(TestBackend::new(64, 34)).unwrap();
+    terminal
+        .draw(|frame| {
+            draw(
+                frame,
+                &state,
+                &stats,
+                &Controls::new(Duration::from_millis(100)),
+            )
+        })
+        .unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut screen: String = buffer
+        .content()
+        .chunks(buffer.area.width as usize)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n")
+        .collect();
+    screen = screen
+        .lines()
+        .map(|line| line.trim_end().to_string() + "\n")
+        .collect();
+    assert_snapshot("tui_frame", &screen);
+}
@@ -0,0 +1,230 @@
+use num_collect_maze::{
+    compose::parse_policy_expr,
+    policy::{
+        parse_evaluator_phases, parse_policy, policy_names, POLICY_PARAMS, TIMED_VARIANT_PARAMS,
+    },
+    spec::{did_you_mean, edit_distance, SpecError},
+};
+
+fn spec_error(spec: &str) -> SpecError {
+    match parse_policy(spec, 0) {
+        Ok(policy) => panic!("{spec:?} was parsed as {}", policy.name()),
+        Err(error) => error,
+    }
+}
+
+#[test]
+fn edit_distance_counts_single_character_edits() {
+    assert_eq!(edit_distance("chokudai", "chokudai"), 0);
+    assert_eq!(edit_distance("chokudi", "chokudai"), 1);
+    assert_eq!(edit_distance("bema", "beam"), 1);
+    assert_eq!(edit_distance("beem", "beam"), 1);
+    assert_eq!(edit_distance("", "beam"), 4);
+    assert_eq!(did_you_mean("gredy", &policy_names()), Some("greedy"));
+    assert_eq!(did_you_mean("xyz", &policy_names()), None);
+}
+
+#[test]
+fn unknown_policies_list_the_valid_names_and_a_suggestion() {
+    let error = spec_error("chokudi:width=1");
+    assert_eq!(
+        error,
+        SpecError::UnknownName {
+            kind: "policy",
+            name: "chokudi".to_string(),
+            valid: policy_names(),
+        }
+    );
+    assert_eq!(error.suggestion(), Some("chokudai"));
+    let message = error.to_string();
+    assert!(message.starts_with("unknown policy \"chokudi\" (did you mean `chokudai`?)"));
+    assert!(message.contains("expected one of: random, greedy, exact, beam"));
+
+    // 綴りの遠い名前には候補を出さない
+    let error = spec_error("simulated-annealing");
+    assert_eq!(error.suggestion(), None);
+    assert!(!error.to_string().contains("did you mean"));
+}
+
+#[test]
+fn unknown_parameters_are_rejected_per_policy() {
+    let error = spec_error("beam:widht=5");
+    assert_eq!(error.suggestion(), Some("width"));
+    assert!(error
+        .to_string()
+        .starts_with("unknown parameter \"widht\" for beam (did you mean `width`?)"));
+    // 他のAIのパラメータは受け付けない
+    assert!(matches!(
+        spec_error("beam:num=2"),
+        SpecError::UnknownParam { key, .. } if key == "num"
+    ));
+    assert!(spec_error("greedy:width=3")
+        .to_string()
+        .ends_with("expected one of: dist, phases"));
+    // どのAIにも付けられるパラメータ
+    assert!(parse_policy("greedy:dist=50", 0).is_ok());
+}
+
+#[test]
+fn parameters_of_the_other_timed_variant_are_rejected() {
+    for spec in [
+        "chokudai:width=2,check=3",
+        "chokudai:width=2,tt=1",
+        "beam:width=3,margin=5",
+        "beam:width=3,bound=1",
+        "beam:width=3,tt=1",
+    ] {
+        assert!(
+            spec_error(spec).to_string().contains("add ms= or us="),
+            "{spec}"
+        );
+    }
+    for spec in [
+        "chokudai:width=2,ms=5,num=3",
+        "chokudai:width=2,us=500,num=3",
+        "beam:width=3,ms=5,depth=4",
+        "beam:width=3,ms=5,attr=vote",
+    ] {
+        assert!(
+            spec_error(spec)
+                .to_string()
+                .contains("remove it or ms=/us="),
+            "{spec}"
+        );
+    }
+    for spec in [
+        "chokudai:width=2,depth=5,num=3",
+        "chokudai:width=2,depth=5,ms=5,check=2,tt=1",
+        "beam:width=3,depth=4,attr=vote",
+        "beam:width=3,ms=5,margin=5,bound=1,tt=1",
+    ] {
+        assert!(parse_policy(spec, 0).is_ok(), "{spec}");
+    }
+}
+
+#[test]
+fn malformed_values_name_the_parameter() {
+    assert_eq!(
+        spec_error("beam:width"),
+        SpecError::InvalidParam {
+            param: "width".to_string(),
+            spec: "beam:width".to_string(),
+            expected: "key=value",
+        }
+    );
+    assert_eq!(
+        spec_error("beam:width=five").to_string(),
+        "invalid parameter \"width=five\" in \"beam:width=five\" (expected a non-negative integer)"
+    );
+    assert_eq!(spec_error("beam:attr=votes").suggestion(), Some("vote"));
+    assert!(spec_error("beam:dist=50,phases=potential")
+        .to_string()
+        .contains("cannot be combined"));
+}
+
+#[test]
+fn percentages_must_be_at_most_100() {
+    assert_eq!(
+        spec_error("two-phase:ms=10,split=150"),
+        SpecError::InvalidParam {
+            param: "split=150".to_string(),
+            spec: "two-phase:ms=10,split=150".to_string(),
+            expected: "a percentage from 0 to 100",
+        }
+    );
+    assert_eq!(
+        spec_error("epsilon-greedy:eps=101").to_string(),
+        "invalid parameter \"eps=101\" in \"epsilon-greedy:eps=101\" (expected a percentage from 0 to 100)"
+    );
+    assert!(matches!(
+        spec_error("rhea:mut=200"),
+        SpecError::InvalidParam { .. }
+    ));
+    for spec in [
+        "two-phase:ms=10,split=0",
+        "two-phase:ms=10,split=100",
+        "epsilon-greedy:eps=100",
+        "rhea:mut=100",
+    ] {
+        assert!(parse_policy(spec, 0).is_ok(), "{spec}");
+    }
+}
+
+#[test]
+fn evaluator_names_are_checked() {
+    let error = match parse_evaluator_phases("potental/dist@t30") {
+        Ok(_) => panic!("unknown evaluator was accepted"),
+        Err(error) => error,
+    };
+    assert_eq!(error.suggestion(), Some("potential"));
+    assert!(error.to_string().starts_with("unknown evaluator"));
+    assert_eq!(
+        spec_error("beam:phases=score/bound@x3"),
+        SpecError::Invalid(
+            "invalid phase condition \"x3\" in \"score/bound@x3\" (e.g. t30 or p200)".to_string()
+        )
+    );
+}
+
+#[test]
+fn expressions_suggest_combinators_too() {
+    let error = match parse_policy_expr("fallbak(greedy, beam)", 0) {
+        Ok(_) => panic!("unknown combinator was accepted"),
+        Err(error) => error,
+    };
+    assert_eq!(error.suggestion(), Some("fallback"));
+    assert!(error.to_string().contains("switch_at_turn"));
+    // 式の中の末端のAIのパラメータも確かめる
+    assert_eq!(
+        spec_error("fallback(greedy, beam(depht=3))").suggestion(),
+        Some("depth")
+    );
+    assert!(matches!(
+        spec_error("timeout(greedy, 5s)"),
+        SpecError::InvalidParam {
+            expected: "a duration such as 5ms",
+            ..
+        }
+    ));
+    assert!(matches!(
+        spec_error("fallback(greedy beam)"),
+        SpecError::Invalid(_)
+    ));
+}
+
+#[test]
+fn every_listed_parameter_is_accepted() {
+    for &(name, params) in POLICY_PARAMS {
+        // 時間制限の有無で版が分かれるAIは、それぞれの版が受け付けるものだけを付ける
+        let variants: Vec<Vec<&str>> =
+            match TIMED_VARIANT_PARAMS.iter().find(|&&(n, _, _)| n == name) {
+                Some(&(_, timed_only, untimed_only)) => vec![
+                    params
+                        .iter()
+                        .copied()
+                        .filter(|key| !untimed_only.contains(key))
+                        .collect(),
+                    params
+                        .iter()
+                        .copied()
+                        .filter(|key| !timed_only.contains(key) && !["ms", "us"].contains(key))
+                        .collect(),
+                ],
+                None => vec![params.to_vec()],
+            };
+        for keys in variants {
+            let values: Vec<String> = keys
+                .iter()
+                .map(|&key| match key {
+                    "attr" => "attr=vote".to_string(),
+                    "agg" => "agg=best".to_string(),
+                    // 時間制限付きのAIが時間を使い切らないように短くする
+                    "ms" | "us" => format!("{key}=1"),
+                    _ => format!("{key}=2"),
+                })
+                .collect();
+            let spec = format!("{name}:{}", values.join(","));
+            assert!(parse_policy(&spec, 0).is_ok(), "{spec}");
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use num_collect_maze::{
+    stats::{student_t_975, wilcoxon_signed_rank, RunningStats, TDigest},
+    util::splitmix64,
+};
+
+/// 0〜999の擬似乱数をn個
+fn values(n: u64) -> Vec<f64> {
+    (0..n).map(|i| (splitmix64(i) % 1000) as f64).collect()
+}
+
+#[test]
+fn running_stats_match_two_pass() {
+    let values = values(10_000);
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance =
+        values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+
+    let mut stats = RunningStats::default();
+    let (mut left, mut right) = (RunningStats::default(), RunningStats::default());
+    for (i, &x) in values.iter().enumerate() {
+        stats.push(x);
+        if i % 3 == 0 { &mut left } else { &mut right }.push(x);
+    }
+    left.merge(&right);
+    for s in [stats, left] {
+        assert!((s.mean - mean).abs() < 1e-9);
+        assert!((s.variance() - variance).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn t_digest_quantiles_are_close_to_exact() {
+    let values = values(100_000);
+    let mut sorted = values.clone();
+    sorted.sort_by(f64::total_cmp);
+
+    let mut digest = TDigest::default();
+    let (mut left, mut right) = (TDigest::default(), TDigest::default());
+    for (i, &x) in values.iter().enumerate() {
+        digest.push(x);
+        if i % 2 == 0 { &mut left } else { &mut right }.push(x);
+    }
+    left.merge(&right);
+    assert!(digest.centroids.len() < 300);
+    for d in [&mut digest, &mut left] {
+        for q in [0.01, 0.05, 0.5, 0.95, 0.99] {
+            let exact = sorted[((sorted.len() - 1) as f64 * q) as usize];
+            let estimate = d.quantile(q);
+            assert!(
+                (estimate - exact).abs() <= 5.,
+                "q={q}: {estimate} vs {exact}"
+            );
+        }
+    }
+}
+
+#[test]
+fn student_t_matches_table() {
+    for (df, expected) in [
+        (1, 12.706),
+        (5, 2.571),
+        (10, 2.228),
+        (29, 2.045),
+        (99, 1.984),
+    ] {
+        assert!((student_t_975(df) - expected).abs() < 0.005, "df={df}");
+    }
+}
+
+#[test]
+fn wilcoxon_signed_rank_matches_hand_computation() {
+    // 差が0の組を除いた9組、|5|が2つ同順位
+    let differences = [15., -7., 5., 20., 0., -9., 17., -12., 5., -10.];
+    let result = wilcoxon_signed_rank(&differences);
+    assert_eq!(result.n, 9);
+    assert_eq!((result.w_plus, result.w_minus), (27., 18.));
+    assert!((result.p_value - 0.635).abs() < 0.005, "{}", result.p_value);
+
+    let all_positive: Vec<f64> = (1..=30).map(f64::from).collect();
+    assert!(wilcoxon_signed_rank(&all_positive).p_value < 1e-5);
+    let symmetric: Vec<f64> = (1..=30).flat_map(|d| [d as f64, -d as f64]).collect();
+    assert!(wilcoxon_signed_rank(&symmetric).p_value > 0.95);
+}
@@ -0,0 +1,123 @@
+use std::{sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{Eval, GameState, State},
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::{
+        beam_search_action_with_stop_rule_us_and_stats,
+        beam_search_action_with_time_threshold_us_and_stats, SearchStats, StopRule,
+    },
+    util::{with_time_source, GameClock, ManualClock},
+};
+
+/// 時刻を読むたびに1us進む時計で、時間制限 `us` の探索を決定的に動かす
+fn search(state: &State, us: u128, stop_rule: StopRule) -> SearchStats {
+    let mut stats = SearchStats::default();
+    with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+        || {
+            beam_search_action_with_stop_rule_us_and_stats(state, 3, us, stop_rule, &mut stats)
+                .unwrap();
+        },
+    );
+    stats
+}
+
+#[test]
+fn the_default_rule_searches_until_the_deadline() {
+    let state = State::new(0);
+    let stopped = search(&state, 20_000, StopRule::default());
+    let mut stats = SearchStats::default();
+    with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(1))),
+        || {
+            beam_search_action_with_time_threshold_us_and_stats(&state, 3, 20_000, &mut stats)
+                .unwrap();
+        },
+    );
+    assert_eq!(stopped, stats);
+    assert_eq!(stats.early_stops, 0);
+}
+
+#[test]
+fn a_clear_lead_stops_the_search_early() {
+    let state = State::new(0);
+    let full = search(&state, 20_000, StopRule::default());
+    // 差が0以上なら1段読んだところで必ず満たす
+    let stopped = search(
+        &state,
+        20_000,
+        StopRule {
+            margin: Some(0),
+            bound: false,
+        },
+    );
+    assert_eq!(stopped.early_stops, 1);
+    assert_eq!(stopped.max_depth_reached, 1);
+    assert!(stopped.elapsed_us < full.elapsed_us);
+    // 届かない差では、ビームに最初の行動が2種類以上残っている間は打ち切らない
+    let never = search(
+        &state,
+        20_000,
+        StopRule {
+            margin: Some(i64::MAX),
+            bound: false,
+        },
+    );
+    assert!(never.max_depth_reached > 1);
+}
+
+#[test]
+fn reaching_the_bound_stops_the_search_early() {
+    // 得点が残っていなければ1手読みで上界に届く
+    let mut state = State::new(0);
+    while state.remaining_turns() > 3 {
+        let action = state.legal_actions()[0];
+        state.advance(action);
+    }
+    state.points.fill(0);
+    assert_eq!(state.evaluation_bound(), Some(Eval::from(state.game_score)));
+    let stats = search(
+        &state,
+        20_000,
+        StopRule {
+            margin: None,
+            bound: true,
+        },
+    );
+    assert_eq!(stats.early_stops, 1);
+    assert_eq!(stats.max_depth_reached, 1);
+}
+
+#[test]
+fn the_game_clock_banks_unused_time() {
+    let mut clock = GameClock::new(1000);
+    clock.record(400);
+    assert_eq!(clock.allowance_us(), 1600);
+    clock.record(1600);
+    assert_eq!(clock.bank_us, 0);
+    clock.record(5000);
+    assert_eq!(clock.allowance_us(), 1000);
+}
+
+#[test]
+fn policies_take_the_stop_rule_from_the_spec() {
+    let policy = parse_policy("beam:width=3,ms=5,margin=100,bound=1", 0).unwrap();
+    assert_eq!(policy.name(), "beam(width=3, ms=5, margin=100, bound=1)");
+    assert!(parse_policy("beam:width=3,margin=100", 0).is_err());
+
+    let config = PlayConfig {
+        bank_time: true,
+        game: num_collect_maze::game::GameConfig {
+            end_turn: 10,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut policy = parse_policy("beam:width=3,us=2000,margin=0", 0).unwrap();
+    let result = run_game(0, policy.as_mut(), &config);
+    let stats = result.search_stats.unwrap();
+    // 最後の手はゲームが終わるので打ち切りには数えない
+    assert_eq!(stats.early_stops, stats.searches - 1);
+}
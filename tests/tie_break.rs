@@ -0,0 +1,173 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use num_collect_maze::{
+    game::{Coord, Direction, Eval, GameState, MazeState},
+    policy::parse_policy,
+    search::{
+        beam_search_action, beam_search_action_with_attribution, chokudai_search_action,
+        Attribution,
+    },
+};
+
+#[test]
+fn equal_evaluations_are_ordered_by_turn_then_position() {
+    let mut a = MazeState::new(0);
+    a.evaluated_score = Eval(10);
+    let mut b = a.clone();
+
+    b.turn += 1;
+    // 同じ評価値なら少ないターン数で着いた方が先
+    assert_eq!(a.cmp(&b), Ordering::Greater);
+
+    b.turn = a.turn;
+    a.character = Coord::new(1, 2);
+    b.character = Coord::new(1, 3);
+    assert_eq!(a.cmp(&b), Ordering::Less);
+    assert!(a != b);
+
+    b.character = Coord::new(1, 2);
+    assert!(a == b);
+    b.evaluated_score = Eval(11);
+    assert_eq!(a.cmp(&b), Ordering::Less);
+}
+
+#[test]
+fn tied_children_are_chosen_by_position() {
+    // 得点のない盤面では全ての子が同点になり、行き先の位置で決まる
+    let mut state = MazeState::new(3);
+    state.points.fill(0);
+    let expected = state
+        .legal_actions()
+        .into_iter()
+        .max_by_key(|&action| {
+            let next = state.character.offset(action).unwrap();
+            (next.y, next.x)
+        })
+        .unwrap();
+    assert_eq!(beam_search_action(&state, 3, 1).unwrap(), expected);
+    assert_eq!(chokudai_search_action(&state, 1, 1, 3).unwrap(), expected);
+}
+
+/// 子を全て `BinaryHeap` に入れる素朴なビームサーチ (同点は先に入れた方を先に取り出す)
+fn heap_beam_search_action(state: &MazeState, width: usize, depth: usize) -> Direction {
+    let mut now_beam = BinaryHeap::from([(state.clone(), Reverse(0))]);
+    let mut best = None;
+    for t in 0..depth {
+        let mut next_beam = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        let mut popped = 0;
+        while popped < width {
+            let Some((now_state, _)) = now_beam.pop() else {
+                break;
+            };
+            if !seen.insert(now_state.state_hash().unwrap()) {
+                continue;
+            }
+            popped += 1;
+            for action in now_state.legal_actions() {
+                let mut next_state = now_state.clone();
+                next_state.advance(action);
+                next_state.evaluate();
+                if t == 0 {
+                    next_state.set_first_action(action);
+                }
+                let order = Reverse(next_beam.len());
+                next_beam.push((next_state, order));
+            }
+        }
+        now_beam = next_beam;
+        let Some((top, _)) = now_beam.peek() else {
+            break;
+        };
+        best = Some(top.first_action());
+        if top.is_done() {
+            break;
+        }
+    }
+    best.unwrap()
+}
+
+#[test]
+fn selecting_the_top_children_keeps_the_heap_order() {
+    // 得点の少ない盤面では同点と重複が多く、上位を選び直すことが多い
+    for seed in 0..4 {
+        let mut state = MazeState::new(seed);
+        if seed % 2 == 1 {
+            state.points.iter_mut().for_each(|point| *point %= 2);
+        }
+        for (width, depth) in [(1, 5), (3, 10), (50, 8), (300, 6)] {
+            assert_eq!(
+                beam_search_action(&state, width, depth).unwrap(),
+                heap_beam_search_action(&state, width, depth),
+                "seed={seed} width={width} depth={depth}"
+            );
+        }
+    }
+}
+
+#[test]
+fn best_leaf_attribution_follows_the_same_beam() {
+    // 最良の葉から親をたどった行動は、葉が引き継いだ最初の行動と同じ
+    for seed in 0..10 {
+        let mut state = MazeState::new(seed);
+        let mut flat = state.clone();
+        flat.points.fill(0);
+        for _ in 0..30 {
+            for state in [&state, &flat] {
+                for (width, depth) in [(1, 3), (3, 5), (8, 10)] {
+                    assert_eq!(
+                        beam_search_action_with_attribution(
+                            state,
+                            width,
+                            depth,
+                            Attribution::BestLeaf
+                        ),
+                        beam_search_action(state, width, depth),
+                        "seed={seed} turn={} width={width} depth={depth}",
+                        state.turn
+                    );
+                }
+            }
+            let action = beam_search_action(&state, 3, 3).unwrap();
+            state.advance(action);
+            flat.advance(action);
+        }
+    }
+}
+
+#[test]
+fn estimated_values_peak_at_the_chosen_action() {
+    for spec in [
+        "beam:width=1,depth=5",
+        "beam:width=5,depth=10",
+        "beam:width=3,depth=40",
+        "beam:width=5,depth=10,attr=vote",
+        "beam:width=2,depth=10,dist=50",
+        "beam:width=5,depth=20,dist=50",
+    ] {
+        let mut policy = parse_policy(spec, 0).unwrap();
+        for seed in 0..5 {
+            let mut state = MazeState::new(seed);
+            for _ in 0..20 {
+                let chosen = policy.select_action(&state);
+                let values = policy.evaluate_actions(&state);
+                let best = values
+                    .iter()
+                    .map(|&(_, value)| value)
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let chosen_value = values.iter().find(|&&(a, _)| a == chosen).unwrap().1;
+                assert_eq!(chosen_value, best, "{spec} seed {seed} turn {}", state.turn);
+                if spec.starts_with("beam:width=1,") {
+                    // 幅1ならビームに残るのは選んだ手から始まる状態だけ
+                    assert!(values
+                        .iter()
+                        .all(|&(a, value)| a == chosen || value == Eval::MIN.0 as f64));
+                }
+                state.advance(chosen);
+            }
+        }
+    }
+}
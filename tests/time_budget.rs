@@ -0,0 +1,103 @@
+use std::{sync::Arc, time::Duration};
+
+use num_collect_maze::{
+    game::{MazeState, END_TURN},
+    policy::{budget_param, parse_policy},
+    search::{
+        beam_search_action_with_time_threshold_us, chokudai_search_action_with_stats,
+        chokudai_search_action_with_stats_us, two_phase_search_action, SearchStats,
+        CHOKUDAI_CHECK_INTERVAL,
+    },
+    util::{with_time_source, ManualClock},
+};
+
+/// 時刻を読むたびに100us進む時計で、時間制限 `time_threshold_us` [us] のchokudaiサーチを1回行う
+fn ticking_chokudai_us(seed: u64, time_threshold_us: u128) -> (usize, SearchStats) {
+    let state = MazeState::new(seed);
+    let mut stats = SearchStats::default();
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(100))),
+        || {
+            chokudai_search_action_with_stats_us(
+                &state,
+                1,
+                END_TURN,
+                time_threshold_us,
+                CHOKUDAI_CHECK_INTERVAL,
+                &mut stats,
+            )
+        },
+    );
+    (action.unwrap().index(), stats)
+}
+
+#[test]
+fn sub_millisecond_budgets_stop_the_search_in_time() {
+    let (_, stats) = ticking_chokudai_us(0, 500);
+    // 500us目の確認で打ち切り、記録のための読み取りで100us進む
+    assert_eq!(stats.elapsed_us, 600);
+    assert_eq!(stats.searches, 1);
+    assert!(stats.nodes_expanded > 0);
+}
+
+#[test]
+fn millisecond_budgets_are_microsecond_budgets() {
+    for seed in 0..3 {
+        let state = MazeState::new(seed);
+        let mut stats = SearchStats::default();
+        let action = with_time_source(
+            Arc::new(ManualClock::ticking(Duration::from_micros(100))),
+            || {
+                chokudai_search_action_with_stats(
+                    &state,
+                    1,
+                    END_TURN,
+                    1,
+                    CHOKUDAI_CHECK_INTERVAL,
+                    &mut stats,
+                )
+            },
+        );
+        let (action_us, stats_us) = ticking_chokudai_us(seed, 1000);
+        assert_eq!(action.unwrap().index(), action_us);
+        assert_eq!(stats, stats_us);
+    }
+}
+
+#[test]
+fn tiny_budgets_fall_back_to_greedy_without_searching() {
+    let state = MazeState::new(0);
+    let action = beam_search_action_with_time_threshold_us(&state, 5, 50).unwrap();
+    assert!(state.legal_actions().contains(&action));
+}
+
+#[test]
+fn two_phase_split_over_100_percent_does_not_underflow() {
+    let state = MazeState::new(0);
+    let action = with_time_source(
+        Arc::new(ManualClock::ticking(Duration::from_micros(100))),
+        || two_phase_search_action(&state, 5, 2, 2, 2, 10, 150),
+    )
+    .unwrap();
+    assert!(state.legal_actions().contains(&action));
+}
+
+#[test]
+fn policies_take_budgets_in_microseconds() {
+    assert_eq!(budget_param(10_000), "ms=10");
+    assert_eq!(budget_param(250), "us=250");
+
+    let policy = parse_policy("beam:width=5,us=500", 0).unwrap();
+    assert_eq!(policy.name(), "beam(width=5, us=500)");
+    assert_eq!(policy.time_threshold_us(), Some(500));
+    // `us` は `ms` より優先する
+    let policy = parse_policy("chokudai:width=1,ms=10,us=2000", 0).unwrap();
+    assert_eq!(policy.name(), "chokudai(width=1, depth=100, ms=2)");
+    let mut policy = parse_policy("parallel-chokudai:ms=3,threads=1", 0).unwrap();
+    assert_eq!(policy.time_threshold_us(), Some(3000));
+    policy.set_time_threshold_us(750);
+    assert_eq!(
+        policy.name(),
+        "parallel-chokudai(width=1, depth=100, us=750, threads=1)"
+    );
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "tui")]
+
+use std::time::Duration;
+
+use num_collect_maze::{
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    tui::{run, Controls},
+};
+use ratatui::{backend::TestBackend, crossterm::event::KeyCode, Terminal};
+
+/// `keys` を順に押し、使い切ったら `q` を押す
+fn scripted(
+    keys: Vec<Option<KeyCode>>,
+) -> impl FnMut(Duration) -> std::io::Result<Option<KeyCode>> {
+    let mut keys = keys.into_iter();
+    move |_| Ok(keys.next().unwrap_or(Some(KeyCode::Char('q'))))
+}
+
+fn screen(terminal: &Terminal<TestBackend>) -> String {
+    let buffer = terminal.backend().buffer();
+    buffer
+        .content()
+        .chunks(buffer.area.width as usize)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn tui_plays_the_same_game_as_run_game() {
+    let mut terminal = Terminal::new(TestBackend::new(64, 34)).unwrap();
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let (state, stats) = run(
+        &mut terminal,
+        3,
+        policy.as_mut(),
+        &mut Controls::new(Duration::ZERO),
+        &mut scripted(vec![None; 200]),
+    )
+    .unwrap();
+    let expected = run_game(
+        3,
+        parse_policy("greedy", 0).unwrap().as_mut(),
+        &PlayConfig::default(),
+    );
+    assert_eq!(state.game_score.as_usize(), expected.game_score);
+    assert!(stats.searched_turns > 0);
+    let screen = screen(&terminal);
+    assert!(screen.contains("status: done"), "{screen}");
+    assert!(
+        screen.contains(&format!("score: {}", expected.game_score)),
+        "{screen}"
+    );
+}
+
+#[test]
+fn paused_tui_advances_one_turn_per_step() {
+    let mut terminal = Terminal::new(TestBackend::new(64, 34)).unwrap();
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let mut controls = Controls::new(Duration::from_millis(100));
+    let keys = [' ', 'n', 'n', '+'].map(|c| Some(KeyCode::Char(c)));
+    let (state, stats) = run(
+        &mut terminal,
+        3,
+        policy.as_mut(),
+        &mut controls,
+        &mut scripted(keys.to_vec()),
+    )
+    .unwrap();
+    assert_eq!(state.turn, 2);
+    assert_eq!(stats.searched_turns, 2);
+    assert!(controls.paused);
+    assert_eq!(controls.delay, Duration::from_millis(50));
+    let screen = screen(&terminal);
+    assert!(screen.contains("turn: 2/"), "{screen}");
+    assert!(screen.contains("status: paused"), "{screen}");
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use num_collect_maze::{
+    game::MazeState,
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::greedy_action,
+    util::ManualClock,
+    visualize::{animate, AnimationConfig},
+};
+
+#[test]
+fn virtual_clock_paces_frames_without_sleeping() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let result = run_game(3, policy.as_mut(), &PlayConfig::default());
+    let config = AnimationConfig {
+        fps: 4.,
+        clear_screen: false,
+        color: false,
+    };
+    let clock = ManualClock::default();
+    let mut out = vec![];
+    let start = Instant::now();
+    let frames = animate(&result, &config, &clock, &mut out).unwrap();
+
+    // 100ターン分を4fpsで再生すると25秒かかるが、仮想の時計なので実際には待たない
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(frames.len(), result.actions.len() + 1);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.turn, i);
+        assert_eq!(frame.shown, Duration::from_millis(250) * i as u32);
+        assert!(!frame.late);
+    }
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches("turn:").count(), frames.len());
+    assert!(out.contains(&format!("score:\t{}\n", result.game_score)));
+}
+
+#[test]
+fn late_frames_do_not_accumulate_delay() {
+    let mut policy = parse_policy("greedy", 0).unwrap();
+    let result = run_game(5, policy.as_mut(), &PlayConfig::default());
+    let config = AnimationConfig {
+        fps: 10.,
+        clear_screen: false,
+        color: false,
+    };
+    // 時刻を読むたびに30ms進むので、描くのに毎フレーム60ms程かかる
+    let clock = ManualClock::ticking(Duration::from_millis(30));
+    let frames = animate(&result, &config, &clock, &mut std::io::sink()).unwrap();
+    let last = frames.last().unwrap();
+    // 予定時刻は開始から決まるので、遅れは1フレーム分の描画時間を超えて積み重ならない
+    assert!(
+        last.shown - last.due <= Duration::from_millis(100),
+        "{last:?}"
+    );
+}
+
+/// ANSIエスケープ `\x1b[...m` を取り除く
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[test]
+fn color_rendering_matches_plain_board_except_last_move() {
+    let mut state = MazeState::new(4);
+    let mut last_action = None;
+    for _ in 0..5 {
+        let action = greedy_action(&state).unwrap();
+        state.advance(action);
+        last_action = Some(action);
+    }
+    let colored = state.render_color(last_action);
+    assert!(colored.contains("\x1b[1;30;43m@"));
+    let plain = strip_ansi(&colored);
+    let arrows: Vec<_> = plain.match_indices(['<', '>', '^', 'v']).collect();
+    assert_eq!(arrows.len(), 1, "{plain}");
+    assert_eq!(plain.replace(['<', '>', '^', 'v'], "."), state.to_string());
+    assert_eq!(strip_ansi(&state.render_color(None)), state.to_string());
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use num_collect_maze::{
+    game::{Coord, Eval, GameConfig, MazeState, H, W},
+    play::{run_game, PlayConfig},
+    policy::parse_policy,
+    search::endgame_solver_action,
+    warm::{neighbor_table, potential_table, with_transposition_table, TranspositionTable},
+};
+
+#[test]
+fn shared_tables_match_direct_computation() {
+    for y in 0..H {
+        for x in 0..W {
+            let mut state = MazeState::new(0);
+            state.character = Coord::new(y as i32, x as i32);
+            let actions: Vec<_> = neighbor_table()
+                .neighbors(state.character.index())
+                .iter()
+                .map(|&(action, _)| action)
+                .collect();
+            assert_eq!(actions, state.legal_actions());
+        }
+    }
+    for distance in 0..H + W {
+        for point in 0..12 {
+            let expected = 100 * point as i64 / ((distance as i64 + 1) * (distance as i64 + 1));
+            assert_eq!(potential_table().potential(distance, point), expected);
+        }
+    }
+}
+
+/// 残り `turns` ターンの盤面
+fn endgame_state(seed: u64, turns: usize) -> MazeState {
+    let config = GameConfig {
+        end_turn: turns,
+        ..Default::default()
+    };
+    MazeState::with_config(seed, &config)
+}
+
+#[test]
+fn one_solve_fills_the_table_along_the_best_line() {
+    let table = Arc::new(TranspositionTable::default());
+    let (score, solves) = with_transposition_table(table.clone(), || {
+        let mut state = endgame_state(3, 12);
+        while !state.is_done() {
+            let action = endgame_solver_action(&state).unwrap();
+            state.advance(action);
+        }
+        (state.game_score.as_usize(), table.misses())
+    });
+    // 最初の1回だけ解き、残りのターンは表を引く
+    assert_eq!(solves, 1);
+    assert_eq!(table.hits(), 11);
+
+    // ターンごとに空の表で解き直しても同じ得点になる
+    let mut state = endgame_state(3, 12);
+    while !state.is_done() {
+        let action =
+            with_transposition_table(Arc::default(), || endgame_solver_action(&state)).unwrap();
+        state.advance(action);
+    }
+    assert_eq!(state.game_score.as_usize(), score);
+}
+
+#[test]
+fn evaluation_settings_get_their_own_entries() {
+    let plain = endgame_state(3, 12);
+    let penalized = MazeState::with_config(
+        3,
+        &GameConfig {
+            end_turn: 12,
+            revisit_penalty: 5,
+            ..Default::default()
+        },
+    );
+    let noisy = MazeState::with_config(
+        3,
+        &GameConfig {
+            end_turn: 12,
+            eval_noise: 5,
+            ..Default::default()
+        },
+    );
+    let keys = [&plain, &penalized, &noisy].map(TranspositionTable::key);
+    assert_ne!(keys[0], keys[1]);
+    assert_ne!(keys[0], keys[2]);
+    assert_ne!(keys[1], keys[2]);
+
+    // 差し替えた評価関数はキーにできないので表を使わない
+    let table = Arc::new(TranspositionTable::default());
+    let state = plain.with_evaluator(Arc::new(|_: &MazeState| Eval(0)));
+    with_transposition_table(table.clone(), || endgame_solver_action(&state)).unwrap();
+    assert!(table.is_empty());
+    assert_eq!(table.misses(), 0);
+}
+
+#[test]
+fn isolated_caches_do_not_change_results() {
+    let shared = PlayConfig {
+        endgame_turns: Some(10),
+        ..Default::default()
+    };
+    let isolated = PlayConfig {
+        isolate_caches: true,
+        ..shared.clone()
+    };
+    for seed in 0..3 {
+        let mut policy = parse_policy("greedy", 0).unwrap();
+        let a = run_game(seed, policy.as_mut(), &shared);
+        let b = run_game(seed, policy.as_mut(), &isolated);
+        assert_eq!(a.game_score, b.game_score);
+        assert_eq!(a.token, b.token);
+    }
+}